@@ -1,3 +1,6 @@
 fn main() {
-    println!("cargo:rustc-link-lib=cgroup");
+    // cgroup is only available on Linux
+    if std::env::var("CARGO_CFG_TARGET_OS").as_deref() == Ok("linux") {
+        println!("cargo:rustc-link-lib=cgroup");
+    }
 }
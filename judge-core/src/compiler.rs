@@ -137,6 +137,15 @@ impl Compiler {
         }
     }
 
+    /// The program the compile command runs, e.g. `g++`.
+    pub fn command_program(&self) -> &str {
+        self.command_builder
+            .command_template
+            .split_whitespace()
+            .next()
+            .unwrap_or_default()
+    }
+
     pub fn compile(
         &self,
         src_path: &PathBuf,
@@ -148,6 +157,7 @@ impl Compiler {
                 src_path
             )));
         }
+        let _span = enter_span!("compile", language = %self.language);
 
         let src_path_string = get_pathbuf_str(src_path)?;
         let target_path_string = get_pathbuf_str(target_path)?;
@@ -1,3 +1,5 @@
+use crate::sandbox::SandboxSetupStep;
+#[cfg(target_os = "linux")]
 use libseccomp::error::SeccompError;
 use nix::errno::Errno;
 use std::ffi::NulError;
@@ -8,6 +10,7 @@ use std::string::FromUtf8Error;
 #[derive(Debug)]
 pub enum JudgeCoreError {
     NixErrno(Errno),
+    #[cfg(target_os = "linux")]
     SeccompError(SeccompError),
     FFINulError(NulError),
     IOError(io::Error),
@@ -15,6 +18,47 @@ pub enum JudgeCoreError {
     AnyhowError(anyhow::Error),
     FromUtf8Error(FromUtf8Error),
     CompileError(String),
+    /// A checker executor is needed but not set
+    CheckerNotConfigured,
+    /// An interactor executor is needed but not set
+    InteractorNotConfigured,
+    InputFileMissing(PathBuf),
+    ExecutableNotFound(PathBuf),
+    /// The executable can't be run, e.g. for a missing execute bit or a wrong format
+    ExecutableNotRunnable {
+        path: PathBuf,
+        reason: String,
+    },
+    /// Building the syscall filter failed, e.g. for an unknown syscall name
+    SeccompSetupFailed(String),
+    /// The forked process failed to set itself up, e.g. to apply its CPU affinity, and never
+    /// ran the program
+    SandboxSetupFailed {
+        step: SandboxSetupStep,
+        errno: Errno,
+    },
+    /// The config can't work, e.g. a limit the host can't provide, see `JudgeConfig::validate()`
+    InvalidConfig(String),
+    /// A forked listener sent an exit report which can't be read, e.g. truncated, too large
+    /// or not valid JSON, see `read_exit_message()`
+    MalformedExitReport(String),
+    /// A process was still there after `SIGKILL` and is reaped in the background, along with
+    /// its `/proc/<pid>/stat`. See `Sandbox::set_reap_timeout()`.
+    ProcessUnkillable {
+        pid: i32,
+        stat: String,
+    },
+}
+
+impl JudgeCoreError {
+    /// Whether it comes from a write to a full filesystem (`ENOSPC`).
+    pub fn is_disk_full(&self) -> bool {
+        match self {
+            JudgeCoreError::NixErrno(errno) => *errno == Errno::ENOSPC,
+            JudgeCoreError::IOError(error) => error.raw_os_error() == Some(libc::ENOSPC),
+            _ => false,
+        }
+    }
 }
 
 impl From<Errno> for JudgeCoreError {
@@ -23,6 +67,7 @@ impl From<Errno> for JudgeCoreError {
     }
 }
 
+#[cfg(target_os = "linux")]
 impl From<SeccompError> for JudgeCoreError {
     fn from(error: SeccompError) -> JudgeCoreError {
         JudgeCoreError::SeccompError(error)
@@ -0,0 +1,276 @@
+use std::{env, fs, os::unix::fs::PermissionsExt, path::PathBuf};
+
+use nix::{
+    sys::wait::{waitpid, WaitStatus},
+    unistd::{fork, ForkResult},
+};
+use serde_derive::Serialize;
+
+use crate::compiler::{Compiler, Language};
+use crate::error::JudgeCoreError;
+#[cfg(target_os = "linux")]
+use crate::run::sandbox::build_scmp_filter;
+#[cfg(target_os = "linux")]
+use crate::sandbox::SyscallFilterLoader;
+use crate::sandbox::{sandbox_user_id, RlimitConfigs, SeccompMode, SANDBOX_USERNAME};
+
+/// What the judger is going to be asked for, see `health_check()`.
+#[derive(Debug, Clone, Serialize)]
+pub struct HealthCheckConfig {
+    /// Languages whose compiler must be installed
+    pub languages: Vec<Language>,
+    /// Limits sandboxed processes get, usually the largest ones of the contest
+    pub rlimit_configs: RlimitConfigs,
+    pub seccomp_mode: SeccompMode,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum HealthStatus {
+    Pass,
+    Fail,
+    /// The check does not apply to the config or the platform
+    Skip,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HealthCheck {
+    pub name: String,
+    pub status: HealthStatus,
+    pub detail: String,
+    /// How to fix a failed check
+    pub remediation: Option<String>,
+}
+
+impl HealthCheck {
+    fn pass(name: &str, detail: String) -> Self {
+        Self {
+            name: name.to_string(),
+            status: HealthStatus::Pass,
+            detail,
+            remediation: None,
+        }
+    }
+
+    fn fail(name: &str, detail: String, remediation: String) -> Self {
+        Self {
+            name: name.to_string(),
+            status: HealthStatus::Fail,
+            detail,
+            remediation: Some(remediation),
+        }
+    }
+
+    fn skip(name: &str, detail: String) -> Self {
+        Self {
+            name: name.to_string(),
+            status: HealthStatus::Skip,
+            detail,
+            remediation: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HealthReport {
+    pub checks: Vec<HealthCheck>,
+}
+
+impl HealthReport {
+    /// No check failed.
+    pub fn is_healthy(&self) -> bool {
+        self.checks
+            .iter()
+            .all(|check| check.status != HealthStatus::Fail)
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = &HealthCheck> {
+        self.checks
+            .iter()
+            .filter(|check| check.status == HealthStatus::Fail)
+    }
+}
+
+/// Check that the judger can run what `config` asks for on this host,
+/// meant for failing fast at startup rather than in the middle of a contest.
+pub fn health_check(config: &HealthCheckConfig) -> HealthReport {
+    let mut checks = vec![
+        check_privileges(),
+        check_sandbox_user(),
+        check_seccomp(config.seccomp_mode),
+        check_namespaces(),
+        check_rlimits(&config.rlimit_configs),
+    ];
+    checks.extend(
+        config
+            .languages
+            .iter()
+            .map(|language| check_compiler(*language)),
+    );
+    for check in &checks {
+        if check.status == HealthStatus::Fail {
+            log::warn!("Health check {} failed: {}", check.name, check.detail);
+        }
+    }
+    HealthReport { checks }
+}
+
+/// Only a judger run by root can switch sandboxed processes to `SANDBOX_USERNAME`.
+pub fn check_privileges() -> HealthCheck {
+    const NAME: &str = "privileges";
+    let uid = unsafe { libc::getuid() };
+    if uid == 0 {
+        HealthCheck::pass(NAME, "running as root".to_string())
+    } else {
+        HealthCheck::fail(
+            NAME,
+            format!(
+                "running as uid {}, sandboxed processes keep the privileges of the judger",
+                uid
+            ),
+            format!(
+                "run the judger as root, sandboxed processes are then run as {}",
+                SANDBOX_USERNAME
+            ),
+        )
+    }
+}
+
+pub fn check_sandbox_user() -> HealthCheck {
+    const NAME: &str = "sandbox user";
+    match sandbox_user_id() {
+        Ok(user_id) => HealthCheck::pass(NAME, format!("{} has uid {}", SANDBOX_USERNAME, user_id)),
+        Err(e) => HealthCheck::fail(
+            NAME,
+            format!("can't find the uid of {}: {:?}", SANDBOX_USERNAME, e),
+            format!("create the user, e.g. `useradd {}`", SANDBOX_USERNAME),
+        ),
+    }
+}
+
+/// Load the restricted filter in a forked child, the judger itself stays unfiltered.
+///
+/// With `SeccompMode::Preferred` a filter which can't be loaded is skipped, as the sandbox would.
+pub fn check_seccomp(seccomp_mode: SeccompMode) -> HealthCheck {
+    const NAME: &str = "seccomp";
+    if seccomp_mode == SeccompMode::Off {
+        return HealthCheck::skip(NAME, "the syscall filter is off".to_string());
+    }
+    #[cfg(target_os = "linux")]
+    let result = build_scmp_filter(true, false)
+        .map_err(|e| format!("can't build the syscall filter: {}", e))
+        .and_then(|filter| match run_in_child(|| filter.load().is_ok()) {
+            Ok(true) => Ok(()),
+            Ok(false) => Err("can't load the syscall filter".to_string()),
+            Err(e) => Err(format!("can't fork to load the syscall filter: {:?}", e)),
+        });
+    #[cfg(not(target_os = "linux"))]
+    let result: Result<(), String> = Err("seccomp is not available on this platform".to_string());
+
+    match result {
+        Ok(()) => HealthCheck::pass(NAME, "the syscall filter loads".to_string()),
+        Err(detail) if seccomp_mode == SeccompMode::Preferred => HealthCheck::skip(
+            NAME,
+            format!("{}, processes run without the filter", detail),
+        ),
+        Err(detail) => HealthCheck::fail(
+            NAME,
+            detail,
+            "run on Linux with seccomp enabled, in a container allow the `seccomp` syscall, \
+             or set `SeccompMode::Preferred`"
+                .to_string(),
+        ),
+    }
+}
+
+/// The sandbox does not isolate processes in namespaces yet, nothing to check.
+pub fn check_namespaces() -> HealthCheck {
+    HealthCheck::skip(
+        "namespaces",
+        "the sandbox does not use namespaces".to_string(),
+    )
+}
+
+/// Set `rlimit_configs` in a forked child, as the sandbox does before running a program: as the
+/// sandbox user when the judger is root, which can't raise a hard limit of the judger.
+///
+/// The sandbox lowers a limit the host does not permit rather than failing, so does the check,
+/// failing when any limit ends up lower than configured.
+pub fn check_rlimits(rlimit_configs: &RlimitConfigs) -> HealthCheck {
+    const NAME: &str = "rlimits";
+    let limits = rlimit_configs.loaded_limits();
+    let user_id = if unsafe { libc::getuid() } == 0 {
+        match sandbox_user_id() {
+            Ok(user_id) => Some(user_id),
+            Err(e) => {
+                return HealthCheck::fail(
+                    NAME,
+                    format!("can't find the uid of {}: {:?}", SANDBOX_USERNAME, e),
+                    format!("create the user, e.g. `useradd {}`", SANDBOX_USERNAME),
+                )
+            }
+        }
+    } else {
+        None
+    };
+    let loaded = run_in_child(|| {
+        if let Some(user_id) = user_id {
+            if unsafe { libc::setuid(user_id) } != 0 {
+                return false;
+            }
+        }
+        limits.load_rlimits().is_ok() && limits.all_loaded()
+    });
+    match loaded {
+        Ok(true) => HealthCheck::pass(NAME, "the limits can be set".to_string()),
+        Ok(false) => HealthCheck::fail(
+            NAME,
+            "the sandbox user can't be given the limits, one exceeds a hard limit of the judger"
+                .to_string(),
+            "raise the hard limits of the judger, e.g. with `ulimit -H`, or run it as root"
+                .to_string(),
+        ),
+        Err(e) => HealthCheck::fail(
+            NAME,
+            format!("can't fork to set the limits: {:?}", e),
+            "check the process limit of the judger".to_string(),
+        ),
+    }
+}
+
+pub fn check_compiler(language: Language) -> HealthCheck {
+    let name = format!("compiler {}", language);
+    let compiler = Compiler::new(language, vec![]);
+    let program = compiler.command_program();
+    match find_in_path(program) {
+        Some(path) => HealthCheck::pass(&name, format!("{} is {:?}", program, path)),
+        None => HealthCheck::fail(
+            &name,
+            format!("{} is not found in PATH", program),
+            format!("install {} or add it to PATH", program),
+        ),
+    }
+}
+
+fn find_in_path(program: &str) -> Option<PathBuf> {
+    let paths = env::var_os("PATH")?;
+    env::split_paths(&paths)
+        .map(|dir| dir.join(program))
+        .find(|path| {
+            fs::metadata(path).is_ok_and(|metadata| {
+                metadata.is_file() && metadata.permissions().mode() & 0o111 != 0
+            })
+        })
+}
+
+/// Whether `f` returns true in a forked child, not to change the judger itself.
+fn run_in_child(f: impl FnOnce() -> bool) -> Result<bool, JudgeCoreError> {
+    match unsafe { fork() }? {
+        ForkResult::Child => {
+            let code = if f() { 0 } else { 1 };
+            unsafe { libc::_exit(code) }
+        }
+        ForkResult::Parent { child } => {
+            Ok(matches!(waitpid(child, None)?, WaitStatus::Exited(_, 0)))
+        }
+    }
+}
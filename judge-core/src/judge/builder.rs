@@ -1,11 +1,18 @@
 use serde_derive::Serialize;
 
-use std::{fs, path::PathBuf};
+use std::{
+    fs,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
 
 use crate::{
     compiler::{Compiler, Language},
     error::{path_not_exist, JudgeCoreError},
-    judge::{CheckerConfig, ProgramConfig, RuntimeConfig, TestdataConfig},
+    judge::{
+        CheckerConfig, CheckerSource, ProgramConfig, ReferenceConfig, RuntimeConfig, SetupConfig,
+        TestdataConfig,
+    },
     package::PackageType,
     run::executor::Executor,
 };
@@ -23,6 +30,12 @@ pub struct JudgeBuilder {
     pub program_config: ProgramConfig,
     pub checker_config: CheckerConfig,
     pub runtime_config: RuntimeConfig,
+    /// `JudgeConfig::setup` of every case, packages don't provide one.
+    pub setup_config: Option<SetupConfig>,
+    /// `JudgeConfig::reference` of every case, packages don't provide one.
+    pub reference_config: Option<ReferenceConfig>,
+    /// Time `new()` spent compiling the program.
+    pub compile_time: Duration,
 }
 
 pub struct JudgeBuilderInput {
@@ -31,6 +44,8 @@ pub struct JudgeBuilderInput {
     pub runtime_path: PathBuf,
     pub src_language: Language,
     pub src_path: PathBuf,
+    /// Replaces the checker of the package, see `CheckerConfig::select()`.
+    pub checker: Option<CheckerSource>,
 }
 
 impl JudgeBuilder {
@@ -45,7 +60,11 @@ impl JudgeBuilder {
 
         fs::create_dir_all(input.runtime_path.clone())?;
 
-        let checker_config = package_agent.load_checker(input.runtime_path.join("checker.out"))?;
+        let mut checker_config =
+            package_agent.load_checker(input.runtime_path.join("checker.out"))?;
+        if let Some(checker) = &input.checker {
+            checker_config.select(checker)?;
+        }
 
         // copy testcases to runtime path
         let runtime_testcases_path = input.runtime_path.join("data");
@@ -53,20 +72,23 @@ impl JudgeBuilder {
 
         let rlimit_config = package_agent.get_rlimit_configs()?;
         log::info!("rlimit read {:?}", rlimit_config);
-        let runtime_config = RuntimeConfig {
-            rlimit_configs: rlimit_config,
-        };
+        let runtime_config = RuntimeConfig::new(rlimit_config, input.runtime_path.clone());
 
+        let compile_start = Instant::now();
         let program_config = if input.src_path.exists() {
             let compiler = Compiler::new(input.src_language, vec![]);
             compiler.compile(&input.src_path, &input.runtime_path.join("program"))?;
             ProgramConfig {
                 executor: Executor::new(input.src_language, input.runtime_path.join("program"))?,
-                output_file_path: input.runtime_path.join("program.out"),
+                output_file_path: Some(input.runtime_path.join("program.out")),
+                program_args: vec![],
+                stdin_prefix_path: None,
             }
         } else {
             return Err(path_not_exist(&input.src_path));
         };
+        let compile_time = compile_start.elapsed();
+        log::debug!("Compiled {:?} in {:?}", input.src_path, compile_time);
 
         Ok(Self {
             judge_type: JudgeType::COMMON,
@@ -74,6 +96,9 @@ impl JudgeBuilder {
             program_config,
             checker_config,
             runtime_config,
+            setup_config: None,
+            reference_config: None,
+            compile_time,
         })
     }
 }
@@ -1,132 +1,784 @@
 use crate::judge::result::{
-    check_checker_result, check_user_result, get_max_mem, get_run_time, JudgeResultInfo,
+    check_checker_result, check_checker_timeout, check_output_file, check_reference_result,
+    check_setup_result, check_user_result, describe_user_result, get_disk_write_bytes, get_max_mem,
+    get_run_time, read_checker_result_file, reason_code, reported_effective_limits,
+    user_reason_code, CheckerOutcome, JudgeResultInfo, JudgeTimings, PerformanceSignals,
+};
+use crate::run::executor::Executor;
+use crate::sandbox::{Limits, RlimitConfigs, SandboxExitInfo};
+use crate::utils::{
+    compare_bytes, compare_files_by_tokens, compare_line_tokens, compare_text_files,
+    compare_unordered_lines, diff_files, get_pathbuf_str, open_testdata, OutputComparison,
+};
+use crate::{
+    error::JudgeCoreError,
+    run::sandbox::{ExecutorSandbox, SandboxBackend},
 };
-use crate::sandbox::SCRIPT_LIMIT_CONFIG;
-use crate::utils::{compare_files, get_pathbuf_str};
-use crate::{error::JudgeCoreError, run::sandbox::ExecutorSandbox};
 
 use super::result::JudgeVerdict;
-use super::JudgeConfig;
+use super::stdin::StdinFeeder;
+use super::{
+    CheckerConfig, JudgeConfig, ProgramConfig, ReferenceConfig, RuntimeConfig, TestdataConfig,
+};
+
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::os::unix::{
+    fs::PermissionsExt,
+    io::{AsRawFd, RawFd},
+};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+static TEMP_FILE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+const TEMP_FILE_ATTEMPTS: usize = 100;
 
-use std::fs::File;
-use std::os::unix::io::{AsRawFd, RawFd};
-use std::path::PathBuf;
-use std::time::Duration;
+/// A path in `scratch_dir` no other judge of this process uses.
+fn temp_file_path(scratch_dir: &Path, kind: &str, extension: &str) -> PathBuf {
+    scratch_dir.join(format!(
+        "judge-{}-{}-{}.{}",
+        kind,
+        std::process::id(),
+        TEMP_FILE_COUNTER.fetch_add(1, Ordering::SeqCst),
+        extension
+    ))
+}
+
+/// Create a new file at a `temp_file_path()`, opened for writing.
+///
+/// Its name is predictable, so an existing file is never opened: in a shared directory like
+/// `/tmp`, it may be a symlink planted by anyone to have a file of theirs choosing
+/// overwritten. Another name is tried instead.
+fn create_temp_file(
+    scratch_dir: &Path,
+    kind: &str,
+    extension: &str,
+) -> io::Result<(File, PathBuf)> {
+    for _ in 0..TEMP_FILE_ATTEMPTS {
+        let path = temp_file_path(scratch_dir, kind, extension);
+        match File::options().write(true).create_new(true).open(&path) {
+            Ok(file) => return Ok((file, path)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                log::warn!("Temporary file {:?} already exists, trying another", path)
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        format!("No new temporary file in {:?}", scratch_dir),
+    ))
+}
 
-fn run_user(
+/// `config` capturing the stdout of the program to a new file of `RuntimeConfig::scratch_dir`
+/// when it has no `ProgramConfig::output_file_path`, along with the file to remove once judged.
+pub(crate) fn with_captured_output(
     config: &JudgeConfig,
-) -> Result<(Option<JudgeVerdict>, Duration, i64, i32), JudgeCoreError> {
-    let input_file = File::open(&config.test_data.input_file_path)?;
+) -> Result<Option<(JudgeConfig, PathBuf)>, JudgeCoreError> {
+    if config.program.output_file_path.is_some() {
+        return Ok(None);
+    }
+    let (_, capture_path) = create_temp_file(&config.runtime.scratch_dir, "stdout", "out")?;
+    log::debug!("Capturing the stdout of the program to {:?}", capture_path);
+    let mut config = config.clone();
+    config.program.output_file_path = Some(capture_path.clone());
+    Ok(Some((config, capture_path)))
+}
+
+/// A run of the user program.
+struct UserRun {
+    verdict: Option<JudgeVerdict>,
+    result: SandboxExitInfo,
+    /// How long it took, from spawning the program to reaping it
+    execution: Duration,
+    /// See `JudgeResultInfo::stdin_bytes_read`
+    stdin_bytes_read: u64,
+}
 
-    if !config.program.output_file_path.exists() {
-        File::create(&config.program.output_file_path)?;
+/// Run the user program, rerun while it exceeds the time limit up to
+/// `RuntimeConfig::timing_runs` runs in all, `execution` summing them all.
+///
+/// A faster rerun which ends the same way replaces the run along with its output, a run killed
+/// for its time ending the same way as any other since how it would have ended is not known.
+/// Any other rerun is discarded: the runs only tell the jitter of the time apart, not which
+/// of two behaviors to judge.
+fn run_user<S: SandboxBackend>(config: &JudgeConfig) -> Result<UserRun, JudgeCoreError> {
+    let _span = enter_span!("run");
+    let output_file_path = config.program.output_path()?;
+    let mut user_run = run_user_once::<S>(config, output_file_path)?;
+    // Each run writes beside the output, which is replaced by the output of a faster one
+    let output_dir = output_file_path.parent().unwrap_or(Path::new("."));
+    for run in 1..config.runtime.timing_runs {
+        if user_run.verdict != Some(JudgeVerdict::TimeLimitExceeded) {
+            break;
+        }
+        let (_, rerun_file_path) = create_temp_file(output_dir, "rerun", "out")?;
+        let rerun = match run_user_once::<S>(config, &rerun_file_path) {
+            Ok(rerun) => rerun,
+            Err(e) => {
+                let _ = fs::remove_file(&rerun_file_path);
+                return Err(e);
+            }
+        };
+        log::debug!(
+            "Run {} of the user process took {:?}",
+            run + 1,
+            get_run_time(&rerun.result)
+        );
+        let execution = user_run.execution + rerun.execution;
+        let same_ending = rerun.result.exit_status == user_run.result.exit_status
+            || killed_for_time(&config.runtime, &user_run.result)
+            || killed_for_time(&config.runtime, &rerun.result);
+        if same_ending && get_run_time(&rerun.result) < get_run_time(&user_run.result) {
+            fs::rename(&rerun_file_path, output_file_path)?;
+            user_run = rerun;
+        } else {
+            fs::remove_file(&rerun_file_path)?;
+        }
+        user_run.execution = execution;
+    }
+    Ok(user_run)
+}
+
+/// Whether the run was killed by a signal past the CPU or the wall time limit, which cut it
+/// short of its own ending.
+fn killed_for_time(runtime: &RuntimeConfig, raw_info: &SandboxExitInfo) -> bool {
+    let over_cpu_time = runtime
+        .cpu_time_limit()
+        .is_some_and(|time_limit| get_run_time(raw_info) > time_limit);
+    let over_wall_time = runtime
+        .wall_time_limit
+        .is_some_and(|wall_time_limit| raw_info.real_time_cost > wall_time_limit);
+    raw_info.exit_signal != 0 && (over_cpu_time || over_wall_time)
+}
+
+/// Run the user program once on the input of `config`, writing its output to
+/// `output_file_path`.
+fn run_user_once<S: SandboxBackend>(
+    config: &JudgeConfig,
+    output_file_path: &Path,
+) -> Result<UserRun, JudgeCoreError> {
+    if !config.test_data.input_file_path.exists() {
+        return Err(JudgeCoreError::InputFileMissing(
+            config.test_data.input_file_path.clone(),
+        ));
+    }
+    let input_file = open_testdata(
+        &config.test_data.input_file_path,
+        &config.runtime.scratch_dir,
+    )?;
+    let mut stdin_feeder = StdinFeeder::new(input_file, config.runtime.max_stdin_bytes)?;
+
+    if !output_file_path.exists() {
+        File::create(output_file_path)?;
     }
     let program_output_file = File::options()
         .write(true)
         .truncate(true) // Overwrite the whole content of this file
-        .open(&config.program.output_file_path)?;
+        .open(output_file_path)?;
 
-    let input_raw_fd: RawFd = input_file.as_raw_fd();
+    let input_raw_fd: RawFd = stdin_feeder.stdin_fd();
     let program_output_raw_fd: RawFd = program_output_file.as_raw_fd();
 
-    let user_executor = config.program.executor.clone();
-    let mut user_sandbox = ExecutorSandbox::new(
+    let user_executor = config.program.executor_for(&config.test_data)?;
+    let mut user_sandbox = S::create(
         user_executor,
-        config.runtime.rlimit_configs.clone(),
+        config.runtime.user_rlimit_configs(),
         Some(input_raw_fd),
         Some(program_output_raw_fd),
         true,
     )?;
+    user_sandbox.apply_runtime_config(&config.runtime);
 
     log::debug!("Spawning user process");
-    let _user_spawn = user_sandbox.spawn()?;
+    let execution_start = config.runtime.clock().now();
+    let user_pid = user_sandbox.spawn()?;
+    stdin_feeder.start(user_pid)?;
     log::debug!("Waiting for user process");
-    let user_result = user_sandbox.wait()?;
-    let user_time = get_run_time(&user_result);
-    let max_mem = get_max_mem(&user_result);
-    Ok((
-        check_user_result(config, &user_result),
-        user_time,
-        max_mem,
-        user_result.exit_status,
-    ))
+    let mut user_result = user_sandbox.wait()?;
+    let execution = config.runtime.clock().now() - execution_start;
+    let stdin_usage = stdin_feeder.finish()?;
+    user_result.stdin_limit_killed = stdin_usage.limit_killed;
+    Ok(UserRun {
+        verdict: check_user_result(config, &user_result),
+        result: user_result,
+        execution,
+        stdin_bytes_read: stdin_usage.bytes_read,
+    })
+}
+
+/// Run the user program once on `input` with the config of the cases to come, discarding
+/// the result, see `RuntimeConfig::warmup_input`. Failing to is only logged.
+pub(crate) fn warm_up<S: SandboxBackend>(config: &JudgeConfig, input: &Path) {
+    let output_file_path = match create_temp_file(&config.runtime.scratch_dir, "warmup", "out") {
+        Ok((_, output_file_path)) => output_file_path,
+        Err(e) => {
+            log::warn!("Failed to warm up the user program: {:?}", e);
+            return;
+        }
+    };
+    let mut config = config.clone();
+    config.test_data.input_file_path = input.to_path_buf();
+    config.runtime.timing_runs = 1;
+    match run_user_once::<S>(&config, &output_file_path) {
+        Ok(user_run) => log::debug!(
+            "Warmup run of the user process took {:?}",
+            get_run_time(&user_run.result)
+        ),
+        Err(e) => log::warn!("Failed to warm up the user program: {:?}", e),
+    }
+    let _ = fs::remove_file(output_file_path);
 }
 
-pub fn run_checker(config: &JudgeConfig) -> Result<(JudgeVerdict, i32), JudgeCoreError> {
-    if let Some(mut checker_executor) = config.checker.executor.clone() {
+/// Run the checker, returning its outcome along with its raw exit status.
+pub fn run_checker(config: &JudgeConfig) -> Result<(CheckerOutcome, i32), JudgeCoreError> {
+    run_checker_with::<ExecutorSandbox>(config)
+}
+
+/// `run_checker()` in sandboxes of `S`.
+pub fn run_checker_with<S: SandboxBackend>(
+    config: &JudgeConfig,
+) -> Result<(CheckerOutcome, i32), JudgeCoreError> {
+    if let Some(checker_executor) = &config.checker.executor {
+        let mut checker_executor = config
+            .checker
+            .executor_for(checker_executor, &config.test_data);
         let first_args = String::from("");
-        let checker_args = vec![
+        let mut checker_args = vec![
             first_args,
             get_pathbuf_str(&config.test_data.input_file_path)?,
-            get_pathbuf_str(&config.program.output_file_path)?,
+            get_pathbuf_str(config.program.output_path()?)?,
             get_pathbuf_str(&config.test_data.answer_file_path)?,
             get_pathbuf_str(&config.checker.output_file_path)?,
         ];
+        if let Some(parser) = &config.checker.result_parser {
+            checker_args.extend(parser.args());
+            // Written by the checker, which may run as another user
+            File::create(&config.checker.output_file_path)?;
+            fs::set_permissions(
+                &config.checker.output_file_path,
+                fs::Permissions::from_mode(0o666),
+            )?;
+        }
+        checker_args.extend(config.checker.extra_args.iter().cloned());
         checker_executor.set_additional_args(checker_args);
 
-        let mut checker_process = ExecutorSandbox::new(
+        // Kept open until the checker exits
+        let input_file = if config.checker.feed_input_to_stdin {
+            Some(open_testdata(
+                &config.test_data.input_file_path,
+                &config.runtime.scratch_dir,
+            )?)
+        } else {
+            None
+        };
+        let mut checker_process = S::create(
             checker_executor,
-            SCRIPT_LIMIT_CONFIG.clone(),
-            None,
+            RlimitConfigs::default(),
+            input_file.as_ref().map(|file| file.as_raw_fd()),
             None,
             false,
         )?;
+        checker_process.set_seccomp_mode(config.runtime.seccomp_mode);
+        checker_process.set_limits(config.runtime.checker_limits.clone());
 
         log::debug!("Spawning checker process");
         let _checker_spawn = checker_process.spawn()?;
         log::debug!("Waiting for checker process");
         let checker_result = checker_process.wait()?;
-        Ok((
-            check_checker_result(&checker_result),
-            checker_result.exit_status,
-        ))
+        let timeout = check_checker_timeout(&checker_result, &config.runtime.checker_limits);
+        let outcome = match (timeout, &config.checker.result_parser) {
+            (Some(timeout), _) => timeout,
+            (None, Some(parser)) if checker_result.exit_signal == 0 => {
+                read_checker_result_file(parser.as_ref(), &config.checker.output_file_path)
+            }
+            _ => check_checker_result(&checker_result),
+        };
+        Ok((outcome, checker_result.exit_status))
     } else {
-        Err(JudgeCoreError::AnyhowError(anyhow::anyhow!(
-            "Checker executor is not set"
-        )))
+        Err(JudgeCoreError::CheckerNotConfigured)
     }
 }
 
 pub fn run_judge(config: &JudgeConfig) -> Result<JudgeResultInfo, JudgeCoreError> {
-    let (user_verdict, user_time, max_mem, user_exit_status) = run_user(config)?;
+    run_judge_with::<ExecutorSandbox>(config)
+}
+
+/// `run_judge()` with the user program and the checker in sandboxes of `S`.
+pub fn run_judge_with<S: SandboxBackend>(
+    config: &JudgeConfig,
+) -> Result<JudgeResultInfo, JudgeCoreError> {
+    if let Some((config, capture_path)) = with_captured_output(config)? {
+        let result = run_judge_with::<S>(&config);
+        let _ = fs::remove_file(capture_path);
+        return result;
+    }
+    judge_case::<S>(config).or_else(host_failure)
+}
+
+/// `run_judge_with()` of a config whose program has an output file.
+fn judge_case<S: SandboxBackend>(config: &JudgeConfig) -> Result<JudgeResultInfo, JudgeCoreError> {
+    let _span = enter_span!(
+        "judge",
+        submission_id = config.runtime.submission_id.as_deref()
+    );
+    let judge_start = config.runtime.clock().now();
+    if let Some(result) = check_testdata_integrity(config)? {
+        return Ok(result);
+    }
+    if let Some(result) = run_setup::<S>(config)? {
+        return Ok(result);
+    }
+    let answered_config;
+    let config = match &config.reference {
+        Some(reference) => match run_reference::<S>(config, reference)? {
+            Ok(answer_file_path) => {
+                answered_config = JudgeConfig {
+                    test_data: TestdataConfig {
+                        answer_file_path,
+                        ..config.test_data.clone()
+                    },
+                    ..config.clone()
+                };
+                &answered_config
+            }
+            Err(result) => return Ok(result),
+        },
+        None => config,
+    };
+    let UserRun {
+        verdict: user_verdict,
+        result: user_result,
+        execution,
+        stdin_bytes_read,
+    } = run_user::<S>(config)?;
+    let checking_start = config.runtime.clock().now();
+    let missing_output = check_output_file(config, &user_result);
+    let reason_code = user_verdict
+        .as_ref()
+        .or(missing_output.as_ref().map(|(verdict, _)| verdict))
+        .and_then(|verdict| user_reason_code(config, &user_result, verdict));
+    let verdict_message = user_verdict
+        .as_ref()
+        .map(|verdict| describe_user_result(&user_result, verdict));
+    // What the run tells, the verdict and what reached it are filled in below
+    let user_ran = JudgeResultInfo {
+        verdict: JudgeVerdict::SystemError,
+        time_usage: get_run_time(&user_result),
+        memory_usage_bytes: get_max_mem(&user_result),
+        disk_write_bytes: get_disk_write_bytes(&user_result),
+        stdin_bytes_read: Some(stdin_bytes_read),
+        exit_status: user_result.exit_status,
+        checker_exit_status: 0,
+        score: 0.0,
+        interactor_time_usage: None,
+        interactor_memory_usage_bytes: None,
+        message: String::new(),
+        reason_code: None,
+        decided_by: None,
+        timings: None,
+        started_at: Some(user_result.start_timestamp_ms),
+        finished_at: Some(user_result.end_timestamp_ms),
+        transcript: None,
+        performance: Some(PerformanceSignals::from(&user_result.resource_usage)),
+        core_dump: user_result.core_dump.clone(),
+        effective_limits: reported_effective_limits(&config.runtime, &user_result),
+        raw: config.runtime.verbose.then_some(user_result),
+    };
     if let Some(verdict) = user_verdict {
+        let message = missing_output
+            .map(|(_, message)| message)
+            .or(verdict_message)
+            .unwrap_or_default();
+        // A verdict policy may accept from the exit code alone
+        let score = if verdict == JudgeVerdict::Accepted {
+            1.0
+        } else {
+            0.0
+        };
         return Ok(JudgeResultInfo {
             verdict,
-            time_usage: user_time,
-            memory_usage_bytes: max_mem,
-            exit_status: user_exit_status,
-            checker_exit_status: 0,
+            score,
+            message,
+            reason_code,
+            timings: build_timings(config, judge_start, execution, checking_start),
+            ..user_ran
         });
     }
-
-    log::debug!("Creating sandbox for checker process");
-    if let Some(_checker_executor) = config.checker.executor.clone() {
-        let (verdict, checker_exit_status) = run_checker(config)?;
-        Ok(JudgeResultInfo {
+    if let Some((verdict, message)) = missing_output {
+        return Ok(JudgeResultInfo {
             verdict,
-            time_usage: user_time,
-            memory_usage_bytes: max_mem,
-            exit_status: user_exit_status,
-            checker_exit_status,
-        })
-    } else if compare_files(
-        &PathBuf::from(&config.program.output_file_path),
-        &PathBuf::from(&config.test_data.answer_file_path),
-    ) {
-        Ok(JudgeResultInfo {
-            verdict: JudgeVerdict::Accepted,
-            time_usage: user_time,
-            memory_usage_bytes: max_mem,
-            exit_status: user_exit_status,
-            checker_exit_status: 0,
-        })
+            message,
+            reason_code,
+            timings: build_timings(config, judge_start, execution, checking_start),
+            ..user_ran
+        });
+    }
+
+    let (outcome, checker_exit_status) = check_answers::<S>(config)?;
+    Ok(JudgeResultInfo {
+        verdict: outcome.verdict,
+        checker_exit_status,
+        score: outcome.score,
+        message: outcome.message,
+        reason_code: outcome.reason_code,
+        decided_by: Some(config.checker.decided_by()),
+        timings: build_timings(config, judge_start, execution, checking_start),
+        ..user_ran
+    })
+}
+
+/// Timings of a case for `RuntimeConfig::record_timings`, ending now.
+///
+/// `prepare` is what `execution` leaves of the time before `checking_start`.
+pub(crate) fn build_timings(
+    config: &JudgeConfig,
+    judge_start: Instant,
+    execution: Duration,
+    checking_start: Instant,
+) -> Option<JudgeTimings> {
+    if !config.runtime.record_timings {
+        return None;
+    }
+    let checking_end = config.runtime.clock().now();
+    Some(JudgeTimings {
+        prepare: (checking_start - judge_start).saturating_sub(execution),
+        execution,
+        checking: checking_end - checking_start,
+        total: checking_end - judge_start,
+    })
+}
+
+/// A `SystemError` result when the test data is too large or does not match its checksums,
+/// see `TestdataConfig::check_file_sizes()` and `TestdataConfig::verify_checksums()`.
+pub(crate) fn check_testdata_integrity(
+    config: &JudgeConfig,
+) -> Result<Option<JudgeResultInfo>, JudgeCoreError> {
+    if let Some(message) = config
+        .test_data
+        .check_file_sizes(config.runtime.max_testdata_bytes)?
+    {
+        log::error!("Refusing to judge: {}", message);
+        return Ok(Some(JudgeResultInfo::system_error(
+            message,
+            reason_code::SE_TESTDATA_TOO_LARGE,
+        )));
+    }
+    Ok(config.test_data.verify_checksums()?.map(|message| {
+        log::error!("Refusing to judge: {}", message);
+        JudgeResultInfo::system_error(message, reason_code::SE_TESTDATA_INTEGRITY)
+    }))
+}
+
+/// Run the setup program of `config`, if any. A `SystemError` result when it fails.
+pub(crate) fn run_setup<S: SandboxBackend>(
+    config: &JudgeConfig,
+) -> Result<Option<JudgeResultInfo>, JudgeCoreError> {
+    let setup = match &config.setup {
+        Some(setup) => setup,
+        None => return Ok(None),
+    };
+    let _span = enter_span!("setup");
+    let mut setup_executor = setup.executor.clone();
+    setup_executor.set_program_args(
+        std::iter::once(get_pathbuf_str(&config.test_data.input_file_path)?)
+            .chain(setup.args.iter().cloned())
+            .collect(),
+    );
+    let mut setup_process = S::create(setup_executor, RlimitConfigs::default(), None, None, false)?;
+    setup_process.set_seccomp_mode(config.runtime.seccomp_mode);
+    setup_process.set_limits(setup.limits.clone());
+
+    log::debug!("Spawning setup process");
+    let _setup_spawn = setup_process.spawn()?;
+    log::debug!("Waiting for setup process");
+    let setup_result = setup_process.wait()?;
+    Ok(check_setup_result(&setup_result, &setup.limits)
+        .map(|message| JudgeResultInfo::system_error(message, reason_code::SE_SETUP_FAILED)))
+}
+
+/// The answer of the case made by `reference`, run on the input unless it is cached, or the
+/// `SystemError` result of the case when the reference fails.
+fn run_reference<S: SandboxBackend>(
+    config: &JudgeConfig,
+    reference: &ReferenceConfig,
+) -> Result<Result<PathBuf, JudgeResultInfo>, JudgeCoreError> {
+    let answer_path = reference.answer_path(&config.test_data.input_file_path)?;
+    if answer_path.exists() {
+        log::debug!("Reference answer cached at {:?}", answer_path);
+        return Ok(Ok(answer_path));
+    }
+    let _span = enter_span!("reference");
+    fs::create_dir_all(&reference.cache_dir)?;
+    let input_file = open_testdata(
+        &config.test_data.input_file_path,
+        &config.runtime.scratch_dir,
+    )?;
+    // Written aside then renamed, a judge running at the same time never reads it half done
+    let (partial_file, partial_path) = create_temp_file(&reference.cache_dir, "reference", "ans")?;
+    let mut reference_process = S::create(
+        reference.executor.clone(),
+        RlimitConfigs::default(),
+        Some(input_file.as_raw_fd()),
+        Some(partial_file.as_raw_fd()),
+        false,
+    )?;
+    reference_process.set_seccomp_mode(config.runtime.seccomp_mode);
+    reference_process.set_limits(reference.limits.clone());
+
+    log::debug!("Spawning reference process");
+    let _reference_spawn = reference_process.spawn()?;
+    log::debug!("Waiting for reference process");
+    let reference_result = reference_process.wait()?;
+    if let Some(message) = check_reference_result(&reference_result, &reference.limits) {
+        let _ = fs::remove_file(&partial_path);
+        return Ok(Err(JudgeResultInfo::system_error(
+            message,
+            reason_code::SE_REFERENCE_FAILED,
+        )));
+    }
+    fs::rename(&partial_path, &answer_path)?;
+    Ok(Ok(answer_path))
+}
+
+/// A `SystemError` result for an error of a case which comes from the judging host: a full
+/// filesystem, see `JudgeCoreError::is_disk_full()`, a process which couldn't be killed or
+/// set up. Other errors are returned as they are.
+pub(crate) fn host_failure(error: JudgeCoreError) -> Result<JudgeResultInfo, JudgeCoreError> {
+    if let JudgeCoreError::SandboxSetupFailed { step, errno } = &error {
+        return Ok(JudgeResultInfo::system_error(
+            format!(
+                "Failed to {} of a process: {}",
+                step.description(),
+                errno.desc()
+            ),
+            reason_code::SE_SANDBOX_SETUP,
+        ));
+    }
+    if let JudgeCoreError::ProcessUnkillable { pid, stat } = &error {
+        return Ok(JudgeResultInfo::system_error(
+            format!(
+                "Process {} could not be killed, left behind in state: {}",
+                pid, stat
+            ),
+            reason_code::SE_UNKILLABLE,
+        ));
+    }
+    let cause = match &error {
+        JudgeCoreError::NixErrno(errno) if error.is_disk_full() => errno.desc().to_owned(),
+        JudgeCoreError::IOError(io_error) if error.is_disk_full() => io_error.to_string(),
+        _ => return Err(error),
+    };
+    log::error!("Aborting the case, the disk is full: {}", cause);
+    Ok(JudgeResultInfo::system_error(
+        format!("Disk full on the judging host: {}", cause),
+        reason_code::SE_DISK_FULL,
+    ))
+}
+
+/// Check the output against every acceptable answer, stopping at the first one accepting it.
+///
+/// When none does, the result against `answer_file_path` is reported.
+fn check_answers<S: SandboxBackend>(
+    config: &JudgeConfig,
+) -> Result<(CheckerOutcome, i32), JudgeCoreError> {
+    let _span = enter_span!("check");
+    let mut first_result = None;
+    for answer_file_path in config.test_data.answer_file_paths() {
+        let (mut outcome, checker_exit_status) = check_answer::<S>(config, answer_file_path)?;
+        if outcome.verdict == JudgeVerdict::Accepted {
+            if !config.test_data.extra_answer_file_paths.is_empty() {
+                if !outcome.message.is_empty() {
+                    outcome.message.push_str(", ");
+                }
+                outcome
+                    .message
+                    .push_str(&format!("matched answer {:?}", answer_file_path));
+            }
+            return Ok((outcome, checker_exit_status));
+        }
+        first_result.get_or_insert((outcome, checker_exit_status));
+    }
+    Ok(first_result.expect("There is always at least one answer"))
+}
+
+fn check_answer<S: SandboxBackend>(
+    config: &JudgeConfig,
+    answer_file_path: &PathBuf,
+) -> Result<(CheckerOutcome, i32), JudgeCoreError> {
+    if config.checker.executor.is_some() {
+        log::debug!("Creating sandbox for checker process");
+        if *answer_file_path == config.test_data.answer_file_path {
+            return run_checker_with::<S>(config);
+        }
+        let mut config = config.clone();
+        config.test_data.answer_file_path = answer_file_path.clone();
+        run_checker_with::<S>(&config)
+    } else if let Some(custom_comparison) = &config.checker.custom_comparison {
+        let outcome = custom_comparison.compare(
+            &config.test_data.input_file_path,
+            config.program.output_path()?,
+            answer_file_path,
+        );
+        Ok((outcome, 0))
     } else {
-        Ok(JudgeResultInfo {
-            verdict: JudgeVerdict::WrongAnswer,
-            time_usage: user_time,
-            memory_usage_bytes: max_mem,
-            exit_status: user_exit_status,
-            checker_exit_status: 0,
-        })
+        let output_file_path = config.program.output_path()?;
+        let mismatch = match config.checker.comparison {
+            OutputComparison::Lines => (!compare_text_files(
+                output_file_path,
+                answer_file_path,
+                &config.checker.normalizers,
+                &config.checker.text,
+            )?)
+            .then(String::new),
+            OutputComparison::UnorderedLines => (!compare_unordered_lines(
+                output_file_path,
+                answer_file_path,
+                &config.checker.normalizers,
+                &config.checker.text,
+            )?)
+            .then(String::new),
+            OutputComparison::LineTokens => (!compare_line_tokens(
+                output_file_path,
+                answer_file_path,
+                &config.checker.normalizers,
+                &config.checker.text,
+            )?)
+            .then(String::new),
+            OutputComparison::Tokens(comparison) => {
+                (!compare_files_by_tokens(output_file_path, answer_file_path, comparison)?)
+                    .then(String::new)
+            }
+            OutputComparison::Bytes => compare_bytes(output_file_path, answer_file_path)?
+                .map(|offset| format!("Output differs from the answer at byte {}", offset)),
+        };
+        let mismatch = match (mismatch, config.checker.diff_lines) {
+            (Some(message), Some(max_lines)) if message.is_empty() => Some(
+                diff_files(
+                    output_file_path,
+                    answer_file_path,
+                    config.checker.comparison,
+                    &config.checker.normalizers,
+                    &config.checker.text,
+                    max_lines,
+                )?
+                .unwrap_or_default(),
+            ),
+            (mismatch, _) => mismatch,
+        };
+        match mismatch {
+            None => Ok((
+                CheckerOutcome::new(JudgeVerdict::Accepted, String::new()),
+                0,
+            )),
+            Some(message) => Ok((
+                CheckerOutcome::new(JudgeVerdict::WrongAnswer, message)
+                    .with_reason_code(reason_code::WA_MISMATCH),
+                0,
+            )),
+        }
+    }
+}
+
+/// `run_judge()` on test data held in memory instead of the files of `config.test_data`, e.g.
+/// generated by the caller, `answer` being the only acceptable answer.
+///
+/// They are written to `RuntimeConfig::scratch_dir` for the programs to read, and removed
+/// once judged along with the captured output.
+pub fn run_judge_bytes(
+    config: &JudgeConfig,
+    input: &[u8],
+    answer: &[u8],
+) -> Result<JudgeResultInfo, JudgeCoreError> {
+    let (mut input_file, input_file_path) =
+        create_temp_file(&config.runtime.scratch_dir, "input", "in")?;
+    let (mut answer_file, answer_file_path) =
+        match create_temp_file(&config.runtime.scratch_dir, "answer", "ans") {
+            Ok(created) => created,
+            Err(e) => {
+                let _ = fs::remove_file(&input_file_path);
+                return Err(e.into());
+            }
+        };
+    let config = JudgeConfig {
+        test_data: TestdataConfig {
+            input_file_path: input_file_path.clone(),
+            answer_file_path: answer_file_path.clone(),
+            extra_answer_file_paths: vec![],
+            // The checksums are of the files replaced by the bytes
+            input_sha256: None,
+            answer_sha256: None,
+            ..config.test_data.clone()
+        },
+        ..config.clone()
+    };
+    let result = match input_file
+        .write_all(input)
+        .and_then(|()| answer_file.write_all(answer))
+    {
+        Ok(()) => run_judge(&config),
+        Err(e) => host_failure(e.into()),
+    };
+    let _ = fs::remove_file(&input_file_path);
+    let _ = fs::remove_file(&answer_file_path);
+    result
+}
+
+/// Judge one input/answer pair with the default checker, within `limits`, see
+/// `RuntimeConfig::with_limits()`.
+///
+/// The output of the program is captured to a temporary file, see `ProgramConfig::output_file_path`.
+pub fn judge_single(
+    executor: Executor,
+    input_file_path: &Path,
+    answer_file_path: &Path,
+    limits: Limits,
+) -> Result<JudgeResultInfo, JudgeCoreError> {
+    run_judge(&single_config(
+        executor,
+        input_file_path,
+        answer_file_path,
+        &limits,
+    ))
+}
+
+/// `judge_single()` on an input and answer held in memory, see `run_judge_bytes()`.
+pub fn judge_single_bytes(
+    executor: Executor,
+    input: &[u8],
+    answer: &[u8],
+    limits: Limits,
+) -> Result<JudgeResultInfo, JudgeCoreError> {
+    let config = single_config(executor, Path::new(""), Path::new(""), &limits);
+    run_judge_bytes(&config, input, answer)
+}
+
+fn single_config(
+    executor: Executor,
+    input_file_path: &Path,
+    answer_file_path: &Path,
+    limits: &Limits,
+) -> JudgeConfig {
+    let scratch_dir = std::env::temp_dir();
+    JudgeConfig {
+        test_data: TestdataConfig {
+            input_file_path: input_file_path.to_path_buf(),
+            answer_file_path: answer_file_path.to_path_buf(),
+            extra_answer_file_paths: vec![],
+            input_sha256: None,
+            answer_sha256: None,
+            case_index: None,
+        },
+        runtime: RuntimeConfig::with_limits(limits, scratch_dir.clone()),
+        program: ProgramConfig {
+            executor,
+            output_file_path: None,
+            program_args: vec![],
+            stdin_prefix_path: None,
+        },
+        checker: CheckerConfig::new(temp_file_path(&scratch_dir, "single-checker", "out")),
+        setup: None,
+        reference: None,
     }
 }
@@ -0,0 +1,114 @@
+use serde_derive::Serialize;
+
+use crate::error::JudgeCoreError;
+use crate::run::executor::Executor;
+use crate::run::sandbox::{ExecutorSandbox, SandboxBackend};
+
+use super::common::run_judge_with;
+use super::result::{JudgeResultInfo, JudgeVerdict, VerdictPriority};
+use super::testset::aggregate_verdict;
+use super::{JudgeConfig, TestdataConfig};
+
+/// A solution written by the setters of a problem, with the verdict they mean it to get,
+/// e.g. `TimeLimitExceeded` for a too slow one. See `check_solutions()`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ExpectedSolution {
+    /// Tells the solution apart in the mismatch report, e.g. its file name
+    pub name: String,
+    pub executor: Executor,
+    pub expected_verdict: JudgeVerdict,
+}
+
+/// How an `ExpectedSolution` fared on the cases, see `mismatch()`.
+#[derive(Debug, Clone, Serialize)]
+pub struct SolutionCheck {
+    pub name: String,
+    pub expected_verdict: JudgeVerdict,
+    /// The verdict of the cases ranked by `VerdictPriority::default()`
+    pub verdict: JudgeVerdict,
+    /// One per case, in their order
+    pub results: Vec<JudgeResultInfo>,
+}
+
+impl SolutionCheck {
+    pub fn matched(&self) -> bool {
+        self.verdict == self.expected_verdict
+    }
+
+    /// What went differently than expected, `None` when the verdict matched.
+    ///
+    /// The first case which got the verdict is named, along with its message if any.
+    pub fn mismatch(&self) -> Option<String> {
+        if self.matched() {
+            return None;
+        }
+        let mut report = format!(
+            "{}: expected {}, got {}",
+            self.name, self.expected_verdict, self.verdict
+        );
+        if let Some((case_index, result)) = self
+            .results
+            .iter()
+            .enumerate()
+            .find(|(_, result)| result.verdict == self.verdict)
+        {
+            report.push_str(&format!(" on case {}", case_index));
+            if !result.message.is_empty() {
+                report.push_str(&format!(" ({})", result.message));
+            }
+        }
+        Some(report)
+    }
+}
+
+/// Judge each of `solutions` on every case of `cases`, telling whether it got the verdict
+/// it was meant to, for validating a problem before it is used.
+///
+/// Each solution replaces the program executor of `config`, its checker and runtime are
+/// used as is, as well as its output path. Every case is judged whatever the verdicts, an
+/// error of the judge stops the whole check.
+pub fn check_solutions(
+    config: &JudgeConfig,
+    cases: &[TestdataConfig],
+    solutions: &[ExpectedSolution],
+) -> Result<Vec<SolutionCheck>, JudgeCoreError> {
+    check_solutions_with::<ExecutorSandbox>(config, cases, solutions)
+}
+
+/// `check_solutions()` with every program in sandboxes of `S`.
+pub fn check_solutions_with<S: SandboxBackend>(
+    config: &JudgeConfig,
+    cases: &[TestdataConfig],
+    solutions: &[ExpectedSolution],
+) -> Result<Vec<SolutionCheck>, JudgeCoreError> {
+    solutions
+        .iter()
+        .map(|solution| check_solution_with::<S>(config, cases, solution))
+        .collect()
+}
+
+/// `check_solutions()` for a single solution.
+pub fn check_solution_with<S: SandboxBackend>(
+    config: &JudgeConfig,
+    cases: &[TestdataConfig],
+    solution: &ExpectedSolution,
+) -> Result<SolutionCheck, JudgeCoreError> {
+    let mut config = config.clone();
+    config.program.executor = solution.executor.clone();
+    let mut results = vec![];
+    for test_data in cases {
+        config.test_data = test_data.clone();
+        results.push(run_judge_with::<S>(&config)?);
+    }
+    let verdict = aggregate_verdict(&results, &VerdictPriority::default());
+    let check = SolutionCheck {
+        name: solution.name.clone(),
+        expected_verdict: solution.expected_verdict.clone(),
+        verdict,
+        results,
+    };
+    if let Some(mismatch) = check.mismatch() {
+        log::info!("Solution check failed, {}", mismatch);
+    }
+    Ok(check)
+}
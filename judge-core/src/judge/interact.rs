@@ -1,125 +1,481 @@
 use crate::error::JudgeCoreError;
-use crate::judge::common::run_checker;
-use crate::judge::result::{check_user_result, JudgeVerdict};
+use crate::judge::common::{
+    build_timings, check_testdata_integrity, host_failure, run_checker, run_setup,
+    with_captured_output,
+};
+use crate::judge::result::{
+    check_user_result, describe_user_result, get_disk_write_bytes, get_max_mem, get_run_time,
+    reason_code, reported_effective_limits, user_reason_code, JudgeVerdict, PerformanceSignals,
+};
+use crate::judge::stdin::unread_bytes;
 use crate::run::executor::Executor;
-use crate::run::process_listener::{ProcessExitMessage, ProcessListener};
-use crate::run::sandbox::ExecutorSandbox;
-use crate::sandbox::{SandboxExitInfo, SCRIPT_LIMIT_CONFIG};
+use crate::run::process_listener::{read_exit_message, ProcessExitMessage, ProcessListener};
+use crate::run::sandbox::{ExecutorSandbox, SandboxBackend};
+use crate::sandbox::{get_unix_timestamp_ms, SandboxExitInfo, SCRIPT_LIMIT_CONFIG};
 use crate::utils::get_pathbuf_str;
 
 use nix::errno::Errno;
 use nix::fcntl::{fcntl, FcntlArg, OFlag};
 use nix::sys::epoll::{Epoll, EpollCreateFlags, EpollEvent, EpollFlags, EpollTimeout};
 use nix::unistd::{pipe, read, write};
-use std::fs::File;
-use std::os::fd::BorrowedFd;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::os::fd::{BorrowedFd, OwnedFd};
 use std::os::unix::io::{AsRawFd, RawFd};
-use std::path::PathBuf;
-use std::time::Duration;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
 
-use super::result::JudgeResultInfo;
+use super::result::{CheckerOutcome, DecidedBy, JudgeResultInfo};
 use super::JudgeConfig;
 
 const USER_EXIT_SIGNAL: u8 = 41u8;
 const INTERACTOR_EXIT_SIGNAL: u8 = 42u8;
 
-fn set_fd_non_blocking(fd: RawFd) -> Result<libc::c_int, JudgeCoreError> {
+/// Bytes moved in one direction per turn of the epoll loop,
+/// so that a flooding side can't starve the other one.
+const PUMP_CHUNK_SIZE: usize = 4096;
+
+/// How often the peak memory of the running user program is read, for a result when it
+/// exits without a report.
+const USER_RSS_SAMPLE_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Bytes of the end of the transcript reported when the user program hits a limit.
+const PARTIAL_TRANSCRIPT_LEN: u64 = 64 * 1024;
+
+/// Make a proxy end of a pipe non-blocking, keeping its other status flags.
+///
+/// `O_NONBLOCK` belongs to the open file description, which is shared by every copy
+/// of the fd. Each end of a pipe has its own description though, so the ends the
+/// children use as stdin and stdout stay blocking. Only called once per fd, at setup.
+fn set_fd_non_blocking(fd: RawFd) -> Result<(), JudgeCoreError> {
     log::debug!("Setting fd={} to non blocking", fd);
-    Ok(fcntl(fd, FcntlArg::F_SETFL(OFlag::O_NONBLOCK))?)
+    let flags = OFlag::from_bits_truncate(fcntl(fd, FcntlArg::F_GETFL)?);
+    fcntl(fd, FcntlArg::F_SETFL(flags | OFlag::O_NONBLOCK))?;
+    Ok(())
 }
 
-/// write the content of `from` to `to`, record to output.
-/// `from` will be set to non-blocking mode.
-fn pump_proxy_pipe(from: RawFd, to: RawFd, output: RawFd) -> Result<(), JudgeCoreError> {
-    log::debug!("Pumping from {} to {} with output {}", from, to, output);
-    set_fd_non_blocking(from)?;
+/// The merged transcript of both directions, written to the output file once per turn
+/// of the epoll loop.
+///
+/// Chunks of both directions go through the same buffer, in the order the proxy reads
+/// them. A chunk is recorded before it is delivered, so a side
+/// can't answer a message its answer would then precede: replaying the file reproduces
+/// the interaction as it happened. It is flushed before `run_interact()` returns,
+/// on errors too, and synced to disk when `RuntimeConfig::sync_transcript` is set.
+struct Transcript {
+    file: File,
+    pending: Vec<u8>,
+    sync: bool,
+    unsynced: bool,
+}
 
-    let mut buf = [0; 1024];
-    loop {
-        match read(from, &mut buf) {
-            Ok(nread) => {
-                log::debug!("{} read. {} -> {}", nread, from, to);
-                // We should be really careful here
-                // not using OwnedFd here because it will close the fd
-                write(unsafe { BorrowedFd::borrow_raw(to) }, &buf[..nread])?;
-                write(unsafe { BorrowedFd::borrow_raw(output) }, &buf[..nread])?;
+impl Transcript {
+    fn new(file: File, sync: bool) -> Self {
+        Self {
+            file,
+            pending: vec![],
+            sync,
+            unsynced: false,
+        }
+    }
+
+    /// Append `chunk` after everything recorded before.
+    fn record(&mut self, chunk: &[u8]) {
+        self.pending.extend_from_slice(chunk);
+    }
+
+    /// Write what was recorded during the turn.
+    fn write_turn(&mut self) -> io::Result<()> {
+        if !self.pending.is_empty() {
+            self.file.write_all(&self.pending)?;
+            self.pending.clear();
+            self.unsynced = true;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_turn()?;
+        self.file.flush()?;
+        if self.sync && self.unsynced {
+            self.file.sync_all()?;
+            self.unsynced = false;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Transcript {
+    fn drop(&mut self) {
+        if let Err(e) = self.flush() {
+            log::warn!("Failed to flush the interaction transcript: {}", e);
+        }
+    }
+}
+
+/// One direction of the proxy, from the write end of a side to the read end of the other.
+///
+/// Both fds are made non-blocking by `new()`. When `to` is full, the rest of the chunk is kept
+/// in `pending` and `from` is not read again until it's written. When `to` is closed, data for
+/// it is handled as once the receiver exited.
+struct ProxyDirection {
+    from: RawFd,
+    to: RawFd,
+    pending: Vec<u8>,
+    /// The receiving side exited, data for it is recorded but dropped
+    receiver_exited: bool,
+    /// The receiving side is being replaced, `from` is not read until it is
+    paused: bool,
+    /// Lines read from `from`, the queries made when it's the user program
+    lines: u64,
+    /// Bytes written to `to`, whether it read them or not
+    delivered: u64,
+}
+
+impl ProxyDirection {
+    fn new(from: RawFd, to: RawFd) -> Result<Self, JudgeCoreError> {
+        set_fd_non_blocking(from)?;
+        set_fd_non_blocking(to)?;
+        Ok(Self {
+            from,
+            to,
+            pending: vec![],
+            receiver_exited: false,
+            paused: false,
+            lines: 0,
+            delivered: 0,
+        })
+    }
+
+    /// Move at most `PUMP_CHUNK_SIZE` bytes, recording them to `transcript`.
+    /// Returns the number of bytes read from `from`.
+    ///
+    /// Watches `to` instead of `from` in `epoll` while there is pending data.
+    fn pump(
+        &mut self,
+        epoll: &Epoll,
+        transcript: &mut Transcript,
+    ) -> Result<usize, JudgeCoreError> {
+        if self.paused {
+            return Ok(0);
+        }
+        let was_blocked = self.is_blocked();
+        let mut nread = 0;
+        if !was_blocked {
+            let mut buf = [0; PUMP_CHUNK_SIZE];
+            match read(self.from, &mut buf) {
+                Ok(n) => {
+                    nread = n;
+                    self.lines += buf[..nread].iter().filter(|&&byte| byte == b'\n').count() as u64;
+                    transcript.record(&buf[..nread]);
+                    log::debug!("{} read. {} -> {}", nread, self.from, self.to);
+                    if self.receiver_exited {
+                        return Ok(nread);
+                    }
+                    self.pending.extend_from_slice(&buf[..nread]);
+                }
+                Err(Errno::EAGAIN) => return Ok(0),
+                Err(e) => return Err(e.into()),
             }
-            Err(e) => {
-                if e == Errno::EAGAIN || e == Errno::EWOULDBLOCK {
-                    return Ok(());
+        }
+        if self.receiver_exited {
+            self.pending.clear();
+        }
+        while !self.pending.is_empty() {
+            match write(unsafe { BorrowedFd::borrow_raw(self.to) }, &self.pending) {
+                Ok(nwrite) => {
+                    self.pending.drain(..nwrite);
+                    self.delivered += nwrite as u64;
+                }
+                Err(Errno::EAGAIN) => break,
+                // The receiving side closed its stdin, its exit is classified once reported
+                Err(Errno::EPIPE) => {
+                    log::debug!("{} is closed, dropping data for it", self.to);
+                    self.receiver_exited = true;
+                    self.pending.clear();
                 }
-                panic!("failed to read from pipe");
+                Err(e) => return Err(e.into()),
             }
         }
+        match (was_blocked, self.is_blocked()) {
+            (false, true) => {
+                log::debug!("{} is full, waiting for it to be writable", self.to);
+                modify_epoll_fd(epoll, self.from, EpollFlags::empty())?;
+                add_epoll_fd_with(epoll, self.to, EpollFlags::EPOLLOUT)?;
+            }
+            (true, false) => {
+                log::debug!("{} is writable again", self.to);
+                epoll.delete(unsafe { BorrowedFd::borrow_raw(self.to) })?;
+                modify_epoll_fd(epoll, self.from, EpollFlags::EPOLLIN)?;
+            }
+            _ => {}
+        }
+        Ok(nread)
     }
-}
 
-/// `from` will be set to non-blocking mode.
-fn read_string_from_fd(from: RawFd) -> Result<String, JudgeCoreError> {
-    set_fd_non_blocking(from)?;
+    /// Deliver `data` before anything read from `from`, which is not read until it's written.
+    fn prepend(
+        &mut self,
+        epoll: &Epoll,
+        transcript: &mut Transcript,
+        data: Vec<u8>,
+    ) -> Result<(), JudgeCoreError> {
+        if data.is_empty() {
+            return Ok(());
+        }
+        transcript.record(&data);
+        self.pending = data;
+        modify_epoll_fd(epoll, self.from, EpollFlags::empty())?;
+        add_epoll_fd_with(epoll, self.to, EpollFlags::EPOLLOUT)
+    }
 
-    let mut res_buf = Vec::new();
-    let mut buf = [0; 1024];
-    log::debug!("Reading from fd={}", from);
-    loop {
-        log::debug!("Reading from fd={}", from);
-        match read(from, &mut buf) {
-            Ok(nread) => {
-                log::debug!("{} read. {}", nread, from);
-                res_buf.extend_from_slice(&buf[..nread]);
-            }
-            Err(e) => {
-                if e == Errno::EAGAIN || e == Errno::EWOULDBLOCK {
-                    let buf_string = String::from_utf8(res_buf)?;
-                    return Ok(buf_string);
+    /// Read from `from` instead, the sending side of a new phase, once what the exited one
+    /// left in the current `from` is read. It's recorded and delivered first.
+    fn switch_from(
+        &mut self,
+        epoll: &Epoll,
+        transcript: &mut Transcript,
+        from: RawFd,
+    ) -> Result<(), JudgeCoreError> {
+        let was_blocked = self.is_blocked();
+        let mut buf = [0; PUMP_CHUNK_SIZE];
+        loop {
+            match read(self.from, &mut buf) {
+                Ok(0) | Err(Errno::EAGAIN) => break,
+                Ok(nread) => {
+                    self.lines += buf[..nread].iter().filter(|&&byte| byte == b'\n').count() as u64;
+                    transcript.record(&buf[..nread]);
+                    if !self.receiver_exited {
+                        self.pending.extend_from_slice(&buf[..nread]);
+                    }
                 }
-                panic!("failed to read from pipe");
+                Err(e) => return Err(e.into()),
             }
         }
+        epoll.delete(unsafe { BorrowedFd::borrow_raw(self.from) })?;
+        set_fd_non_blocking(from)?;
+        self.from = from;
+        if self.is_blocked() {
+            add_epoll_fd_with(epoll, from, EpollFlags::empty())?;
+            if !was_blocked {
+                add_epoll_fd_with(epoll, self.to, EpollFlags::EPOLLOUT)?;
+            }
+        } else {
+            add_epoll_fd(epoll, from)?;
+        }
+        Ok(())
+    }
+
+    /// Whether `from` still holds data that wasn't read yet.
+    fn has_unread(&self) -> Result<bool, JudgeCoreError> {
+        Ok(unread_bytes(self.from)? > 0)
+    }
+
+    /// Bytes delivered to `to` and read from there.
+    fn received(&self) -> Result<u64, JudgeCoreError> {
+        Ok(self.delivered.saturating_sub(unread_bytes(self.to)?))
+    }
+
+    /// Stop reading `from` until `switch_to()`, the receiving side exited at the end of a phase.
+    fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    /// Deliver to `to` instead, the receiving side of a new phase, pending data first.
+    fn switch_to(&mut self, epoll: &Epoll, to: RawFd) -> Result<(), JudgeCoreError> {
+        set_fd_non_blocking(to)?;
+        if self.is_blocked() {
+            epoll.delete(unsafe { BorrowedFd::borrow_raw(self.to) })?;
+            add_epoll_fd_with(epoll, to, EpollFlags::EPOLLOUT)?;
+        }
+        self.to = to;
+        self.paused = false;
+        Ok(())
+    }
+
+    fn is_blocked(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Stop delivering to the exited side, without blocking the loop on its full pipe.
+    ///
+    /// Nothing is read here: what is still in `from` is moved by the next `pump()`,
+    /// in the same order as for the other direction whichever exit is handled first.
+    /// Pending data was recorded when it was read, it is only dropped.
+    fn set_receiver_exited(&mut self, epoll: &Epoll) -> Result<(), JudgeCoreError> {
+        self.receiver_exited = true;
+        if self.is_blocked() {
+            self.pending.clear();
+            epoll.delete(unsafe { BorrowedFd::borrow_raw(self.to) })?;
+            modify_epoll_fd(epoll, self.from, EpollFlags::EPOLLIN)?;
+        }
+        Ok(())
     }
 }
 
-fn read_msg_from_fd(from: RawFd) -> Result<ProcessExitMessage, JudgeCoreError> {
-    let buf_string = read_string_from_fd(from as RawFd)?;
-    log::debug!("Raw Result info: {}", buf_string);
-    let msg: ProcessExitMessage = serde_json::from_str(&buf_string)?;
-    Ok(msg)
+/// The user program sent more lines than `RuntimeConfig::max_interactions`.
+fn exceeds_max_interactions(config: &JudgeConfig, user_to_interactor: &ProxyDirection) -> bool {
+    config
+        .runtime
+        .max_interactions
+        .is_some_and(|max_interactions| user_to_interactor.lines > max_interactions)
 }
 
 fn add_epoll_fd(epoll: &Epoll, fd: RawFd) -> Result<(), JudgeCoreError> {
-    let event = EpollEvent::new(EpollFlags::EPOLLIN, fd as u64);
-    log::debug!("Adding fd={} to epoll", fd);
+    add_epoll_fd_with(epoll, fd, EpollFlags::EPOLLIN)
+}
+
+fn add_epoll_fd_with(epoll: &Epoll, fd: RawFd, flags: EpollFlags) -> Result<(), JudgeCoreError> {
+    let event = EpollEvent::new(flags, fd as u64);
+    log::debug!("Adding fd={} to epoll with {:?}", fd, flags);
     Ok(epoll.add(unsafe { BorrowedFd::borrow_raw(fd) }, event)?)
 }
 
+fn modify_epoll_fd(epoll: &Epoll, fd: RawFd, flags: EpollFlags) -> Result<(), JudgeCoreError> {
+    let mut event = EpollEvent::new(flags, fd as u64);
+    Ok(epoll.modify(unsafe { BorrowedFd::borrow_raw(fd) }, &mut event)?)
+}
+
 pub fn run_interact(
     config: &JudgeConfig,
-    mut interactor_executor: Executor,
+    interactor_executor: Executor,
+    output_path: &PathBuf,
+) -> Result<Option<JudgeResultInfo>, JudgeCoreError> {
+    run_interact_phases(config, &[interactor_executor], output_path)
+}
+
+/// `run_interact()` in phases, one per interactor of `interactor_executors`, run one after
+/// another against the same user program.
+///
+/// The user program is spawned once and stays alive across phases, its stdin and stdout
+/// proxied to the interactor of the running phase. A phase ends when its interactor exits,
+/// the interactor of the next one is started right away and gets what the user program
+/// writes from then on. What the previous interactor didn't read is lost, so each phase
+/// should begin with its interactor writing.
+///
+/// The interaction ends before its last phase when an interactor exits with another code
+/// than 0, when the user program exits during a phase which isn't the last one, or during a
+/// later one without writing any line in it. The rest is killed and unless the user program or
+/// `CheckerConfig::interactor_policy` tell otherwise, the verdict is `WrongAnswer` with the
+/// `WA_UNFINISHED_INTERACTION` reason code. Otherwise the verdict is reached after the last
+/// phase as by `run_interact()`, the interactor policy applying to its interactor.
+pub fn run_interact_phases(
+    config: &JudgeConfig,
+    interactor_executors: &[Executor],
+    output_path: &PathBuf,
+) -> Result<Option<JudgeResultInfo>, JudgeCoreError> {
+    if interactor_executors.is_empty() {
+        return Err(JudgeCoreError::InteractorNotConfigured);
+    }
+    if let Some((config, capture_path)) = with_captured_output(config)? {
+        let result = run_interact_phases(&config, interactor_executors, output_path);
+        let _ = fs::remove_file(capture_path);
+        return result;
+    }
+    interact(config, interactor_executors, output_path)
+        .or_else(|error| host_failure(error).map(Some))
+}
+
+/// The interactor of a phase, with both ends of its pipes. The proxy keeps the ends the
+/// interactor uses too, so that its reads end with EAGAIN instead of EOF once it exited.
+struct InteractorPhase {
+    listener: ProcessListener,
+    path: PathBuf,
+    /// Read by the proxy, the stdout of the interactor
+    proxy_read: OwnedFd,
+    /// Written by the proxy, the stdin of the interactor
+    proxy_write: OwnedFd,
+    exit_read: OwnedFd,
+    /// The ends the interactor and its listener use
+    _child_ends: [OwnedFd; 3],
+}
+
+impl InteractorPhase {
+    /// Spawn `executor` as the interactor of a phase, its exit report watched by `epoll`.
+    fn spawn(
+        config: &JudgeConfig,
+        executor: &Executor,
+        epoll: &Epoll,
+    ) -> Result<Self, JudgeCoreError> {
+        log::debug!("Creating the pipes of the interactor");
+        let (proxy_read, write_proxy) = pipe()?;
+        let (read_proxy, proxy_write) = pipe()?;
+        let (exit_read, exit_write) = pipe()?;
+        add_epoll_fd(epoll, exit_read.as_raw_fd())?;
+        let mut listener = ProcessListener::new()?;
+        listener.setup_exit_report(exit_write.as_raw_fd(), INTERACTOR_EXIT_SIGNAL);
+
+        let first_args: String = String::from("");
+        let interact_args = vec![
+            first_args,
+            get_pathbuf_str(&config.test_data.input_file_path)?,
+            get_pathbuf_str(config.program.output_path()?)?,
+            get_pathbuf_str(&config.test_data.answer_file_path)?,
+        ];
+        let mut interactor_executor = config.checker.executor_for(executor, &config.test_data);
+        interactor_executor.set_additional_args(interact_args);
+        let path = interactor_executor.path.clone();
+        let mut interact_sandbox = ExecutorSandbox::new(
+            interactor_executor,
+            SCRIPT_LIMIT_CONFIG.clone(),
+            Some(read_proxy.as_raw_fd()),
+            Some(write_proxy.as_raw_fd()),
+            false,
+        )?;
+        interact_sandbox
+            .sandbox
+            .set_seccomp_mode(config.runtime.seccomp_mode);
+        listener.spawn_with_sandbox(&mut interact_sandbox)?;
+        Ok(Self {
+            listener,
+            path,
+            proxy_read,
+            proxy_write,
+            exit_read,
+            _child_ends: [write_proxy, read_proxy, exit_write],
+        })
+    }
+}
+
+/// `run_interact_phases()` of a config whose program has an output file.
+fn interact(
+    config: &JudgeConfig,
+    interactor_executors: &[Executor],
     output_path: &PathBuf,
 ) -> Result<Option<JudgeResultInfo>, JudgeCoreError> {
+    let _span = enter_span!(
+        "judge",
+        submission_id = config.runtime.submission_id.as_deref()
+    );
+    let judge_start = config.runtime.clock().now();
+    if !config.test_data.input_file_path.exists() {
+        return Err(JudgeCoreError::InputFileMissing(
+            config.test_data.input_file_path.clone(),
+        ));
+    }
+    if let Some(result) = check_testdata_integrity(config)? {
+        return Ok(Some(result));
+    }
+    if let Some(result) = run_setup::<ExecutorSandbox>(config)? {
+        return Ok(Some(result));
+    }
+
     log::debug!("Creating epoll");
     let epoll = Epoll::new(EpollCreateFlags::EPOLL_CLOEXEC)?;
 
     log::debug!("Creating interact pipes");
     let (proxy_read_user, user_write_proxy) = pipe()?;
-    let (proxy_read_interactor, interactor_write_proxy) = pipe()?;
     let (user_read_proxy, proxy_write_user) = pipe()?;
-    let (interactor_read_proxy, proxy_write_interactor) = pipe()?;
 
     log::debug!("Adding read proxy fds to epoll");
     add_epoll_fd(&epoll, proxy_read_user.as_raw_fd())?;
-    add_epoll_fd(&epoll, proxy_read_interactor.as_raw_fd())?;
 
     log::debug!("Creating exit report pipes with epoll");
     let (user_exit_read, user_exit_write) = pipe()?;
-    let (interactor_exit_read, interactor_exit_write) = pipe()?;
     add_epoll_fd(&epoll, user_exit_read.as_raw_fd())?;
-    add_epoll_fd(&epoll, interactor_exit_read.as_raw_fd())?;
 
     let mut user_listener = ProcessListener::new()?;
-    let mut interact_listener = ProcessListener::new()?;
     user_listener.setup_exit_report(user_exit_write.as_raw_fd(), USER_EXIT_SIGNAL);
-    interact_listener.setup_exit_report(interactor_exit_write.as_raw_fd(), INTERACTOR_EXIT_SIGNAL);
 
     if !PathBuf::from(&output_path).exists() {
         File::create(output_path)?;
@@ -128,115 +484,538 @@ pub fn run_interact(
         .write(true)
         .truncate(true) // Overwrite the whole content of this file
         .open(output_path)?;
-    let output_raw_fd: RawFd = output_file.as_raw_fd();
+    let mut transcript = Transcript::new(output_file, config.runtime.sync_transcript);
 
+    let run_span = enter_span!("run");
     let mut user_sandbox = ExecutorSandbox::new(
-        config.program.executor.clone(),
-        config.runtime.rlimit_configs.clone(),
+        config.program.executor_for(&config.test_data)?,
+        config.runtime.user_rlimit_configs(),
         Some(user_read_proxy.as_raw_fd()),
         Some(user_write_proxy.as_raw_fd()),
         true,
     )?;
+    user_sandbox.apply_runtime_config(&config.runtime);
+    let execution_start = config.runtime.clock().now();
+    let user_started_at = get_unix_timestamp_ms(SystemTime::now());
     user_listener.spawn_with_sandbox(&mut user_sandbox)?;
 
-    let first_args: String = String::from("");
-    let interact_args = vec![
-        first_args,
-        get_pathbuf_str(&config.test_data.input_file_path)?,
-        get_pathbuf_str(&config.program.output_file_path)?,
-        get_pathbuf_str(&config.test_data.answer_file_path)?,
-    ];
-    interactor_executor.set_additional_args(interact_args);
-    let mut interact_sandbox = ExecutorSandbox::new(
-        interactor_executor,
-        SCRIPT_LIMIT_CONFIG.clone(),
-        Some(interactor_read_proxy.as_raw_fd()),
-        Some(interactor_write_proxy.as_raw_fd()),
-        false,
+    let mut phase = 0;
+    let mut interactor = InteractorPhase::spawn(config, &interactor_executors[phase], &epoll)?;
+    add_epoll_fd(&epoll, interactor.proxy_read.as_raw_fd())?;
+
+    let mut user_to_interactor = ProxyDirection::new(
+        proxy_read_user.as_raw_fd(),
+        interactor.proxy_write.as_raw_fd(),
+    )?;
+    let mut interactor_to_user = ProxyDirection::new(
+        interactor.proxy_read.as_raw_fd(),
+        proxy_write_user.as_raw_fd(),
     )?;
-    interact_listener.spawn_with_sandbox(&mut interact_sandbox)?;
+    if let Some(stdin_prefix_path) = &config.program.stdin_prefix_path {
+        log::debug!("Feeding {:?} to the user program first", stdin_prefix_path);
+        interactor_to_user.prepend(&epoll, &mut transcript, fs::read(stdin_prefix_path)?)?;
+    }
 
+    // Each turn, exit reports are handled before any data, then both directions
+    // move at most one chunk, the one going first alternating between turns.
+    // The transcript records the chunks in the same order.
+    // So neither side can delay the detection of an exit or starve the other by flooding.
+    // Handling an exit reads no data, so exits and data of the same batch give the same
+    // transcript whatever order epoll reports them in.
+    // The interactor of the next phase is started at the end of the turn its previous one
+    // exited in, taking over once what that one left in its pipe is moved.
     log::debug!("Starting epoll");
     let mut events = [EpollEvent::empty(); 128];
     let mut user_exited = false;
     let mut interactor_exited = false;
     let mut option_user_result: Option<SandboxExitInfo> = None;
+    let mut option_interactor_result: Option<SandboxExitInfo> = None;
+    // CPU time and peak memory of the interactors which exited, in the order of the phases
+    let mut interactor_usages: Vec<(Duration, i64)> = vec![];
+    // The interactor of the phase exited, the next one is started at the end of the turn
+    let mut phase_over = false;
+    // The interaction ended before the last phase
+    let mut unfinished = false;
+    // Lines the user program had written when the phase started
+    let mut phase_start_lines = 0;
+    let mut user_first = true;
+    // What the judger last saw of the user program, in case it exits without a report:
+    // its peak memory, then how long it ran and when it finished
+    let mut user_peak_rss = 0;
+    let mut last_rss_sample: Option<Instant> = None;
+    let mut user_finished: Option<(Duration, u64)> = None;
     loop {
         let num_events = epoll.wait(&mut events, EpollTimeout::NONE)?;
+        if !user_exited
+            && last_rss_sample.is_none_or(|sampled| sampled.elapsed() >= USER_RSS_SAMPLE_INTERVAL)
+        {
+            if let Some(rss) = user_listener.spawned_peak_rss() {
+                user_peak_rss = user_peak_rss.max(rss);
+            }
+            last_rss_sample = Some(Instant::now());
+        }
         log::debug!("{} events found!", num_events);
+        let ready_fds: Vec<RawFd> = events
+            .iter()
+            .take(num_events)
+            .map(|event| event.data() as RawFd)
+            .collect();
+        log::debug!("Ready fds: {:?}", ready_fds);
 
-        for event in events.iter().take(num_events) {
-            log::debug!("Event: {:?}", event);
-            let fd = event.data() as RawFd;
-            if fd == user_exit_read.as_raw_fd() {
-                log::debug!("{:?} user fd exited", fd);
-                user_exited = true;
-                let exit_msg = read_msg_from_fd(fd)?;
-                option_user_result = exit_msg.option_run_result;
-            }
-            if fd == interactor_exit_read.as_raw_fd() {
-                log::debug!("{:?} interactor fd exited", fd);
+        if ready_fds.contains(&user_exit_read.as_raw_fd()) {
+            log::debug!("user fd exited");
+            user_exited = true;
+            user_finished = Some((
+                config.runtime.clock().now() - execution_start,
+                get_unix_timestamp_ms(SystemTime::now()),
+            ));
+            let exit_msg = match read_exit_message(user_exit_read.as_raw_fd()) {
+                Err(JudgeCoreError::MalformedExitReport(reason)) => {
+                    return Ok(Some(malformed_exit_report("user program", reason)));
+                }
+                result => result?,
+            };
+            user_listener.reap()?;
+            option_user_result = exit_msg.option_run_result;
+            epoll.delete(&user_exit_read)?;
+            interactor_to_user.set_receiver_exited(&epoll)?;
+            let last_phase = phase + 1 == interactor_executors.len();
+            // The last reply may be in the same batch as the exit, unread yet
+            let silent_phase = phase > 0
+                && user_to_interactor.lines == phase_start_lines
+                && !user_to_interactor.has_unread()?;
+            if !interactor_exited && (!last_phase || silent_phase) {
+                log::debug!(
+                    "User program exited in phase {}, killing the interactor",
+                    phase
+                );
+                unfinished = true;
+                interactor.listener.kill()?;
                 interactor_exited = true;
-                let _interactor_result: ProcessExitMessage = read_msg_from_fd(fd)?;
+                epoll.delete(&interactor.exit_read)?;
+                user_to_interactor.set_receiver_exited(&epoll)?;
             }
-            if fd == proxy_read_user.as_raw_fd() {
-                log::debug!("proxy_read_user {} fd read", fd);
-                pump_proxy_pipe(
-                    proxy_read_user.as_raw_fd(),
-                    proxy_write_interactor.as_raw_fd(),
-                    output_raw_fd.as_raw_fd(),
-                )?;
+        }
+        if !interactor_exited && ready_fds.contains(&interactor.exit_read.as_raw_fd()) {
+            log::debug!("interactor fd exited");
+            let exit_msg: ProcessExitMessage =
+                match read_exit_message(interactor.exit_read.as_raw_fd()) {
+                    Err(JudgeCoreError::MalformedExitReport(reason)) => {
+                        return Ok(Some(malformed_exit_report("interactor", reason)));
+                    }
+                    result => result?,
+                };
+            interactor.listener.reap()?;
+            option_interactor_result = exit_msg.option_run_result;
+            epoll.delete(&interactor.exit_read)?;
+            if let Some(interactor_result) = &option_interactor_result {
+                interactor_usages.push((
+                    get_run_time(interactor_result),
+                    get_max_mem(interactor_result),
+                ));
             }
-            if fd == proxy_read_interactor.as_raw_fd() {
-                log::debug!("proxy_read_interactor {} fd read", fd);
-                pump_proxy_pipe(
-                    proxy_read_interactor.as_raw_fd(),
-                    proxy_write_user.as_raw_fd(),
-                    output_raw_fd.as_raw_fd(),
-                )?;
+            let succeeded = option_interactor_result
+                .as_ref()
+                .is_some_and(|interactor_result| interactor_result.exit_status == 0);
+            if phase + 1 == interactor_executors.len() {
+                interactor_exited = true;
+                user_to_interactor.set_receiver_exited(&epoll)?;
+            } else if succeeded && !user_exited {
+                log::debug!("Phase {} is over", phase);
+                phase_over = true;
+                user_to_interactor.pause();
+            } else {
+                log::debug!("Interaction ended in phase {}", phase);
+                unfinished = true;
+                interactor_exited = true;
+                user_to_interactor.set_receiver_exited(&epoll)?;
+                if !user_exited {
+                    user_listener.kill()?;
+                    user_exited = true;
+                }
+            }
+        }
+        // The verdict no longer depends on the interactor, which may wait forever
+        // for a user program killed for its limits
+        if let (Some(user_result), false) = (&option_user_result, interactor_exited) {
+            if let Some(verdict) = check_user_result(config, user_result) {
+                log::debug!("User program got {}, killing the interactor", verdict);
+                interactor.listener.kill()?;
+                interactor_exited = true;
+                phase_over = false;
+                epoll.delete(&interactor.exit_read)?;
+                user_to_interactor.set_receiver_exited(&epoll)?;
             }
         }
         if user_exited && interactor_exited {
             log::debug!("Both user and interactor exited");
+            // What they wrote last may still be in the pipes, it belongs to the transcript.
+            // The proxy holds the write ends too, so reads end with EAGAIN instead of EOF.
+            loop {
+                let directions = if user_first {
+                    [&mut user_to_interactor, &mut interactor_to_user]
+                } else {
+                    [&mut interactor_to_user, &mut user_to_interactor]
+                };
+                let mut drained = 0;
+                for direction in directions {
+                    drained += direction.pump(&epoll, &mut transcript)?;
+                }
+                transcript.write_turn()?;
+                user_first = !user_first;
+                if drained == 0 {
+                    break;
+                }
+            }
+            break;
+        }
+
+        let directions = if user_first {
+            [&mut user_to_interactor, &mut interactor_to_user]
+        } else {
+            [&mut interactor_to_user, &mut user_to_interactor]
+        };
+        for direction in directions {
+            if ready_fds.contains(&direction.from) || ready_fds.contains(&direction.to) {
+                direction.pump(&epoll, &mut transcript)?;
+            }
+        }
+        transcript.write_turn()?;
+        user_first = !user_first;
+        if phase_over {
+            phase += 1;
+            log::debug!("Starting phase {}", phase);
+            let next_interactor =
+                InteractorPhase::spawn(config, &interactor_executors[phase], &epoll)?;
+            interactor_to_user.switch_from(
+                &epoll,
+                &mut transcript,
+                next_interactor.proxy_read.as_raw_fd(),
+            )?;
+            user_to_interactor.switch_to(&epoll, next_interactor.proxy_write.as_raw_fd())?;
+            transcript.write_turn()?;
+            interactor = next_interactor;
+            option_interactor_result = None;
+            phase_start_lines = user_to_interactor.lines;
+            phase_over = false;
+        }
+        if exceeds_max_interactions(config, &user_to_interactor) {
+            log::debug!("User program made too many queries, killing both sides");
+            // Its listener is left to report how it ran until then
+            if !user_exited && user_listener.kill_spawned()? {
+                let exit_msg = match read_exit_message(user_exit_read.as_raw_fd()) {
+                    Err(JudgeCoreError::MalformedExitReport(reason)) => {
+                        return Ok(Some(malformed_exit_report("user program", reason)));
+                    }
+                    result => result?,
+                };
+                user_listener.reap()?;
+                option_user_result = exit_msg.option_run_result;
+            } else if !user_exited {
+                user_listener.kill()?;
+            }
+            if !interactor_exited {
+                interactor.listener.kill()?;
+            }
             break;
         }
     }
     log::debug!("Epoll finished!");
+    transcript.flush()?;
+    run_span.exit();
+    let checking_start = config.runtime.clock().now();
+    let execution = checking_start - execution_start;
+    let interactor_time_usage = (!interactor_usages.is_empty())
+        .then(|| interactor_usages.iter().map(|(time, _)| *time).sum());
+    let interactor_memory_usage_bytes = interactor_usages.iter().map(|(_, memory)| *memory).max();
+    let interactor_path = interactor.path.clone();
+    let stdin_bytes_read = interactor_to_user.received()?;
+    let unfinished_outcome = || CheckerOutcome {
+        verdict: JudgeVerdict::WrongAnswer,
+        score: 0.0,
+        message: format!(
+            "The interaction ended in phase {} of {}",
+            phase + 1,
+            interactor_executors.len()
+        ),
+        reason_code: Some(reason_code::WA_UNFINISHED_INTERACTION.to_owned()),
+    };
+    // The user program may have been killed by the end of the interaction, with no result
+    let unfinished_result =
+        |user_result: Option<&SandboxExitInfo>, outcome: CheckerOutcome| JudgeResultInfo {
+            verdict: outcome.verdict,
+            time_usage: user_result.map_or(Duration::ZERO, |result| result.real_time_cost),
+            memory_usage_bytes: user_result.map_or(0, |result| result.resource_usage.max_rss),
+            disk_write_bytes: user_result.map_or(0, get_disk_write_bytes),
+            stdin_bytes_read: user_result.map(|_| stdin_bytes_read),
+            exit_status: user_result.map_or(0, |result| result.exit_status),
+            checker_exit_status: 0,
+            score: outcome.score,
+            interactor_time_usage,
+            interactor_memory_usage_bytes,
+            message: outcome.message,
+            reason_code: outcome.reason_code,
+            decided_by: Some(DecidedBy::Interactor(interactor_path.clone())),
+            timings: build_timings(config, judge_start, execution, checking_start),
+            started_at: user_result.map(|result| result.start_timestamp_ms),
+            finished_at: user_result.map(|result| result.end_timestamp_ms),
+            transcript: None,
+            performance: user_result.map(|result| PerformanceSignals::from(&result.resource_usage)),
+            core_dump: user_result.and_then(|result| result.core_dump.clone()),
+            effective_limits: user_result
+                .and_then(|result| reported_effective_limits(&config.runtime, result)),
+            raw: None,
+        };
 
+    if exceeds_max_interactions(config, &user_to_interactor) {
+        let outcome = CheckerOutcome {
+            verdict: JudgeVerdict::QueryLimitExceeded,
+            score: 0.0,
+            message: format!(
+                "Made {} queries, over the limit of {}",
+                user_to_interactor.lines,
+                config.runtime.max_interactions.unwrap_or_default()
+            ),
+            reason_code: Some(reason_code::QUERY_LIMIT.to_owned()),
+        };
+        return Ok(Some(JudgeResultInfo {
+            transcript: Some(read_transcript_tail(output_path)?),
+            ..unfinished_result(option_user_result.as_ref(), outcome)
+        }));
+    }
+    let interactor_outcome = config
+        .checker
+        .interactor_policy
+        .as_ref()
+        .zip(option_interactor_result.as_ref())
+        .and_then(|(policy, interactor_result)| policy.outcome(interactor_result))
+        .filter(|outcome| {
+            outcome.verdict != JudgeVerdict::Accepted
+                || (!unfinished && config.checker.executor.is_none())
+        });
     if let Some(user_result) = option_user_result {
         let option_user_verdict = check_user_result(config, &user_result);
         if let Some(verdict) = option_user_verdict {
+            let message = describe_user_result(&user_result, &verdict);
+            let reason_code = user_reason_code(config, &user_result, &verdict);
+            let transcript = match verdict {
+                JudgeVerdict::TimeLimitExceeded | JudgeVerdict::IdlenessLimitExceeded => {
+                    Some(read_transcript_tail(output_path)?)
+                }
+                _ => None,
+            };
+            // A verdict policy may accept from the exit code alone
+            let score = if verdict == JudgeVerdict::Accepted {
+                1.0
+            } else {
+                0.0
+            };
             return Ok(Some(JudgeResultInfo {
                 verdict,
                 time_usage: user_result.real_time_cost,
                 memory_usage_bytes: user_result.resource_usage.max_rss,
+                disk_write_bytes: get_disk_write_bytes(&user_result),
+                stdin_bytes_read: Some(stdin_bytes_read),
+                exit_status: user_result.exit_status,
+                checker_exit_status: 0,
+                score,
+                interactor_time_usage,
+                interactor_memory_usage_bytes,
+                message,
+                reason_code,
+                decided_by: None,
+                timings: build_timings(config, judge_start, execution, checking_start),
+                started_at: Some(user_result.start_timestamp_ms),
+                finished_at: Some(user_result.end_timestamp_ms),
+                transcript,
+                performance: Some(PerformanceSignals::from(&user_result.resource_usage)),
+                core_dump: user_result.core_dump.clone(),
+                effective_limits: reported_effective_limits(&config.runtime, &user_result),
+                raw: config.runtime.verbose.then_some(user_result),
+            }));
+        }
+        if let Some(outcome) = interactor_outcome {
+            log::debug!("Interactor decided {:?}", outcome.verdict);
+            return Ok(Some(JudgeResultInfo {
+                verdict: outcome.verdict,
+                time_usage: user_result.real_time_cost,
+                memory_usage_bytes: user_result.resource_usage.max_rss,
+                disk_write_bytes: get_disk_write_bytes(&user_result),
+                stdin_bytes_read: Some(stdin_bytes_read),
                 exit_status: user_result.exit_status,
                 checker_exit_status: 0,
+                score: outcome.score,
+                interactor_time_usage,
+                interactor_memory_usage_bytes,
+                message: outcome.message,
+                reason_code: outcome.reason_code,
+                decided_by: Some(DecidedBy::Interactor(interactor_path.clone())),
+                timings: build_timings(config, judge_start, execution, checking_start),
+                started_at: Some(user_result.start_timestamp_ms),
+                finished_at: Some(user_result.end_timestamp_ms),
+                transcript: None,
+                performance: Some(PerformanceSignals::from(&user_result.resource_usage)),
+                core_dump: user_result.core_dump.clone(),
+                effective_limits: reported_effective_limits(&config.runtime, &user_result),
+                raw: config.runtime.verbose.then_some(user_result),
             }));
         }
+        if unfinished {
+            return Ok(Some(unfinished_result(
+                Some(&user_result),
+                unfinished_outcome(),
+            )));
+        }
         log::debug!("Running checker process");
-        if let Some(_checker_executor) = config.checker.executor.clone() {
-            let (verdict, checker_exit_status) = run_checker(config)?;
+        if config.checker.executor.is_some() {
+            let (outcome, checker_exit_status) =
+                run_interact_checker(config, output_path, config.checker.pass_transcript)?;
             Ok(Some(JudgeResultInfo {
-                verdict,
+                verdict: outcome.verdict,
                 time_usage: user_result.real_time_cost,
                 memory_usage_bytes: user_result.resource_usage.max_rss,
+                disk_write_bytes: get_disk_write_bytes(&user_result),
+                stdin_bytes_read: Some(stdin_bytes_read),
                 exit_status: user_result.exit_status,
                 checker_exit_status,
+                score: outcome.score,
+                interactor_time_usage,
+                interactor_memory_usage_bytes,
+                message: outcome.message,
+                reason_code: outcome.reason_code,
+                decided_by: Some(config.checker.decided_by()),
+                timings: build_timings(config, judge_start, execution, checking_start),
+                started_at: Some(user_result.start_timestamp_ms),
+                finished_at: Some(user_result.end_timestamp_ms),
+                transcript: None,
+                performance: Some(PerformanceSignals::from(&user_result.resource_usage)),
+                core_dump: user_result.core_dump.clone(),
+                effective_limits: reported_effective_limits(&config.runtime, &user_result),
+                raw: config.runtime.verbose.then_some(user_result),
             }))
         } else {
-            Err(JudgeCoreError::AnyhowError(anyhow::anyhow!(
-                "Checker path is not provided"
-            )))
+            Err(JudgeCoreError::CheckerNotConfigured)
         }
+    } else if unfinished {
+        let outcome = interactor_outcome.unwrap_or_else(unfinished_outcome);
+        Ok(Some(unfinished_result(None, outcome)))
     } else {
-        // interactor output should be checked here
+        // The user program exited without a report, what the judger saw of it is reported
+        let (run_time, finished_at) =
+            user_finished.unwrap_or((execution, get_unix_timestamp_ms(SystemTime::now())));
         Ok(Some(JudgeResultInfo {
             verdict: JudgeVerdict::IdlenessLimitExceeded,
-            time_usage: Duration::new(0, 0),
-            memory_usage_bytes: 0,
+            time_usage: run_time,
+            memory_usage_bytes: user_peak_rss,
+            disk_write_bytes: 0,
+            stdin_bytes_read: None,
             exit_status: 0,
             checker_exit_status: 0,
+            score: 0.0,
+            interactor_time_usage,
+            interactor_memory_usage_bytes,
+            message: String::new(),
+            reason_code: Some(reason_code::IDLE.to_owned()),
+            // A limit of the user program, not the interaction
+            decided_by: None,
+            timings: build_timings(config, judge_start, execution, checking_start),
+            started_at: Some(user_started_at),
+            finished_at: Some(finished_at),
+            transcript: Some(read_transcript_tail(output_path)?),
+            performance: None,
+            core_dump: None,
+            effective_limits: None,
+            raw: None,
         }))
     }
 }
+
+/// A `SystemError` result when the exit report of `side` can't be read. Both sides are
+/// killed by their listeners being dropped, the interaction can't be trusted anyway.
+fn malformed_exit_report(side: &str, reason: String) -> JudgeResultInfo {
+    log::error!("Malformed exit report of the {}: {}", side, reason);
+    JudgeResultInfo::system_error(
+        format!("Malformed exit report of the {}: {}", side, reason),
+        reason_code::SE_EXIT_REPORT,
+    )
+}
+
+/// The last `PARTIAL_TRANSCRIPT_LEN` bytes of the transcript, for `JudgeResultInfo::transcript`.
+fn read_transcript_tail(transcript_path: &Path) -> Result<String, JudgeCoreError> {
+    let mut file = File::open(transcript_path)?;
+    let len = file.metadata()?.len();
+    file.seek(SeekFrom::Start(len.saturating_sub(PARTIAL_TRANSCRIPT_LEN)))?;
+    let mut tail = vec![];
+    file.read_to_end(&mut tail)?;
+    Ok(String::from_utf8_lossy(&tail).into_owned())
+}
+
+/// Run the checker after an interaction recorded to `transcript_path`, passing it the
+/// transcript with `pass_transcript`.
+fn run_interact_checker(
+    config: &JudgeConfig,
+    transcript_path: &Path,
+    pass_transcript: bool,
+) -> Result<(CheckerOutcome, i32), JudgeCoreError> {
+    let _span = enter_span!("check");
+    if pass_transcript {
+        let mut config = config.clone();
+        config
+            .checker
+            .extra_args
+            .insert(0, get_pathbuf_str(&transcript_path.to_path_buf())?);
+        run_checker(&config)
+    } else {
+        run_checker(config)
+    }
+}
+
+/// Check a previously recorded interaction again, without running any program.
+///
+/// The checker of `config` is run as `run_interact()` would have after the session
+/// recorded to `transcript_path`, e.g. to find out why a past submission got its verdict.
+/// The transcript is all there is to check, so it is passed to the checker whatever
+/// `CheckerConfig::pass_transcript` says. Nothing is run for the user program, so its usage
+/// and exit status are reported as zero.
+pub fn replay_interaction(
+    config: &JudgeConfig,
+    transcript_path: &Path,
+) -> Result<JudgeResultInfo, JudgeCoreError> {
+    if !transcript_path.exists() {
+        return Err(JudgeCoreError::AnyhowError(anyhow::anyhow!(
+            "Transcript {:?} not found",
+            transcript_path
+        )));
+    }
+    if let Some(result) = check_testdata_integrity(config)? {
+        return Ok(result);
+    }
+    if config.checker.executor.is_none() {
+        return Err(JudgeCoreError::CheckerNotConfigured);
+    }
+    log::debug!("Replaying interaction {:?}", transcript_path);
+    let (outcome, checker_exit_status) = run_interact_checker(config, transcript_path, true)?;
+    Ok(JudgeResultInfo {
+        verdict: outcome.verdict,
+        time_usage: Duration::ZERO,
+        memory_usage_bytes: 0,
+        disk_write_bytes: 0,
+        stdin_bytes_read: None,
+        exit_status: 0,
+        checker_exit_status,
+        score: outcome.score,
+        interactor_time_usage: None,
+        interactor_memory_usage_bytes: None,
+        message: outcome.message,
+        reason_code: outcome.reason_code,
+        decided_by: Some(config.checker.decided_by()),
+        timings: None,
+        started_at: None,
+        finished_at: None,
+        transcript: None,
+        performance: None,
+        core_dump: None,
+        effective_limits: None,
+        raw: None,
+    })
+}
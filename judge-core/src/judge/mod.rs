@@ -1,17 +1,299 @@
-use std::path::PathBuf;
+use std::{
+    fs, io,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
 
 use serde_derive::Serialize;
 
-use crate::{run::executor::Executor, sandbox::RlimitConfigs};
+use crate::utils::sha256_fields_and_file;
+#[cfg(feature = "checksum")]
+use crate::utils::sha256_file;
+use crate::{
+    compiler::Language,
+    error::JudgeCoreError,
+    judge::result::{
+        CheckerResultParser, CustomComparison, DecidedBy, InteractorExitPolicy, VerdictPolicy,
+    },
+    run::executor::Executor,
+    sandbox::{
+        clock::{Clock, MonotonicClock},
+        host::HostResources,
+        Limits, RlimitConfigs, SeccompMode, DEFAULT_CHECKER_LIMITS,
+    },
+    utils::{
+        get_pathbuf_str, FloatComparison, Normalizer, OutputComparison, TextPolicy, TokenComparison,
+    },
+};
 
 pub mod builder;
 pub mod common;
+pub mod expect;
+/// Interactive judging relies on epoll, which is only available on Linux
+#[cfg(target_os = "linux")]
 pub mod interact;
 pub mod result;
+mod stdin;
+pub mod stress;
+pub mod testset;
+
+/// Generous for any problem, it only stops broken packages from exhausting the judger.
+pub const DEFAULT_MAX_TESTDATA_BYTES: u64 = 1024 * 1024 * 1024;
+
+/// The environment variable holding the index of the case, see `CheckerConfig::pass_case_index`.
+pub const CASE_INDEX_ENV: &str = "JUDGE_CASE_INDEX";
 
 #[derive(Debug, Clone, Serialize)]
 pub struct RuntimeConfig {
     pub rlimit_configs: RlimitConfigs,
+    /// Attach the raw exit info of the user program to the result, for auditing.
+    pub verbose: bool,
+    /// Real time after which the user program is killed, catching the ones
+    /// sleeping or blocked on I/O that the CPU limit never would.
+    pub wall_time_limit: Option<Duration>,
+    /// Niceness of the user program, see `setpriority(2)`.
+    pub nice: Option<i32>,
+    /// Cores the user program may run on, pinning it makes timing more reproducible.
+    /// Only honored on Linux.
+    pub cpu_affinity: Option<Vec<usize>>,
+    /// Kill the user program once it gains almost no CPU time for this long,
+    /// reporting `IdlenessLimitExceeded`. See `Sandbox::set_idle_time_limit()`.
+    pub idle_time_limit: Option<Duration>,
+    /// Bytes the user program may write to disk-backed filesystems, its output file
+    /// included. Going over kills it and reports `DiskLimitExceeded`.
+    /// See `Sandbox::set_disk_write_limit()`.
+    pub disk_write_limit: Option<u64>,
+    /// Tasks the user program and its children may have at once, threads included.
+    /// See `Sandbox::set_thread_limit()` for how they are counted.
+    pub max_threads: Option<u64>,
+    /// Let the user program start subprocesses, otherwise it is killed as it tries and reported
+    /// as `RestrictedFunction`. They are still capped by `max_threads`.
+    /// See `ExecutorSandbox::set_allow_subprocesses()`.
+    pub allow_subprocesses: bool,
+    /// Time the user program is given to exit on `SIGTERM`, e.g. flushing its output,
+    /// before `SIGKILL` when it is killed for its limits. Zero kills it right away.
+    pub kill_grace_period: Duration,
+    /// CPU time the user program may use past the limit of `rlimit_configs` before it is
+    /// `TimeLimitExceeded`, absorbing the jitter of measuring it at the boundary.
+    /// The effective limit is the sum, see `cpu_time_limit()`. `RLIMIT_CPU` is raised
+    /// by the margin rounded up to whole seconds, so that the kernel never kills a program
+    /// the check would accept.
+    pub cpu_time_grace: Duration,
+    /// Sync the transcript of interactive judging to disk before returning.
+    pub sync_transcript: bool,
+    /// Lines the user program may send to the interactor, each one counting as a query, its
+    /// final answer included. Going over kills both sides and reports `QueryLimitExceeded`.
+    /// Only used by interactive judging.
+    pub max_interactions: Option<u64>,
+    /// Bytes of its input the user program may read from stdin, which is fed through a pipe.
+    /// A program blocked reading past them is killed and reported as `RestrictedFunction`.
+    /// Only enforced on Linux, and not by interactive judging, where the interactor feeds it.
+    pub max_stdin_bytes: Option<u64>,
+    /// Wall time the cases of a test set may take together, the cases left once it is spent
+    /// are not run but reported as `NotRun`. Each case is given at most what is left of it as
+    /// its wall time limit. See `run_testset()`.
+    pub total_time_budget: Option<Duration>,
+    /// Run the user program once on this input before the first case of a test set,
+    /// its result discarded, so that the cases don't pay for cold caches or the warmup
+    /// of a JIT. See `run_testset()`.
+    pub warmup_input: Option<PathBuf>,
+    /// Most times the user program is run on each case, rerun while it is over the time limit
+    /// so that a slow run doesn't make a spurious `TimeLimitExceeded`. A faster run ending the
+    /// same way, e.g. with the same exit code, is kept along with its output, any other the
+    /// first run is. A run killed for its time limit ends the same way as any other. Each run costs the whole time of the program, `0` and `1` run it once.
+    /// Only used by non-interactive judging.
+    pub timing_runs: u32,
+    /// Applies to every sandboxed process of the judge, checkers included.
+    pub seccomp_mode: SeccompMode,
+    /// Decides the verdict of the user program from how it ran,
+    /// `DefaultVerdictPolicy` when `None`.
+    #[serde(skip)]
+    pub verdict_policy: Option<Arc<dyn VerdictPolicy>>,
+    /// Measures the time of the judge: the timings, the time budget of a test set and, through
+    /// `Sandbox::set_clock()`, the limits in time of its sandboxes. `MonotonicClock` when `None`,
+    /// see `clock()`.
+    #[serde(skip)]
+    pub clock: Option<Arc<dyn Clock>>,
+    /// Report `JudgeResultInfo::timings`.
+    pub record_timings: bool,
+    /// Caps of the checker, which is reported as `SystemError` when it runs out of time.
+    /// See `sandbox::DEFAULT_CHECKER_LIMITS`.
+    pub checker_limits: Limits,
+    /// Input and answer files larger than this are not judged, reported as `SystemError`
+    /// before anything reads them. See `DEFAULT_MAX_TESTDATA_BYTES`.
+    pub max_testdata_bytes: u64,
+    /// Tags the spans of the judge with the `tracing` feature, telling apart the logs
+    /// of judges running at the same time.
+    pub submission_id: Option<String>,
+    /// Where the judge creates its intermediate files, e.g. the captured stdout of the program
+    /// or decompressed test data, and the base of a relative `StressConfig::work_dir`.
+    /// Usually `std::env::temp_dir()`.
+    pub scratch_dir: PathBuf,
+    /// Keep the core of a crashed user program in a directory of its own in this one, reported
+    /// in `JudgeResultInfo::core_dump`. Off by default, for debugging: cores may be large and
+    /// where they land depends on the host. See `Sandbox::set_core_dump_dir()`.
+    pub core_dump_dir: Option<PathBuf>,
+}
+
+impl RuntimeConfig {
+    /// Runs the user program within `rlimit_configs` and nothing else: no other limit, one
+    /// timing run and the default caps of the checker and of the test data.
+    pub fn new(rlimit_configs: RlimitConfigs, scratch_dir: PathBuf) -> Self {
+        Self {
+            rlimit_configs,
+            verbose: false,
+            wall_time_limit: None,
+            nice: None,
+            cpu_affinity: None,
+            idle_time_limit: None,
+            disk_write_limit: None,
+            max_threads: None,
+            allow_subprocesses: false,
+            kill_grace_period: Duration::ZERO,
+            cpu_time_grace: Duration::ZERO,
+            sync_transcript: false,
+            max_interactions: None,
+            max_stdin_bytes: None,
+            total_time_budget: None,
+            warmup_input: None,
+            timing_runs: 1,
+            seccomp_mode: SeccompMode::default(),
+            verdict_policy: None,
+            clock: None,
+            record_timings: false,
+            checker_limits: DEFAULT_CHECKER_LIMITS,
+            max_testdata_bytes: DEFAULT_MAX_TESTDATA_BYTES,
+            submission_id: None,
+            scratch_dir,
+            core_dump_dir: None,
+        }
+    }
+
+    /// `new()` with the caps of `limits`, the other way round from `limits()`.
+    ///
+    /// `rlimit_configs` only hold whole seconds, the fraction of a second of
+    /// `Limits::cpu_time` past them is `cpu_time_grace`. Like the rlimits of a package,
+    /// `Limits::output_bytes` and `Limits::max_processes` are not loaded for the user program,
+    /// see `RlimitConfigs::loaded_limits()`.
+    pub fn with_limits(limits: &Limits, scratch_dir: PathBuf) -> Self {
+        let cap = |cap: Option<u64>| cap.map(|cap| (cap, cap));
+        let rlimit_configs = RlimitConfigs {
+            stack_limit: cap(limits.stack_bytes),
+            as_limit: cap(limits.memory_bytes),
+            cpu_limit: limits
+                .cpu_time
+                .map(|cpu_time| (cpu_time.as_secs(), cpu_time.as_secs() + 1)),
+            nproc_limit: cap(limits.max_processes),
+            fsize_limit: cap(limits.output_bytes),
+            open_files_limit: cap(limits.open_files),
+        };
+        Self {
+            wall_time_limit: limits.wall_time,
+            disk_write_limit: limits.disk_write_bytes,
+            max_threads: limits.max_threads,
+            cpu_time_grace: limits.cpu_time.map_or(Duration::ZERO, |cpu_time| {
+                Duration::from_nanos(cpu_time.subsec_nanos().into())
+            }),
+            ..Self::new(rlimit_configs, scratch_dir)
+        }
+    }
+
+    /// The clock of `clock`, or the monotonic clock of the system.
+    pub fn clock(&self) -> &dyn Clock {
+        self.clock.as_deref().unwrap_or(&MonotonicClock)
+    }
+
+    /// CPU time over which the user program is `TimeLimitExceeded`, `cpu_time_grace` included.
+    pub fn cpu_time_limit(&self) -> Option<Duration> {
+        self.rlimit_configs
+            .get_cpu_limit_duration()
+            .map(|time_limit| time_limit + self.cpu_time_grace)
+    }
+
+    /// The rlimit configs the user program is run with, `RLIMIT_CPU` raised by
+    /// `cpu_time_grace`.
+    pub fn user_rlimit_configs(&self) -> RlimitConfigs {
+        let grace = &self.cpu_time_grace;
+        let grace_secs = grace.as_secs() + u64::from(grace.subsec_nanos() > 0);
+        RlimitConfigs {
+            cpu_limit: self
+                .rlimit_configs
+                .cpu_limit
+                .map(|(soft, hard)| (soft + grace_secs, hard + grace_secs)),
+            ..self.rlimit_configs.clone()
+        }
+    }
+
+    /// The caps of the user program, from `rlimit_configs`, `wall_time_limit`,
+    /// `disk_write_limit` and `max_threads`.
+    pub fn limits(&self) -> Limits {
+        Limits {
+            wall_time: self.wall_time_limit,
+            disk_write_bytes: self.disk_write_limit,
+            max_threads: self.max_threads,
+            ..Limits::from(&self.rlimit_configs)
+        }
+    }
+
+    /// What `host` can't provide of this config, e.g. more memory than the host has.
+    pub fn host_capability_problems(&self, host: &HostResources) -> Vec<String> {
+        let mut problems = vec![];
+        if let (Some((memory_limit, _)), Some(total_memory)) =
+            (self.rlimit_configs.as_limit, host.total_memory_bytes)
+        {
+            if memory_limit > total_memory {
+                problems.push(format!(
+                    "memory limit of {} bytes exceeds the {} bytes of memory of the host",
+                    memory_limit, total_memory
+                ));
+            }
+        }
+        if let (Some(cpu_affinity), Some(cpu_count)) = (&self.cpu_affinity, host.cpu_count) {
+            for cpu in cpu_affinity.iter().filter(|cpu| **cpu >= cpu_count) {
+                problems.push(format!(
+                    "CPU {} of the affinity is not one of the {} CPUs of the host",
+                    cpu, cpu_count
+                ));
+            }
+        }
+        if let Some(ceilings) = &host.rlimit_ceilings {
+            let limits = self.user_rlimit_configs().loaded_limits();
+            // Loaded as both the soft and hard limit, but for `RLIMIT_CPU`, see `Limits::load()`
+            let caps = [
+                ("stack", limits.stack_bytes, ceilings.stack_limit),
+                ("memory", limits.memory_bytes, ceilings.as_limit),
+                (
+                    "CPU time",
+                    limits.get_cpu_kill_duration().map(|hard| hard.as_secs()),
+                    ceilings.cpu_limit,
+                ),
+                ("open files", limits.open_files, ceilings.open_files_limit),
+            ];
+            for (name, hard, ceiling) in caps {
+                if let (Some(hard), Some((_, ceiling))) = (hard, ceiling) {
+                    if hard > ceiling {
+                        problems.push(format!(
+                            "{} limit of {} exceeds the hard limit {} of the judger",
+                            name, hard, ceiling
+                        ));
+                    }
+                }
+            }
+        }
+        problems
+    }
+}
+
+/// How `JudgeConfig::validate()` treats a problem.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub enum ValidationSeverity {
+    /// Log a warning and carry on
+    #[default]
+    Warn,
+    /// Fail with `JudgeCoreError::InvalidConfig`
+    Error,
 }
 
 /// When `executor` is `None`, default checker will be used.
@@ -19,18 +301,352 @@ pub struct RuntimeConfig {
 pub struct CheckerConfig {
     pub executor: Option<Executor>,
     pub output_file_path: PathBuf,
+    /// Only used by the default checker, see `utils::compare_files`.
+    pub normalizers: Vec<Normalizer>,
+    /// How the default checker decodes both files for `OutputComparison::Lines`,
+    /// `OutputComparison::UnorderedLines` and `OutputComparison::LineTokens`.
+    pub text: TextPolicy,
+    /// How the default checker compares the output with the answer.
+    /// `normalizers` are only applied by `OutputComparison::Lines`,
+    /// `OutputComparison::UnorderedLines` and `OutputComparison::LineTokens`.
+    pub comparison: OutputComparison,
+    /// Put a unified diff of at most this many lines, from the answer to the output, in the
+    /// message of a `WrongAnswer` of the default checker. See `utils::diff_files()`.
+    pub diff_lines: Option<usize>,
+    /// Appended to the checker's argv after the standard file paths.
+    pub extra_args: Vec<String>,
+    /// Feed the input file of the test case to the checker's stdin.
+    pub feed_input_to_stdin: bool,
+    /// Pass the transcript of interactive judging to the checker, as the first argument after
+    /// the standard file paths and before `extra_args`, so that it can validate the whole
+    /// session. Only used by interactive judging, `replay_interaction()` always passes it.
+    pub pass_transcript: bool,
+    /// Set `CASE_INDEX_ENV` to `TestdataConfig::case_index` for the checker and the interactor,
+    /// e.g. for case-specific tolerances. It is not an argument, which testlib would reject.
+    pub pass_case_index: bool,
+    /// Read the verdict from `output_file_path`, written by the checker, instead of its exit
+    /// code. The arguments of the parser come right after the standard file paths.
+    #[serde(skip)]
+    pub result_parser: Option<Arc<dyn CheckerResultParser>>,
+    /// Asked for a verdict from the exit of the interactor before the checker runs, e.g.
+    /// `TestlibInteractorPolicy` for interactors which reject the program by their exit code.
+    /// Only used by interactive judging.
+    #[serde(skip)]
+    pub interactor_policy: Option<Arc<dyn InteractorExitPolicy>>,
+    /// Compare the output with the answer instead of the default checker, ignoring
+    /// `comparison`, `normalizers` and `diff_lines`. Only used without `executor`.
+    #[serde(skip)]
+    pub custom_comparison: Option<CustomComparison>,
+}
+
+/// A program preparing the state a case needs, e.g. unpacking data or generating a scenario.
+///
+/// Unlike compilation it runs for every case, before the user program. Like the checker
+/// its system calls are not restricted, yet it runs as the sandbox user when the judger is
+/// root, so it can only write where that user can.
+#[derive(Debug, Clone, Serialize)]
+pub struct SetupConfig {
+    pub executor: Executor,
+    /// Passed to the program after the path of the input file of the case.
+    pub args: Vec<String>,
+    /// Caps of the setup program, the case is a `SystemError` when it runs out of time.
+    pub limits: Limits,
+}
+
+/// A trusted solution whose output on the input of a case is the answer of the case, for test
+/// data without answer files. See `JudgeConfig::reference`.
+///
+/// It runs sandboxed like the setup program, before the user program. Its outputs are cached in
+/// `cache_dir` by the content of the input, so each input is only answered once however many
+/// submissions are judged on it. Clear the cache when the reference changes.
+#[derive(Debug, Clone, Serialize)]
+pub struct ReferenceConfig {
+    pub executor: Executor,
+    /// Caps of the reference, the case is a `SystemError` when it runs out of time.
+    pub limits: Limits,
+    pub cache_dir: PathBuf,
+}
+
+impl ReferenceConfig {
+    /// Where the output of the reference on `input_file_path` is cached, named by the SHA-256
+    /// of the reference command and of the input. It is the same across builds and hosts
+    /// sharing `cache_dir`, and no input can be crafted to be served the answer of another.
+    pub fn answer_path(&self, input_file_path: &Path) -> Result<PathBuf, JudgeCoreError> {
+        let mut fields = vec![self.executor.path.as_os_str().as_bytes()];
+        fields.extend(self.executor.additional_args.iter().map(String::as_bytes));
+        let digest = sha256_fields_and_file(&fields, input_file_path)?;
+        Ok(self.cache_dir.join(format!("{}.ans", digest)))
+    }
+}
+
+impl CheckerConfig {
+    /// The default checker comparing the lines of the output with the answer as they are.
+    pub fn new(output_file_path: PathBuf) -> Self {
+        Self {
+            executor: None,
+            output_file_path,
+            normalizers: vec![],
+            text: TextPolicy::default(),
+            comparison: OutputComparison::Lines,
+            diff_lines: None,
+            extra_args: vec![],
+            feed_input_to_stdin: false,
+            pass_transcript: false,
+            pass_case_index: false,
+            result_parser: None,
+            interactor_policy: None,
+            custom_comparison: None,
+        }
+    }
+
+    /// Which route of this config checks the output.
+    pub fn decided_by(&self) -> DecidedBy {
+        match (&self.executor, self.comparison) {
+            (Some(executor), _) => DecidedBy::ExternalChecker(executor.path.clone()),
+            (None, _) if self.custom_comparison.is_some() => DecidedBy::CustomClosure,
+            (
+                None,
+                OutputComparison::Lines
+                | OutputComparison::UnorderedLines
+                | OutputComparison::Bytes,
+            ) => DecidedBy::BuiltinExact,
+            (
+                None,
+                OutputComparison::Tokens(TokenComparison::Exact) | OutputComparison::LineTokens,
+            ) => DecidedBy::BuiltinToken,
+            (None, OutputComparison::Tokens(TokenComparison::Float(_))) => DecidedBy::BuiltinFloat,
+            (None, OutputComparison::Tokens(TokenComparison::Integer)) => DecidedBy::BuiltinInteger,
+        }
+    }
+
+    /// `executor` given the environment this config asks for `test_data`,
+    /// see `pass_case_index`.
+    pub fn executor_for(&self, executor: &Executor, test_data: &TestdataConfig) -> Executor {
+        let mut executor = executor.clone();
+        if let (true, Some(case_index)) = (self.pass_case_index, test_data.case_index) {
+            executor.set_env(CASE_INDEX_ENV, &case_index.to_string());
+        }
+        executor
+    }
+
+    /// Check with `source`, replacing the executor and the comparison of this config.
+    ///
+    /// A built-in name is resolved here, so that an unknown one fails before anyone is judged.
+    /// An external checker is run as a compiled executable.
+    pub fn select(&mut self, source: &CheckerSource) -> Result<(), JudgeCoreError> {
+        match source {
+            CheckerSource::Builtin(name) => {
+                self.comparison = BuiltinChecker::from_name(name)?.comparison();
+                self.executor = None;
+            }
+            CheckerSource::External(path) => {
+                self.executor = Some(Executor::new(Language::Cpp, path.clone())?);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Error allowed by the `fcmp` built-in checker, see `FloatComparison::eps`.
+pub const DEFAULT_FLOAT_EPS: f64 = 1e-6;
+
+/// A default checker behavior chosen by name, like the standard checkers of testlib
+/// that problem packages reference.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum BuiltinChecker {
+    /// Tokens must be equal
+    Wcmp,
+    /// Tokens are compared as floats with `DEFAULT_FLOAT_EPS` of error
+    Fcmp,
+    /// Lines must have the same tokens, see `utils::compare_line_tokens()`
+    Lcmp,
+    /// Tokens are compared as 64-bit integers, any other token is rejected
+    Ncmp,
+}
+
+impl BuiltinChecker {
+    pub const NAMES: [&'static str; 4] = ["wcmp", "fcmp", "lcmp", "ncmp"];
+
+    /// `JudgeCoreError::InvalidConfig` for a name which is not one of `NAMES`.
+    pub fn from_name(name: &str) -> Result<Self, JudgeCoreError> {
+        match name {
+            "wcmp" => Ok(Self::Wcmp),
+            "fcmp" => Ok(Self::Fcmp),
+            "lcmp" => Ok(Self::Lcmp),
+            "ncmp" => Ok(Self::Ncmp),
+            _ => Err(JudgeCoreError::InvalidConfig(format!(
+                "unknown built-in checker {:?}, expected one of {}",
+                name,
+                Self::NAMES.join(", ")
+            ))),
+        }
+    }
+
+    pub fn comparison(&self) -> OutputComparison {
+        match self {
+            Self::Wcmp => OutputComparison::Tokens(TokenComparison::Exact),
+            Self::Fcmp => OutputComparison::Tokens(TokenComparison::Float(FloatComparison::new(
+                DEFAULT_FLOAT_EPS,
+            ))),
+            Self::Lcmp => OutputComparison::LineTokens,
+            Self::Ncmp => OutputComparison::Tokens(TokenComparison::Integer),
+        }
+    }
+}
+
+/// The checker of a problem as an operator configures it, see `CheckerConfig::select()`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum CheckerSource {
+    /// One of `BuiltinChecker::NAMES`
+    Builtin(String),
+    /// The path of an external checker
+    External(PathBuf),
+}
+
+impl From<&str> for CheckerSource {
+    /// A path when it contains a `/`, e.g. `./checker`, a built-in name otherwise.
+    fn from(checker: &str) -> Self {
+        if checker.contains('/') {
+            Self::External(PathBuf::from(checker))
+        } else {
+            Self::Builtin(checker.to_owned())
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize)]
 pub struct ProgramConfig {
     pub executor: Executor,
-    pub output_file_path: PathBuf,
+    /// Where the stdout of the program is written, then checked.
+    /// `None` captures it to a temporary file, removed once judged.
+    pub output_file_path: Option<PathBuf>,
+    /// Passed to the program, with `{input}` replaced by the path of the input file.
+    /// `{case}` is replaced by the index of the test case, see `with_case_index()`.
+    pub program_args: Vec<String>,
+    /// Fed to the stdin of the program before anything from the interactor, e.g. initial
+    /// parameters of a hybrid problem. Recorded first to the transcript.
+    /// Only used by interactive judging.
+    pub stdin_prefix_path: Option<PathBuf>,
+}
+
+impl ProgramConfig {
+    /// `output_file_path`, which the judge sets before running the program when it is `None`.
+    pub fn output_path(&self) -> Result<&PathBuf, JudgeCoreError> {
+        self.output_file_path.as_ref().ok_or_else(|| {
+            JudgeCoreError::InvalidConfig("the output of the program is not captured".to_owned())
+        })
+    }
+
+    /// This config with `{case}` in `program_args` replaced by `case_index`,
+    /// as done by `run_testset()`.
+    pub fn with_case_index(&self, case_index: usize) -> Self {
+        let mut config = self.clone();
+        for arg in &mut config.program_args {
+            *arg = arg.replace("{case}", &case_index.to_string());
+        }
+        config
+    }
+
+    /// The executor of the program, given its args for `test_data`.
+    pub fn executor_for(&self, test_data: &TestdataConfig) -> Result<Executor, JudgeCoreError> {
+        let mut executor = self.executor.clone();
+        if !self.program_args.is_empty() {
+            let input = get_pathbuf_str(&test_data.input_file_path)?;
+            executor.set_program_args(
+                self.program_args
+                    .iter()
+                    .map(|arg| arg.replace("{input}", &input))
+                    .collect(),
+            );
+        }
+        Ok(executor)
+    }
 }
 
 #[derive(Debug, Clone, Serialize)]
 pub struct TestdataConfig {
     pub input_file_path: PathBuf,
     pub answer_file_path: PathBuf,
+    /// Other acceptable answers, the output is accepted when it matches any of them.
+    pub extra_answer_file_paths: Vec<PathBuf>,
+    /// Expected SHA-256 of the input file in hex, verified before each run.
+    pub input_sha256: Option<String>,
+    /// Expected SHA-256 of the answer file in hex, verified before each run.
+    pub answer_sha256: Option<String>,
+    /// Position of the case in its test set, set by `run_testset()`.
+    pub case_index: Option<usize>,
+}
+
+impl TestdataConfig {
+    /// `answer_file_path` followed by `extra_answer_file_paths`.
+    pub fn answer_file_paths(&self) -> impl Iterator<Item = &PathBuf> {
+        std::iter::once(&self.answer_file_path).chain(self.extra_answer_file_paths.iter())
+    }
+
+    /// Check the sizes of the input and answer files before anything reads them.
+    ///
+    /// Returns which file is larger than `max_bytes`, missing files are not checked here.
+    pub fn check_file_sizes(&self, max_bytes: u64) -> Result<Option<String>, JudgeCoreError> {
+        let files = std::iter::once(("input", &self.input_file_path))
+            .chain(self.answer_file_paths().map(|path| ("answer", path)));
+        for (kind, path) in files {
+            let len = match fs::metadata(path) {
+                Ok(metadata) => metadata.len(),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            };
+            if len > max_bytes {
+                return Ok(Some(format!(
+                    "test data too large: {} file {:?} has {} bytes, over the limit of {}",
+                    kind, path, len, max_bytes
+                )));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Check the input and answer files against their expected checksums, if any.
+    ///
+    /// Returns what is wrong with the test data, e.g. a mismatching file.
+    /// Without the `checksum` feature, test data with checksums can't be verified at all.
+    pub fn verify_checksums(&self) -> Result<Option<String>, JudgeCoreError> {
+        let expected_checksums = [
+            (&self.input_file_path, &self.input_sha256),
+            (&self.answer_file_path, &self.answer_sha256),
+        ];
+        for (path, expected) in expected_checksums {
+            let expected = match expected {
+                Some(expected) => expected,
+                None => continue,
+            };
+            #[cfg(feature = "checksum")]
+            {
+                let actual = sha256_file(path)?;
+                if !actual.eq_ignore_ascii_case(expected) {
+                    return Ok(Some(format!(
+                        "test data integrity check failed: {:?} has SHA-256 {}, expected {}",
+                        path, actual, expected
+                    )));
+                }
+            }
+            #[cfg(not(feature = "checksum"))]
+            {
+                log::debug!("Can't verify {:?} against SHA-256 {}", path, expected);
+                return Ok(Some(String::from(
+                    "test data integrity can't be verified: the checksum feature is disabled",
+                )));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Fill `input_sha256` and `answer_sha256` with the checksums of the current files.
+    #[cfg(feature = "checksum")]
+    pub fn populate_checksums(&mut self) -> Result<(), JudgeCoreError> {
+        self.input_sha256 = Some(sha256_file(&self.input_file_path)?);
+        self.answer_sha256 = Some(sha256_file(&self.answer_file_path)?);
+        Ok(())
+    }
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -39,4 +655,32 @@ pub struct JudgeConfig {
     pub runtime: RuntimeConfig,
     pub program: ProgramConfig,
     pub checker: CheckerConfig,
+    /// Run before the user program of the case, which is not run when it fails.
+    pub setup: Option<SetupConfig>,
+    /// Answers the case in place of `TestdataConfig::answer_file_path`, after the setup program.
+    /// Not used by interactive judging.
+    pub reference: Option<ReferenceConfig>,
+}
+
+impl JudgeConfig {
+    /// Check the limits of the config against the host, before anyone is judged with it.
+    ///
+    /// Returns the problems found when they are only warned about.
+    pub fn validate(&self, severity: ValidationSeverity) -> Result<Vec<String>, JudgeCoreError> {
+        let problems = self
+            .runtime
+            .host_capability_problems(&HostResources::detect()?);
+        if problems.is_empty() {
+            return Ok(problems);
+        }
+        match severity {
+            ValidationSeverity::Warn => {
+                for problem in &problems {
+                    log::warn!("Config may not work on this host: {}", problem);
+                }
+                Ok(problems)
+            }
+            ValidationSeverity::Error => Err(JudgeCoreError::InvalidConfig(problems.join("; "))),
+        }
+    }
 }
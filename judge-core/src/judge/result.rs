@@ -1,18 +1,166 @@
 use serde_derive::Serialize;
 
-use std::{fmt, ops::Add, time::Duration};
+use nix::sys::signal::Signal;
 
-use crate::sandbox::SandboxExitInfo;
+use std::{
+    collections::HashMap,
+    convert::TryFrom,
+    fmt, fs,
+    ops::Add,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
 
-use super::JudgeConfig;
+use crate::sandbox::{EffectiveLimits, Limits, Rusage, SandboxExitInfo};
+
+use super::{JudgeConfig, RuntimeConfig};
+
+// Exit codes of testlib checkers, see `TResult` in testlib.h
+const TESTLIB_EXIT_OK: i32 = 0;
+const TESTLIB_EXIT_WA: i32 = 1;
+const TESTLIB_EXIT_PE: i32 = 2;
+const TESTLIB_EXIT_FAIL: i32 = 3;
+const TESTLIB_EXIT_POINTS: i32 = 7;
 
 #[derive(Debug, Serialize, Clone)]
 pub struct JudgeResultInfo {
     pub verdict: JudgeVerdict,
     pub time_usage: Duration,
     pub memory_usage_bytes: i64,
+    /// Bytes the user program wrote to disk-backed filesystems, its output file included.
+    /// See `Sandbox::set_disk_write_limit()`.
+    pub disk_write_bytes: u64,
+    /// Bytes the user program read from its stdin, `None` when it was not run.
+    ///
+    /// Its stdin is a pipe, this is what was fed to it less what was left unread there, so it
+    /// can't be mapped to memory as some fast input routines try to. Interactive judging counts
+    /// what the proxy delivered the same way.
+    pub stdin_bytes_read: Option<u64>,
     pub exit_status: i32,
     pub checker_exit_status: i32,
+    /// Share of the full score in `[0, 1]`, see `CheckerOutcome::score`.
+    pub score: f64,
+    /// CPU time of the interactor, only reported by interactive judging.
+    pub interactor_time_usage: Option<Duration>,
+    /// Peak memory of the interactor, only reported by interactive judging.
+    pub interactor_memory_usage_bytes: Option<i64>,
+    /// Extra detail on how the verdict was reached, empty when there is nothing to add.
+    pub message: String,
+    /// Stable, machine-readable cause of the verdict, `None` when accepted. See `reason_code`.
+    pub reason_code: Option<String>,
+    /// What reached the verdict, `None` when it comes from how the user program ran,
+    /// e.g. a time or idleness limit, or when the case was not judged.
+    pub decided_by: Option<DecidedBy>,
+    /// Where the time went, only reported when `RuntimeConfig::record_timings` is set.
+    pub timings: Option<JudgeTimings>,
+    /// Unix timestamp in milliseconds when the user program was spawned,
+    /// `None` when it was not run.
+    pub started_at: Option<u64>,
+    /// Unix timestamp in milliseconds when the user program was reaped,
+    /// `None` when it was not run.
+    pub finished_at: Option<u64>,
+    /// The end of the interaction when the user program was stopped by a time or idleness
+    /// limit, to see how far it got. Only reported by interactive judging.
+    pub transcript: Option<String>,
+    /// Scheduling and paging counters of the user program, `None` when it was not run.
+    pub performance: Option<PerformanceSignals>,
+    /// The core dumped by the user program when it crashed,
+    /// only with `RuntimeConfig::core_dump_dir` set.
+    pub core_dump: Option<PathBuf>,
+    /// The limits the user program really ran with, to tell apart from the requested
+    /// `RuntimeConfig::limits()`. Only reported when `RuntimeConfig::verbose` is set or
+    /// when the host lowered some, see `EffectiveLimits::lowered`.
+    pub effective_limits: Option<EffectiveLimits>,
+    /// Raw exit info of the user program, only reported when `RuntimeConfig::verbose` is set.
+    pub raw: Option<SandboxExitInfo>,
+}
+
+impl JudgeResultInfo {
+    /// A `SystemError` result with no usage, e.g. for a case whose user program was not run.
+    pub fn system_error(message: String, reason_code: &str) -> Self {
+        Self {
+            verdict: JudgeVerdict::SystemError,
+            time_usage: Duration::ZERO,
+            memory_usage_bytes: 0,
+            disk_write_bytes: 0,
+            stdin_bytes_read: None,
+            exit_status: 0,
+            checker_exit_status: 0,
+            score: 0.0,
+            interactor_time_usage: None,
+            interactor_memory_usage_bytes: None,
+            message,
+            reason_code: Some(reason_code.to_owned()),
+            decided_by: None,
+            timings: None,
+            started_at: None,
+            finished_at: None,
+            transcript: None,
+            performance: None,
+            core_dump: None,
+            effective_limits: None,
+            raw: None,
+        }
+    }
+}
+
+/// The route which reached the verdict of `JudgeResultInfo`, for auditing.
+#[derive(Debug, PartialEq, Eq, Serialize, Clone)]
+pub enum DecidedBy {
+    /// The default checker comparing lines after the normalizers, in order or not, or bytes
+    BuiltinExact,
+    /// The default checker comparing tokens as they are, line by line or not
+    BuiltinToken,
+    /// The default checker comparing tokens as numbers, see `FloatComparison`
+    BuiltinFloat,
+    /// The default checker comparing tokens as integers, rejecting any other token
+    BuiltinInteger,
+    /// `CheckerConfig::custom_comparison`
+    CustomClosure,
+    /// The checker at the path
+    ExternalChecker(PathBuf),
+    /// The interaction with the interactor at the path, cut short before any checker ran
+    Interactor(PathBuf),
+}
+
+/// Counters from the rusage of the user program, which don't affect the verdict but help
+/// spotting suspicious behavior.
+#[derive(Debug, PartialEq, Eq, Serialize, Clone, Copy, Default)]
+pub struct PerformanceSignals {
+    /// Times the program gave up the CPU while waiting, e.g. on I/O or a sleep. Many of them
+    /// for a program which should be computing hint at yielding to game the scheduler.
+    pub voluntary_context_switches: i64,
+    /// Times the program was preempted, high when it competes with other processes for the
+    /// CPU, which makes its time less reliable.
+    pub involuntary_context_switches: i64,
+    /// Page faults served without I/O, mostly the first touch of allocated memory.
+    pub minor_page_faults: i64,
+    /// Page faults which required I/O, many of them mean the host is swapping.
+    pub major_page_faults: i64,
+}
+
+impl From<&Rusage> for PerformanceSignals {
+    fn from(rusage: &Rusage) -> Self {
+        Self {
+            voluntary_context_switches: rusage.voluntary_context_switches,
+            involuntary_context_switches: rusage.involuntary_context_switches,
+            minor_page_faults: rusage.minor_page_faults,
+            major_page_faults: rusage.page_faults,
+        }
+    }
+}
+
+/// Real time spent in each phase of judging a case, which add up to `total`.
+#[derive(Debug, PartialEq, Serialize, Clone, Default)]
+pub struct JudgeTimings {
+    /// Verifying the test data, opening files and setting up the sandboxes
+    pub prepare: Duration,
+    /// From spawning the user program to reaping it, the whole interaction if interactive
+    pub execution: Duration,
+    /// Checking the output, by the checker or the default comparison
+    pub checking: Duration,
+    pub total: Duration,
 }
 
 #[derive(Debug, PartialEq, Serialize, Clone)]
@@ -21,10 +169,218 @@ pub enum JudgeVerdict {
     WrongAnswer,
     TimeLimitExceeded,
     IdlenessLimitExceeded,
+    /// Sent more queries to the interactor than `RuntimeConfig::max_interactions`
+    QueryLimitExceeded,
+    /// Wrote more to disk than `RuntimeConfig::disk_write_limit`
+    DiskLimitExceeded,
     RuntimeError,
+    /// Killed by the syscall filter of the sandbox for making a forbidden system call
+    RestrictedFunction,
     PartialScore,
     SystemError,
     CompileError,
+    /// Not run because a case it depends on did not pass,
+    /// neither a pass nor a failure of the program
+    Skipped,
+    /// Not run by the choice of the operator, e.g. filtered out or past the time budget of the
+    /// test set. Left out of `VerdictPriority::aggregate()`.
+    NotRun,
+}
+
+/// The vocabulary of `JudgeResultInfo::reason_code`.
+///
+/// Codes are part of the API: they are never renamed nor reused for another cause,
+/// so that integrators can match on them instead of parsing messages.
+pub mod reason_code {
+    /// CPU time of the user program over the limit
+    pub const TLE_CPU: &str = "TLE_CPU";
+    /// Real time of the user program over the wall time limit
+    pub const TLE_WALL: &str = "TLE_WALL";
+    /// User program killed for being idle
+    pub const IDLE: &str = "IDLE";
+    /// User program killed for sending too many queries to the interactor
+    pub const QUERY_LIMIT: &str = "QUERY_LIMIT";
+    /// User program killed for writing over the disk write limit
+    pub const DISK_LIMIT: &str = "DISK_LIMIT";
+    /// User program killed by `SIGSYS` from the syscall filter, which doesn't tell which
+    /// system call it made
+    pub const SECCOMP: &str = "SECCOMP";
+    /// User program killed for a system call the syscall filter held, followed by its name,
+    /// e.g. `SECCOMP_socket`
+    pub const SECCOMP_PREFIX: &str = "SECCOMP_";
+    /// User program killed for trying to start a subprocess
+    pub const SUBPROCESS: &str = "SUBPROCESS";
+    /// User program killed for reading its stdin past `RuntimeConfig::max_stdin_bytes`
+    pub const STDIN_LIMIT: &str = "STDIN_LIMIT";
+    /// User program killed by a signal, followed by its name, e.g. `RE_SIGNAL_SIGSEGV`
+    pub const RE_SIGNAL_PREFIX: &str = "RE_SIGNAL_";
+    /// User program exited with a non-zero code
+    pub const RE_NONZERO_EXIT: &str = "RE_NONZERO_EXIT";
+    /// User program exited normally without leaving its output file
+    pub const SE_NO_OUTPUT: &str = "SE_NO_OUTPUT";
+    /// Output differs from the answer according to the default checker
+    pub const WA_MISMATCH: &str = "WA_MISMATCH";
+    /// Output rejected by the checker
+    pub const WA_CHECKER: &str = "WA_CHECKER";
+    /// Output rejected by the checker for its format
+    pub const WA_PRESENTATION: &str = "WA_PRESENTATION";
+    /// Output given partial points by the checker
+    pub const CHECKER_POINTS: &str = "CHECKER_POINTS";
+    /// Checker reported its own failure
+    pub const SE_CHECKER_FAIL: &str = "SE_CHECKER_FAIL";
+    /// Checker killed by a signal, followed by its name, e.g. `SE_CHECKER_SIGNAL_SIGSEGV`
+    pub const SE_CHECKER_SIGNAL_PREFIX: &str = "SE_CHECKER_SIGNAL_";
+    /// Checker exited with a code testlib doesn't define
+    pub const SE_CHECKER_EXIT: &str = "SE_CHECKER_EXIT";
+    /// Checker ran out of the time of `RuntimeConfig::checker_limits`
+    pub const SE_CHECKER_TIMEOUT: &str = "SE_CHECKER_TIMEOUT";
+    /// Checker left a result file which can't be read, see `CheckerConfig::result_parser`
+    pub const SE_CHECKER_RESULT_FILE: &str = "SE_CHECKER_RESULT_FILE";
+    /// Output rejected by the interactor, see `CheckerConfig::interactor_policy`
+    pub const WA_INTERACTOR: &str = "WA_INTERACTOR";
+    /// Output given partial points by the interactor
+    pub const INTERACTOR_POINTS: &str = "INTERACTOR_POINTS";
+    /// The interaction ended before its last phase, see `run_interact_phases()`
+    pub const WA_UNFINISHED_INTERACTION: &str = "WA_UNFINISHED_INTERACTION";
+    /// Interactor reported its own failure
+    pub const SE_INTERACTOR_FAIL: &str = "SE_INTERACTOR_FAIL";
+    /// Interactor killed by a signal, followed by its name, e.g. `SE_INTERACTOR_SIGNAL_SIGSEGV`
+    pub const SE_INTERACTOR_SIGNAL_PREFIX: &str = "SE_INTERACTOR_SIGNAL_";
+    /// Interactor exited with a code testlib doesn't define
+    pub const SE_INTERACTOR_EXIT: &str = "SE_INTERACTOR_EXIT";
+    /// Test data doesn't match its checksums
+    pub const SE_TESTDATA_INTEGRITY: &str = "SE_TESTDATA_INTEGRITY";
+    /// Input or answer file over `RuntimeConfig::max_testdata_bytes`
+    pub const SE_TESTDATA_TOO_LARGE: &str = "SE_TESTDATA_TOO_LARGE";
+    /// Setup program failed or timed out, the user program was not run
+    pub const SE_SETUP_FAILED: &str = "SE_SETUP_FAILED";
+    /// Reference solution failed or timed out, see `JudgeConfig::reference`
+    pub const SE_REFERENCE_FAILED: &str = "SE_REFERENCE_FAILED";
+    /// A write of the judge found the filesystem full. The case was aborted, see
+    /// `JudgeCoreError::is_disk_full()`.
+    pub const SE_DISK_FULL: &str = "SE_DISK_FULL";
+    /// A process of the case survived `SIGKILL` and was left behind, e.g. stuck in
+    /// uninterruptible sleep. See `JudgeCoreError::ProcessUnkillable`.
+    pub const SE_UNKILLABLE: &str = "SE_UNKILLABLE";
+    /// A sandbox failed to set up its process, which never ran its program.
+    /// See `JudgeCoreError::SandboxSetupFailed`.
+    pub const SE_SANDBOX_SETUP: &str = "SE_SANDBOX_SETUP";
+    /// Case not run, the test set spent `RuntimeConfig::total_time_budget` before it, or
+    /// `TimeLimitExceeded` when it was cut short as the budget ran out
+    pub const TIME_BUDGET: &str = "TIME_BUDGET";
+    /// Case not run, left out by `TestsetHooks::cases`
+    pub const FILTERED_OUT: &str = "FILTERED_OUT";
+    /// The exit report of the user program or the interactor can't be read,
+    /// see `JudgeCoreError::MalformedExitReport`. Only reported by interactive judging.
+    pub const SE_EXIT_REPORT: &str = "SE_EXIT_REPORT";
+    /// The judger failed to judge the case, with its error as the message. Only reported by
+    /// the judger binaries, the library returns the error.
+    pub const SE_JUDGE_ERROR: &str = "SE_JUDGE_ERROR";
+}
+
+/// What a checker made of the output of the user program.
+#[derive(Debug, PartialEq, Serialize, Clone)]
+pub struct CheckerOutcome {
+    pub verdict: JudgeVerdict,
+    /// Share of the full score in `[0, 1]` the output is worth.
+    pub score: f64,
+    /// Extra detail on the verdict, empty when there is nothing to add.
+    pub message: String,
+    /// See `JudgeResultInfo::reason_code`.
+    pub reason_code: Option<String>,
+}
+
+impl CheckerOutcome {
+    /// Full score when accepted, none otherwise.
+    pub fn new(verdict: JudgeVerdict, message: String) -> Self {
+        let score = if verdict == JudgeVerdict::Accepted {
+            1.0
+        } else {
+            0.0
+        };
+        Self {
+            verdict,
+            score,
+            message,
+            reason_code: None,
+        }
+    }
+
+    pub fn with_reason_code(mut self, reason_code: impl Into<String>) -> Self {
+        self.reason_code = Some(reason_code.into());
+        self
+    }
+}
+
+/// `SIGSEGV` for 11, the number itself when it isn't a known signal.
+fn signal_name(signal: i32) -> String {
+    Signal::try_from(signal)
+        .map(|signal| format!("{:?}", signal))
+        .unwrap_or_else(|_| signal.to_string())
+}
+
+/// Which verdict wins when the verdicts of several cases make a single one,
+/// see `VerdictPriority::aggregate()`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct VerdictPriority {
+    /// From the verdict reported first to the one reported last.
+    /// Verdicts which are not listed come after all of them.
+    pub order: Vec<JudgeVerdict>,
+}
+
+impl Default for VerdictPriority {
+    /// The worst verdict, problems with the judge or the build before those of the run,
+    /// crashes before limits, and limits before the output being wrong.
+    fn default() -> Self {
+        Self {
+            order: vec![
+                JudgeVerdict::CompileError,
+                JudgeVerdict::SystemError,
+                JudgeVerdict::RestrictedFunction,
+                JudgeVerdict::RuntimeError,
+                JudgeVerdict::TimeLimitExceeded,
+                JudgeVerdict::IdlenessLimitExceeded,
+                JudgeVerdict::QueryLimitExceeded,
+                JudgeVerdict::DiskLimitExceeded,
+                JudgeVerdict::WrongAnswer,
+                JudgeVerdict::PartialScore,
+                JudgeVerdict::Skipped,
+                JudgeVerdict::Accepted,
+            ],
+        }
+    }
+}
+
+impl VerdictPriority {
+    /// The verdict of `verdicts` ranked first, the earliest one among equals.
+    /// `NotRun` verdicts count neither as a pass nor as a failure, they are only the verdict
+    /// when there is nothing else. `Accepted` when there is none.
+    pub fn aggregate<'a>(
+        &self,
+        verdicts: impl IntoIterator<Item = &'a JudgeVerdict>,
+    ) -> JudgeVerdict {
+        let mut not_run = false;
+        let mut first: Option<&JudgeVerdict> = None;
+        for verdict in verdicts {
+            if *verdict == JudgeVerdict::NotRun {
+                not_run = true;
+            } else if first.is_none_or(|first| self.rank(verdict) < self.rank(first)) {
+                first = Some(verdict);
+            }
+        }
+        match (first, not_run) {
+            (Some(first), _) => first.clone(),
+            (None, true) => JudgeVerdict::NotRun,
+            (None, false) => JudgeVerdict::Accepted,
+        }
+    }
+
+    fn rank(&self, verdict: &JudgeVerdict) -> usize {
+        self.order
+            .iter()
+            .position(|ranked| ranked == verdict)
+            .unwrap_or(self.order.len())
+    }
 }
 
 impl fmt::Display for JudgeVerdict {
@@ -40,13 +396,103 @@ pub fn get_run_time(raw_info: &SandboxExitInfo) -> Duration {
     utime.add(stime)
 }
 
+/// Peak memory of the user program, excluding what the sandbox inherited from the judger.
+///
+/// The kernel keeps the larger of the pre-`execve` and post-`execve` peaks,
+/// so the result may undercount by at most `baseline_rss`.
 pub fn get_max_mem(raw_info: &SandboxExitInfo) -> i64 {
     let rusage = &raw_info.resource_usage;
-    rusage.max_rss
+    (rusage.max_rss - raw_info.baseline_rss).max(0)
+}
+
+/// Bytes written to disk-backed filesystems, counted in blocks of 512 bytes by the kernel.
+pub fn get_disk_write_bytes(raw_info: &SandboxExitInfo) -> u64 {
+    raw_info.resource_usage.block_output_operations.max(0) as u64 * 512
+}
+
+/// `SandboxExitInfo::effective_limits` when `RuntimeConfig::verbose` is set or when the host
+/// lowered some of them.
+pub(crate) fn reported_effective_limits(
+    runtime: &RuntimeConfig,
+    raw_info: &SandboxExitInfo,
+) -> Option<EffectiveLimits> {
+    raw_info
+        .effective_limits
+        .clone()
+        .filter(|limits| runtime.verbose || !limits.lowered.is_empty())
+}
+
+/// Decides the verdict of the user program from how it ran, before its output is checked.
+///
+/// Set one in `RuntimeConfig::verdict_policy` to follow other conventions than
+/// `DefaultVerdictPolicy`, e.g. for some exit codes.
+pub trait VerdictPolicy: fmt::Debug + Send + Sync {
+    /// `None` when the output of the program should be checked.
+    fn classify(&self, raw_info: &SandboxExitInfo, runtime: &RuntimeConfig)
+        -> Option<JudgeVerdict>;
+}
+
+/// Limits first, CPU time before idleness and wall time, then the way the program exited.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DefaultVerdictPolicy;
+
+impl VerdictPolicy for DefaultVerdictPolicy {
+    fn classify(
+        &self,
+        raw_info: &SandboxExitInfo,
+        runtime: &RuntimeConfig,
+    ) -> Option<JudgeVerdict> {
+        classify_user_result(raw_info, runtime)
+    }
+}
+
+/// Maps exit codes of the user program to verdicts, e.g. when the exit code is the answer or a
+/// convention of the problem. `DefaultVerdictPolicy` decides first, a mapped exit code only
+/// replaces `RuntimeError` or checking the output, and programs killed by a signal are never
+/// mapped. A program accepted by its exit code gets the full score without being checked.
+#[derive(Debug, Clone, Default)]
+pub struct ExitCodeVerdictPolicy {
+    pub verdicts: HashMap<i32, JudgeVerdict>,
+}
+
+impl ExitCodeVerdictPolicy {
+    pub fn new(verdicts: HashMap<i32, JudgeVerdict>) -> Self {
+        Self { verdicts }
+    }
+}
+
+impl VerdictPolicy for ExitCodeVerdictPolicy {
+    fn classify(
+        &self,
+        raw_info: &SandboxExitInfo,
+        runtime: &RuntimeConfig,
+    ) -> Option<JudgeVerdict> {
+        let verdict = DefaultVerdictPolicy.classify(raw_info, runtime);
+        if raw_info.exit_signal != 0 {
+            return verdict;
+        }
+        match verdict {
+            None | Some(JudgeVerdict::RuntimeError) => {
+                self.verdicts.get(&raw_info.exit_code).cloned().or(verdict)
+            }
+            _ => verdict,
+        }
+    }
 }
 
+/// The verdict of the user program according to `RuntimeConfig::verdict_policy`.
 pub fn check_user_result(config: &JudgeConfig, raw_info: &SandboxExitInfo) -> Option<JudgeVerdict> {
-    if let Some(time_limit) = config.runtime.rlimit_configs.get_cpu_limit_duration() {
+    match &config.runtime.verdict_policy {
+        Some(policy) => policy.classify(raw_info, &config.runtime),
+        None => DefaultVerdictPolicy.classify(raw_info, &config.runtime),
+    }
+}
+
+fn classify_user_result(
+    raw_info: &SandboxExitInfo,
+    runtime: &RuntimeConfig,
+) -> Option<JudgeVerdict> {
+    if let Some(time_limit) = runtime.cpu_time_limit() {
         let run_time = get_run_time(raw_info);
         if run_time > time_limit {
             log::debug!("User program run time: {:?}", run_time);
@@ -55,6 +501,53 @@ pub fn check_user_result(config: &JudgeConfig, raw_info: &SandboxExitInfo) -> Op
         }
     }
 
+    if raw_info.idle_killed {
+        log::debug!("User program real time: {:?}", raw_info.real_time_cost);
+        return Some(JudgeVerdict::IdlenessLimitExceeded);
+    }
+
+    // What it wrote after the last poll of the sandbox is only known once it exited
+    if raw_info.disk_limit_killed
+        || runtime
+            .disk_write_limit
+            .is_some_and(|limit| get_disk_write_bytes(raw_info) > limit)
+    {
+        log::debug!(
+            "User program wrote {} bytes",
+            get_disk_write_bytes(raw_info)
+        );
+        return Some(JudgeVerdict::DiskLimitExceeded);
+    }
+
+    if let Some(wall_time_limit) = runtime.wall_time_limit {
+        if raw_info.real_time_cost > wall_time_limit {
+            log::debug!("User program real time: {:?}", raw_info.real_time_cost);
+            log::debug!("Wall time limit: {:?}", wall_time_limit);
+            return Some(JudgeVerdict::TimeLimitExceeded);
+        }
+    }
+
+    if raw_info.subprocess_killed {
+        log::debug!("User program tried to start a subprocess");
+        return Some(JudgeVerdict::RestrictedFunction);
+    }
+
+    if raw_info.stdin_limit_killed {
+        log::debug!("User program tried to read its stdin past the limit");
+        return Some(JudgeVerdict::RestrictedFunction);
+    }
+
+    if let Some(syscall) = &raw_info.blocked_syscall {
+        log::debug!("User program made the forbidden system call {}", syscall);
+        return Some(JudgeVerdict::RestrictedFunction);
+    }
+
+    // The seccomp filter kills with `SIGSYS`, which programs hardly get otherwise
+    if raw_info.exit_signal == Signal::SIGSYS as i32 {
+        log::debug!("User program killed by SIGSYS");
+        return Some(JudgeVerdict::RestrictedFunction);
+    }
+
     let exit_status = raw_info.exit_status;
     log::debug!("User program exit status: {}", exit_status);
     match exit_status {
@@ -63,13 +556,437 @@ pub fn check_user_result(config: &JudgeConfig, raw_info: &SandboxExitInfo) -> Op
     }
 }
 
-pub fn check_checker_result(raw_info: &SandboxExitInfo) -> JudgeVerdict {
-    // TODO: return verdict according to the checker output
-    let exit_status = raw_info.exit_status;
-    log::debug!("Checker program exit status: {}", exit_status);
-    match exit_status {
-        0 => JudgeVerdict::Accepted,
-        256 => JudgeVerdict::WrongAnswer,
-        _ => JudgeVerdict::SystemError,
+/// Extra detail on a verdict of `check_user_result()`, empty when there is nothing to add.
+pub fn describe_user_verdict(verdict: &JudgeVerdict) -> String {
+    match verdict {
+        // The fallback filter kills the process right away, which doesn't tell which one it
+        // was, `describe_user_result()` names the one the filter held
+        JudgeVerdict::RestrictedFunction => {
+            String::from("Killed by SIGSYS for making a system call forbidden by the sandbox")
+        }
+        _ => String::new(),
+    }
+}
+
+/// Like `describe_user_verdict()`, telling which restricted function it was when it is known.
+pub fn describe_user_result(raw_info: &SandboxExitInfo, verdict: &JudgeVerdict) -> String {
+    match verdict {
+        JudgeVerdict::RestrictedFunction if raw_info.subprocess_killed => {
+            String::from("Subprocess creation not allowed")
+        }
+        JudgeVerdict::RestrictedFunction if raw_info.stdin_limit_killed => {
+            String::from("Reading stdin past the limit not allowed")
+        }
+        JudgeVerdict::RestrictedFunction if raw_info.blocked_syscall.is_some() => format!(
+            "Killed for making the system call {}, forbidden by the sandbox",
+            raw_info.blocked_syscall.as_deref().unwrap_or_default()
+        ),
+        _ => describe_user_verdict(verdict),
+    }
+}
+
+/// Reason code of a verdict of `check_user_result()` or `check_output_file()`.
+pub fn user_reason_code(
+    config: &JudgeConfig,
+    raw_info: &SandboxExitInfo,
+    verdict: &JudgeVerdict,
+) -> Option<String> {
+    let reason_code = match verdict {
+        JudgeVerdict::TimeLimitExceeded => {
+            let cpu_exceeded = config
+                .runtime
+                .cpu_time_limit()
+                .is_some_and(|time_limit| get_run_time(raw_info) > time_limit);
+            if cpu_exceeded {
+                reason_code::TLE_CPU.to_owned()
+            } else {
+                reason_code::TLE_WALL.to_owned()
+            }
+        }
+        JudgeVerdict::IdlenessLimitExceeded => reason_code::IDLE.to_owned(),
+        JudgeVerdict::DiskLimitExceeded => reason_code::DISK_LIMIT.to_owned(),
+        JudgeVerdict::RestrictedFunction if raw_info.subprocess_killed => {
+            reason_code::SUBPROCESS.to_owned()
+        }
+        JudgeVerdict::RestrictedFunction if raw_info.stdin_limit_killed => {
+            reason_code::STDIN_LIMIT.to_owned()
+        }
+        JudgeVerdict::RestrictedFunction => match &raw_info.blocked_syscall {
+            Some(syscall) => format!("{}{}", reason_code::SECCOMP_PREFIX, syscall),
+            None => reason_code::SECCOMP.to_owned(),
+        },
+        JudgeVerdict::RuntimeError if raw_info.exit_signal != 0 => format!(
+            "{}{}",
+            reason_code::RE_SIGNAL_PREFIX,
+            signal_name(raw_info.exit_signal)
+        ),
+        JudgeVerdict::RuntimeError => reason_code::RE_NONZERO_EXIT.to_owned(),
+        JudgeVerdict::SystemError => reason_code::SE_NO_OUTPUT.to_owned(),
+        _ => return None,
+    };
+    Some(reason_code)
+}
+
+/// Check that the user program left its output file, along with a message for the result.
+///
+/// When it is missing, a crash of the program is the cause (`RuntimeError`),
+/// otherwise the judge itself is not set up right (`SystemError`).
+pub fn check_output_file(
+    config: &JudgeConfig,
+    raw_info: &SandboxExitInfo,
+) -> Option<(JudgeVerdict, String)> {
+    let output_file_path = match &config.program.output_file_path {
+        Some(output_file_path) => output_file_path,
+        None => {
+            return Some((
+                JudgeVerdict::SystemError,
+                String::from("The output of the program is not captured"),
+            ))
+        }
+    };
+    if output_file_path.exists() {
+        return None;
+    }
+    log::debug!("Output file {:?} not found", output_file_path);
+    if raw_info.exit_status != 0 {
+        Some((
+            JudgeVerdict::RuntimeError,
+            format!(
+                "Output file {:?} not found after the program exited abnormally",
+                output_file_path
+            ),
+        ))
+    } else {
+        Some((
+            JudgeVerdict::SystemError,
+            format!(
+                "Output file {:?} not found after the program exited normally",
+                output_file_path
+            ),
+        ))
+    }
+}
+
+/// `SystemError` when the checker went past the time of `limits`,
+/// whatever it exited with once killed.
+pub fn check_checker_timeout(
+    raw_info: &SandboxExitInfo,
+    limits: &Limits,
+) -> Option<CheckerOutcome> {
+    if !exceeds_time_limits(raw_info, limits) {
+        return None;
+    }
+    log::warn!(
+        "Checker timed out after {:?} of CPU time, {:?} of real time",
+        get_run_time(raw_info),
+        raw_info.real_time_cost
+    );
+    Some(
+        CheckerOutcome::new(JudgeVerdict::SystemError, "Checker timed out".to_string())
+            .with_reason_code(reason_code::SE_CHECKER_TIMEOUT),
+    )
+}
+
+/// Why the setup program failed, see `JudgeConfig::setup`. `None` when it exited with 0.
+pub fn check_setup_result(raw_info: &SandboxExitInfo, limits: &Limits) -> Option<String> {
+    check_trusted_result("Setup program", raw_info, limits)
+}
+
+/// Why the reference solution failed, see `JudgeConfig::reference`. `None` when it exited with 0.
+pub fn check_reference_result(raw_info: &SandboxExitInfo, limits: &Limits) -> Option<String> {
+    check_trusted_result("Reference solution", raw_info, limits)
+}
+
+fn check_trusted_result(name: &str, raw_info: &SandboxExitInfo, limits: &Limits) -> Option<String> {
+    let message = if exceeds_time_limits(raw_info, limits) {
+        format!("{} timed out", name)
+    } else if raw_info.exit_signal != 0 {
+        format!(
+            "{} killed by signal {}",
+            name,
+            signal_name(raw_info.exit_signal)
+        )
+    } else if raw_info.exit_code != 0 {
+        format!("{} exited with code {}", name, raw_info.exit_code)
+    } else {
+        return None;
+    };
+    log::warn!("{}", message);
+    Some(message)
+}
+
+fn exceeds_time_limits(raw_info: &SandboxExitInfo, limits: &Limits) -> bool {
+    let cpu_exceeded = limits
+        .cpu_time
+        .is_some_and(|cpu_time| get_run_time(raw_info) > cpu_time);
+    let wall_exceeded = limits
+        .wall_time
+        .is_some_and(|wall_time| raw_info.real_time_cost > wall_time);
+    cpu_exceeded || wall_exceeded
+}
+
+/// Map the exit of a checker to its outcome.
+///
+/// Only the exit codes defined by testlib are treated as the checker's opinion on the
+/// contestant's output. A checker killed by a signal, or exiting with any other code,
+/// is considered broken and reported as `SystemError` so that operators can notice it.
+/// The exit code alone doesn't tell how many points a `PartialScore` is worth,
+/// so its score is left at zero.
+pub fn check_checker_result(raw_info: &SandboxExitInfo) -> CheckerOutcome {
+    testlib_exit_outcome(raw_info, "Checker", &CHECKER_REASON_CODES)
+}
+
+/// `check_checker_result()` for an interactor, with reason codes of its own.
+pub fn check_interactor_result(raw_info: &SandboxExitInfo) -> CheckerOutcome {
+    testlib_exit_outcome(raw_info, "Interactor", &INTERACTOR_REASON_CODES)
+}
+
+/// The reason codes of a testlib program whose exit tells the verdict.
+struct TestlibReasonCodes {
+    wrong_answer: &'static str,
+    points: &'static str,
+    fail: &'static str,
+    signal_prefix: &'static str,
+    exit: &'static str,
+}
+
+const CHECKER_REASON_CODES: TestlibReasonCodes = TestlibReasonCodes {
+    wrong_answer: reason_code::WA_CHECKER,
+    points: reason_code::CHECKER_POINTS,
+    fail: reason_code::SE_CHECKER_FAIL,
+    signal_prefix: reason_code::SE_CHECKER_SIGNAL_PREFIX,
+    exit: reason_code::SE_CHECKER_EXIT,
+};
+
+const INTERACTOR_REASON_CODES: TestlibReasonCodes = TestlibReasonCodes {
+    wrong_answer: reason_code::WA_INTERACTOR,
+    points: reason_code::INTERACTOR_POINTS,
+    fail: reason_code::SE_INTERACTOR_FAIL,
+    signal_prefix: reason_code::SE_INTERACTOR_SIGNAL_PREFIX,
+    exit: reason_code::SE_INTERACTOR_EXIT,
+};
+
+fn testlib_exit_outcome(
+    raw_info: &SandboxExitInfo,
+    program: &str,
+    codes: &TestlibReasonCodes,
+) -> CheckerOutcome {
+    log::debug!("{} program exit status: {}", program, raw_info.exit_status);
+    if raw_info.exit_signal != 0 {
+        log::warn!("{} killed by signal {}", program, raw_info.exit_signal);
+        return CheckerOutcome::new(
+            JudgeVerdict::SystemError,
+            format!("{} killed by signal {}", program, raw_info.exit_signal),
+        )
+        .with_reason_code(format!(
+            "{}{}",
+            codes.signal_prefix,
+            signal_name(raw_info.exit_signal)
+        ));
+    }
+
+    match raw_info.exit_code {
+        TESTLIB_EXIT_OK => CheckerOutcome::new(JudgeVerdict::Accepted, String::new()),
+        TESTLIB_EXIT_WA => CheckerOutcome::new(JudgeVerdict::WrongAnswer, String::new())
+            .with_reason_code(codes.wrong_answer),
+        TESTLIB_EXIT_PE => CheckerOutcome::new(JudgeVerdict::WrongAnswer, String::new())
+            .with_reason_code(reason_code::WA_PRESENTATION),
+        TESTLIB_EXIT_POINTS => CheckerOutcome::new(JudgeVerdict::PartialScore, String::new())
+            .with_reason_code(codes.points),
+        TESTLIB_EXIT_FAIL => {
+            log::warn!("{} reported failure", program);
+            CheckerOutcome::new(
+                JudgeVerdict::SystemError,
+                format!("{} reported failure", program),
+            )
+            .with_reason_code(codes.fail)
+        }
+        exit_code => {
+            log::warn!("{} exited with unrecognized code {}", program, exit_code);
+            CheckerOutcome::new(
+                JudgeVerdict::SystemError,
+                format!("{} exited with unrecognized code {}", program, exit_code),
+            )
+            .with_reason_code(codes.exit)
+        }
+    }
+}
+
+/// Decides the verdict from how the interactor exited, see `CheckerConfig::interactor_policy`.
+pub trait InteractorExitPolicy: fmt::Debug + Send + Sync {
+    /// `None` leaves the verdict to the checker.
+    ///
+    /// An `Accepted` outcome does too when a checker is configured, so that it has the last
+    /// word on the transcript. Any other outcome is the verdict of the case.
+    fn outcome(&self, raw_info: &SandboxExitInfo) -> Option<CheckerOutcome>;
+}
+
+/// The exit codes of testlib interactors, see `check_interactor_result()`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TestlibInteractorPolicy;
+
+impl InteractorExitPolicy for TestlibInteractorPolicy {
+    fn outcome(&self, raw_info: &SandboxExitInfo) -> Option<CheckerOutcome> {
+        Some(check_interactor_result(raw_info))
+    }
+}
+
+/// Reads the verdict a checker wrote to its result file, see `CheckerConfig::result_parser`.
+pub trait CheckerResultParser: fmt::Debug + Send + Sync {
+    /// Passed to the checker right after the standard file paths, e.g. to ask for the format.
+    fn args(&self) -> Vec<String> {
+        vec![]
     }
+
+    /// What is wrong with `content` when it is not a result.
+    fn parse(&self, content: &str) -> Result<CheckerOutcome, String>;
+}
+
+/// A comparison written in Rust, taking the paths of the input, the output and the answer,
+/// see `CheckerConfig::custom_comparison`.
+///
+/// It must be deterministic, a rejudge reaching another verdict would be a mess to explain.
+#[derive(Clone)]
+pub struct CustomComparison(Arc<CompareFn>);
+
+type CompareFn = dyn Fn(&Path, &Path, &Path) -> CheckerOutcome + Send + Sync;
+
+impl CustomComparison {
+    pub fn new(
+        compare: impl Fn(&Path, &Path, &Path) -> CheckerOutcome + Send + Sync + 'static,
+    ) -> Self {
+        Self(Arc::new(compare))
+    }
+
+    pub fn compare(&self, input: &Path, output: &Path, answer: &Path) -> CheckerOutcome {
+        (self.0)(input, output, answer)
+    }
+}
+
+impl fmt::Debug for CustomComparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("CustomComparison")
+    }
+}
+
+/// The XML result of testlib checkers run with `-appes`, as in
+/// `<result outcome = "points" points = "0.5">message</result>`.
+///
+/// The points of a `points` outcome are taken as the share of the full score.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TestlibXmlResultParser;
+
+impl CheckerResultParser for TestlibXmlResultParser {
+    fn args(&self) -> Vec<String> {
+        vec![String::from("-appes")]
+    }
+
+    fn parse(&self, content: &str) -> Result<CheckerOutcome, String> {
+        let start = content.find("<result").ok_or("no <result> element found")?;
+        let element = &content[start + "<result".len()..];
+        let tag_end = element.find('>').ok_or("unterminated <result> tag")?;
+        let (attributes, body) = element.split_at(tag_end);
+        let body = &body[1..];
+        let message = unescape_xml(&body[..body.find("</result>").unwrap_or(body.len())]);
+
+        let outcome = xml_attribute(attributes, "outcome").ok_or("no outcome attribute found")?;
+        let outcome = match outcome {
+            "accepted" => CheckerOutcome::new(JudgeVerdict::Accepted, message),
+            "wrong-answer" => CheckerOutcome::new(JudgeVerdict::WrongAnswer, message)
+                .with_reason_code(reason_code::WA_CHECKER),
+            "presentation-error" => CheckerOutcome::new(JudgeVerdict::WrongAnswer, message)
+                .with_reason_code(reason_code::WA_PRESENTATION),
+            "points" | "partially-correct" => {
+                let points = xml_attribute(attributes, "points").ok_or("no points attribute")?;
+                let points = points
+                    .parse::<f64>()
+                    .map_err(|_| format!("invalid points {:?}", points))?;
+                let mut outcome = CheckerOutcome::new(JudgeVerdict::PartialScore, message)
+                    .with_reason_code(reason_code::CHECKER_POINTS);
+                outcome.score = points.clamp(0.0, 1.0);
+                outcome
+            }
+            "fail" => CheckerOutcome::new(JudgeVerdict::SystemError, message)
+                .with_reason_code(reason_code::SE_CHECKER_FAIL),
+            outcome => return Err(format!("unknown outcome {:?}", outcome)),
+        };
+        Ok(outcome)
+    }
+}
+
+/// The value of `name` in the attributes of an XML tag, spaces around `=` allowed.
+fn xml_attribute<'a>(attributes: &'a str, name: &str) -> Option<&'a str> {
+    let mut rest = attributes;
+    while let Some(position) = rest.find(name) {
+        let preceded_by_space = !rest[..position].ends_with(|c: char| !c.is_whitespace());
+        rest = &rest[position + name.len()..];
+        let value = rest.trim_start();
+        if !preceded_by_space || !value.starts_with('=') {
+            continue;
+        }
+        let value = value[1..].trim_start();
+        let quote = value.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+        let value = &value[1..];
+        return value.find(quote).map(|end| &value[..end]);
+    }
+    None
+}
+
+fn unescape_xml(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// A verdict on the first line, optionally followed by the share of the score for `PC`,
+/// and the message on the next lines, as in `PC 0.5`.
+///
+/// The verdict is one of `AC`, `WA`, `PE`, `PC` and `FAIL`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PlainResultParser;
+
+impl CheckerResultParser for PlainResultParser {
+    fn parse(&self, content: &str) -> Result<CheckerOutcome, String> {
+        let (first_line, message) = content.split_once('\n').unwrap_or((content, ""));
+        let message = message.trim_end().to_owned();
+        let mut words = first_line.split_whitespace();
+        let outcome = match words.next().ok_or("no verdict found")? {
+            "AC" => CheckerOutcome::new(JudgeVerdict::Accepted, message),
+            "WA" => CheckerOutcome::new(JudgeVerdict::WrongAnswer, message)
+                .with_reason_code(reason_code::WA_CHECKER),
+            "PE" => CheckerOutcome::new(JudgeVerdict::WrongAnswer, message)
+                .with_reason_code(reason_code::WA_PRESENTATION),
+            "PC" => {
+                let score = words.next().ok_or("no score found")?;
+                let score = score
+                    .parse::<f64>()
+                    .map_err(|_| format!("invalid score {:?}", score))?;
+                let mut outcome = CheckerOutcome::new(JudgeVerdict::PartialScore, message)
+                    .with_reason_code(reason_code::CHECKER_POINTS);
+                outcome.score = score.clamp(0.0, 1.0);
+                outcome
+            }
+            "FAIL" => CheckerOutcome::new(JudgeVerdict::SystemError, message)
+                .with_reason_code(reason_code::SE_CHECKER_FAIL),
+            verdict => return Err(format!("unknown verdict {:?}", verdict)),
+        };
+        Ok(outcome)
+    }
+}
+
+/// The outcome a checker wrote to `path`, `SystemError` when it can't be read.
+pub fn read_checker_result_file(parser: &dyn CheckerResultParser, path: &Path) -> CheckerOutcome {
+    let parsed = fs::read_to_string(path)
+        .map_err(|err| err.to_string())
+        .and_then(|content| parser.parse(&content));
+    parsed.unwrap_or_else(|err| {
+        log::warn!("Checker result file {:?} can't be read: {}", path, err);
+        CheckerOutcome::new(
+            JudgeVerdict::SystemError,
+            format!("Checker result file can't be read: {}", err),
+        )
+        .with_reason_code(reason_code::SE_CHECKER_RESULT_FILE)
+    })
 }
@@ -0,0 +1,281 @@
+//! The stdin of the user program, fed through a pipe to count what it reads.
+
+use crate::{error::JudgeCoreError, sandbox::pidfd_open};
+use nix::{
+    errno::Errno,
+    fcntl::{fcntl, FcntlArg, FdFlag, OFlag},
+    unistd::pipe,
+};
+use std::{
+    fs::File,
+    io::{self, Read},
+    os::fd::{AsRawFd, OwnedFd, RawFd},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+/// Bytes copied to the pipe at once.
+const FEED_CHUNK_LEN: usize = 64 * 1024;
+
+/// How often the feeder checks whether it should stop, or whether the program is blocked
+/// reading past `RuntimeConfig::max_stdin_bytes`.
+const FEED_POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// Feeds the input of the user program to its stdin through a pipe, counting what it reads.
+///
+/// A thread copies the input to the pipe and closes it at the end of the input, the program
+/// reading it like a file to its end. What the program read is what was copied less what is
+/// left in the pipe once it exited.
+///
+/// With `max_bytes`, at most that many bytes are copied. When the input is longer, the pipe
+/// is kept open and the program is killed once it is blocked reading past them, which is only
+/// told apart on Linux, from `/proc/<pid>/syscall`.
+pub(crate) struct StdinFeeder {
+    /// The end the program reads, also kept by the judger to count what is left in the pipe
+    read_end: OwnedFd,
+    /// Taken by the thread once started
+    feed: Option<Feed>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<io::Result<FeedOutcome>>>,
+}
+
+/// What the thread of a `StdinFeeder` needs.
+struct Feed {
+    input: File,
+    write_end: OwnedFd,
+    max_bytes: Option<u64>,
+}
+
+#[derive(Default)]
+struct FeedOutcome {
+    written: u64,
+    limit_killed: bool,
+}
+
+/// The end of feeding the stdin of a program, see `StdinFeeder::finish()`.
+pub(crate) struct StdinUsage {
+    /// See `JudgeResultInfo::stdin_bytes_read`
+    pub(crate) bytes_read: u64,
+    /// The program was killed for reading past `RuntimeConfig::max_stdin_bytes`
+    pub(crate) limit_killed: bool,
+}
+
+impl StdinFeeder {
+    pub(crate) fn new(input: File, max_bytes: Option<u64>) -> Result<Self, JudgeCoreError> {
+        let (read_end, write_end) = pipe()?;
+        // Only the stdin of the program is left open in it, as what `dup2()` makes of
+        // `read_end`, for the end of the input to close the pipe
+        for fd in [&read_end, &write_end] {
+            fcntl(fd.as_raw_fd(), FcntlArg::F_SETFD(FdFlag::FD_CLOEXEC))?;
+        }
+        let flags = OFlag::from_bits_truncate(fcntl(write_end.as_raw_fd(), FcntlArg::F_GETFL)?);
+        fcntl(
+            write_end.as_raw_fd(),
+            FcntlArg::F_SETFL(flags | OFlag::O_NONBLOCK),
+        )?;
+        Ok(Self {
+            read_end,
+            feed: Some(Feed {
+                input,
+                write_end,
+                max_bytes,
+            }),
+            stop: Arc::new(AtomicBool::new(false)),
+            thread: None,
+        })
+    }
+
+    /// The fd to redirect the stdin of the program from.
+    pub(crate) fn stdin_fd(&self) -> RawFd {
+        self.read_end.as_raw_fd()
+    }
+
+    /// Start feeding the program of `pid`, spawned with `stdin_fd()` as its stdin and not
+    /// reaped yet.
+    pub(crate) fn start(&mut self, pid: i32) -> Result<(), JudgeCoreError> {
+        let mut feed = match self.feed.take() {
+            Some(feed) => feed,
+            None => return Ok(()),
+        };
+        // Only needed to kill the program, opened while its pid can't be reused
+        let pidfd = match feed.max_bytes {
+            Some(_) => match pidfd_open(pid) {
+                Ok(pidfd) => Some(pidfd),
+                Err(e) => {
+                    log::warn!(
+                        "No pidfd for pid={}, its stdin limit is not enforced: {}",
+                        pid,
+                        e
+                    );
+                    feed.max_bytes = None;
+                    None
+                }
+            },
+            None => None,
+        };
+        let stop = self.stop.clone();
+        self.thread = Some(
+            thread::Builder::new()
+                .name(format!("stdin-{}", pid))
+                .spawn(move || feed.run(pid, pidfd, &stop))?,
+        );
+        Ok(())
+    }
+
+    /// Stop feeding the program, which exited, and tell how much of its stdin it read.
+    pub(crate) fn finish(mut self) -> Result<StdinUsage, JudgeCoreError> {
+        let outcome = self.stop_thread()?;
+        Ok(StdinUsage {
+            bytes_read: outcome
+                .written
+                .saturating_sub(unread_bytes(self.read_end.as_raw_fd())?),
+            limit_killed: outcome.limit_killed,
+        })
+    }
+
+    fn stop_thread(&mut self) -> Result<FeedOutcome, JudgeCoreError> {
+        self.stop.store(true, Ordering::Relaxed);
+        match self.thread.take() {
+            Some(thread) => match thread.join() {
+                Ok(outcome) => Ok(outcome?),
+                Err(_) => Err(JudgeCoreError::AnyhowError(anyhow::anyhow!(
+                    "The thread feeding stdin panicked"
+                ))),
+            },
+            None => Ok(FeedOutcome::default()),
+        }
+    }
+}
+
+impl Drop for StdinFeeder {
+    fn drop(&mut self) {
+        let _ = self.stop_thread();
+    }
+}
+
+impl Feed {
+    fn run(
+        mut self,
+        pid: i32,
+        pidfd: Option<OwnedFd>,
+        stop: &AtomicBool,
+    ) -> io::Result<FeedOutcome> {
+        let mut outcome = FeedOutcome::default();
+        let mut buf = vec![0u8; FEED_CHUNK_LEN];
+        let (mut begin, mut end) = (0, 0);
+        while !stop.load(Ordering::Relaxed) {
+            if begin == end {
+                let room = self.max_bytes.map_or(FEED_CHUNK_LEN, |max_bytes| {
+                    (max_bytes - outcome.written).min(FEED_CHUNK_LEN as u64) as usize
+                });
+                if room == 0 {
+                    // One more byte tells whether the input goes on past the limit
+                    if self.input.read(&mut buf[..1])? > 0 {
+                        outcome.limit_killed = self.watch_past_limit(pid, pidfd.as_ref(), stop);
+                    }
+                    break;
+                }
+                end = self.input.read(&mut buf[..room])?;
+                begin = 0;
+                if end == 0 {
+                    break;
+                }
+            }
+            if !poll_fd(self.write_end.as_raw_fd(), libc::POLLOUT) {
+                continue;
+            }
+            match nix::unistd::write(&self.write_end, &buf[begin..end]) {
+                Ok(written) => {
+                    begin += written;
+                    outcome.written += written as u64;
+                }
+                Err(Errno::EAGAIN | Errno::EINTR) => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        // Dropping `self` closes the pipe, the program reads the end of the input
+        Ok(outcome)
+    }
+
+    /// Wait for the program to be blocked reading past the limit, to kill it then.
+    ///
+    /// Returns whether it was killed, not when it exited before.
+    fn watch_past_limit(&self, pid: i32, pidfd: Option<&OwnedFd>, stop: &AtomicBool) -> bool {
+        let pidfd = match pidfd {
+            Some(pidfd) => pidfd,
+            None => return false,
+        };
+        while !stop.load(Ordering::Relaxed) {
+            let drained = unread_bytes(self.write_end.as_raw_fd()).is_ok_and(|unread| unread == 0);
+            if drained && blocked_reading_stdin(pid) {
+                log::warn!("Process pid={} tried to read its stdin past the limit", pid);
+                return pidfd_kill(pidfd);
+            }
+            thread::sleep(FEED_POLL_INTERVAL);
+        }
+        false
+    }
+}
+
+/// Whether `fd` is ready for `events` within `FEED_POLL_INTERVAL`.
+fn poll_fd(fd: RawFd, events: libc::c_short) -> bool {
+    let mut poll_fd = libc::pollfd {
+        fd,
+        events,
+        revents: 0,
+    };
+    let timeout = FEED_POLL_INTERVAL.as_millis() as libc::c_int;
+    unsafe { libc::poll(&mut poll_fd, 1, timeout) == 1 && poll_fd.revents & events != 0 }
+}
+
+/// Bytes in the pipe of `fd` which were not read yet, from either end.
+pub(crate) fn unread_bytes(fd: RawFd) -> io::Result<u64> {
+    let mut unread: libc::c_int = 0;
+    if unsafe { libc::ioctl(fd, libc::FIONREAD, &mut unread) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(unread.max(0) as u64)
+}
+
+/// Whether the process is blocked in a `read()` or `readv()` of its stdin.
+#[cfg(target_os = "linux")]
+fn blocked_reading_stdin(pid: i32) -> bool {
+    let syscall = match std::fs::read_to_string(format!("/proc/{}/syscall", pid)) {
+        Ok(syscall) => syscall,
+        Err(_) => return false,
+    };
+    let mut fields = syscall.split_whitespace();
+    let number = fields
+        .next()
+        .and_then(|number| number.parse::<libc::c_long>().ok());
+    let fd = fields.next();
+    matches!(number, Some(libc::SYS_read | libc::SYS_readv)) && fd == Some("0x0")
+}
+
+#[cfg(not(target_os = "linux"))]
+fn blocked_reading_stdin(_pid: i32) -> bool {
+    false
+}
+
+#[cfg(target_os = "linux")]
+fn pidfd_kill(pidfd: &OwnedFd) -> bool {
+    let res = unsafe {
+        libc::syscall(
+            libc::SYS_pidfd_send_signal,
+            pidfd.as_raw_fd(),
+            libc::SIGKILL,
+            std::ptr::null::<libc::siginfo_t>(),
+            0,
+        )
+    };
+    res == 0
+}
+
+#[cfg(not(target_os = "linux"))]
+fn pidfd_kill(_pidfd: &OwnedFd) -> bool {
+    false
+}
@@ -0,0 +1,145 @@
+use std::{fs, fs::File, os::unix::io::AsRawFd, path::PathBuf};
+
+use anyhow::anyhow;
+use serde_derive::Serialize;
+
+use crate::error::JudgeCoreError;
+use crate::run::executor::Executor;
+use crate::run::sandbox::{ExecutorSandbox, SandboxBackend};
+use crate::sandbox::RlimitConfigs;
+
+use super::common::run_judge_with;
+use super::result::{JudgeResultInfo, JudgeVerdict};
+use super::{JudgeConfig, TestdataConfig};
+
+/// Random tests for a submission, see `run_stress()`.
+#[derive(Debug, Clone, Serialize)]
+pub struct StressConfig {
+    /// Writes an input to stdout, given the seed as its only argument
+    pub generator: Executor,
+    /// A trusted solution, writing the answer of an input
+    pub reference: Executor,
+    /// Limits of the generator and the reference
+    pub rlimit_configs: RlimitConfigs,
+    /// Seed of the first iteration, each next one gets the following seed
+    pub seed: u64,
+    pub iterations: usize,
+    /// Where the input and answer of the current iteration are written,
+    /// relative to `RuntimeConfig::scratch_dir` unless absolute
+    pub work_dir: PathBuf,
+    /// Where the input of the failing iteration is saved
+    pub failing_input_path: PathBuf,
+}
+
+/// The first iteration of `run_stress()` the submission did not pass.
+#[derive(Debug, Clone, Serialize)]
+pub struct StressFailure {
+    pub iteration: usize,
+    pub seed: u64,
+    pub result: JudgeResultInfo,
+}
+
+/// Judge the program of `config` on inputs made by a generator, against the answers of a
+/// reference solution, until it fails.
+///
+/// The test data of `config` is replaced by the files of each iteration, its checker and
+/// runtime are used as is. A failing input is copied to `failing_input_path`.
+/// The generator or the reference failing is an error, not a failure of the submission.
+pub fn run_stress(
+    config: &JudgeConfig,
+    stress: &StressConfig,
+) -> Result<Option<StressFailure>, JudgeCoreError> {
+    run_stress_with::<ExecutorSandbox>(config, stress)
+}
+
+/// `run_stress()` with every program in sandboxes of `S`.
+pub fn run_stress_with<S: SandboxBackend>(
+    config: &JudgeConfig,
+    stress: &StressConfig,
+) -> Result<Option<StressFailure>, JudgeCoreError> {
+    let work_dir = config.runtime.scratch_dir.join(&stress.work_dir);
+    fs::create_dir_all(&work_dir)?;
+    let input_file_path = work_dir.join("stress.in");
+    let answer_file_path = work_dir.join("stress.ans");
+    let mut config = config.clone();
+    config.test_data = TestdataConfig {
+        input_file_path: input_file_path.clone(),
+        answer_file_path: answer_file_path.clone(),
+        extra_answer_file_paths: vec![],
+        input_sha256: None,
+        answer_sha256: None,
+        case_index: None,
+    };
+
+    for iteration in 0..stress.iterations {
+        let seed = stress.seed.wrapping_add(iteration as u64);
+        log::debug!("Stress iteration {} with seed {}", iteration, seed);
+
+        let mut generator = stress.generator.clone();
+        generator.set_additional_args(vec![String::from(""), seed.to_string()]);
+        run_trusted::<S>(
+            &config,
+            stress,
+            "Generator",
+            generator,
+            None,
+            &input_file_path,
+        )?;
+        run_trusted::<S>(
+            &config,
+            stress,
+            "Reference",
+            stress.reference.clone(),
+            Some(&input_file_path),
+            &answer_file_path,
+        )?;
+
+        let result = run_judge_with::<S>(&config)?;
+        if result.verdict != JudgeVerdict::Accepted {
+            log::info!(
+                "Stress iteration {} with seed {} got {}",
+                iteration,
+                seed,
+                result.verdict
+            );
+            fs::copy(&input_file_path, &stress.failing_input_path)?;
+            return Ok(Some(StressFailure {
+                iteration,
+                seed,
+                result,
+            }));
+        }
+    }
+    Ok(None)
+}
+
+/// Run the generator or the reference, which must exit normally.
+fn run_trusted<S: SandboxBackend>(
+    config: &JudgeConfig,
+    stress: &StressConfig,
+    name: &str,
+    executor: Executor,
+    input_file_path: Option<&PathBuf>,
+    output_file_path: &PathBuf,
+) -> Result<(), JudgeCoreError> {
+    let input_file = input_file_path.map(File::open).transpose()?;
+    let output_file = File::create(output_file_path)?;
+    let mut sandbox = S::create(
+        executor,
+        stress.rlimit_configs.clone(),
+        input_file.as_ref().map(|file| file.as_raw_fd()),
+        Some(output_file.as_raw_fd()),
+        false,
+    )?;
+    sandbox.set_seccomp_mode(config.runtime.seccomp_mode);
+    sandbox.spawn()?;
+    let exit_info = sandbox.wait()?;
+    if exit_info.exit_status != 0 {
+        return Err(JudgeCoreError::AnyhowError(anyhow!(
+            "{} exited with status {}",
+            name,
+            exit_info.exit_status
+        )));
+    }
+    Ok(())
+}
@@ -0,0 +1,378 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    marker::PhantomData,
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+    sync::mpsc::Sender,
+    time::Duration,
+};
+
+use anyhow::anyhow;
+use serde_derive::Serialize;
+
+use crate::error::{path_not_exist, JudgeCoreError};
+use crate::run::sandbox::{ExecutorSandbox, SandboxBackend};
+
+use super::builder::{JudgeBuilder, JudgeType};
+use super::common::{run_judge_with, warm_up};
+use super::result::{reason_code, JudgeResultInfo, JudgeVerdict, VerdictPriority};
+use super::{JudgeConfig, TestdataConfig};
+
+/// What to do with a test case, decided by `TestsetHooks::before_case`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaseAction {
+    Run,
+    /// Do not run this case, but go on with the next ones
+    Skip,
+    /// Stop running the whole test set
+    Abort,
+}
+
+/// Which cases of a test set to run, see `TestsetHooks::cases`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CaseFilter {
+    Indices(BTreeSet<usize>),
+    /// e.g. `3..=5` for cases 3, 4 and 5
+    Range(RangeInclusive<usize>),
+}
+
+impl CaseFilter {
+    pub fn contains(&self, case_index: usize) -> bool {
+        match self {
+            Self::Indices(indices) => indices.contains(&case_index),
+            Self::Range(range) => range.contains(&case_index),
+        }
+    }
+}
+
+pub type BeforeCaseHook<'a> = Box<dyn Fn(usize, &TestdataConfig) -> CaseAction + 'a>;
+pub type AfterCaseHook<'a> = Box<dyn Fn(usize, &JudgeResultInfo) + 'a>;
+
+/// Sent through `TestsetHooks::progress` once a case is judged.
+#[derive(Debug, Clone, Serialize)]
+pub struct JudgeProgress {
+    pub case_index: usize,
+    /// Number of cases in the test set, skipped ones included
+    pub total: usize,
+    pub verdict: JudgeVerdict,
+}
+
+/// Optional callbacks around each test case, leave them `None` to run every case.
+///
+/// Hooks are called on the thread running the test set, in the order of the cases.
+/// They borrow from the caller, so they don't need to be `Send` nor `Sync`.
+#[derive(Default)]
+pub struct TestsetHooks<'a> {
+    /// Only run these cases, e.g. to investigate a failing one. The other ones are reported as
+    /// `NotRun`, `before_case` is not called for them.
+    pub cases: Option<CaseFilter>,
+    pub before_case: Option<BeforeCaseHook<'a>>,
+    pub after_case: Option<AfterCaseHook<'a>>,
+    /// Receives a `JudgeProgress` for every judged case, right after `after_case`
+    /// and in the same order as the returned results. The channel is unbounded
+    /// so judging never waits for the receiver, a dropped receiver is ignored.
+    pub progress: Option<Sender<JudgeProgress>>,
+}
+
+/// A `NotRun` result, the case being left out by the operator.
+fn not_run(message: String, reason_code: &str) -> JudgeResultInfo {
+    JudgeResultInfo {
+        verdict: JudgeVerdict::NotRun,
+        ..JudgeResultInfo::system_error(message, reason_code)
+    }
+}
+
+/// The verdict of a whole test set from the results of its cases, ranked by `priority`.
+pub fn aggregate_verdict(results: &[JudgeResultInfo], priority: &VerdictPriority) -> JudgeVerdict {
+    priority.aggregate(results.iter().map(|result| &result.verdict))
+}
+
+/// Run every test case of `judge` with `run_judge`.
+///
+/// Results are returned in the order of the cases, cases skipped by `TestsetHooks::before_case`
+/// are left out. Cases filtered out by `TestsetHooks::cases` are reported as `NotRun` with the
+/// `FILTERED_OUT` reason code. Once the cases took `RuntimeConfig::total_time_budget` together,
+/// the next ones are not run either, reported as `NotRun` with the `TIME_BUDGET` reason code.
+/// The user program is run on `RuntimeConfig::warmup_input` before the first case judged,
+/// not counted in the budget.
+/// Interactive judges are refused, as the builder holds no interactor.
+/// Judging stops at the first error. See `run_testset_iter()` to get the results one by one.
+pub fn run_testset(
+    judge: &JudgeBuilder,
+    hooks: &TestsetHooks,
+) -> Result<Vec<JudgeResultInfo>, JudgeCoreError> {
+    run_testset_with::<ExecutorSandbox>(judge, hooks)
+}
+
+/// `run_testset()` with every case judged by `run_judge_with::<S>`.
+pub fn run_testset_with<S: SandboxBackend>(
+    judge: &JudgeBuilder,
+    hooks: &TestsetHooks,
+) -> Result<Vec<JudgeResultInfo>, JudgeCoreError> {
+    run_testset_iter_with::<S>(judge, hooks).collect()
+}
+
+/// The results of `run_testset()` as they come, each case is judged by the `next()` call
+/// returning it.
+///
+/// A case is over, its processes reaped, before its result is returned, so dropping the
+/// iterator leaves nothing running and no further case is judged.
+pub fn run_testset_iter<'a>(
+    judge: &'a JudgeBuilder,
+    hooks: &'a TestsetHooks<'a>,
+) -> TestsetIter<'a, ExecutorSandbox> {
+    run_testset_iter_with::<ExecutorSandbox>(judge, hooks)
+}
+
+/// `run_testset_iter()` with every case judged by `run_judge_with::<S>`.
+pub fn run_testset_iter_with<'a, S: SandboxBackend>(
+    judge: &'a JudgeBuilder,
+    hooks: &'a TestsetHooks<'a>,
+) -> TestsetIter<'a, S> {
+    TestsetIter {
+        judge,
+        hooks,
+        next_case: 0,
+        spent: Duration::ZERO,
+        warmed_up: false,
+        finished: false,
+        backend: PhantomData,
+    }
+}
+
+/// See `run_testset_iter()`, it ends after the first error.
+pub struct TestsetIter<'a, S: SandboxBackend> {
+    judge: &'a JudgeBuilder,
+    hooks: &'a TestsetHooks<'a>,
+    next_case: usize,
+    /// Wall time of the cases judged so far, see `RuntimeConfig::total_time_budget`
+    spent: Duration,
+    /// Whether `RuntimeConfig::warmup_input` was run
+    warmed_up: bool,
+    finished: bool,
+    backend: PhantomData<S>,
+}
+
+impl<S: SandboxBackend> TestsetIter<'_, S> {
+    fn judge_next(&mut self) -> Result<Option<JudgeResultInfo>, JudgeCoreError> {
+        if matches!(self.judge.judge_type, JudgeType::INTERACT) {
+            return Err(JudgeCoreError::InteractorNotConfigured);
+        }
+
+        let total = self.judge.testdata_configs.len();
+        while let Some(test_data) = self.judge.testdata_configs.get(self.next_case) {
+            let idx = self.next_case;
+            self.next_case += 1;
+            let _span = enter_span!("case", index = idx);
+            let filtered_out = self
+                .hooks
+                .cases
+                .as_ref()
+                .is_some_and(|cases| !cases.contains(idx));
+            let action = if filtered_out {
+                CaseAction::Run
+            } else {
+                match &self.hooks.before_case {
+                    Some(before_case) => before_case(idx, test_data),
+                    None => CaseAction::Run,
+                }
+            };
+            match action {
+                CaseAction::Run => {}
+                CaseAction::Skip => {
+                    log::debug!("Skipping testcase {}", idx);
+                    continue;
+                }
+                CaseAction::Abort => {
+                    log::debug!("Aborting test set before testcase {}", idx);
+                    return Ok(None);
+                }
+            }
+
+            let result = if filtered_out {
+                log::debug!("Testcase {} is filtered out", idx);
+                not_run(String::from("Filtered out"), reason_code::FILTERED_OUT)
+            } else {
+                match self.judge.runtime_config.total_time_budget {
+                    Some(budget) if self.spent >= budget => {
+                        log::debug!("Out of time budget before testcase {}", idx);
+                        not_run(
+                            format!("Total time budget of {:?} exceeded", budget),
+                            reason_code::TIME_BUDGET,
+                        )
+                    }
+                    _ => self.judge_case(idx, test_data)?,
+                }
+            };
+            if let Some(after_case) = &self.hooks.after_case {
+                after_case(idx, &result);
+            }
+            if let Some(progress) = &self.hooks.progress {
+                let _ = progress.send(JudgeProgress {
+                    case_index: idx,
+                    total,
+                    verdict: result.verdict.clone(),
+                });
+            }
+            return Ok(Some(result));
+        }
+        Ok(None)
+    }
+
+    /// Run the case `idx`, counting its time against the budget.
+    ///
+    /// Its wall time limit is lowered to what is left of the budget, a case cut short by it
+    /// is reported as `TimeLimitExceeded` with the `TIME_BUDGET` reason code.
+    fn judge_case(
+        &mut self,
+        idx: usize,
+        test_data: &TestdataConfig,
+    ) -> Result<JudgeResultInfo, JudgeCoreError> {
+        log::debug!("Running testcase {}", idx);
+        let mut runtime = self.judge.runtime_config.clone();
+        let budget_left = runtime
+            .total_time_budget
+            .map(|budget| budget.saturating_sub(self.spent))
+            .filter(|left| runtime.wall_time_limit.is_none_or(|limit| *left < limit));
+        if budget_left.is_some() {
+            runtime.wall_time_limit = budget_left;
+        }
+        let judge_config = JudgeConfig {
+            test_data: TestdataConfig {
+                case_index: Some(idx),
+                ..test_data.clone()
+            },
+            program: self.judge.program_config.with_case_index(idx),
+            checker: self.judge.checker_config.clone(),
+            runtime,
+            setup: self.judge.setup_config.clone(),
+            reference: self.judge.reference_config.clone(),
+        };
+        if !self.warmed_up {
+            self.warmed_up = true;
+            if let Some(warmup_input) = &self.judge.runtime_config.warmup_input {
+                warm_up::<S>(&judge_config, warmup_input);
+            }
+        }
+        let clock = self.judge.runtime_config.clock();
+        let case_start = clock.now();
+        let mut result = run_judge_with::<S>(&judge_config)?;
+        self.spent += clock.now() - case_start;
+        if let (Some(budget), Some(_)) = (self.judge.runtime_config.total_time_budget, budget_left)
+        {
+            if result.reason_code.as_deref() == Some(reason_code::TLE_WALL) {
+                log::debug!("Testcase {} was cut short by the time budget", idx);
+                result.reason_code = Some(reason_code::TIME_BUDGET.to_owned());
+                result.message = format!("Total time budget of {:?} exceeded", budget);
+            }
+        }
+        Ok(result)
+    }
+}
+
+impl<S: SandboxBackend> Iterator for TestsetIter<'_, S> {
+    type Item = Result<JudgeResultInfo, JudgeCoreError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+        let next = self.judge_next().transpose();
+        if !matches!(next, Some(Ok(_))) {
+            self.finished = true;
+        }
+        next
+    }
+}
+
+/// Test cases paired up from a flat directory, see `from_dir()`.
+#[derive(Debug, Clone)]
+pub struct TestSet {
+    pub cases: Vec<TestdataConfig>,
+}
+
+impl TestSet {
+    /// Pair every `<stem>.<in_ext>` in `dir` with its `<stem>.<ans_ext>`.
+    ///
+    /// Cases are ordered by stem, numeric stems by value (so `10` comes after `9`)
+    /// and before the other ones, which are ordered by name.
+    /// An input without an answer, or an answer without an input, is an error.
+    pub fn from_dir(dir: &Path, in_ext: &str, ans_ext: &str) -> Result<Self, JudgeCoreError> {
+        if !dir.is_dir() {
+            return Err(path_not_exist(&dir.to_path_buf()));
+        }
+
+        let mut pairs: BTreeMap<CaseKey, (Option<PathBuf>, Option<PathBuf>)> = BTreeMap::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if !path.is_file() {
+                continue;
+            }
+            let (stem, ext) = match (path.file_stem(), path.extension()) {
+                (Some(stem), Some(ext)) => (stem.to_string_lossy().to_string(), ext),
+                _ => continue,
+            };
+            let pair = pairs.entry(CaseKey::new(stem)).or_default();
+            if ext == in_ext {
+                pair.0 = Some(path);
+            } else if ext == ans_ext {
+                pair.1 = Some(path);
+            }
+        }
+
+        let mut cases = vec![];
+        for (key, pair) in pairs {
+            match pair {
+                (Some(input_file_path), Some(answer_file_path)) => cases.push(TestdataConfig {
+                    input_file_path,
+                    answer_file_path,
+                    extra_answer_file_paths: vec![],
+                    input_sha256: None,
+                    answer_sha256: None,
+                    case_index: None,
+                }),
+                (Some(input_file_path), None) => {
+                    return Err(JudgeCoreError::AnyhowError(anyhow!(
+                        "Answer file .{} not found for input {:?}",
+                        ans_ext,
+                        input_file_path
+                    )))
+                }
+                (None, Some(answer_file_path)) => {
+                    return Err(JudgeCoreError::InputFileMissing(
+                        answer_file_path.with_extension(in_ext),
+                    ))
+                }
+                (None, None) => log::debug!("Ignoring unrelated files of {}", key.2),
+            }
+        }
+        log::debug!("Found {} testcases in {:?}", cases.len(), dir);
+        Ok(Self { cases })
+    }
+
+    /// Record the checksums of every case, see `TestdataConfig::populate_checksums()`.
+    ///
+    /// Meant for packaging, e.g. `TestSet::from_dir(dir, "in", "ans")?.with_checksums()?`,
+    /// so that the cases judged elsewhere are verified against the data as it is now.
+    #[cfg(feature = "checksum")]
+    pub fn with_checksums(mut self) -> Result<Self, JudgeCoreError> {
+        for case in &mut self.cases {
+            case.populate_checksums()?;
+        }
+        Ok(self)
+    }
+}
+
+/// Sort key of a case stem, numeric stems first and by value.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct CaseKey(bool, u64, String);
+
+impl CaseKey {
+    fn new(stem: String) -> Self {
+        match stem.parse() {
+            Ok(value) => Self(false, value, stem),
+            Err(_) => Self(true, 0, stem),
+        }
+    }
+}
@@ -1,6 +1,17 @@
+#[cfg(not(any(target_os = "linux", feature = "portable")))]
+compile_error!(
+    "judge-core only supports Linux, enable the `portable` feature to build a reduced sandbox"
+);
+
+/// Spans of the judge phases for the `tracing` feature, see `enter_span!`
+#[macro_use]
+mod span;
+
 /// Helper for compiling source into an executable
 pub mod compiler;
 pub mod error;
+/// Startup checks of what the judger needs from the host
+pub mod health;
 /// Prepared functions for running one single judge
 pub mod judge;
 pub mod package;
@@ -9,6 +20,9 @@ pub mod run;
 pub mod utils;
 
 /// Currently an unused module
+#[cfg(target_os = "linux")]
 mod cgroup;
 
 pub mod sandbox;
+
+pub use health::health_check;
@@ -7,9 +7,10 @@ use crate::{
     error::JudgeCoreError,
     judge::{CheckerConfig, TestdataConfig},
     sandbox::{RlimitConfigs, DEFAULT_RLIMIT_CONFIGS},
+    utils::parse_memory,
 };
 
-use super::PackageAgent;
+use super::{apply_checker_directives, PackageAgent, OUTPUT_VALIDATORS_DIR};
 
 pub struct ICPCPackageAgent {
     package_path: PathBuf,
@@ -42,6 +43,7 @@ impl PackageAgent for ICPCPackageAgent {
         let mut cpu_limit = DEFAULT_RLIMIT_CONFIGS.cpu_limit;
         let nproc_limit = DEFAULT_RLIMIT_CONFIGS.nproc_limit;
         let mut fsize_limit = DEFAULT_RLIMIT_CONFIGS.fsize_limit;
+        let open_files_limit = DEFAULT_RLIMIT_CONFIGS.open_files_limit;
         log::debug!("reading rlimit from {:?}", self.package_path);
 
         let time_limit_path = self.package_path.join(".timelimit");
@@ -58,16 +60,24 @@ impl PackageAgent for ICPCPackageAgent {
             let content = fs::read_to_string(yaml_path).unwrap();
             let problem_meta = serde_yaml::from_str::<serde_yaml::Value>(&content).unwrap();
             if let Some(limits) = problem_meta.get("limits") {
+                // A bare number is in MiB, a string carries its unit, see `parse_memory()`
                 if let Some(memory_limit) = limits.get("memory") {
                     if let Some(memory_u64) = memory_limit.as_u64() {
                         // the unit of as_limit is byte
                         // TODO: we need some comment for developer to know this
                         as_limit = Some((memory_u64 * 1024 * 1024, memory_u64 * 1024 * 1024));
+                    } else if let Some(memory_str) = memory_limit.as_str() {
+                        let memory_bytes = parse_memory(memory_str)?;
+                        as_limit = Some((memory_bytes, memory_bytes));
                     }
                 }
+                // A bare number is in bytes
                 if let Some(output) = limits.get("output") {
                     if let Some(output_u64) = output.as_u64() {
                         fsize_limit = Some((output_u64, output_u64));
+                    } else if let Some(output_str) = output.as_str() {
+                        let output_bytes = parse_memory(output_str)?;
+                        fsize_limit = Some((output_bytes, output_bytes));
                     }
                 }
             }
@@ -79,6 +89,7 @@ impl PackageAgent for ICPCPackageAgent {
             cpu_limit,
             nproc_limit,
             fsize_limit,
+            open_files_limit,
         })
     }
 
@@ -102,17 +113,16 @@ impl PackageAgent for ICPCPackageAgent {
     }
 
     fn load_checker(&self, checker_output_path: PathBuf) -> Result<CheckerConfig, JudgeCoreError> {
-        let output_validators_path = self.package_path.join("output_validators");
+        let output_validators_path = self.package_path.join(OUTPUT_VALIDATORS_DIR);
         if output_validators_path.exists() {
             log::warn!("Output validators found, but not supported yet");
         } else {
             log::info!("No output validators found, using default checker");
         }
 
-        Ok(CheckerConfig {
-            executor: None,
-            output_file_path: checker_output_path,
-        })
+        let mut checker_config = CheckerConfig::new(checker_output_path);
+        apply_checker_directives(&self.package_path, &mut checker_config)?;
+        Ok(checker_config)
     }
 }
 
@@ -131,6 +141,10 @@ fn copy_testdata_recursively(
                 testdata_configs.push(TestdataConfig {
                     input_file_path: src.clone(),
                     answer_file_path: answer_path,
+                    extra_answer_file_paths: vec![],
+                    input_sha256: None,
+                    answer_sha256: None,
+                    case_index: None,
                 });
             }
         }
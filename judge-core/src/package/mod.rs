@@ -1,13 +1,18 @@
 pub mod icpc;
 
-use std::{path::PathBuf, str::FromStr};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
 use serde_derive::{Deserialize, Serialize};
 
 use crate::{
     error::JudgeCoreError,
-    judge::{CheckerConfig, TestdataConfig},
+    judge::{CheckerConfig, CheckerSource, TestdataConfig, DEFAULT_FLOAT_EPS},
     sandbox::RlimitConfigs,
+    utils::{FloatComparison, OutputComparison, TokenComparison},
 };
 
 use self::icpc::ICPCPackageAgent;
@@ -48,3 +53,88 @@ impl PackageType {
         }
     }
 }
+
+/// The file of a package declaring how its outputs are checked, see `apply_checker_directives()`.
+pub const CHECKER_DIRECTIVES_FILE: &str = "checker.txt";
+
+/// The directory of a package holding the programs checking its outputs.
+pub const OUTPUT_VALIDATORS_DIR: &str = "output_validators";
+
+/// Configure `checker` from the `CHECKER_DIRECTIVES_FILE` of the package at `package_path`,
+/// if it has one.
+///
+/// Each line is a directive, applied in order. Empty lines and lines starting with `#`
+/// are skipped:
+/// - `checker <name>`, one of `BuiltinChecker::NAMES`
+/// - `comparison <mode>`, one of `lines`, `unordered_lines`, `tokens`, `integers`, `floats`
+///   and `bytes`, floats allowing `DEFAULT_FLOAT_EPS` of error
+/// - `float eps=<eps>`, tokens compared as floats with `eps` of error
+///
+/// Anything else is `JudgeCoreError::InvalidConfig`, as is any directive of a package with an
+/// `OUTPUT_VALIDATORS_DIR`, which checks the outputs itself.
+pub fn apply_checker_directives(
+    package_path: &Path,
+    checker: &mut CheckerConfig,
+) -> Result<(), JudgeCoreError> {
+    let path = package_path.join(CHECKER_DIRECTIVES_FILE);
+    if !path.is_file() {
+        return Ok(());
+    }
+    log::debug!("Reading checker directives from {:?}", path);
+    let content = fs::read_to_string(&path)?;
+    let has_output_validator = package_path.join(OUTPUT_VALIDATORS_DIR).exists();
+    for (index, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let invalid = || {
+            JudgeCoreError::InvalidConfig(format!(
+                "unrecognized directive {:?} at line {} of {:?}",
+                line,
+                index + 1,
+                path
+            ))
+        };
+        if has_output_validator {
+            return Err(JudgeCoreError::InvalidConfig(format!(
+                "directive {:?} at line {} of {:?} conflicts with the output validator of the package",
+                line,
+                index + 1,
+                path
+            )));
+        }
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words[..] {
+            ["checker", name] => checker.select(&CheckerSource::Builtin(name.to_owned()))?,
+            ["comparison", mode] => {
+                checker.comparison = match mode {
+                    "lines" => OutputComparison::Lines,
+                    "unordered_lines" => OutputComparison::UnorderedLines,
+                    "line_tokens" => OutputComparison::LineTokens,
+                    "tokens" => OutputComparison::Tokens(TokenComparison::Exact),
+                    "integers" => OutputComparison::Tokens(TokenComparison::Integer),
+                    "floats" => OutputComparison::Tokens(TokenComparison::Float(
+                        FloatComparison::new(DEFAULT_FLOAT_EPS),
+                    )),
+                    "bytes" => OutputComparison::Bytes,
+                    _ => return Err(invalid()),
+                };
+                checker.executor = None;
+            }
+            ["float", eps] => {
+                let eps = eps
+                    .strip_prefix("eps=")
+                    .and_then(|eps| eps.parse::<f64>().ok())
+                    .filter(|eps| eps.is_finite() && *eps >= 0.0)
+                    .ok_or_else(invalid)?;
+                checker.comparison =
+                    OutputComparison::Tokens(TokenComparison::Float(FloatComparison::new(eps)));
+                checker.executor = None;
+            }
+            _ => return Err(invalid()),
+        }
+        log::debug!("Applied checker directive {:?}", line);
+    }
+    Ok(())
+}
@@ -0,0 +1,116 @@
+use crate::error::JudgeCoreError;
+use crate::sandbox::{unkillable, DEFAULT_REAP_TIMEOUT};
+use nix::errno::Errno;
+use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
+use nix::unistd::Pid;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const REAP_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Owns a forked child until it is reaped, so that no early return leaves it running
+/// or as a zombie.
+///
+/// Dropping the guard kills the child, or the process group it leads, with `SIGKILL`
+/// and reaps it, giving up after the reap timeout, `DEFAULT_REAP_TIMEOUT` unless
+/// `set_reap_timeout()` says otherwise. Call `reap()` once the child is known to exit by itself.
+#[derive(Debug)]
+pub struct ChildGuard {
+    pid: Option<Pid>,
+    group: bool,
+    reap_timeout: Duration,
+}
+
+impl ChildGuard {
+    /// Guard `pid` alone.
+    pub fn new(pid: Pid) -> Self {
+        Self {
+            pid: Some(pid),
+            group: false,
+            reap_timeout: DEFAULT_REAP_TIMEOUT,
+        }
+    }
+
+    /// Guard `pid` along with the process group it leads.
+    pub fn new_group_leader(pid: Pid) -> Self {
+        Self {
+            pid: Some(pid),
+            group: true,
+            reap_timeout: DEFAULT_REAP_TIMEOUT,
+        }
+    }
+
+    /// Time the child is given to be reaped once killed, see `Sandbox::set_reap_timeout()`.
+    pub fn set_reap_timeout(&mut self, timeout: Duration) {
+        self.reap_timeout = timeout;
+    }
+
+    pub fn reap_timeout(&self) -> Duration {
+        self.reap_timeout
+    }
+
+    pub fn pid(&self) -> Option<Pid> {
+        self.pid
+    }
+
+    /// Wait for the child to exit by itself.
+    pub fn reap(mut self) -> Result<WaitStatus, JudgeCoreError> {
+        let pid = self.pid.take().expect("The guarded child is gone");
+        loop {
+            match waitpid(pid, None) {
+                Err(Errno::EINTR) => continue,
+                result => return Ok(result?),
+            }
+        }
+    }
+
+    /// Kill the child and wait for it, as dropping the guard does but with the errors.
+    ///
+    /// A child which is already gone is not an error, one which is not reaped in time is
+    /// `JudgeCoreError::ProcessUnkillable` and reaped in the background.
+    pub fn kill(mut self) -> Result<(), JudgeCoreError> {
+        match self.pid.take() {
+            Some(pid) => self.kill_and_reap(pid),
+            None => Ok(()),
+        }
+    }
+
+    fn kill_and_reap(&self, pid: Pid) -> Result<(), JudgeCoreError> {
+        log::debug!("Killing child {} (group: {})", pid, self.group);
+        let res = if self.group {
+            unsafe { libc::killpg(pid.as_raw(), libc::SIGKILL) }
+        } else {
+            unsafe { libc::kill(pid.as_raw(), libc::SIGKILL) }
+        };
+        // An exited child can't be signaled once its group is empty, it still has to be reaped
+        let killed = match (res, Errno::last()) {
+            (-1, errno) if errno != Errno::ESRCH => Err(errno),
+            _ => Ok(()),
+        };
+        let reap_deadline = Instant::now() + self.reap_timeout;
+        loop {
+            match waitpid(pid, Some(WaitPidFlag::WNOHANG)) {
+                Ok(WaitStatus::StillAlive) if Instant::now() < reap_deadline => {
+                    thread::sleep(REAP_POLL_INTERVAL)
+                }
+                Ok(WaitStatus::StillAlive) => {
+                    return Err(unkillable(pid.as_raw(), self.reap_timeout))
+                }
+                Err(Errno::EINTR) => continue,
+                Ok(_) | Err(Errno::ECHILD) => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(killed?)
+    }
+}
+
+impl Drop for ChildGuard {
+    fn drop(&mut self) {
+        if let Some(pid) = self.pid.take() {
+            if let Err(e) = self.kill_and_reap(pid) {
+                log::warn!("Failed to clean up child {}: {:?}", pid, e);
+            }
+        }
+    }
+}
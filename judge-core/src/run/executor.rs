@@ -1,14 +1,65 @@
-use crate::error::{path_not_exist, JudgeCoreError};
+use crate::error::JudgeCoreError;
 use crate::{compiler::Language, utils::get_pathbuf_str};
-use nix::unistd::execve;
+use libc::c_char;
+use nix::errno::Errno;
 use serde_derive::Serialize;
-use std::{convert::Infallible, ffi::CString, path::PathBuf};
+use std::{
+    convert::Infallible, ffi::CString, fs, io, iter, os::unix::fs::PermissionsExt, path::PathBuf,
+    ptr,
+};
 
 #[derive(Debug, Clone, Serialize)]
 pub struct Executor {
     pub language: Language,
     pub path: PathBuf,
     pub additional_args: Vec<String>,
+    /// The whole environment of the program, which is run with none by default
+    pub env: Vec<(String, String)>,
+}
+
+/// The arguments of `execve` for an `Executor`, see `Executor::prepare_exec()`.
+///
+/// Nothing is allocated by `exec()`, so a forked child can call it right away without
+/// growing the resident set it inherited from the judger, which counts in its peak memory.
+#[derive(Debug)]
+pub struct PreparedExec {
+    command: CString,
+    /// Only owned for `arg_ptrs` and `env_ptrs`
+    _args: Vec<CString>,
+    _env: Vec<CString>,
+    /// Null-terminated pointers to the strings, whose buffers don't move along with them
+    arg_ptrs: Vec<*const c_char>,
+    env_ptrs: Vec<*const c_char>,
+}
+
+impl PreparedExec {
+    fn new(command: CString, args: Vec<CString>, env: Vec<CString>) -> Self {
+        let pointers = |strings: &[CString]| {
+            strings
+                .iter()
+                .map(|s| s.as_ptr())
+                .chain(iter::once(ptr::null()))
+                .collect()
+        };
+        Self {
+            arg_ptrs: pointers(&args),
+            env_ptrs: pointers(&env),
+            command,
+            _args: args,
+            _env: env,
+        }
+    }
+
+    pub fn exec(&self) -> Result<Infallible, JudgeCoreError> {
+        unsafe {
+            libc::execve(
+                self.command.as_ptr(),
+                self.arg_ptrs.as_ptr(),
+                self.env_ptrs.as_ptr(),
+            )
+        };
+        Err(JudgeCoreError::NixErrno(Errno::last()))
+    }
 }
 
 // Do not do logging or some other additional things which may use system calls
@@ -16,33 +67,88 @@ pub struct Executor {
 impl Executor {
     pub fn new(language: Language, path: PathBuf) -> Result<Self, JudgeCoreError> {
         if !path.exists() {
-            return Err(path_not_exist(&path));
+            return Err(JudgeCoreError::ExecutableNotFound(path));
         }
 
         Ok(Self {
             language,
             path,
             additional_args: vec![],
+            env: vec![],
         })
     }
 
+    /// Check that the command exists and has an execute bit, right before it's run.
+    ///
+    /// Problems only `execve` can find, like a wrong format, are caught by `ExecutorSandbox::spawn()`.
+    pub fn check_runnable(&self) -> Result<(), JudgeCoreError> {
+        let path = self.get_command_path()?;
+        let reason = match fs::metadata(&path) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => "not found",
+            Err(e) => return Err(e.into()),
+            Ok(metadata) if !metadata.is_file() => "not a regular file",
+            Ok(metadata) if metadata.permissions().mode() & 0o111 == 0 => "not executable",
+            Ok(_) => return Ok(()),
+        };
+        Err(JudgeCoreError::ExecutableNotRunnable {
+            path,
+            reason: reason.to_string(),
+        })
+    }
+
+    /// Path of the file passed to `execve`, the interpreter for scripts.
+    pub fn get_command_path(&self) -> Result<PathBuf, JudgeCoreError> {
+        let (command, _) = self.build_execute_cmd_with_args()?;
+        Ok(PathBuf::from(command))
+    }
+
     pub fn set_additional_args(&mut self, args: Vec<String>) {
         self.additional_args = args;
     }
 
+    /// Pass `args` to the program after its own name, whatever its language, unlike
+    /// `set_additional_args()` which starts with the `argv[0]` of compiled programs.
+    pub fn set_program_args(&mut self, args: Vec<String>) {
+        if args.is_empty() {
+            self.additional_args = args;
+            return;
+        }
+        let own_name = match self.language {
+            Language::Python => None,
+            Language::Rust | Language::Cpp => Some(String::new()),
+        };
+        self.additional_args = own_name.into_iter().chain(args).collect();
+    }
+
+    /// Set `key` to `value` in the environment of the program.
+    pub fn set_env(&mut self, key: &str, value: &str) {
+        self.env.retain(|(existing, _)| existing != key);
+        self.env.push((key.to_owned(), value.to_owned()));
+    }
+
     pub fn exec(&self) -> Result<Infallible, JudgeCoreError> {
+        self.prepare_exec()?.exec()
+    }
+
+    /// What `exec()` passes to `execve`, built beforehand so that a forked child only has
+    /// to exec, see `PreparedExec`.
+    pub fn prepare_exec(&self) -> Result<PreparedExec, JudgeCoreError> {
         let (command, args) = self.build_execute_cmd_with_args()?;
         let mut final_args = args;
         final_args.extend(self.additional_args.clone());
-        let c_args = final_args
+        let args = final_args
             .iter()
             .map(|s| CString::new(s.as_bytes()))
             .collect::<Result<Vec<_>, _>>()?;
-        Ok(execve(
-            &CString::new(command)?,
-            c_args.as_slice(),
-            &[CString::new("")?],
-        )?)
+        let env = match self.env.is_empty() {
+            true => vec![CString::new("")?],
+            false => self
+                .env
+                .iter()
+                .map(|(key, value)| CString::new(format!("{}={}", key, value)))
+                .collect::<Result<Vec<_>, _>>()?,
+        };
+        Ok(PreparedExec::new(CString::new(command)?, args, env))
     }
 
     fn build_execute_cmd_with_args(&self) -> Result<(String, Vec<String>), JudgeCoreError> {
@@ -1,3 +1,4 @@
+pub mod child_guard;
 pub mod executor;
 pub mod process_listener;
 pub mod sandbox;
@@ -1,12 +1,36 @@
+use super::child_guard::ChildGuard;
 use super::sandbox::ExecutorSandbox;
 use crate::{error::JudgeCoreError, sandbox::SandboxExitInfo};
-use nix::unistd::{fork, write, ForkResult};
+use nix::errno::Errno;
+use nix::sys::wait::{waitid, Id, WaitPidFlag, WaitStatus};
+use nix::unistd::{fork, read, setpgid, write, ForkResult, Pid};
 use serde_derive::{Deserialize, Serialize};
+use std::fs;
 use std::os::{fd::BorrowedFd, unix::io::RawFd};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Each exit message is prefixed by its length as a big-endian u32,
+/// so that the reader knows when the whole message has arrived.
+const EXIT_MESSAGE_HEADER_LEN: usize = 4;
+
+/// Largest exit message `read_exit_message()` accepts, a real one takes less than a KiB.
+pub const MAX_EXIT_MESSAGE_LEN: usize = 64 * 1024;
+
+/// Deepest nesting of arrays and objects `read_exit_message()` accepts, a real message
+/// nests 4 levels deep. Far below the recursion limit of `serde_json`.
+pub const MAX_EXIT_MESSAGE_DEPTH: usize = 16;
+
+/// How often `ProcessListener::kill_spawned()` looks again for the process to kill while the
+/// listener is about to spawn it or to report its exit.
+const KILL_SPAWNED_RETRY_INTERVAL: Duration = Duration::from_millis(1);
 
 pub struct ProcessListener {
     child_exit_fd: i32,
     exit_signal: u8,
+    /// The forked listener, leading the process group of the process it spawned.
+    /// Dropping the listener kills the group unless it has been reaped.
+    listener: Option<ChildGuard>,
 }
 
 impl ProcessListener {
@@ -16,6 +40,7 @@ impl ProcessListener {
         Ok(Self {
             child_exit_fd,
             exit_signal,
+            listener: None,
         })
     }
 
@@ -30,10 +55,14 @@ impl ProcessListener {
                 exit_signal: self.exit_signal,
                 option_run_result,
             };
-            let buf = serde_json::to_vec(&msg).expect("Serialize failed.");
+            let buf = encode_exit_message(&msg).expect("Serialize failed.");
             // We should be really careful here
             // not using OwnedFd here because it will close the fd
-            write(unsafe { BorrowedFd::borrow_raw(self.child_exit_fd) }, &buf).unwrap();
+            let exit_fd = unsafe { BorrowedFd::borrow_raw(self.child_exit_fd) };
+            let mut written = 0;
+            while written < buf.len() {
+                written += write(exit_fd, &buf[written..]).unwrap();
+            }
         }
     }
 
@@ -41,13 +70,39 @@ impl ProcessListener {
         &mut self,
         sandbox: &mut ExecutorSandbox,
     ) -> Result<Option<()>, JudgeCoreError> {
+        // The errors of the listener can't reach the caller, catch what we can beforehand
+        sandbox.check_runnable()?;
         match unsafe { fork() } {
-            Ok(ForkResult::Parent { .. }) => Ok(Some(())),
+            Ok(ForkResult::Parent { child }) => {
+                // Also done by the child, whichever runs first
+                let _ = setpgid(child, child);
+                let mut listener = ChildGuard::new_group_leader(child);
+                listener.set_reap_timeout(sandbox.sandbox.reap_timeout());
+                self.listener = Some(listener);
+                Ok(Some(()))
+            }
             Ok(ForkResult::Child) => {
-                let process = sandbox.spawn()?;
+                // So that `kill()` reaches the spawned process too
+                let _ = setpgid(Pid::from_raw(0), Pid::from_raw(0));
+                let process = match sandbox.spawn() {
+                    Ok(process) => process,
+                    Err(e) => {
+                        log::error!("Failed to spawn the process: {:?}", e);
+                        self.report_exit(None);
+                        unsafe { libc::_exit(1) };
+                    }
+                };
                 // listen to the status of sandbox
                 log::debug!("Wait for process {}.", process);
-                let run_result = sandbox.wait()?;
+                // Never return into the caller, it would run its cleanup in this fork
+                let run_result = match sandbox.wait() {
+                    Ok(run_result) => run_result,
+                    Err(e) => {
+                        log::error!("Failed to wait for process {}: {:?}", process, e);
+                        self.report_exit(None);
+                        unsafe { libc::_exit(1) };
+                    }
+                };
                 log::debug!("Process {} exit.", process);
                 self.report_exit(Some(run_result));
                 unsafe { libc::_exit(0) };
@@ -57,6 +112,139 @@ impl ProcessListener {
             }
         }
     }
+
+    /// Kill the listener along with the process it spawned, which won't report its exit.
+    pub fn kill(&mut self) -> Result<(), JudgeCoreError> {
+        match self.listener.take() {
+            Some(listener) => {
+                // The spawned process leads a process group of its own, see `Sandbox::spawn()`
+                if let Some(pid) = listener.pid() {
+                    if let Err(e) = kill_spawned_groups(pid, false) {
+                        log::warn!("Failed to kill what listener {} spawned: {:?}", pid, e);
+                    }
+                }
+                listener.kill()
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Kill the process the listener spawned along with its process group, leaving the
+    /// listener to report its exit.
+    ///
+    /// Returns whether the listener reports an exit, which is also the case when the process
+    /// exited by itself meanwhile. Not when there is no listener, or when it neither spawns
+    /// the process nor exits within its reap timeout.
+    pub fn kill_spawned(&self) -> Result<bool, JudgeCoreError> {
+        let (pid, reap_timeout) = match &self.listener {
+            Some(listener) => match listener.pid() {
+                Some(pid) => (pid, listener.reap_timeout()),
+                None => return Ok(false),
+            },
+            None => return Ok(false),
+        };
+        let deadline = Instant::now() + reap_timeout;
+        loop {
+            match kill_spawned_groups(pid, true)? {
+                SpawnedGroups::Killed | SpawnedGroups::ListenerExited => return Ok(true),
+                SpawnedGroups::NoneRunning if Instant::now() < deadline => {
+                    thread::sleep(KILL_SPAWNED_RETRY_INTERVAL)
+                }
+                SpawnedGroups::NoneRunning => return Ok(false),
+            }
+        }
+    }
+
+    /// Peak resident set size of the process the listener spawned, in the unit of
+    /// `Rusage::max_rss`, read from `/proc` while it runs. `None` when nothing runs.
+    pub fn spawned_peak_rss(&self) -> Option<i64> {
+        let pid = self.listener.as_ref()?.pid()?;
+        let children = fs::read_to_string(format!("/proc/{0}/task/{0}/children", pid)).ok()?;
+        children
+            .split_whitespace()
+            .filter_map(|child| {
+                let status = fs::read_to_string(format!("/proc/{}/status", child)).ok()?;
+                status
+                    .lines()
+                    .find_map(|line| line.strip_prefix("VmHWM:"))
+                    .and_then(|kib| kib.trim().trim_end_matches("kB").trim().parse().ok())
+            })
+            .max()
+    }
+
+    /// Wait for the listener, which exits by itself once it has reported the exit.
+    pub fn reap(&mut self) -> Result<(), JudgeCoreError> {
+        if let Some(listener) = self.listener.take() {
+            listener.reap()?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for ProcessListener {
+    fn drop(&mut self) {
+        // The guard of the listener only kills the group of the listener
+        if let Some(pid) = self.listener.as_ref().and_then(ChildGuard::pid) {
+            if let Err(e) = kill_spawned_groups(pid, false) {
+                log::warn!("Failed to kill what listener {} spawned: {:?}", pid, e);
+            }
+        }
+    }
+}
+
+/// What `kill_spawned_groups()` found of the children of a listener.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SpawnedGroups {
+    /// Some were running, and are killed
+    Killed,
+    /// None is running: the listener is about to spawn one, or to report the exit of the one
+    /// it reaped
+    NoneRunning,
+    /// The listener exited, after reporting, and is left for its guard to reap
+    ListenerExited,
+}
+
+/// Kill the process groups led by the children of `listener`.
+///
+/// The listener is stopped meanwhile, so that it can't reap a child and let its pid, the id
+/// of its group, be taken by another process. It is resumed afterwards with `resume`.
+fn kill_spawned_groups(listener: Pid, resume: bool) -> Result<SpawnedGroups, JudgeCoreError> {
+    Errno::result(unsafe { libc::kill(listener.as_raw(), libc::SIGSTOP) })?;
+    // Without reaping it, its guard does
+    let flags = WaitPidFlag::WSTOPPED | WaitPidFlag::WEXITED | WaitPidFlag::WNOWAIT;
+    loop {
+        match waitid(Id::Pid(listener), flags) {
+            Err(Errno::EINTR) => continue,
+            Ok(WaitStatus::Stopped(..)) => break,
+            Ok(_) => return Ok(SpawnedGroups::ListenerExited),
+            Err(e) => return Err(e.into()),
+        }
+    }
+    let children = fs::read_to_string(format!("/proc/{0}/task/{0}/children", listener));
+    let mut killed = false;
+    for pid in children
+        .as_deref()
+        .unwrap_or_default()
+        .split_whitespace()
+        .filter_map(|pid| pid.parse::<i32>().ok())
+    {
+        log::debug!(
+            "Killing process group {} spawned by listener {}",
+            pid,
+            listener
+        );
+        killed |=
+            unsafe { libc::killpg(pid, libc::SIGKILL) == 0 || libc::kill(pid, libc::SIGKILL) == 0 };
+    }
+    if resume {
+        Errno::result(unsafe { libc::kill(listener.as_raw(), libc::SIGCONT) })?;
+    }
+    children?;
+    Ok(if killed {
+        SpawnedGroups::Killed
+    } else {
+        SpawnedGroups::NoneRunning
+    })
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -64,3 +252,95 @@ pub struct ProcessExitMessage {
     pub exit_signal: u8,
     pub option_run_result: Option<SandboxExitInfo>,
 }
+
+pub fn encode_exit_message(msg: &ProcessExitMessage) -> Result<Vec<u8>, JudgeCoreError> {
+    let payload = serde_json::to_vec(msg)?;
+    let mut buf = (payload.len() as u32).to_be_bytes().to_vec();
+    buf.extend_from_slice(&payload);
+    Ok(buf)
+}
+
+/// Read one framed exit message, blocking until it has fully arrived.
+///
+/// Whatever the listener sends, a message which can't be read fails with
+/// `MalformedExitReport`, the other errors come from reading the fd.
+pub fn read_exit_message(from: RawFd) -> Result<ProcessExitMessage, JudgeCoreError> {
+    read_exit_message_with_limit(from, MAX_EXIT_MESSAGE_LEN)
+}
+
+/// `read_exit_message()` refusing messages longer than `max_len` bytes.
+///
+/// The length is checked against the header before anything is allocated for the message,
+/// so a bogus header can't exhaust the memory of the judger. Likewise the nesting is checked
+/// against `MAX_EXIT_MESSAGE_DEPTH` before the message is parsed.
+pub fn read_exit_message_with_limit(
+    from: RawFd,
+    max_len: usize,
+) -> Result<ProcessExitMessage, JudgeCoreError> {
+    let mut header = [0; EXIT_MESSAGE_HEADER_LEN];
+    read_exact_from_fd(from, &mut header)?;
+    let msg_len = u32::from_be_bytes(header) as usize;
+    if msg_len > max_len {
+        return Err(JudgeCoreError::MalformedExitReport(format!(
+            "Exit message of {} bytes exceeds the limit of {} bytes",
+            msg_len, max_len
+        )));
+    }
+    log::debug!("Reading exit message of {} bytes from fd={}", msg_len, from);
+
+    let mut payload = vec![0; msg_len];
+    read_exact_from_fd(from, &mut payload)?;
+    log::debug!("Raw Result info: {}", String::from_utf8_lossy(&payload));
+    check_json_depth(&payload, MAX_EXIT_MESSAGE_DEPTH)?;
+    serde_json::from_slice(&payload).map_err(|e| {
+        JudgeCoreError::MalformedExitReport(format!("Exit message is not valid: {}", e))
+    })
+}
+
+/// Fail when the arrays and objects of `json` nest deeper than `max_depth`,
+/// brackets in strings aside. Nothing else of the syntax is checked.
+fn check_json_depth(json: &[u8], max_depth: usize) -> Result<(), JudgeCoreError> {
+    let mut depth: usize = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    for &byte in json {
+        match (in_string, byte) {
+            (true, _) if escaped => escaped = false,
+            (true, b'\\') => escaped = true,
+            (true, b'"') => in_string = false,
+            (true, _) => {}
+            (false, b'"') => in_string = true,
+            (false, b'[' | b'{') => {
+                depth += 1;
+                if depth > max_depth {
+                    return Err(JudgeCoreError::MalformedExitReport(format!(
+                        "Exit message nests deeper than {} levels",
+                        max_depth
+                    )));
+                }
+            }
+            (false, b']' | b'}') => depth = depth.saturating_sub(1),
+            (false, _) => {}
+        }
+    }
+    Ok(())
+}
+
+fn read_exact_from_fd(from: RawFd, buf: &mut [u8]) -> Result<(), JudgeCoreError> {
+    let mut nread = 0;
+    while nread < buf.len() {
+        match read(from, &mut buf[nread..]) {
+            Ok(0) => {
+                return Err(JudgeCoreError::MalformedExitReport(format!(
+                    "Exit report closed after {} of {} bytes",
+                    nread,
+                    buf.len()
+                )))
+            }
+            Ok(n) => nread += n,
+            Err(Errno::EINTR) => continue,
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(())
+}
@@ -1,22 +1,73 @@
 use crate::error::JudgeCoreError;
+use crate::judge::RuntimeConfig;
+use crate::sandbox::Limits;
 use crate::sandbox::RlimitConfigs;
 use crate::sandbox::Sandbox;
 use crate::sandbox::SandboxExitInfo;
+use crate::sandbox::SeccompMode;
+#[cfg(target_os = "linux")]
+use crate::sandbox::SyscallFilterLoader;
 use libc::rusage;
-use libseccomp::{ScmpAction, ScmpFilterContext, ScmpSyscall};
+#[cfg(target_os = "linux")]
+use libseccomp::{
+    check_api, error::SeccompError, ScmpAction, ScmpArgCompare, ScmpCompareOp, ScmpFilterContext,
+    ScmpSyscall, ScmpVersion,
+};
+use nix::errno::Errno;
+#[cfg(not(target_os = "linux"))]
+use nix::fcntl::{fcntl, FcntlArg, FdFlag};
 use nix::unistd::close;
 use nix::unistd::dup2;
+#[cfg(not(target_os = "linux"))]
+use nix::unistd::pipe;
+#[cfg(target_os = "linux")]
+use nix::{fcntl::OFlag, unistd::pipe2};
 use serde_derive::{Deserialize, Serialize};
-use std::io;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::os::fd::OwnedFd;
 use std::os::unix::io::{AsRawFd, RawFd};
 use std::time::Duration;
 
 use super::executor::Executor;
 
+/// A sandbox running one executor, the judge is generic over it.
+///
+/// `ExecutorSandbox` is the default one. Other backends, e.g. handing the run over
+/// to a container runtime or a remote worker, only have to report the exit the same way.
+pub trait SandboxBackend: Sized {
+    /// Prepare to run `executor` with its stdin and stdout redirected, see `ExecutorSandbox::new()`.
+    fn create(
+        executor: Executor,
+        rlimit_configs: RlimitConfigs,
+        input_redirect: Option<RawFd>,
+        output_redirect: Option<RawFd>,
+        restricted: bool,
+    ) -> Result<Self, JudgeCoreError>;
+
+    fn set_seccomp_mode(&mut self, seccomp_mode: SeccompMode);
+
+    /// Apply the settings of the user program beyond its rlimits.
+    fn apply_runtime_config(&mut self, runtime: &RuntimeConfig);
+
+    /// Cap the process with `limits` on top of its rlimit configs, see `Sandbox::set_limits()`.
+    fn set_limits(&mut self, limits: Limits);
+
+    /// Start the process, returning its pid.
+    fn spawn(&mut self) -> Result<i32, JudgeCoreError>;
+
+    /// Reap the process once it exits.
+    fn wait(&self) -> Result<SandboxExitInfo, JudgeCoreError>;
+
+    fn kill(&self) -> Result<(), JudgeCoreError>;
+}
+
 pub struct ExecutorSandbox {
     executor: Executor,
     input_redirect: Option<RawFd>,
     output_redirect: Option<RawFd>,
+    restricted: bool,
+    allow_subprocesses: bool,
 
     pub sandbox: Sandbox,
 }
@@ -30,35 +81,83 @@ impl ExecutorSandbox {
         restricted: bool,
     ) -> Result<Self, JudgeCoreError> {
         log::debug!("Create sandbox with restricted={}", restricted);
-        let mut scmp_filter = match restricted {
-            true => ScmpFilterContext::new_filter(ScmpAction::KillProcess)?,
-            false => ScmpFilterContext::new_filter(ScmpAction::Allow)?,
-        };
-        if restricted {
-            let white_list = DEFAULT_SCMP_WHITELIST;
-            for s in white_list.iter() {
-                let syscall = ScmpSyscall::from_name(s)?;
-                scmp_filter.add_rule_exact(ScmpAction::Allow, syscall)?;
+        #[cfg(target_os = "linux")]
+        let scmp_filter: Option<Box<dyn SyscallFilterLoader>> = Some(Box::new(
+            build_scmp_filter(restricted, !restricted)
+                .map_err(|e| JudgeCoreError::SeccompSetupFailed(e.to_string()))?,
+        ));
+        #[cfg(not(target_os = "linux"))]
+        let scmp_filter = {
+            if restricted {
+                log::warn!("Seccomp is not available, syscalls of the process are not restricted");
             }
-        }
+            None
+        };
 
-        let sandbox = Sandbox::new(Some(rlimit_configs), Some(scmp_filter))?;
+        let mut sandbox = Sandbox::new(Some(rlimit_configs), None)?;
+        sandbox.set_syscall_filter(scmp_filter);
         Ok(Self {
             executor,
             input_redirect,
             output_redirect,
+            restricted,
+            allow_subprocesses: !restricted,
             sandbox,
         })
     }
 
+    /// Let the process start subprocesses, or kill it as soon as it tries, which is reported
+    /// by `SandboxExitInfo::subprocess_killed`. Takes effect from the next `spawn()`.
+    ///
+    /// Restricted sandboxes don't allow them unless told to, the others do. Starting a thread
+    /// is not starting a subprocess, yet the filter of restricted sandboxes only allows it
+    /// along with subprocesses. Either way they count against the thread limit, see
+    /// `Sandbox::set_thread_limit()`. Only honored on Linux.
+    pub fn set_allow_subprocesses(&mut self, allow: bool) {
+        #[cfg(not(target_os = "linux"))]
+        if !allow {
+            log::warn!("Subprocesses can't be forbidden on this platform, ignoring it");
+        }
+        self.allow_subprocesses = allow;
+    }
+
     pub fn wait(&self) -> Result<SandboxExitInfo, JudgeCoreError> {
         self.sandbox.wait()
     }
 
+    /// See `Sandbox::try_wait()`, to run the program from an event loop.
+    pub fn try_wait(&mut self) -> Result<Option<SandboxExitInfo>, JudgeCoreError> {
+        self.sandbox.try_wait()
+    }
+
+    /// See `Sandbox::exit_fd()`.
+    pub fn exit_fd(&self) -> Result<OwnedFd, JudgeCoreError> {
+        self.sandbox.exit_fd()
+    }
+
+    /// See `Executor::check_runnable()`.
+    pub fn check_runnable(&self) -> Result<(), JudgeCoreError> {
+        self.executor.check_runnable()
+    }
+
     /// WARNING:   
     /// Unsafe to use `println!()` (or `unwrap()`) in child process.
     /// See more in `fork()` document.
+    ///
+    /// The executable is checked first, and a failed `execve` in the child is reported
+    /// back through a close-on-exec pipe, both as `ExecutableNotRunnable`.
     pub fn spawn(&mut self) -> Result<i32, JudgeCoreError> {
+        self.executor.check_runnable()?;
+        // `new()` built the filter for the default of the sandbox
+        #[cfg(target_os = "linux")]
+        if self.allow_subprocesses == self.restricted {
+            let scmp_filter = build_scmp_filter(self.restricted, self.allow_subprocesses)
+                .map_err(|e| JudgeCoreError::SeccompSetupFailed(e.to_string()))?;
+            self.sandbox.set_syscall_filter(Some(Box::new(scmp_filter)));
+        }
+        // Closed by a successful `execve`, otherwise the child writes its errno to it
+        let (exec_error_read, exec_error_write) = cloexec_pipe()?;
+
         let before_limit = {
             let input_redirect = self.input_redirect;
             let output_redirect = self.output_redirect;
@@ -67,7 +166,7 @@ impl ExecutorSandbox {
                 if let Some(output_redirect) = output_redirect {
                     dup2(output_redirect, stderr_raw_fd).expect("Failed to dup2 stderr");
                 } else {
-                    close(io::stdin().as_raw_fd()).expect("Failed to close stdin");
+                    close(stderr_raw_fd).expect("Failed to close stderr");
                 }
 
                 let stdin_raw_fd = io::stdin().as_raw_fd();
@@ -87,14 +186,238 @@ impl ExecutorSandbox {
         };
 
         let after_limit = {
-            let executor = self.executor.clone();
+            let prepared_exec = self.executor.prepare_exec()?;
+            let exec_error_fd = exec_error_write.as_raw_fd();
             move || {
-                executor.exec().expect("Failed to exec");
+                let errno = match prepared_exec.exec() {
+                    Err(JudgeCoreError::NixErrno(errno)) => errno as i32,
+                    _ => 0,
+                };
+                let buf = errno.to_ne_bytes();
+                unsafe {
+                    libc::write(
+                        exec_error_fd,
+                        buf.as_ptr() as *const libc::c_void,
+                        buf.len(),
+                    );
+                    libc::_exit(1);
+                }
             }
         };
 
-        self.sandbox.spawn(before_limit, after_limit)
+        let child_pid = self.sandbox.spawn(before_limit, after_limit)?;
+        drop(exec_error_write);
+        let mut buf = [0u8; 4];
+        if File::from(exec_error_read).read_exact(&mut buf).is_ok() {
+            // The child exits by itself, don't leave a zombie
+            self.sandbox.wait()?;
+            return Err(JudgeCoreError::ExecutableNotRunnable {
+                path: self.executor.get_command_path()?,
+                reason: format!(
+                    "failed to exec: {}",
+                    Errno::from_raw(i32::from_ne_bytes(buf))
+                ),
+            });
+        }
+        Ok(child_pid)
+    }
+}
+
+impl SandboxBackend for ExecutorSandbox {
+    fn create(
+        executor: Executor,
+        rlimit_configs: RlimitConfigs,
+        input_redirect: Option<RawFd>,
+        output_redirect: Option<RawFd>,
+        restricted: bool,
+    ) -> Result<Self, JudgeCoreError> {
+        Self::new(
+            executor,
+            rlimit_configs,
+            input_redirect,
+            output_redirect,
+            restricted,
+        )
+    }
+
+    fn set_seccomp_mode(&mut self, seccomp_mode: SeccompMode) {
+        self.sandbox.set_seccomp_mode(seccomp_mode);
+    }
+
+    fn apply_runtime_config(&mut self, runtime: &RuntimeConfig) {
+        self.sandbox.set_wall_time_limit(runtime.wall_time_limit);
+        self.sandbox.set_nice(runtime.nice);
+        self.sandbox.set_idle_time_limit(runtime.idle_time_limit);
+        self.sandbox.set_disk_write_limit(runtime.disk_write_limit);
+        self.sandbox.set_thread_limit(runtime.max_threads);
+        self.set_allow_subprocesses(runtime.allow_subprocesses);
+        self.sandbox
+            .set_kill_grace_period(runtime.kill_grace_period);
+        self.sandbox.set_seccomp_mode(runtime.seccomp_mode);
+        self.sandbox.set_cpu_affinity(runtime.cpu_affinity.clone());
+        if let Some(clock) = &runtime.clock {
+            self.sandbox.set_clock(clock.clone());
+        }
+        self.sandbox
+            .set_core_dump_dir(runtime.core_dump_dir.clone());
+        // The program runs in the core dump directory, it can't be found from there
+        if runtime.core_dump_dir.is_some() {
+            if let Ok(path) = fs::canonicalize(&self.executor.path) {
+                self.executor.path = path;
+            }
+        }
+    }
+
+    fn set_limits(&mut self, limits: Limits) {
+        self.sandbox.set_limits(limits);
+    }
+
+    fn spawn(&mut self) -> Result<i32, JudgeCoreError> {
+        ExecutorSandbox::spawn(self)
+    }
+
+    fn wait(&self) -> Result<SandboxExitInfo, JudgeCoreError> {
+        ExecutorSandbox::wait(self)
+    }
+
+    fn kill(&self) -> Result<(), JudgeCoreError> {
+        self.sandbox.kill()
+    }
+}
+
+/// A pipe closed on `execve`, flagged as it is created where the platform allows it,
+/// so that no process forked meanwhile by another thread keeps it open.
+#[cfg(target_os = "linux")]
+fn cloexec_pipe() -> Result<(OwnedFd, OwnedFd), Errno> {
+    pipe2(OFlag::O_CLOEXEC)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn cloexec_pipe() -> Result<(OwnedFd, OwnedFd), Errno> {
+    let (read, write) = pipe()?;
+    for fd in [&read, &write] {
+        fcntl(fd.as_raw_fd(), FcntlArg::F_SETFD(FdFlag::FD_CLOEXEC))?;
+    }
+    Ok((read, write))
+}
+
+/// The syscall filter of an `ExecutorSandbox`.
+///
+/// Restricted filters hold the system calls they don't allow for the sandbox to tell which
+/// one it was and kill the process, see `SandboxExitInfo::blocked_syscall`. Where they can't
+/// be held, or the sandbox can't take the listener, they kill the process right away.
+#[cfg(target_os = "linux")]
+pub(crate) struct ExecutorFilter {
+    filter: ScmpFilterContext,
+    /// Kills the process for what `filter` holds, when nothing listens to it
+    fallback: Option<ScmpFilterContext>,
+}
+
+#[cfg(target_os = "linux")]
+impl SyscallFilterLoader for ExecutorFilter {
+    fn load(&self) -> Result<(), JudgeCoreError> {
+        SyscallFilterLoader::load(&self.filter)
     }
+
+    fn listener_fd(&self) -> Option<RawFd> {
+        self.filter.listener_fd()
+    }
+
+    fn load_fallback(&self) -> Result<(), JudgeCoreError> {
+        match &self.fallback {
+            Some(fallback) => SyscallFilterLoader::load(fallback),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) fn build_scmp_filter(
+    restricted: bool,
+    allow_subprocesses: bool,
+) -> Result<ExecutorFilter, SeccompError> {
+    let can_hold = matches!(check_api(6, ScmpVersion::from((2, 5, 0))), Ok(true));
+    // Stacked on the filter, killing wins over holding
+    let fallback = if restricted && can_hold {
+        Some(build_filter_context(true, allow_subprocesses, false)?)
+    } else {
+        None
+    };
+    Ok(ExecutorFilter {
+        filter: build_filter_context(restricted, allow_subprocesses, can_hold)?,
+        fallback,
+    })
+}
+
+#[cfg(target_os = "linux")]
+fn build_filter_context(
+    restricted: bool,
+    allow_subprocesses: bool,
+    can_hold: bool,
+) -> Result<ScmpFilterContext, SeccompError> {
+    let mut scmp_filter = match (restricted, can_hold) {
+        (true, true) => ScmpFilterContext::new_filter(ScmpAction::Notify)?,
+        (true, false) => ScmpFilterContext::new_filter(ScmpAction::KillProcess)?,
+        (false, _) => ScmpFilterContext::new_filter(ScmpAction::Allow)?,
+    };
+    if restricted {
+        let white_list = DEFAULT_SCMP_WHITELIST;
+        for s in white_list.iter() {
+            let syscall = ScmpSyscall::from_name(s)?;
+            scmp_filter.add_rule_exact(ScmpAction::Allow, syscall)?;
+        }
+    }
+    match (restricted, allow_subprocesses) {
+        (true, true) => {
+            for s in SUBPROCESS_SCMP_WHITELIST.iter() {
+                scmp_filter.add_rule(ScmpAction::Allow, ScmpSyscall::from_name(s)?)?;
+            }
+        }
+        (false, true) => {}
+        (_, false) => deny_subprocesses(&mut scmp_filter, restricted, can_hold)?,
+    }
+    Ok(scmp_filter)
+}
+
+/// Hold the system calls starting a subprocess for the sandbox to notice and kill the process,
+/// see `SyscallFilterLoader::listener_fd()`. Restricted filters hold them already, like any
+/// other system call they don't allow. Where the filter can't hold them, restricted filters
+/// kill the process right away.
+#[cfg(target_os = "linux")]
+fn deny_subprocesses(
+    scmp_filter: &mut ScmpFilterContext,
+    restricted: bool,
+    can_hold: bool,
+) -> Result<(), SeccompError> {
+    if restricted && !can_hold {
+        return Ok(());
+    }
+    if !restricted {
+        let action = if can_hold {
+            ScmpAction::Notify
+        } else {
+            ScmpAction::KillProcess
+        };
+        for s in ["fork", "vfork"] {
+            scmp_filter.add_rule(action, ScmpSyscall::from_name(s)?)?;
+        }
+        // Only threads are created with `CLONE_THREAD`
+        scmp_filter.add_rule_conditional(
+            action,
+            ScmpSyscall::from_name("clone")?,
+            &[ScmpArgCompare::new(
+                0,
+                ScmpCompareOp::MaskedEqual(libc::CLONE_THREAD as u64),
+                0,
+            )],
+        )?;
+    }
+    // Its flags are behind a pointer the filter can't read, libc falls back to `clone`
+    scmp_filter.add_rule(
+        ScmpAction::Errno(libc::ENOSYS),
+        ScmpSyscall::from_name("clone3")?,
+    )?;
+    Ok(())
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -126,7 +449,14 @@ impl From<rusage> for Rusage {
     }
 }
 
-const DEFAULT_SCMP_WHITELIST: [&str; 41] = [
+/// Allowed on top of `DEFAULT_SCMP_WHITELIST` along with subprocesses, for them and threads.
+#[cfg(target_os = "linux")]
+const SUBPROCESS_SCMP_WHITELIST: [&str; 7] = [
+    "fork", "vfork", "clone", "clone3", "wait4", "exit", "madvise",
+];
+
+#[cfg(target_os = "linux")]
+const DEFAULT_SCMP_WHITELIST: [&str; 43] = [
     "read",
     "fstat",
     "mmap",
@@ -168,4 +498,7 @@ const DEFAULT_SCMP_WHITELIST: [&str; 41] = [
     "socket",
     "dup",
     "connect",
+    // Sleeping uses no CPU time, it's bounded by `RuntimeConfig::wall_time_limit`
+    "nanosleep",
+    "clock_nanosleep",
 ];
@@ -0,0 +1,212 @@
+//! The forked process of a sandbox, as it is signaled and waited for.
+
+use libc::c_int;
+use nix::errno::Errno;
+#[cfg(target_os = "linux")]
+use std::os::fd::{AsRawFd, FromRawFd, RawFd};
+use std::{cell::Cell, os::fd::OwnedFd, thread, time::Duration};
+
+/// A system call the syscall filter of a child holds it in, see `Child::held_syscall()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct HeldSyscall {
+    pub(crate) nr: i32,
+    /// Its first argument, e.g. the flags of `clone`
+    pub(crate) arg0: u64,
+}
+
+/// A child process of the sandbox.
+///
+/// It is signaled through a pidfd when the kernel has them (Linux 5.3 and later), so that
+/// once it is reaped, an unrelated process reusing its pid is never signaled instead.
+/// Older kernels and other platforms fall back to the pid.
+///
+/// Reaping is still done with `wait4` by pid, which reports the usage and can't race as
+/// the pid is not released until then.
+#[derive(Debug)]
+pub(crate) struct Child {
+    pid: i32,
+    pidfd: Option<OwnedFd>,
+    /// Notified by the syscall filter of the child when it holds one of its system calls
+    listener: Option<OwnedFd>,
+    /// Set once `wait4` returned the child, see `mark_reaped()`
+    reaped: Cell<bool>,
+}
+
+impl Child {
+    /// Track `pid`, a child which must not be reaped yet for its pidfd to be the right one.
+    pub(crate) fn new(pid: i32) -> Self {
+        let pidfd = match pidfd_open(pid) {
+            Ok(pidfd) => Some(pidfd),
+            Err(e) => {
+                log::debug!("No pidfd for pid={}, falling back to the pid: {}", pid, e);
+                None
+            }
+        };
+        Self {
+            pid,
+            pidfd,
+            listener: None,
+            reaped: Cell::new(false),
+        }
+    }
+
+    pub(crate) fn pid(&self) -> i32 {
+        self.pid
+    }
+
+    /// Record that `wait4` returned the child, which is gone for good.
+    pub(crate) fn mark_reaped(&self) {
+        self.reaped.set(true);
+    }
+
+    pub(crate) fn is_reaped(&self) -> bool {
+        self.reaped.get()
+    }
+
+    pub(crate) fn has_pidfd(&self) -> bool {
+        self.pidfd.is_some()
+    }
+
+    /// Take `target_fd` of the child, the listener of its syscall filter, to tell through
+    /// `held_syscall()` when one of its system calls is held for the judger to decide.
+    ///
+    /// The child must keep the fd open until this returns. It needs a pidfd and Linux 5.6.
+    pub(crate) fn listen_to_filter(&mut self, target_fd: c_int) -> Result<(), Errno> {
+        self.listener = Some(pidfd_getfd(self.pidfd.as_ref(), target_fd)?);
+        Ok(())
+    }
+
+    pub(crate) fn is_listening(&self) -> bool {
+        self.listener.is_some()
+    }
+
+    /// The system call the child is held in by its filter, which only does so for the ones
+    /// it doesn't allow. It stays blocked until it is killed.
+    pub(crate) fn held_syscall(&self) -> Option<HeldSyscall> {
+        match &self.listener {
+            #[cfg(target_os = "linux")]
+            Some(listener) => {
+                let mut poll_fd = libc::pollfd {
+                    fd: listener.as_raw_fd(),
+                    events: libc::POLLIN,
+                    revents: 0,
+                };
+                let held = unsafe {
+                    libc::poll(&mut poll_fd, 1, 0) == 1 && poll_fd.revents & libc::POLLIN != 0
+                };
+                if !held {
+                    return None;
+                }
+                // Doesn't block once polled, fails with `ENOENT` when the child died meanwhile
+                let mut notif: libc::seccomp_notif = unsafe { std::mem::zeroed() };
+                let res = unsafe {
+                    libc::ioctl(
+                        listener.as_raw_fd(),
+                        libc::SECCOMP_IOCTL_NOTIF_RECV,
+                        &mut notif,
+                    )
+                };
+                (res == 0).then_some(HeldSyscall {
+                    nr: notif.data.nr,
+                    arg0: notif.data.args[0],
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// Send `signal`, failing with `ESRCH` once the child is reaped when it has a pidfd.
+    pub(crate) fn signal(&self, signal: c_int) -> Result<(), Errno> {
+        let res = match &self.pidfd {
+            #[cfg(target_os = "linux")]
+            Some(pidfd) => unsafe {
+                libc::syscall(
+                    libc::SYS_pidfd_send_signal,
+                    pidfd.as_raw_fd(),
+                    signal,
+                    std::ptr::null::<libc::siginfo_t>(),
+                    0,
+                ) as c_int
+            },
+            _ => unsafe { libc::kill(self.pid, signal) },
+        };
+        match res {
+            -1 => Err(Errno::last()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Send `signal` to the process group the child leads, see `Sandbox::spawn()`, or to
+    /// the child alone when it could not lead one.
+    ///
+    /// Its pid is the id of the group, which no other process can take until the child is
+    /// reaped. Fails with `ESRCH` from then on.
+    pub(crate) fn signal_group(&self, signal: c_int) -> Result<(), Errno> {
+        if self.is_reaped() {
+            return Err(Errno::ESRCH);
+        }
+        match Errno::result(unsafe { libc::killpg(self.pid, signal) }) {
+            Ok(_) => Ok(()),
+            Err(Errno::ESRCH) => self.signal(signal),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Block until the child exits or `timeout` is over, whichever comes first.
+    ///
+    /// Without a pidfd, this sleeps the whole `timeout`. It doesn't reap the child.
+    pub(crate) fn wait_exit_for(&self, timeout: Duration) {
+        match &self.pidfd {
+            #[cfg(target_os = "linux")]
+            Some(pidfd) => {
+                let mut poll_fd = libc::pollfd {
+                    fd: pidfd.as_raw_fd(),
+                    events: libc::POLLIN,
+                    revents: 0,
+                };
+                // Interrupted early is fine, the caller polls again
+                unsafe { libc::poll(&mut poll_fd, 1, timeout.as_millis() as c_int) };
+            }
+            _ => thread::sleep(timeout),
+        }
+    }
+
+    /// A pidfd of the child of its own, readable once it exits.
+    pub(crate) fn open_pidfd(&self) -> Result<OwnedFd, Errno> {
+        match &self.pidfd {
+            Some(pidfd) => pidfd
+                .try_clone()
+                .map_err(|e| Errno::from_raw(e.raw_os_error().unwrap_or(libc::EBADF))),
+            None => pidfd_open(self.pid),
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) fn pidfd_open(pid: i32) -> Result<OwnedFd, Errno> {
+    let fd = unsafe { libc::syscall(libc::SYS_pidfd_open, pid, 0) };
+    if fd == -1 {
+        return Err(Errno::last());
+    }
+    Ok(unsafe { OwnedFd::from_raw_fd(fd as RawFd) })
+}
+
+#[cfg(target_os = "linux")]
+fn pidfd_getfd(pidfd: Option<&OwnedFd>, target_fd: c_int) -> Result<OwnedFd, Errno> {
+    let pidfd = pidfd.ok_or(Errno::ENOSYS)?;
+    let fd = unsafe { libc::syscall(libc::SYS_pidfd_getfd, pidfd.as_raw_fd(), target_fd, 0) };
+    if fd == -1 {
+        return Err(Errno::last());
+    }
+    Ok(unsafe { OwnedFd::from_raw_fd(fd as RawFd) })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn pidfd_getfd(_pidfd: Option<&OwnedFd>, _target_fd: c_int) -> Result<OwnedFd, Errno> {
+    Err(Errno::ENOSYS)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn pidfd_open(_pid: i32) -> Result<OwnedFd, Errno> {
+    Err(Errno::ENOSYS)
+}
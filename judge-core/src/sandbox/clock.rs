@@ -0,0 +1,61 @@
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Source of the instants a sandbox measures its real time cost and its deadlines with,
+/// and the judge its timings.
+pub trait Clock: fmt::Debug + Send + Sync {
+    fn now(&self) -> Instant;
+}
+
+/// The monotonic clock of the system, used by default.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MonotonicClock;
+
+impl Clock for MonotonicClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// A clock which only moves when `advance()` is called, for deterministic tests.
+///
+/// Clones share the same time. Note that a sandbox waited in a forked child
+/// (like in interactive judging) will not see advances made by the parent.
+///
+/// The waits of the sandbox end only once it is advanced past them, as the wall time limit
+/// does: a process which ignores `SIGTERM` is given `kill_grace_period` of this clock, and one
+/// which is not reaped after `SIGKILL` is waited for until the reap timeout of this clock.
+#[derive(Debug, Clone)]
+pub struct MockClock {
+    base: Instant,
+    offset: Arc<Mutex<Duration>>,
+}
+
+impl MockClock {
+    pub fn new() -> Self {
+        Self {
+            base: Instant::now(),
+            offset: Arc::new(Mutex::new(Duration::ZERO)),
+        }
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        let mut offset = self.offset.lock().unwrap();
+        *offset += duration;
+    }
+}
+
+impl Default for MockClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        self.base + *self.offset.lock().unwrap()
+    }
+}
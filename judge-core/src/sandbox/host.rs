@@ -0,0 +1,58 @@
+use nix::sys::resource::{
+    getrlimit, Resource,
+    Resource::{RLIMIT_AS, RLIMIT_CPU, RLIMIT_FSIZE, RLIMIT_NOFILE, RLIMIT_STACK},
+    RLIM_INFINITY,
+};
+
+use super::RlimitConfigs;
+use crate::error::JudgeCoreError;
+
+/// What the host can give to sandboxed processes, see `JudgeConfig::validate()`.
+#[derive(Debug, Clone)]
+pub struct HostResources {
+    /// Physical memory, `None` when it can't be read
+    pub total_memory_bytes: Option<u64>,
+    /// Online CPUs, `None` when it can't be read
+    pub cpu_count: Option<usize>,
+    /// Hard rlimits of the judger, beyond which an unprivileged judger can't raise the ones
+    /// of its children. `None` for a judger run by root, which can.
+    ///
+    /// Unlimited resources are `None`, `nproc_limit` is never read.
+    pub rlimit_ceilings: Option<RlimitConfigs>,
+}
+
+impl HostResources {
+    /// Read the resources of the host and the limits of the current process.
+    pub fn detect() -> Result<Self, JudgeCoreError> {
+        let rlimit_ceilings = if unsafe { libc::getuid() } == 0 {
+            None
+        } else {
+            Some(RlimitConfigs {
+                stack_limit: read_rlimit(RLIMIT_STACK)?,
+                as_limit: read_rlimit(RLIMIT_AS)?,
+                cpu_limit: read_rlimit(RLIMIT_CPU)?,
+                nproc_limit: None,
+                fsize_limit: read_rlimit(RLIMIT_FSIZE)?,
+                open_files_limit: read_rlimit(RLIMIT_NOFILE)?,
+            })
+        };
+        Ok(Self {
+            total_memory_bytes: sysconf(libc::_SC_PHYS_PAGES)
+                .zip(sysconf(libc::_SC_PAGESIZE))
+                .map(|(pages, page_size)| pages.saturating_mul(page_size)),
+            cpu_count: sysconf(libc::_SC_NPROCESSORS_ONLN).map(|count| count as usize),
+            rlimit_ceilings,
+        })
+    }
+}
+
+/// A limit which is not infinite, as `(soft, hard)`.
+fn read_rlimit(resource: Resource) -> Result<Option<(u64, u64)>, JudgeCoreError> {
+    let (soft, hard) = getrlimit(resource)?;
+    Ok((hard != RLIM_INFINITY).then_some((soft, hard)))
+}
+
+fn sysconf(name: libc::c_int) -> Option<u64> {
+    let value = unsafe { libc::sysconf(name) };
+    (value > 0).then_some(value as u64)
+}
@@ -1,15 +1,48 @@
+mod child;
+pub mod clock;
+pub mod host;
+#[cfg(target_os = "linux")]
+mod pids;
+#[cfg(not(target_os = "linux"))]
+mod portable;
+
+pub(crate) use self::child::pidfd_open;
+use self::child::Child;
+use self::clock::{Clock, MonotonicClock};
+#[cfg(target_os = "linux")]
+use self::pids::PidsCgroup;
 use crate::error::JudgeCoreError;
-use libc::{c_int, rusage, wait4, WEXITSTATUS, WSTOPPED, WTERMSIG};
-use libseccomp::ScmpFilterContext;
+use libc::{c_int, rusage, WEXITSTATUS, WTERMSIG};
+use nix::errno::Errno;
+#[cfg(target_os = "linux")]
+use nix::sys::resource::Resource::RLIMIT_NPROC;
 use nix::sys::resource::{
-    setrlimit,
-    Resource::{RLIMIT_AS, RLIMIT_CPU, RLIMIT_STACK},
+    getrlimit, setrlimit, Resource,
+    Resource::{RLIMIT_AS, RLIMIT_CPU, RLIMIT_FSIZE, RLIMIT_NOFILE, RLIMIT_STACK},
+};
+use nix::unistd::{fork, pipe, ForkResult};
+#[cfg(target_os = "linux")]
+use nix::{
+    sched::{sched_setaffinity, CpuSet},
+    unistd::Pid,
 };
-use nix::unistd::{fork, ForkResult};
 use serde_derive::{Deserialize, Serialize};
 use std::{
+    ffi::CString,
+    fs::{self, File},
+    io::{Read, Write},
+    os::{
+        fd::{AsRawFd, OwnedFd, RawFd},
+        unix::ffi::OsStrExt,
+    },
+    path::{Path, PathBuf},
     process::Command,
-    time::{Duration, Instant},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
 pub static DEFAULT_RLIMIT_CONFIGS: RlimitConfigs = RlimitConfigs {
@@ -18,6 +51,7 @@ pub static DEFAULT_RLIMIT_CONFIGS: RlimitConfigs = RlimitConfigs {
     cpu_limit: Some((1, 2)),
     nproc_limit: Some((1, 1)),
     fsize_limit: Some((1024, 1024)),
+    open_files_limit: Some((64, 64)),
 };
 
 pub static SCRIPT_LIMIT_CONFIG: RlimitConfigs = RlimitConfigs {
@@ -26,101 +60,883 @@ pub static SCRIPT_LIMIT_CONFIG: RlimitConfigs = RlimitConfigs {
     cpu_limit: Some((60, 90)),
     nproc_limit: Some((1, 1)),
     fsize_limit: Some((1024, 1024)),
+    open_files_limit: Some((64, 64)),
+};
+
+/// Limits of a checker unless `RuntimeConfig::checker_limits` says otherwise,
+/// the ones of `SCRIPT_LIMIT_CONFIG` bounded by a wall time.
+pub const DEFAULT_CHECKER_LIMITS: Limits = Limits {
+    cpu_time: Some(Duration::from_secs(60)),
+    wall_time: Some(Duration::from_secs(90)),
+    memory_bytes: Some(1024 * 1024 * 1024),
+    stack_bytes: Some(16 * 1024 * 1024),
+    output_bytes: None,
+    max_processes: None,
+    max_threads: None,
+    open_files: Some(64),
+    disk_write_bytes: None,
 };
 
 pub static SANDBOX_USERNAME: &str = "judger_sandbox";
 
+/// Time a killed process is given to be reaped unless `Sandbox::set_reap_timeout()` says
+/// otherwise, more than enough for any process which can still die.
+pub const DEFAULT_REAP_TIMEOUT: Duration = Duration::from_secs(10);
+
+const WAIT_POLL_INTERVAL: Duration = Duration::from_millis(10);
+/// A process gaining less CPU time than this share of an idle window is idle.
+const IDLE_CPU_PERCENT: u32 = 5;
+
+/// Id of `SANDBOX_USERNAME`, which a judger run by root switches sandboxed processes to.
+pub fn sandbox_user_id() -> Result<u32, JudgeCoreError> {
+    // Run `id -u $SANDBOX_USERNAME` to get the user id
+    let output = Command::new("id")
+        .arg("-u")
+        .arg(SANDBOX_USERNAME)
+        .output()
+        .map_err(|e| JudgeCoreError::AnyhowError(e.into()))?;
+    String::from_utf8(output.stdout)
+        .map_err(|e| JudgeCoreError::AnyhowError(e.into()))?
+        .trim()
+        .parse::<u32>()
+        .map_err(|e| JudgeCoreError::AnyhowError(e.into()))
+}
+
+#[cfg(target_os = "linux")]
+pub type SyscallFilter = libseccomp::ScmpFilterContext;
+#[cfg(not(target_os = "linux"))]
+pub use portable::SyscallFilter;
+
+/// Loads a syscall filter into the current process, see `Sandbox::set_syscall_filter()`.
+pub trait SyscallFilterLoader {
+    fn load(&self) -> Result<(), JudgeCoreError>;
+
+    /// The fd notified of the system calls the loaded filter holds for the judger to decide,
+    /// if it holds any. The sandbox kills a process held this way, telling which system call
+    /// it was, see `SandboxExitInfo::blocked_syscall`.
+    fn listener_fd(&self) -> Option<RawFd> {
+        None
+    }
+
+    /// Load what decides the system calls the loaded filter holds when the sandbox could not
+    /// take its listener, which leaves them failing with `ENOSYS`.
+    fn load_fallback(&self) -> Result<(), JudgeCoreError> {
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl SyscallFilterLoader for SyscallFilter {
+    fn load(&self) -> Result<(), JudgeCoreError> {
+        Ok(libseccomp::ScmpFilterContext::load(self)?)
+    }
+
+    fn listener_fd(&self) -> Option<RawFd> {
+        // Fails unless the filter holds some system calls and is loaded
+        match unsafe { libseccomp_sys::seccomp_notify_fd(self.as_ptr()) } {
+            fd if fd >= 0 => Some(fd),
+            _ => None,
+        }
+    }
+}
+
+/// What to do when the syscall filter can't be loaded,
+/// e.g. in a container without the needed capabilities.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SeccompMode {
+    /// Fail to spawn the process
+    #[default]
+    Required,
+    /// Log a warning and run the process without the filter
+    Preferred,
+    /// Never load the filter
+    Off,
+}
+
+/// The rlimits of a problem package, kept for compatibility: the sandbox only knows `Limits`,
+/// which it turns them into with `loaded_limits()`. Only their soft values count.
 #[derive(Default, Debug, Clone, Serialize)]
 pub struct RlimitConfigs {
     pub stack_limit: Option<(u64, u64)>,
     pub as_limit: Option<(u64, u64)>,
     pub cpu_limit: Option<(u64, u64)>,
+    /// Never loaded, only read through `Limits::max_processes`
     pub nproc_limit: Option<(u64, u64)>,
+    /// Never loaded, only read through `Limits::output_bytes`
     pub fsize_limit: Option<(u64, u64)>,
+    /// `RLIMIT_NOFILE`, opening more fails with `EMFILE`
+    pub open_files_limit: Option<(u64, u64)>,
 }
 
 impl RlimitConfigs {
-    /// Load the rlimit configs to the current process.
+    /// Load the rlimit configs to the current process, see `loaded_limits()`.
+    pub fn load(&self) -> Result<(), JudgeCoreError> {
+        self.loaded_limits().load()
+    }
+
+    /// The limits a sandbox given the configs loads: `Limits::from()` them, without
+    /// `nproc_limit` and `fsize_limit`.
+    pub fn loaded_limits(&self) -> Limits {
+        Limits {
+            max_processes: None,
+            output_bytes: None,
+            ..Limits::from(self)
+        }
+    }
+
+    pub fn get_cpu_limit_duration(&self) -> Option<std::time::Duration> {
+        self.cpu_limit
+            .map(|(soft, _)| std::time::Duration::from_secs(soft))
+    }
+
+    /// The duration after which the process is killed by `RLIMIT_CPU`, see `load()`.
+    pub fn get_cpu_kill_duration(&self) -> Option<std::time::Duration> {
+        self.loaded_limits().get_cpu_kill_duration()
+    }
+}
+
+/// Every resource cap of a process in one place, `None` for unlimited.
+///
+/// Unlike `RlimitConfigs`, each cap is a single value the process may use up to.
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Limits {
+    pub cpu_time: Option<Duration>,
+    /// Enforced by the sandbox watching the process, not by an rlimit
+    pub wall_time: Option<Duration>,
+    /// Size of the address space
+    pub memory_bytes: Option<u64>,
+    pub stack_bytes: Option<u64>,
+    /// Size of any file the process writes
+    pub output_bytes: Option<u64>,
+    /// `RLIMIT_NPROC`, counted for the whole sandbox user: every thread of any of its
+    /// processes is one, those of other sandboxes included. Only honored on Linux.
+    pub max_processes: Option<u64>,
+    /// Tasks of the process and its children, counting each thread as one like
+    /// `max_processes`, see `Sandbox::set_thread_limit()`
+    pub max_threads: Option<u64>,
+    pub open_files: Option<u64>,
+    /// Bytes written to disk-backed filesystems, see `Sandbox::set_disk_write_limit()`
+    pub disk_write_bytes: Option<u64>,
+}
+
+impl Limits {
+    /// Load the limits to the current process as rlimits.
     ///
-    /// One thing should be noted is that `RLIMIT_CPU` is set to +1 second of the given value.
-    /// This is because rlimit will kills the process when CPU almost reaches the limit,
-    /// which can have a few milliseconds of deviation.
+    /// `RLIMIT_CPU` is given +1 second of margin, rounding `cpu_time` up to whole seconds,
+    /// as the kernel signals the process a few milliseconds around the limit. The process is
+    /// signaled at the margin and killed a second later, see `get_cpu_kill_duration()`.
+    /// A cap the host does not permit is lowered to its ceiling, see `EffectiveLimits`.
     pub fn load(&self) -> Result<(), JudgeCoreError> {
-        if let Some(stack_limit) = self.stack_limit {
-            setrlimit(RLIMIT_STACK, stack_limit.0, stack_limit.1)?;
+        Ok(self.load_rlimits()?)
+    }
+
+    /// `load()`, without allocating for the errors, to be called in a forked child.
+    pub(crate) fn load_rlimits(&self) -> Result<(), Errno> {
+        if let Some(cpu_time) = self.cpu_time {
+            let soft = cpu_limit_secs(cpu_time) + 1;
+            setrlimit_clamped(RLIMIT_CPU, soft, soft + 1)?;
         }
-        if let Some(as_limit) = self.as_limit {
-            setrlimit(RLIMIT_AS, as_limit.0, as_limit.1)?;
+        let caps = [
+            (RLIMIT_AS, self.memory_bytes),
+            (RLIMIT_STACK, self.stack_bytes),
+            (RLIMIT_FSIZE, self.output_bytes),
+            (RLIMIT_NOFILE, self.open_files),
+        ];
+        for (resource, cap) in caps {
+            if let Some(cap) = cap {
+                setrlimit_clamped(resource, cap, cap)?;
+            }
         }
-        if let Some(cpu_limit) = self.cpu_limit {
-            setrlimit(RLIMIT_CPU, cpu_limit.0 + 1, cpu_limit.1 + 1)?;
+        #[cfg(target_os = "linux")]
+        if let Some(max_processes) = self.max_processes {
+            setrlimit_clamped(RLIMIT_NPROC, max_processes, max_processes)?;
         }
         Ok(())
     }
 
-    pub fn get_cpu_limit_duration(&self) -> Option<std::time::Duration> {
-        self.cpu_limit
-            .map(|(soft, _)| std::time::Duration::from_secs(soft))
+    /// The duration after which the process is killed by `RLIMIT_CPU`, see `load()`.
+    pub fn get_cpu_kill_duration(&self) -> Option<Duration> {
+        self.cpu_time
+            .map(|cpu_time| Duration::from_secs(cpu_limit_secs(cpu_time) + 2))
+    }
+
+    /// The soft rlimits `load()` asks for, in the order of `EffectiveLimits::from_soft_limits()`,
+    /// `None` for those left as they are.
+    pub(crate) fn requested_soft_limits(&self) -> [Option<u64>; EFFECTIVE_LIMIT_COUNT] {
+        #[cfg(target_os = "linux")]
+        let max_processes = self.max_processes;
+        #[cfg(not(target_os = "linux"))]
+        let max_processes = None;
+        [
+            self.cpu_time.map(|cpu_time| cpu_limit_secs(cpu_time) + 1),
+            self.memory_bytes,
+            self.stack_bytes,
+            self.output_bytes,
+            max_processes,
+            self.open_files,
+        ]
+    }
+
+    /// Whether the current process got at least the soft rlimits `load()` asks for, the host
+    /// lowering none. Doesn't allocate, to be called in a forked child.
+    pub(crate) fn all_loaded(&self) -> bool {
+        EffectiveLimits::read_soft_limits()
+            .iter()
+            .zip(self.requested_soft_limits().iter())
+            .all(|(soft, requested)| requested.is_none_or(|requested| *soft >= requested))
+    }
+
+    /// These limits, with the ones set in `overrides` in place of theirs.
+    fn overridden_by(&self, overrides: &Limits) -> Limits {
+        Limits {
+            cpu_time: overrides.cpu_time.or(self.cpu_time),
+            wall_time: overrides.wall_time.or(self.wall_time),
+            memory_bytes: overrides.memory_bytes.or(self.memory_bytes),
+            stack_bytes: overrides.stack_bytes.or(self.stack_bytes),
+            output_bytes: overrides.output_bytes.or(self.output_bytes),
+            max_processes: overrides.max_processes.or(self.max_processes),
+            max_threads: overrides.max_threads.or(self.max_threads),
+            open_files: overrides.open_files.or(self.open_files),
+            disk_write_bytes: overrides.disk_write_bytes.or(self.disk_write_bytes),
+        }
+    }
+}
+
+fn cpu_limit_secs(cpu_time: Duration) -> u64 {
+    cpu_time.as_secs() + u64::from(cpu_time.subsec_nanos() > 0)
+}
+
+/// `setrlimit()`, lowering both values to the hard limit the process already has when
+/// raising it is not permitted, e.g. `RLIMIT_NOFILE` past `fs.nr_open` even for root.
+///
+/// It runs in the forked child, which can't log: the parent finds what was lowered from the
+/// limits the child reports, see `EffectiveLimits::lowered`.
+fn setrlimit_clamped(resource: Resource, soft: u64, hard: u64) -> Result<(), Errno> {
+    match setrlimit(resource, soft, hard) {
+        Err(Errno::EPERM) => {
+            let (_, ceiling) = getrlimit(resource)?;
+            setrlimit(resource, soft.min(ceiling), hard.min(ceiling))
+        }
+        result => result,
+    }
+}
+
+/// Lower both values of the rlimit to `cap`, keeping the ones already under it.
+#[cfg(target_os = "linux")]
+fn lower_rlimit(resource: Resource, cap: u64) -> Result<(), Errno> {
+    let (soft, hard) = getrlimit(resource)?;
+    setrlimit(resource, soft.min(cap), hard.min(cap))
+}
+
+/// The rlimits of `EffectiveLimits`, in the order of `EffectiveLimits::from_soft_limits()`.
+const EFFECTIVE_LIMIT_COUNT: usize = 6;
+
+/// The rlimits a process really runs with, read back by the process once they are loaded.
+///
+/// They may differ from the requested `Limits` by the rounding of `Limits::load()` or when
+/// the host does not permit as much, so that what was enforced can be audited.
+/// `None` for unlimited.
+///
+/// A limit lowered by the host is listed in `lowered` and logged as a warning by
+/// `Sandbox::spawn()`, the process still runs with it.
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EffectiveLimits {
+    /// The soft `RLIMIT_CPU`, at which the process is signaled, so with the margin of
+    /// `Limits::load()`
+    pub cpu_time: Option<Duration>,
+    pub memory_bytes: Option<u64>,
+    pub stack_bytes: Option<u64>,
+    pub output_bytes: Option<u64>,
+    /// Only read on Linux, like `Limits::max_processes` is only honored there
+    pub max_processes: Option<u64>,
+    pub open_files: Option<u64>,
+    /// The fields above the host set lower than requested
+    pub lowered: Vec<String>,
+}
+
+/// The names of the fields of `EffectiveLimits`, in the order of `from_soft_limits()`.
+const EFFECTIVE_LIMIT_NAMES: [&str; EFFECTIVE_LIMIT_COUNT] = [
+    "cpu_time",
+    "memory_bytes",
+    "stack_bytes",
+    "output_bytes",
+    "max_processes",
+    "open_files",
+];
+
+impl EffectiveLimits {
+    /// The soft rlimits of the current process, `RLIM_INFINITY` when unknown.
+    ///
+    /// Doesn't allocate, to be called in a forked child.
+    fn read_soft_limits() -> [u64; EFFECTIVE_LIMIT_COUNT] {
+        let soft =
+            |resource: Resource| getrlimit(resource).map_or(libc::RLIM_INFINITY, |(soft, _)| soft);
+        #[cfg(target_os = "linux")]
+        let max_processes = soft(RLIMIT_NPROC);
+        #[cfg(not(target_os = "linux"))]
+        let max_processes = libc::RLIM_INFINITY;
+        [
+            soft(RLIMIT_CPU),
+            soft(RLIMIT_AS),
+            soft(RLIMIT_STACK),
+            soft(RLIMIT_FSIZE),
+            max_processes,
+            soft(RLIMIT_NOFILE),
+        ]
+    }
+
+    /// The limits of the soft rlimits `soft` read back, lowered from `requested`
+    /// where below it.
+    fn from_soft_limits(
+        soft: [u64; EFFECTIVE_LIMIT_COUNT],
+        requested: [Option<u64>; EFFECTIVE_LIMIT_COUNT],
+    ) -> Self {
+        let limit = |value: u64| (value != libc::RLIM_INFINITY).then_some(value);
+        let lowered = EFFECTIVE_LIMIT_NAMES
+            .iter()
+            .zip(soft.iter().zip(requested.iter()))
+            .filter(
+                |(_, (soft, requested))| matches!(requested, Some(requested) if *soft < requested),
+            )
+            .map(|(name, _)| name.to_string())
+            .collect();
+        Self {
+            cpu_time: limit(soft[0]).map(Duration::from_secs),
+            memory_bytes: limit(soft[1]),
+            stack_bytes: limit(soft[2]),
+            output_bytes: limit(soft[3]),
+            max_processes: limit(soft[4]),
+            open_files: limit(soft[5]),
+            lowered,
+        }
+    }
+}
+
+/// What the forked process failed at while setting itself up, before running anything, see
+/// `JudgeCoreError::SandboxSetupFailed`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum SandboxSetupStep {
+    /// `Sandbox::set_nice()`
+    Nice = 1,
+    /// `Sandbox::set_cpu_affinity()`
+    CpuAffinity = 2,
+    /// Entering the pids cgroup of `Sandbox::set_thread_limit()`
+    PidsCgroup = 3,
+    /// Lowering `RLIMIT_NPROC` to `Sandbox::set_thread_limit()` without a pids cgroup
+    ThreadLimit = 4,
+    /// Entering the directory of `Sandbox::set_core_dump_dir()`
+    CoreDumpDir = 5,
+    /// Loading the limits of the sandbox as rlimits, see `Limits::load()`
+    Rlimits = 6,
+}
+
+impl SandboxSetupStep {
+    const ALL: [SandboxSetupStep; 6] = [
+        SandboxSetupStep::Nice,
+        SandboxSetupStep::CpuAffinity,
+        SandboxSetupStep::PidsCgroup,
+        SandboxSetupStep::ThreadLimit,
+        SandboxSetupStep::CoreDumpDir,
+        SandboxSetupStep::Rlimits,
+    ];
+
+    fn from_u8(step: u8) -> Option<Self> {
+        Self::ALL.iter().copied().find(|known| *known as u8 == step)
+    }
+
+    /// What was being done, for messages.
+    pub fn description(self) -> &'static str {
+        match self {
+            SandboxSetupStep::Nice => "set the niceness",
+            SandboxSetupStep::CpuAffinity => "set the CPU affinity",
+            SandboxSetupStep::PidsCgroup => "enter the pids cgroup",
+            SandboxSetupStep::ThreadLimit => "lower RLIMIT_NPROC to the thread limit",
+            SandboxSetupStep::CoreDumpDir => "enter the core dump directory",
+            SandboxSetupStep::Rlimits => "set the rlimits",
+        }
+    }
+}
+
+/// The first byte of the report of the child, followed by the step and the errno on failure.
+const SETUP_DONE: u8 = 0;
+const SETUP_FAILED: u8 = 1;
+
+/// Report that the setup of the forked child failed at `step` with `errno` and exit, for the
+/// parent to fail `spawn()` instead of judging a process which never ran the program.
+///
+/// Doesn't allocate, to be called in a forked child.
+fn exit_setup_failed(report_fd: RawFd, step: SandboxSetupStep, errno: Errno) -> ! {
+    let mut buf = [SETUP_FAILED, step as u8, 0, 0, 0, 0];
+    buf[2..].copy_from_slice(&(errno as i32).to_ne_bytes());
+    unsafe {
+        libc::write(report_fd, buf.as_ptr() as *const libc::c_void, buf.len());
+        libc::_exit(1)
+    }
+}
+
+/// The soft limits of the configs, the wall time is left unlimited.
+impl From<&RlimitConfigs> for Limits {
+    fn from(configs: &RlimitConfigs) -> Self {
+        let soft = |limit: Option<(u64, u64)>| limit.map(|(soft, _)| soft);
+        Self {
+            cpu_time: configs.get_cpu_limit_duration(),
+            wall_time: None,
+            memory_bytes: soft(configs.as_limit),
+            stack_bytes: soft(configs.stack_limit),
+            output_bytes: soft(configs.fsize_limit),
+            max_processes: soft(configs.nproc_limit),
+            max_threads: None,
+            open_files: soft(configs.open_files_limit),
+            disk_write_bytes: None,
+        }
     }
 }
 
 pub struct Sandbox {
     pub child_pid: i32,
 
+    child: Option<Child>,
     user_id: u32,
-    rlimit_configs: Option<RlimitConfigs>,
-    scmp_filter: Option<ScmpFilterContext>,
+    /// The rlimit configs given to `new()`, then with `set_limits()` on top
+    limits: Option<Limits>,
+    scmp_filter: Option<Box<dyn SyscallFilterLoader>>,
+    seccomp_mode: SeccompMode,
+    wall_time_limit: Option<Duration>,
+    nice: Option<i32>,
+    cpu_affinity: Option<Vec<usize>>,
+    idle_time_limit: Option<Duration>,
+    disk_write_limit: Option<u64>,
+    thread_limit: Option<u64>,
+    #[cfg(target_os = "linux")]
+    pids_cgroup: Option<PidsCgroup>,
+    kill_grace_period: Duration,
+    reap_timeout: Duration,
+    core_dump_dir: Option<PathBuf>,
+    /// The directory of `core_dump_dir` the spawned process dumps its core to
+    core_dump_run_dir: Option<PathBuf>,
+    baseline_rss: i64,
+    effective_limits: Option<EffectiveLimits>,
+    /// Kept between the calls of `try_wait()`
+    idle_watch: Option<IdleWatch>,
 
+    clock: Arc<dyn Clock>,
     begin_time: Instant,
+    begin_timestamp: SystemTime,
 }
 
 impl Sandbox {
     pub fn new(
         rlimit_configs: Option<RlimitConfigs>,
-        scmp_filter: Option<ScmpFilterContext>,
+        scmp_filter: Option<SyscallFilter>,
     ) -> Result<Self, JudgeCoreError> {
         let child_pid = -1;
-        let begin_time = Instant::now();
+        let clock: Arc<dyn Clock> = Arc::new(MonotonicClock);
+        let begin_time = clock.now();
+        let begin_timestamp = SystemTime::now();
 
-        // Run `id -u $SANDBOX_USERNAME` to get the user id
-        let output = Command::new("id")
-            .arg("-u")
-            .arg(SANDBOX_USERNAME)
-            .output()
-            .map_err(|e| JudgeCoreError::AnyhowError(e.into()))?;
-        let user_id = String::from_utf8(output.stdout)
-            .map_err(|e| JudgeCoreError::AnyhowError(e.into()))?
-            .trim()
-            .parse::<u32>()
-            .map_err(|e| JudgeCoreError::AnyhowError(e.into()))?;
+        let user_id = sandbox_user_id()?;
         if unsafe { libc::getuid() == 0 } {
             log::debug!("Sandbox user id: {}", user_id);
         }
+        #[cfg(not(target_os = "linux"))]
+        log::warn!("Using the portable sandbox, isolation of the process is weak");
         Ok(Self {
             user_id,
-            rlimit_configs,
-            scmp_filter,
+            limits: rlimit_configs.as_ref().map(RlimitConfigs::loaded_limits),
+            scmp_filter: scmp_filter
+                .map(|scmp_filter| Box::new(scmp_filter) as Box<dyn SyscallFilterLoader>),
+            seccomp_mode: SeccompMode::default(),
             child_pid,
+            child: None,
+            wall_time_limit: None,
+            nice: None,
+            idle_time_limit: None,
+            disk_write_limit: None,
+            thread_limit: None,
+            #[cfg(target_os = "linux")]
+            pids_cgroup: None,
+            kill_grace_period: Duration::ZERO,
+            reap_timeout: DEFAULT_REAP_TIMEOUT,
+            core_dump_dir: None,
+            core_dump_run_dir: None,
+            cpu_affinity: None,
+            baseline_rss: 0,
+            effective_limits: None,
+            idle_watch: None,
+            clock,
             begin_time,
+            begin_timestamp,
         })
     }
 
+    /// Replace the clock measuring `real_time_cost` and the limits in time, i.e. the wall time
+    /// and idle time limits, `kill_grace_period` and the reap timeout. Takes effect from the
+    /// next `spawn()`.
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    /// Kill the process once it has run for `limit` of real time, whatever its CPU time is.
+    pub fn set_wall_time_limit(&mut self, limit: Option<Duration>) {
+        self.wall_time_limit = limit;
+    }
+
+    /// Cap the process with `limits`, on top of the rlimit configs given to `new()`: those
+    /// set in `limits` replace theirs.
+    ///
+    /// `Limits::wall_time`, `Limits::disk_write_bytes` and `Limits::max_threads` replace
+    /// the wall time limit, the disk write limit and the thread limit, if set.
+    pub fn set_limits(&mut self, limits: Limits) {
+        if limits.wall_time.is_some() {
+            self.wall_time_limit = limits.wall_time;
+        }
+        if limits.disk_write_bytes.is_some() {
+            self.set_disk_write_limit(limits.disk_write_bytes);
+        }
+        if limits.max_threads.is_some() {
+            self.set_thread_limit(limits.max_threads);
+        }
+        self.limits = Some(match &self.limits {
+            Some(configured) => configured.overridden_by(&limits),
+            None => limits,
+        });
+    }
+
+    /// Replace the syscall filter given to `new()`.
+    pub fn set_syscall_filter(&mut self, scmp_filter: Option<Box<dyn SyscallFilterLoader>>) {
+        self.scmp_filter = scmp_filter;
+    }
+
+    pub fn set_seccomp_mode(&mut self, seccomp_mode: SeccompMode) {
+        self.seccomp_mode = seccomp_mode;
+    }
+
+    /// Niceness of the process, applied before dropping the privileges.
+    pub fn set_nice(&mut self, nice: Option<i32>) {
+        self.nice = nice;
+    }
+
+    /// Cores the process may run on, applied before dropping the privileges.
+    pub fn set_cpu_affinity(&mut self, cpu_affinity: Option<Vec<usize>>) {
+        #[cfg(not(target_os = "linux"))]
+        if cpu_affinity.is_some() {
+            log::warn!("CPU affinity is not supported on this platform, ignoring it");
+        }
+        self.cpu_affinity = cpu_affinity;
+    }
+
+    /// Time given to the process to exit on `SIGTERM` before it is sent `SIGKILL`,
+    /// when it is killed for its limits. Zero sends `SIGKILL` right away.
+    pub fn set_kill_grace_period(&mut self, grace_period: Duration) {
+        self.kill_grace_period = grace_period;
+    }
+
+    /// Time the process is given to be reaped once sent `SIGKILL` for its limits, after which
+    /// it is reaped in the background and waiting fails with `JudgeCoreError::ProcessUnkillable`.
+    /// A process in uninterruptible sleep, e.g. on a stuck I/O, doesn't die until it wakes up.
+    pub fn set_reap_timeout(&mut self, timeout: Duration) {
+        self.reap_timeout = timeout;
+    }
+
+    pub fn reap_timeout(&self) -> Duration {
+        self.reap_timeout
+    }
+
+    /// Let the process dump its core when it crashes, into a directory of its own in `dir`
+    /// which is also its working directory, reported in `SandboxExitInfo::core_dump`.
+    /// Relative paths of its command line are resolved from there.
+    ///
+    /// Where the kernel writes the core is decided by `/proc/sys/kernel/core_pattern` for the
+    /// whole host, it is only found in `dir` when the pattern is the default `core`, which may
+    /// be suffixed by `.<pid>`. Nothing is written when it pipes cores to a program, e.g.
+    /// `systemd-coredump`.
+    /// A program the sandbox user may not read is never dumped either, and the core is cut
+    /// at the file size limit of the process.
+    pub fn set_core_dump_dir(&mut self, dir: Option<PathBuf>) {
+        if dir.is_some() {
+            match fs::read_to_string(CORE_PATTERN_PATH) {
+                Ok(pattern) if pattern.starts_with('/') || pattern.starts_with('|') => {
+                    log::warn!(
+                        "The core pattern of the host is {:?}, cores will not land in the core dump directory",
+                        pattern.trim_end()
+                    );
+                }
+                _ => {}
+            }
+        }
+        self.core_dump_dir = dir;
+    }
+
+    /// Kill the process once it is idle for `limit`, see `IdleWatch` for the heuristic.
+    /// Only honored on Linux.
+    pub fn set_idle_time_limit(&mut self, limit: Option<Duration>) {
+        #[cfg(not(target_os = "linux"))]
+        if limit.is_some() {
+            log::warn!("Idleness detection is not supported on this platform, ignoring it");
+        }
+        self.idle_time_limit = limit;
+    }
+
+    /// Kill the process once it has written more than `limit` bytes to disk-backed
+    /// filesystems, the output file of a redirected stdout included. Only honored on Linux.
+    ///
+    /// Writes are counted by the kernel as pages are dirtied, so a page rewritten before
+    /// reaching the disk counts once, and nothing written to a tmpfs is counted.
+    pub fn set_disk_write_limit(&mut self, limit: Option<u64>) {
+        #[cfg(not(target_os = "linux"))]
+        if limit.is_some() {
+            log::warn!("Disk write limits are not supported on this platform, ignoring it");
+        }
+        self.disk_write_limit = limit;
+    }
+
+    /// Let the process and its children have at most `limit` tasks at once, each thread
+    /// counting as one, the process itself included. Creating one more fails with `EAGAIN`.
+    /// Only honored on Linux.
+    ///
+    /// The tasks are counted by a pids cgroup of the sandbox when the host has a hierarchy
+    /// the judger may write to, removed with the sandbox. Otherwise `RLIMIT_NPROC` is lowered to `limit`,
+    /// which counts every task of the sandbox user, those of other sandboxes included,
+    /// and nothing when the judger is not root, as the sandbox user is then the judger's.
+    pub fn set_thread_limit(&mut self, limit: Option<u64>) {
+        #[cfg(not(target_os = "linux"))]
+        if limit.is_some() {
+            log::warn!("Thread limits are not supported on this platform, ignoring it");
+        }
+        self.thread_limit = limit;
+    }
+
+    /// The soft rlimits the process asks for when loading its limits, in the order of
+    /// `EffectiveLimits::from_soft_limits()`, `None` for those left as they are.
+    fn requested_soft_limits(&self) -> [Option<u64>; EFFECTIVE_LIMIT_COUNT] {
+        #[allow(unused_mut)]
+        let mut requested = self
+            .limits
+            .as_ref()
+            .map_or([None; EFFECTIVE_LIMIT_COUNT], Limits::requested_soft_limits);
+        #[cfg(target_os = "linux")]
+        if let (Some(thread_limit), None) = (self.thread_limit, &self.pids_cgroup) {
+            requested[4] = Some(requested[4].map_or(thread_limit, |cap| cap.min(thread_limit)));
+        }
+        requested
+    }
+
+    /// When the process should be killed by wall-clock, if ever.
+    fn get_deadline(&self) -> Option<Instant> {
+        #[allow(unused_mut)]
+        let mut limit = self.wall_time_limit;
+        // rlimit may not be honored on other platforms, so the CPU limit is a wall-clock one too
+        #[cfg(not(target_os = "linux"))]
+        if let Some(cpu_kill) = self.limits.as_ref().and_then(Limits::get_cpu_kill_duration) {
+            limit = Some(limit.map_or(cpu_kill, |limit| limit.min(cpu_kill)));
+        }
+        limit.map(|limit| self.begin_time + limit)
+    }
+
     pub fn wait(&self) -> Result<SandboxExitInfo, JudgeCoreError> {
+        let child = self.unreaped_child()?;
         let mut status: c_int = 0;
         let mut usage: rusage = get_default_rusage();
-        unsafe {
-            wait4(self.child_pid, &mut status, WSTOPPED, &mut usage);
+        let mut kill_cause = None;
+        // `usage` is filled for this very child only, never aggregated with the others
+        match (
+            self.get_deadline(),
+            self.idle_time_limit,
+            self.disk_write_limit,
+        ) {
+            (None, None, None) if !child.is_listening() => {
+                self.wait4_blocking(&mut status, &mut usage)?
+            }
+            (deadline, idle_time_limit, disk_write_limit) => {
+                let mut idle_watch = idle_time_limit
+                    .map(|limit| IdleWatch::new(self.child_pid, limit, self.clock.now()));
+                let disk_watch = disk_write_limit.map(|limit| DiskWatch {
+                    pid: self.child_pid,
+                    limit,
+                });
+                kill_cause = wait4_with_deadline(
+                    child,
+                    self.clock.as_ref(),
+                    &mut status,
+                    &mut usage,
+                    deadline,
+                    idle_watch.as_mut(),
+                    disk_watch.as_ref(),
+                    self.kill_grace_period,
+                    self.reap_timeout,
+                )?;
+            }
         }
 
         log::info!("Detected process pid={} exit", self.child_pid);
+        Ok(self.exit_info(status, usage, kill_cause))
+    }
+
+    /// `wait()` without blocking, `None` while the process is running.
+    ///
+    /// The limits are checked on each call, a process past one is killed and reaped then,
+    /// waiting out `kill_grace_period` if it has to. Call it until it returns the exit,
+    /// after which the process is gone and calling it again is an error. See `exit_fd()`
+    /// to know when to call it.
+    pub fn try_wait(&mut self) -> Result<Option<SandboxExitInfo>, JudgeCoreError> {
+        self.unreaped_child()?;
+        let mut status: c_int = 0;
+        let mut usage: rusage = get_default_rusage();
+        let mut idle_watch = self.idle_watch.take().or_else(|| {
+            self.idle_time_limit
+                .map(|limit| IdleWatch::new(self.child_pid, limit, self.clock.now()))
+        });
+        let disk_watch = self.disk_write_limit.map(|limit| DiskWatch {
+            pid: self.child_pid,
+            limit,
+        });
+        let polled = poll_exit(
+            self.spawned_child()?,
+            self.clock.as_ref(),
+            &mut status,
+            &mut usage,
+            self.get_deadline(),
+            idle_watch.as_mut(),
+            disk_watch.as_ref(),
+            self.kill_grace_period,
+            self.reap_timeout,
+        );
+        self.idle_watch = idle_watch;
+        let kill_cause = match polled? {
+            ExitPoll::Exited(kill_cause) => kill_cause,
+            ExitPoll::Running => return Ok(None),
+        };
+        log::info!("Detected process pid={} exit", self.child_pid);
+        Ok(Some(self.exit_info(status, usage, kill_cause)))
+    }
+
+    /// Take the listener of the syscall filter of the child at `listener_fd`, if it has one,
+    /// returning whether it was taken.
+    ///
+    /// Without it, the child loads the fallback of the filter, see
+    /// `SyscallFilterLoader::load_fallback()`, as the listener is closed once it execs.
+    fn listen_to_filter(&mut self, listener_fd: RawFd) -> bool {
+        if listener_fd < 0 {
+            return false;
+        }
+        match self.child.as_mut() {
+            Some(child) => match child.listen_to_filter(listener_fd) {
+                Ok(()) => true,
+                Err(e) => {
+                    log::warn!(
+                        "Failed to take the syscall filter listener of pid={}, falling back: {}",
+                        child.pid(),
+                        e
+                    );
+                    false
+                }
+            },
+            None => false,
+        }
+    }
+
+    /// A pidfd of the process, readable once it exits, to watch it in an event loop
+    /// along with others. Its limits are only checked by `try_wait()`, which should
+    /// also be called every now and then when any is set.
+    ///
+    /// Fails with `ENOSYS` where the kernel has no pidfds, see `has_pidfd()`.
+    pub fn exit_fd(&self) -> Result<OwnedFd, JudgeCoreError> {
+        Ok(self.spawned_child()?.open_pidfd()?)
+    }
+
+    /// Whether the process is signaled through a pidfd, immune to the reuse of its pid once
+    /// it is reaped. Only on Linux 5.3 and later, the pid is used otherwise.
+    pub fn has_pidfd(&self) -> bool {
+        self.child.as_ref().is_some_and(Child::has_pidfd)
+    }
 
-        Ok(SandboxExitInfo {
+    fn spawned_child(&self) -> Result<&Child, JudgeCoreError> {
+        self.child.as_ref().ok_or_else(|| {
+            JudgeCoreError::AnyhowError(anyhow::anyhow!("The sandbox has not spawned its process"))
+        })
+    }
+
+    /// The spawned process, as long as it is not reaped: its pid may belong to anyone after.
+    fn unreaped_child(&self) -> Result<&Child, JudgeCoreError> {
+        let child = self.spawned_child()?;
+        if child.is_reaped() {
+            return Err(JudgeCoreError::AnyhowError(anyhow::anyhow!(
+                "The process pid={} has already been waited for",
+                child.pid()
+            )));
+        }
+        Ok(child)
+    }
+
+    fn exit_info(
+        &self,
+        status: c_int,
+        usage: rusage,
+        kill_cause: Option<KillCause>,
+    ) -> SandboxExitInfo {
+        SandboxExitInfo {
             exit_status: status,
             exit_signal: WTERMSIG(status),
             exit_code: WEXITSTATUS(status),
-            real_time_cost: self.begin_time.elapsed(),
+            real_time_cost: self.clock.now().duration_since(self.begin_time),
             resource_usage: Rusage::from(usage),
-        })
+            baseline_rss: self.baseline_rss,
+            effective_limits: self.effective_limits.clone(),
+            idle_killed: kill_cause == Some(KillCause::Idle),
+            disk_limit_killed: kill_cause == Some(KillCause::DiskWrite),
+            subprocess_killed: matches!(kill_cause, Some(KillCause::Subprocess(_))),
+            blocked_syscall: match kill_cause {
+                Some(KillCause::Subprocess(nr) | KillCause::Syscall(nr)) => Some(syscall_name(nr)),
+                _ => None,
+            },
+            stdin_limit_killed: false,
+            core_dump: self
+                .core_dump_run_dir
+                .as_deref()
+                .and_then(|run_dir| find_core_dump(run_dir, self.child_pid, status)),
+            start_timestamp_ms: get_unix_timestamp_ms(self.begin_timestamp),
+            end_timestamp_ms: get_unix_timestamp_ms(SystemTime::now()),
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    fn wait4_blocking(&self, status: &mut c_int, usage: &mut rusage) -> Result<(), JudgeCoreError> {
+        loop {
+            let res = unsafe { libc::wait4(self.child_pid, status, libc::WSTOPPED, usage) };
+            if res != -1 {
+                self.spawned_child()?.mark_reaped();
+                return Ok(());
+            }
+            match Errno::last() {
+                Errno::EINTR => continue,
+                e => return Err(JudgeCoreError::NixErrno(e)),
+            }
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn wait4_blocking(&self, status: &mut c_int, usage: &mut rusage) -> Result<(), JudgeCoreError> {
+        loop {
+            let res = unsafe { libc::wait4(self.child_pid, status, 0, usage) };
+            if res != -1 {
+                self.spawned_child()?.mark_reaped();
+                return Ok(());
+            }
+            match Errno::last() {
+                Errno::EINTR => continue,
+                e => return Err(JudgeCoreError::NixErrno(e)),
+            }
+        }
+    }
+
+    /// Kill the process with `SIGKILL`, along with the process group it leads. It still has
+    /// to be reaped by `wait()`.
+    ///
+    /// Once it is reaped, this fails with `ESRCH` rather than signal whatever process
+    /// reuses its pid.
+    pub fn kill(&self) -> Result<(), JudgeCoreError> {
+        Ok(self.spawned_child()?.signal_group(libc::SIGKILL)?)
     }
 
     /// WARNING:   
@@ -131,17 +947,173 @@ impl Sandbox {
         before_limit: impl Fn(),
         after_limit: impl Fn(),
     ) -> Result<i32, JudgeCoreError> {
-        let now = Instant::now();
+        let now = self.clock.now();
+        let now_timestamp = SystemTime::now();
+        // The child reports whether it is set up, its baseline RSS and its limits, then whether
+        // the syscall filter is loaded and the fd of its listener, which it keeps open until
+        // acknowledged
+        let (report_read, report_write) = pipe()?;
+        let (ack_read, ack_write) = pipe()?;
+        self.core_dump_run_dir = self
+            .core_dump_dir
+            .as_deref()
+            .map(|dir| create_core_dump_run_dir(dir, self.user_id))
+            .transpose()?;
+        let core_dump_cwd = self
+            .core_dump_run_dir
+            .as_ref()
+            .map(|run_dir| CString::new(run_dir.as_os_str().as_bytes()))
+            .transpose()
+            .map_err(|e| JudgeCoreError::AnyhowError(e.into()))?;
+        #[cfg(target_os = "linux")]
+        {
+            self.pids_cgroup = None;
+            self.pids_cgroup = self.thread_limit.and_then(PidsCgroup::create);
+            if self.thread_limit.is_some() && self.pids_cgroup.is_none() {
+                log::warn!("No pids cgroup for the thread limit, lowering RLIMIT_NPROC instead");
+            }
+        }
         match unsafe { fork() } {
             Ok(ForkResult::Parent { child, .. }) => {
                 log::info!("Forked child pid={}", child);
+                // Also done by the child, whichever runs first
+                unsafe { libc::setpgid(child.as_raw(), child.as_raw()) };
                 self.child_pid = child.as_raw();
+                self.child = Some(Child::new(child.as_raw()));
+                self.idle_watch = None;
                 self.begin_time = now;
+                self.begin_timestamp = now_timestamp;
+                drop(report_write);
+                drop(ack_read);
+                let mut report = File::from(report_read);
+                let mut setup = [SETUP_DONE];
+                if report.read_exact(&mut setup).is_ok() && setup[0] == SETUP_FAILED {
+                    let mut failure = [0u8; 5];
+                    report.read_exact(&mut failure)?;
+                    let mut status: c_int = 0;
+                    unsafe { libc::waitpid(self.child_pid, &mut status, 0) };
+                    self.spawned_child()?.mark_reaped();
+                    let step = SandboxSetupStep::from_u8(failure[0]).ok_or_else(|| {
+                        JudgeCoreError::AnyhowError(anyhow::anyhow!(
+                            "Unknown setup step {} of pid={}",
+                            failure[0],
+                            child
+                        ))
+                    })?;
+                    let errno = Errno::from_raw(i32::from_ne_bytes([
+                        failure[1], failure[2], failure[3], failure[4],
+                    ]));
+                    log::error!(
+                        "Failed to {} for pid={}: {}",
+                        step.description(),
+                        child,
+                        errno
+                    );
+                    return Err(JudgeCoreError::SandboxSetupFailed { step, errno });
+                }
+                // The child may die before reporting, count nothing in that case
+                let mut buf = [0u8; 8];
+                self.baseline_rss = match report.read_exact(&mut buf) {
+                    Ok(()) => i64::from_ne_bytes(buf),
+                    Err(_) => 0,
+                };
+                let mut soft_limits = [0u64; EFFECTIVE_LIMIT_COUNT];
+                self.effective_limits = soft_limits
+                    .iter_mut()
+                    .try_for_each(|limit| {
+                        report.read_exact(&mut buf)?;
+                        *limit = u64::from_ne_bytes(buf);
+                        Ok::<_, std::io::Error>(())
+                    })
+                    .ok()
+                    .map(|()| {
+                        EffectiveLimits::from_soft_limits(soft_limits, self.requested_soft_limits())
+                    });
+                if let Some(effective_limits) = &self.effective_limits {
+                    if !effective_limits.lowered.is_empty() {
+                        log::warn!(
+                            "The host lowered the limits {:?} of pid={}: {:?}",
+                            effective_limits.lowered,
+                            child,
+                            effective_limits
+                        );
+                    }
+                }
+                let mut filter_loaded = [1u8];
+                let filter_reported = report.read_exact(&mut filter_loaded).is_ok();
+                let mut listener_fd = [0u8; 4];
+                if report.read_exact(&mut listener_fd).is_ok()
+                    && self.listen_to_filter(i32::from_ne_bytes(listener_fd))
+                {
+                    let _ = File::from(ack_write).write_all(&[1]);
+                } else {
+                    drop(ack_write);
+                }
+                if filter_reported && filter_loaded[0] == 0 {
+                    if self.seccomp_mode == SeccompMode::Required {
+                        // The child exits by itself, don't leave a zombie
+                        let mut status: c_int = 0;
+                        unsafe { libc::waitpid(self.child_pid, &mut status, 0) };
+                        self.spawned_child()?.mark_reaped();
+                        return Err(JudgeCoreError::SeccompSetupFailed(format!(
+                            "Failed to load the syscall filter for pid={}",
+                            child
+                        )));
+                    }
+                    log::warn!(
+                        "Failed to load the syscall filter for pid={}, running without it",
+                        child
+                    );
+                }
                 Ok(child.as_raw())
             }
             // child process should not return to do things outside `spawn()`
             Ok(ForkResult::Child) => {
+                drop(report_read);
+                let report_fd = report_write.as_raw_fd();
+                // Leading a process group of its own, whatever it starts is killed with it
+                unsafe { libc::setpgid(0, 0) };
                 before_limit();
+                // Entering a cgroup needs the privileges about to be dropped
+                #[cfg(target_os = "linux")]
+                if let Some(pids_cgroup) = &self.pids_cgroup {
+                    if let Err(errno) = pids_cgroup.enter() {
+                        exit_setup_failed(report_fd, SandboxSetupStep::PidsCgroup, errno);
+                    }
+                }
+                if let Some(nice) = self.nice {
+                    if unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, nice) } != 0 {
+                        exit_setup_failed(report_fd, SandboxSetupStep::Nice, Errno::last());
+                    }
+                }
+                #[cfg(target_os = "linux")]
+                if let Some(cpu_affinity) = &self.cpu_affinity {
+                    let mut cpu_set = CpuSet::new();
+                    let affinity_set = cpu_affinity
+                        .iter()
+                        .try_for_each(|&cpu| cpu_set.set(cpu))
+                        .and_then(|()| sched_setaffinity(Pid::from_raw(0), &cpu_set));
+                    if let Err(errno) = affinity_set {
+                        exit_setup_failed(report_fd, SandboxSetupStep::CpuAffinity, errno);
+                    }
+                }
+                // The kernel writes the core to the working directory with a relative
+                // core pattern, only root may raise the hard limit
+                if let Some(core_dump_cwd) = &core_dump_cwd {
+                    if unsafe { libc::chdir(core_dump_cwd.as_ptr()) } != 0 {
+                        exit_setup_failed(report_fd, SandboxSetupStep::CoreDumpDir, Errno::last());
+                    }
+                    let unlimited = setrlimit(
+                        Resource::RLIMIT_CORE,
+                        libc::RLIM_INFINITY,
+                        libc::RLIM_INFINITY,
+                    );
+                    if unlimited.is_err() {
+                        if let Ok((_, hard)) = getrlimit(Resource::RLIMIT_CORE) {
+                            let _ = setrlimit(Resource::RLIMIT_CORE, hard, hard);
+                        }
+                    }
+                }
                 // If current user is root
                 // setuid to the user
                 if unsafe { libc::getuid() == 0 } {
@@ -152,13 +1124,91 @@ impl Sandbox {
                         }
                     }
                 }
-                if let Some(rlimit_configs) = &self.rlimit_configs {
-                    rlimit_configs
-                        .load()
-                        .expect("Failed to load rlimit configs");
+                if let Some(limits) = &self.limits {
+                    if let Err(errno) = limits.load_rlimits() {
+                        exit_setup_failed(report_fd, SandboxSetupStep::Rlimits, errno);
+                    }
+                }
+                #[cfg(target_os = "linux")]
+                if let (Some(thread_limit), None) = (self.thread_limit, &self.pids_cgroup) {
+                    if let Err(errno) = lower_rlimit(RLIMIT_NPROC, thread_limit) {
+                        exit_setup_failed(report_fd, SandboxSetupStep::ThreadLimit, errno);
+                    }
+                }
+                // Everything resident so far is inherited from the judger,
+                // report it before the filter may forbid `getrusage`
+                unsafe {
+                    let buf = [SETUP_DONE];
+                    libc::write(
+                        report_write.as_raw_fd(),
+                        buf.as_ptr() as *const libc::c_void,
+                        buf.len(),
+                    );
+                    let mut usage = get_default_rusage();
+                    libc::getrusage(libc::RUSAGE_SELF, &mut usage);
+                    let buf = usage.ru_maxrss.to_ne_bytes();
+                    libc::write(
+                        report_write.as_raw_fd(),
+                        buf.as_ptr() as *const libc::c_void,
+                        buf.len(),
+                    );
+                    for limit in EffectiveLimits::read_soft_limits() {
+                        let buf = limit.to_ne_bytes();
+                        libc::write(
+                            report_write.as_raw_fd(),
+                            buf.as_ptr() as *const libc::c_void,
+                            buf.len(),
+                        );
+                    }
+                }
+                let (filter_loaded, listener_fd) = match &self.scmp_filter {
+                    Some(scmp_filter) if self.seccomp_mode != SeccompMode::Off => {
+                        match scmp_filter.load() {
+                            Ok(()) => (true, scmp_filter.listener_fd().unwrap_or(-1)),
+                            Err(_) => (false, -1),
+                        }
+                    }
+                    _ => (true, -1),
+                };
+                unsafe {
+                    let buf = [filter_loaded as u8];
+                    libc::write(
+                        report_write.as_raw_fd(),
+                        buf.as_ptr() as *const libc::c_void,
+                        buf.len(),
+                    );
+                    let buf = listener_fd.to_ne_bytes();
+                    libc::write(
+                        report_write.as_raw_fd(),
+                        buf.as_ptr() as *const libc::c_void,
+                        buf.len(),
+                    );
+                }
+                drop(report_write);
+                drop(ack_write);
+                if listener_fd >= 0 {
+                    // Written to by the parent once it has taken the listener, closed otherwise
+                    let mut ack = [0u8];
+                    let listened = unsafe {
+                        libc::read(
+                            ack_read.as_raw_fd(),
+                            ack.as_mut_ptr() as *mut libc::c_void,
+                            ack.len(),
+                        )
+                    } == 1;
+                    // Never run with what the filter holds failing softly instead
+                    let fallback_loaded = listened
+                        || self
+                            .scmp_filter
+                            .as_ref()
+                            .is_none_or(|scmp_filter| scmp_filter.load_fallback().is_ok());
+                    if !fallback_loaded {
+                        unsafe { libc::_exit(1) };
+                    }
                 }
-                if let Some(scmp_filter) = &self.scmp_filter {
-                    scmp_filter.load().expect("Failed to load seccomp filter");
+                drop(ack_read);
+                if !filter_loaded && self.seccomp_mode == SeccompMode::Required {
+                    unsafe { libc::_exit(1) };
                 }
                 after_limit();
                 unsafe { libc::_exit(0) };
@@ -168,23 +1218,119 @@ impl Sandbox {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl Drop for Sandbox {
+    /// Kill and reap the process when it was not waited for, e.g. on an error between
+    /// `spawn()` and `wait()`, so that it is neither left running nor as a zombie.
+    fn drop(&mut self) {
+        let child = match self.child.take() {
+            Some(child) if !child.is_reaped() => child,
+            _ => return,
+        };
+        log::debug!(
+            "Killing process pid={} which was not waited for",
+            child.pid()
+        );
+        let mut status: c_int = 0;
+        let mut usage = get_default_rusage();
+        if let Err(e) = terminate(
+            &child,
+            self.clock.as_ref(),
+            &mut status,
+            &mut usage,
+            Duration::ZERO,
+            self.reap_timeout,
+        ) {
+            log::warn!("Failed to clean up process pid={}: {:?}", child.pid(), e);
+        }
+    }
+}
+
+const CORE_PATTERN_PATH: &str = "/proc/sys/kernel/core_pattern";
+
+static CORE_DUMP_DIR_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// A new directory in `dir` for the core of one process, owned by the sandbox user
+/// so that the kernel may write it there. Absolute, as the process changes to it.
+fn create_core_dump_run_dir(dir: &Path, user_id: u32) -> Result<PathBuf, JudgeCoreError> {
+    let run_dir = dir.join(format!(
+        "core-{}-{}",
+        std::process::id(),
+        CORE_DUMP_DIR_COUNTER.fetch_add(1, Ordering::SeqCst)
+    ));
+    fs::create_dir_all(&run_dir)?;
+    let run_dir = fs::canonicalize(run_dir)?;
+    if unsafe { libc::getuid() == 0 } {
+        std::os::unix::fs::chown(&run_dir, Some(user_id), None)?;
+    }
+    Ok(run_dir)
+}
+
+/// The core dumped to `run_dir` by process `pid` exited with `status`, named `core`, or
+/// `core.<pid>` with `kernel.core_uses_pid`. Other files are the writes of the process, never
+/// taken for its core. `run_dir` is removed when it is left empty.
+fn find_core_dump(run_dir: &Path, pid: i32, status: c_int) -> Option<PathBuf> {
+    // Only removed when empty
+    let _ = fs::remove_dir(run_dir);
+    if !(libc::WIFSIGNALED(status) && libc::WCOREDUMP(status)) {
+        return None;
+    }
+    [format!("core.{}", pid), "core".to_owned()]
+        .iter()
+        .map(|name| run_dir.join(name))
+        .find(|path| fs::symlink_metadata(path).is_ok_and(|metadata| metadata.is_file()))
+}
+
+/// Raw information of an exited sandbox process.
+///
+/// Fields are kept in fixed-size types so that the serialized form
+/// is the same regardless of the platform.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SandboxExitInfo {
-    pub exit_status: c_int,
-    pub exit_signal: c_int,
-    pub exit_code: c_int,
+    /// The raw status word returned by `wait4`
+    pub exit_status: i32,
+    pub exit_signal: i32,
+    pub exit_code: i32,
     pub real_time_cost: Duration,
     pub resource_usage: Rusage,
+    /// Max RSS the child already had right before `execve`, mostly pages of the judger it
+    /// shares since `fork()`. Counted in `resource_usage.max_rss`, which `get_max_mem()`
+    /// subtracts it from.
+    pub baseline_rss: i64,
+    /// The limits the process ran with, `None` when it died before reporting them
+    pub effective_limits: Option<EffectiveLimits>,
+    /// Killed for being idle, see `Sandbox::set_idle_time_limit()`
+    pub idle_killed: bool,
+    /// Killed for writing too much, see `Sandbox::set_disk_write_limit()`
+    pub disk_limit_killed: bool,
+    /// Killed for trying to start a subprocess, see `SyscallFilterLoader::listener_fd()`
+    pub subprocess_killed: bool,
+    /// The system call the syscall filter held the process in when it was killed for it, see
+    /// `SyscallFilterLoader::listener_fd()`. `None` when the filter killed it right away
+    /// with `SIGSYS`, which doesn't tell.
+    pub blocked_syscall: Option<String>,
+    /// Killed for reading its stdin past `RuntimeConfig::max_stdin_bytes`, only set by the
+    /// judge feeding it
+    pub stdin_limit_killed: bool,
+    /// The core the process dumped, see `Sandbox::set_core_dump_dir()`
+    pub core_dump: Option<PathBuf>,
+    /// Unix timestamp in milliseconds when the process was spawned
+    pub start_timestamp_ms: u64,
+    /// Unix timestamp in milliseconds when the process was reaped
+    pub end_timestamp_ms: u64,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Rusage {
     pub user_time: Duration,
     pub system_time: Duration,
     pub max_rss: i64,
+    /// Major page faults, which required I/O
     pub page_faults: i64,
+    pub minor_page_faults: i64,
     pub involuntary_context_switches: i64,
     pub voluntary_context_switches: i64,
+    /// Blocks of 512 bytes written to disk-backed filesystems
+    pub block_output_operations: i64,
 }
 
 impl From<rusage> for Rusage {
@@ -200,10 +1346,345 @@ impl From<rusage> for Rusage {
             ),
             max_rss: rusage.ru_maxrss,
             page_faults: rusage.ru_majflt,
+            minor_page_faults: rusage.ru_minflt,
             involuntary_context_switches: rusage.ru_nivcsw,
             voluntary_context_switches: rusage.ru_nvcsw,
+            block_output_operations: rusage.ru_oublock,
+        }
+    }
+}
+
+/// Why `wait4_with_deadline()` killed a process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum KillCause {
+    Deadline,
+    Idle,
+    DiskWrite,
+    /// Held by the syscall filter in the system call of this number, starting a subprocess
+    Subprocess(i32),
+    /// Held by the syscall filter in the system call of this number, which it doesn't allow
+    Syscall(i32),
+}
+
+/// The outcome of one `poll_exit()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ExitPoll {
+    /// The process keeps running
+    Running,
+    /// The process has been reaped, with why it was killed, if it was
+    Exited(Option<KillCause>),
+}
+
+/// Poll the process until it exits, kill it when `deadline` is passed, it turns idle,
+/// it writes too much or it is held in a system call by its filter.
+///
+/// Returns why it was killed, if it was.
+#[allow(clippy::too_many_arguments)]
+fn wait4_with_deadline(
+    child: &Child,
+    clock: &dyn Clock,
+    status: &mut c_int,
+    usage: &mut rusage,
+    deadline: Option<Instant>,
+    mut idle_watch: Option<&mut IdleWatch>,
+    disk_watch: Option<&DiskWatch>,
+    kill_grace_period: Duration,
+    reap_timeout: Duration,
+) -> Result<Option<KillCause>, JudgeCoreError> {
+    loop {
+        if let ExitPoll::Exited(kill_cause) = poll_exit(
+            child,
+            clock,
+            status,
+            usage,
+            deadline,
+            idle_watch.as_deref_mut(),
+            disk_watch,
+            kill_grace_period,
+            reap_timeout,
+        )? {
+            return Ok(kill_cause);
+        }
+        child.wait_exit_for(WAIT_POLL_INTERVAL);
+    }
+}
+
+/// One poll of `wait4_with_deadline()`, killing the process past a limit.
+///
+/// `deadline` is an instant of `clock`, the one measuring `real_time_cost`, which the idle
+/// windows, the grace period and the reap timeout are measured by too.
+#[allow(clippy::too_many_arguments)]
+fn poll_exit(
+    child: &Child,
+    clock: &dyn Clock,
+    status: &mut c_int,
+    usage: &mut rusage,
+    deadline: Option<Instant>,
+    mut idle_watch: Option<&mut IdleWatch>,
+    disk_watch: Option<&DiskWatch>,
+    kill_grace_period: Duration,
+    reap_timeout: Duration,
+) -> Result<ExitPoll, JudgeCoreError> {
+    let pid = child.pid();
+    let res = unsafe { libc::wait4(pid, status, libc::WNOHANG, usage) };
+    match (res, Errno::last()) {
+        (0, _) => {}
+        (-1, Errno::EINTR) => return Ok(ExitPoll::Running),
+        (-1, errno) => return Err(errno.into()),
+        _ => {
+            child.mark_reaped();
+            return Ok(ExitPoll::Exited(None));
+        }
+    }
+    let now = clock.now();
+    let kill_cause = if let Some(held) = child.held_syscall() {
+        let name = syscall_name(held.nr);
+        log::warn!(
+            "Process pid={} made the forbidden system call {}",
+            pid,
+            name
+        );
+        // Only threads are created with `CLONE_THREAD`
+        let subprocess = match name.as_str() {
+            "fork" | "vfork" => true,
+            "clone" => held.arg0 & libc::CLONE_THREAD as u64 == 0,
+            _ => false,
+        };
+        if subprocess {
+            KillCause::Subprocess(held.nr)
+        } else {
+            KillCause::Syscall(held.nr)
+        }
+    } else if deadline.is_some_and(|deadline| now > deadline) {
+        log::warn!("Process pid={} passed the wall-clock deadline", pid);
+        KillCause::Deadline
+    } else if idle_watch
+        .as_mut()
+        .is_some_and(|idle_watch| idle_watch.is_idle(now))
+    {
+        log::warn!("Process pid={} is idle", pid);
+        KillCause::Idle
+    } else if disk_watch.is_some_and(DiskWatch::is_exceeded) {
+        log::warn!("Process pid={} wrote over the disk write limit", pid);
+        KillCause::DiskWrite
+    } else {
+        return Ok(ExitPoll::Running);
+    };
+    // Writing more can't change the verdict, no grace period for it, nor for a process the
+    // filter holds in a system call
+    let grace_period = match kill_cause {
+        KillCause::DiskWrite | KillCause::Subprocess(_) | KillCause::Syscall(_) => Duration::ZERO,
+        _ => kill_grace_period,
+    };
+    terminate(child, clock, status, usage, grace_period, reap_timeout)?;
+    Ok(ExitPoll::Exited(Some(kill_cause)))
+}
+
+/// Kill the process and reap it, first asking it with `SIGTERM` to exit within `grace_period`.
+///
+/// The signals go to the process group it leads, so that what it started goes with it.
+/// A process ignoring `SIGTERM`, or not exiting in time, is sent `SIGKILL` after all.
+/// One still there `reap_timeout` later is left behind, see `unkillable()`.
+/// Both times are measured by `clock`.
+fn terminate(
+    child: &Child,
+    clock: &dyn Clock,
+    status: &mut c_int,
+    usage: &mut rusage,
+    grace_period: Duration,
+    reap_timeout: Duration,
+) -> Result<(), JudgeCoreError> {
+    let pid = child.pid();
+    if !grace_period.is_zero() {
+        let _ = child.signal_group(libc::SIGTERM);
+        let grace_deadline = clock.now() + grace_period;
+        loop {
+            let res = unsafe { libc::wait4(pid, status, libc::WNOHANG, usage) };
+            match (res, Errno::last()) {
+                (0, _) | (-1, Errno::EINTR) => {}
+                (-1, errno) => return Err(errno.into()),
+                _ => {
+                    child.mark_reaped();
+                    return Ok(());
+                }
+            }
+            if clock.now() > grace_deadline {
+                log::warn!("Process pid={} survived SIGTERM", pid);
+                break;
+            }
+            child.wait_exit_for(WAIT_POLL_INTERVAL);
         }
     }
+    let _ = child.signal_group(libc::SIGKILL);
+    let reap_deadline = clock.now() + reap_timeout;
+    loop {
+        let res = unsafe { libc::wait4(pid, status, libc::WNOHANG, usage) };
+        match (res, Errno::last()) {
+            (0, _) | (-1, Errno::EINTR) => {}
+            (-1, errno) => return Err(errno.into()),
+            _ => {
+                child.mark_reaped();
+                return Ok(());
+            }
+        }
+        let now = clock.now();
+        if now >= reap_deadline {
+            // Reaped in the background from now on, it must not be signaled again
+            child.mark_reaped();
+            return Err(unkillable(pid, reap_timeout));
+        }
+        child.wait_exit_for(WAIT_POLL_INTERVAL.min(reap_deadline - now));
+    }
+}
+
+/// The error of a process still there `timeout` after `SIGKILL`, e.g. in uninterruptible sleep
+/// on a stuck I/O. Rather than blocking the judger, it is handed to a thread of its own which
+/// reaps it whenever it dies, its state logged for the operators.
+pub(crate) fn unkillable(pid: i32, timeout: Duration) -> JudgeCoreError {
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid))
+        .map(|stat| stat.trim_end().to_owned())
+        .unwrap_or_else(|e| format!("unavailable: {}", e));
+    log::error!(
+        "Process pid={} survived SIGKILL for {:?}, reaping it in the background. /proc/{}/stat: {}",
+        pid,
+        timeout,
+        pid,
+        stat
+    );
+    reap_in_background(pid);
+    JudgeCoreError::ProcessUnkillable { pid, stat }
+}
+
+/// Wait for `pid` on a thread of its own, so that it doesn't stay a zombie once it dies.
+fn reap_in_background(pid: i32) {
+    let reaper = thread::Builder::new()
+        .name(format!("reaper-{}", pid))
+        .spawn(move || loop {
+            let mut status: c_int = 0;
+            if unsafe { libc::waitpid(pid, &mut status, 0) } != -1 || Errno::last() != Errno::EINTR
+            {
+                log::info!("Reaped process pid={} left behind", pid);
+                return;
+            }
+        });
+    if let Err(e) = reaper {
+        log::warn!("Failed to start reaping process pid={}: {}", pid, e);
+    }
+}
+
+/// Tells whether a running process is idle, i.e. blocked without making progress.
+///
+/// Wall time, from the clock of the sandbox, is cut into consecutive windows of `limit`, and the process is idle once it
+/// gains less than `IDLE_CPU_PERCENT` of a window in CPU time. Waiting for input that
+/// never comes or sleeping is caught this way, while a process burning CPU, even slowed
+/// down by a busy machine, is left to the time limits.
+struct IdleWatch {
+    pid: i32,
+    limit: Duration,
+    window_begin: Instant,
+    window_cpu_time: Duration,
+}
+
+impl IdleWatch {
+    fn new(pid: i32, limit: Duration, now: Instant) -> Self {
+        Self {
+            pid,
+            limit,
+            window_begin: now,
+            window_cpu_time: get_cpu_time(pid).unwrap_or_default(),
+        }
+    }
+
+    fn is_idle(&mut self, now: Instant) -> bool {
+        if now.duration_since(self.window_begin) < self.limit {
+            return false;
+        }
+        // The process may be gone in between, it is reaped on the next poll
+        let cpu_time = match get_cpu_time(self.pid) {
+            Some(cpu_time) => cpu_time,
+            None => return false,
+        };
+        let gained = cpu_time.saturating_sub(self.window_cpu_time);
+        if gained * 100 < self.limit * IDLE_CPU_PERCENT {
+            log::debug!("Process pid={} gained {:?} of CPU time", self.pid, gained);
+            return true;
+        }
+        self.window_begin = now;
+        self.window_cpu_time = cpu_time;
+        false
+    }
+}
+
+/// Tells whether a running process wrote more than `limit` bytes to disk so far,
+/// by the `write_bytes` the kernel counts for it in `/proc/<pid>/io`.
+struct DiskWatch {
+    pid: i32,
+    limit: u64,
+}
+
+impl DiskWatch {
+    fn is_exceeded(&self) -> bool {
+        // The process may be gone in between, it is reaped on the next poll
+        get_disk_write_bytes(self.pid).is_some_and(|written| written > self.limit)
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn get_disk_write_bytes(pid: i32) -> Option<u64> {
+    std::fs::read_to_string(format!("/proc/{}/io", pid))
+        .ok()?
+        .lines()
+        .find_map(|line| line.strip_prefix("write_bytes:"))?
+        .trim()
+        .parse()
+        .ok()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn get_disk_write_bytes(_pid: i32) -> Option<u64> {
+    None
+}
+
+/// CPU time consumed so far by a running process.
+#[cfg(target_os = "linux")]
+fn get_cpu_time(pid: i32) -> Option<Duration> {
+    let mut clock_id: libc::clockid_t = 0;
+    let mut time = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    unsafe {
+        if libc::clock_getcpuclockid(pid, &mut clock_id) != 0
+            || libc::clock_gettime(clock_id, &mut time) != 0
+        {
+            return None;
+        }
+    }
+    Some(Duration::new(time.tv_sec as u64, time.tv_nsec as u32))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn get_cpu_time(_pid: i32) -> Option<Duration> {
+    None
+}
+
+/// Name of the system call of number `nr` on the native architecture.
+#[cfg(target_os = "linux")]
+fn syscall_name(nr: i32) -> String {
+    libseccomp::ScmpSyscall::from(nr)
+        .get_name()
+        .unwrap_or_else(|_| format!("#{}", nr))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn syscall_name(nr: i32) -> String {
+    format!("#{}", nr)
+}
+
+pub(crate) fn get_unix_timestamp_ms(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or(0)
 }
 
 fn get_default_rusage() -> rusage {
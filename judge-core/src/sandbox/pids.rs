@@ -0,0 +1,145 @@
+//! A pids cgroup capping how many tasks a sandboxed program may have, threads included.
+
+use nix::errno::Errno;
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader},
+    os::fd::AsRawFd,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+    time::Duration,
+};
+
+/// Tells apart the cgroups of the sandboxes of one judger.
+static NEXT_CGROUP_ID: AtomicUsize = AtomicUsize::new(0);
+
+const REMOVE_ATTEMPTS: usize = 10;
+const REMOVE_RETRY_INTERVAL: Duration = Duration::from_millis(10);
+
+/// A cgroup of its own for the process of a sandbox and everything it starts.
+///
+/// Unlike `RLIMIT_NPROC`, `pids.max` only counts the tasks in the cgroup, so that the other
+/// processes of the sandbox user don't take from the limit. Both count threads as tasks.
+///
+/// It is made at the root of the pids hierarchy, v1 or v2 with the controller enabled, which
+/// the judger must be allowed to write, usually as root. It is removed once dropped, killing
+/// whatever is left in it.
+#[derive(Debug)]
+pub(crate) struct PidsCgroup {
+    path: PathBuf,
+    /// `cgroup.procs`, opened beforehand for the forked child to enter without allocating
+    procs: File,
+}
+
+impl PidsCgroup {
+    /// A new cgroup of at most `max_tasks` tasks, `None` when the host has none to give.
+    pub(crate) fn create(max_tasks: u64) -> Option<Self> {
+        let root = match find_pids_hierarchy() {
+            Some(root) => root,
+            None => {
+                log::debug!("No pids cgroup hierarchy found");
+                return None;
+            }
+        };
+        let path = root.join(format!(
+            "judger-{}-{}",
+            std::process::id(),
+            NEXT_CGROUP_ID.fetch_add(1, Ordering::Relaxed)
+        ));
+        match Self::create_at(&path, max_tasks) {
+            Ok(cgroup) => Some(cgroup),
+            Err(e) => {
+                log::debug!("Failed to create pids cgroup {:?}: {}", path, e);
+                let _ = fs::remove_dir(&path);
+                None
+            }
+        }
+    }
+
+    fn create_at(path: &Path, max_tasks: u64) -> io::Result<Self> {
+        fs::create_dir(path)?;
+        fs::write(path.join("pids.max"), max_tasks.to_string())?;
+        let procs = OpenOptions::new()
+            .write(true)
+            .open(path.join("cgroup.procs"))?;
+        Ok(Self {
+            path: path.to_owned(),
+            procs,
+        })
+    }
+
+    /// Move the calling process into the cgroup.
+    ///
+    /// It only makes a system call, so that the forked child can call it.
+    pub(crate) fn enter(&self) -> Result<(), Errno> {
+        let pid = b"0";
+        let written = unsafe {
+            libc::write(
+                self.procs.as_raw_fd(),
+                pid.as_ptr() as *const libc::c_void,
+                pid.len(),
+            )
+        };
+        Errno::result(written).map(drop)
+    }
+
+    /// Pids of the processes still in the cgroup.
+    fn remaining_pids(&self) -> Vec<i32> {
+        fs::read_to_string(self.path.join("cgroup.procs"))
+            .map(|procs| procs.lines().filter_map(|pid| pid.parse().ok()).collect())
+            .unwrap_or_default()
+    }
+}
+
+impl Drop for PidsCgroup {
+    fn drop(&mut self) {
+        for _ in 0..REMOVE_ATTEMPTS {
+            match fs::remove_dir(&self.path) {
+                Err(e) if e.raw_os_error() == Some(libc::EBUSY) => {
+                    // Children of the program may outlive it
+                    for pid in self.remaining_pids() {
+                        unsafe { libc::kill(pid, libc::SIGKILL) };
+                    }
+                    thread::sleep(REMOVE_RETRY_INTERVAL);
+                }
+                Err(e) => {
+                    log::warn!("Failed to remove pids cgroup {:?}: {}", self.path, e);
+                    return;
+                }
+                Ok(()) => return,
+            }
+        }
+        log::warn!("Pids cgroup {:?} is still busy, leaving it", self.path);
+    }
+}
+
+/// The mount point of the hierarchy with the pids controller, v2 preferred.
+fn find_pids_hierarchy() -> Option<PathBuf> {
+    let mounts = BufReader::new(File::open("/proc/mounts").ok()?);
+    let mut v1_root = None;
+    for line in mounts.lines().map_while(Result::ok) {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 4 {
+            continue;
+        }
+        let mount_point = PathBuf::from(fields[1]);
+        match fields[2] {
+            "cgroup2" => {
+                let enabled = fs::read_to_string(mount_point.join("cgroup.subtree_control"))
+                    .unwrap_or_default();
+                if enabled
+                    .split_whitespace()
+                    .any(|controller| controller == "pids")
+                {
+                    return Some(mount_point);
+                }
+            }
+            "cgroup" if fields[3].split(',').any(|option| option == "pids") => {
+                v1_root = Some(mount_point);
+            }
+            _ => {}
+        }
+    }
+    v1_root
+}
@@ -0,0 +1,17 @@
+//! Reduced-capability pieces of the sandbox for platforms other than Linux.
+//!
+//! There is no seccomp, so syscalls are never restricted.
+//! Limits are enforced by rlimit where the platform honors it,
+//! and by killing the process once a wall-clock deadline is passed.
+
+use super::SyscallFilterLoader;
+use crate::error::JudgeCoreError;
+
+/// Placeholder of the seccomp filter, loading it does nothing.
+pub struct SyscallFilter;
+
+impl SyscallFilterLoader for SyscallFilter {
+    fn load(&self) -> Result<(), JudgeCoreError> {
+        Ok(())
+    }
+}
@@ -0,0 +1,27 @@
+/// A span entered by `enter_span!`, left when dropped.
+#[cfg(feature = "tracing")]
+pub(crate) struct PhaseSpan(#[allow(dead_code)] pub(crate) tracing::span::EnteredSpan);
+#[cfg(not(feature = "tracing"))]
+pub(crate) struct PhaseSpan;
+
+impl PhaseSpan {
+    /// Leave the span before the end of the scope.
+    #[cfg(target_os = "linux")]
+    pub(crate) fn exit(self) {}
+}
+
+/// Enter a `tracing::info_span!` with the same arguments until the returned `PhaseSpan`
+/// is dropped. Expands to nothing but the guard without the `tracing` feature,
+/// the arguments are not even evaluated.
+#[cfg(feature = "tracing")]
+macro_rules! enter_span {
+    ($($arg:tt)*) => {
+        $crate::span::PhaseSpan(tracing::info_span!($($arg)*).entered())
+    };
+}
+#[cfg(not(feature = "tracing"))]
+macro_rules! enter_span {
+    ($($arg:tt)*) => {
+        $crate::span::PhaseSpan
+    };
+}
@@ -1,20 +1,818 @@
 use anyhow::anyhow;
-use std::path::PathBuf;
-
+use serde::Deserializer;
+use serde_derive::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+#[cfg(feature = "gzip")]
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
 
 use crate::error::JudgeCoreError;
 
-pub fn compare_files(file_path1: &PathBuf, file_path2: &PathBuf) -> bool {
+/// Built-in transformations applied to both output and answer before comparing them.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum Normalizer {
+    /// Trim the end of every line and sort them, so any ordering of lines is accepted.
+    /// Trailing empty lines are dropped before sorting.
+    SortLines,
+    /// Round every token which can be parsed as a float to the given decimal places.
+    /// Tokens of a line are re-joined with a single space.
+    RoundFloats(usize),
+    Lowercase,
+    /// Keep the first `n` lines only, dropping everything after the `n`-th line break,
+    /// e.g. diagnostics printed after the answer. Content with fewer lines is kept as is,
+    /// so a missing line still fails the comparison.
+    FirstLines(usize),
+    /// Keep the whitespace-separated token at the given 0-based index of every line.
+    /// Lines too short to have it become empty, the number of lines is unchanged.
+    Field(usize),
+    /// Replace every run of spaces and tabs of a line by a single space and trim its end,
+    /// so spacing between words doesn't matter but line breaks still do,
+    /// unlike `compare_files_by_tokens()`.
+    CollapseInternalWhitespace,
+}
+
+impl Normalizer {
+    pub fn apply(&self, content: &str) -> String {
+        match self {
+            Self::SortLines => {
+                let mut lines: Vec<&str> = content.trim_end().lines().map(str::trim_end).collect();
+                lines.sort_unstable();
+                lines.join("\n")
+            }
+            Self::RoundFloats(precision) => content
+                .lines()
+                .map(|line| {
+                    line.split_whitespace()
+                        .map(|token| round_float_token(token, *precision))
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+            Self::Lowercase => content.to_lowercase(),
+            Self::FirstLines(n) => content.lines().take(*n).collect::<Vec<_>>().join("\n"),
+            Self::Field(index) => content
+                .lines()
+                .map(|line| line.split_whitespace().nth(*index).unwrap_or_default())
+                .collect::<Vec<_>>()
+                .join("\n"),
+            Self::CollapseInternalWhitespace => content
+                .lines()
+                .map(collapse_whitespace)
+                .collect::<Vec<_>>()
+                .join("\n"),
+        }
+    }
+}
+
+fn collapse_whitespace(line: &str) -> String {
+    let mut collapsed = String::with_capacity(line.len());
+    let mut in_whitespace = false;
+    for c in line.trim_end_matches([' ', '\t']).chars() {
+        if c == ' ' || c == '\t' {
+            if !in_whitespace {
+                collapsed.push(' ');
+            }
+            in_whitespace = true;
+        } else {
+            collapsed.push(c);
+            in_whitespace = false;
+        }
+    }
+    collapsed
+}
+
+fn round_float_token(token: &str, precision: usize) -> String {
+    match token.parse::<f64>() {
+        Ok(value) if value.is_finite() => {
+            let rounded = format!("{:.*}", precision, value);
+            // "-0.00" should be the same as "0.00"
+            match rounded.strip_prefix('-') {
+                Some(unsigned) if unsigned.parse::<f64>() == Ok(0.0) => unsigned.to_owned(),
+                _ => rounded,
+            }
+        }
+        _ => token.to_owned(),
+    }
+}
+
+/// How line breaks are read by `compare_text_files()`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub enum LineEnding {
+    /// `\r\n` and a lone `\r` are read as `\n`, e.g. for answers written on Windows
+    #[default]
+    Lenient,
+    /// Only `\n` breaks a line, a `\r` is content that must match.
+    /// Normalizers split lines like `str::lines()`, dropping a `\r` before `\n`.
+    Lf,
+}
+
+/// How `compare_text_files()` decodes the files before comparing them.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct TextPolicy {
+    /// Label of the encoding of both files, e.g. `Shift_JIS`, UTF-8 when `None`.
+    /// Encodings other than UTF-8 need the `encoding` feature.
+    pub encoding: Option<String>,
+    pub line_ending: LineEnding,
+}
+
+impl TextPolicy {
+    /// Decode the file, `None` when its content is not valid in the encoding.
+    fn read(&self, path: &Path) -> Result<Option<String>, JudgeCoreError> {
+        let mut bytes = vec![];
+        read_testdata(path)?.read_to_end(&mut bytes)?;
+        let content = match self.encoding.as_deref() {
+            None => decode_utf8(bytes),
+            Some(label) if is_utf8_label(label) => decode_utf8(bytes),
+            Some(label) => decode_with_label(label, &bytes)?,
+        };
+        let content = content.map(|content| match self.line_ending {
+            LineEnding::Lenient => content.replace("\r\n", "\n").replace('\r', "\n"),
+            LineEnding::Lf => content,
+        });
+        if content.is_none() {
+            log::warn!("{:?} is not valid in encoding {:?}", path, self.encoding);
+        }
+        Ok(content)
+    }
+}
+
+fn is_utf8_label(label: &str) -> bool {
+    label.eq_ignore_ascii_case("utf-8") || label.eq_ignore_ascii_case("utf8")
+}
+
+/// Without its byte order mark, which editors on Windows like to add.
+fn decode_utf8(bytes: Vec<u8>) -> Option<String> {
+    let content = String::from_utf8(bytes).ok()?;
+    Some(match content.strip_prefix('\u{feff}') {
+        Some(content) => content.to_owned(),
+        None => content,
+    })
+}
+
+#[cfg(feature = "encoding")]
+fn decode_with_label(label: &str, bytes: &[u8]) -> Result<Option<String>, JudgeCoreError> {
+    let encoding = encoding_rs::Encoding::for_label(label.as_bytes())
+        .ok_or_else(|| JudgeCoreError::InvalidConfig(format!("unknown encoding {:?}", label)))?;
+    let (content, _, had_errors) = encoding.decode(bytes);
+    Ok((!had_errors).then(|| content.into_owned()))
+}
+
+#[cfg(not(feature = "encoding"))]
+fn decode_with_label(label: &str, _bytes: &[u8]) -> Result<Option<String>, JudgeCoreError> {
+    Err(JudgeCoreError::InvalidConfig(format!(
+        "encoding {:?} needs the `encoding` feature",
+        label
+    )))
+}
+
+/// Compare the output with the answer, as UTF-8 with lenient line endings.
+///
+/// `normalizers` are applied in order to the whole content of both files first,
+/// then the lines are compared one by one. Trailing blank lines, any number of them,
+/// and the trailing whitespace of every line are ignored on both sides, while a blank
+/// line before other content must match, see `significant_lines()`.
+/// Content which is not valid UTF-8 never matches, see `compare_text_files()`.
+pub fn compare_files(file_path1: &Path, file_path2: &Path, normalizers: &[Normalizer]) -> bool {
+    compare_text_files(file_path1, file_path2, normalizers, &TextPolicy::default()).unwrap()
+}
+
+/// `compare_files()` with both files decoded according to `policy`.
+///
+/// A file which is not valid in the encoding is a mismatch, an unknown encoding
+/// is `JudgeCoreError::InvalidConfig`.
+pub fn compare_text_files(
+    file_path1: &Path,
+    file_path2: &Path,
+    normalizers: &[Normalizer],
+    policy: &TextPolicy,
+) -> Result<bool, JudgeCoreError> {
     log::debug!("Comparing output files");
-    let file1 = BufReader::new(File::open(file_path1).unwrap());
-    let file2 = BufReader::new(File::open(file_path2).unwrap());
+    let (file1_content, file2_content) =
+        match read_normalized(file_path1, file_path2, normalizers, policy)? {
+            Some(contents) => contents,
+            None => return Ok(false),
+        };
+
+    Ok(significant_lines(&file1_content, policy.line_ending)
+        == significant_lines(&file2_content, policy.line_ending))
+}
+
+/// `compare_text_files()` whatever the order of the lines, yet not how many times each occurs:
+/// `a a b` doesn't match `a b b`, nor `a b`.
+///
+/// After `normalizers`, the end of every line is trimmed and trailing empty lines are dropped,
+/// like `Normalizer::SortLines`, then both lists of lines are sorted before comparing.
+pub fn compare_unordered_lines(
+    file_path1: &Path,
+    file_path2: &Path,
+    normalizers: &[Normalizer],
+    policy: &TextPolicy,
+) -> Result<bool, JudgeCoreError> {
+    log::debug!("Comparing output files by unordered lines");
+    let (file1_content, file2_content) =
+        match read_normalized(file_path1, file_path2, normalizers, policy)? {
+            Some(contents) => contents,
+            None => return Ok(false),
+        };
+    Ok(sorted_lines(&file1_content, policy.line_ending)
+        == sorted_lines(&file2_content, policy.line_ending))
+}
+
+/// `compare_text_files()` with each line compared as its whitespace-separated tokens, like the
+/// `lcmp` checker of testlib: `1  2` matches `1 2`, yet not `1` and `2` on lines of their own.
+pub fn compare_line_tokens(
+    file_path1: &Path,
+    file_path2: &Path,
+    normalizers: &[Normalizer],
+    policy: &TextPolicy,
+) -> Result<bool, JudgeCoreError> {
+    log::debug!("Comparing output files by the tokens of each line");
+    let (file1_content, file2_content) =
+        match read_normalized(file_path1, file_path2, normalizers, policy)? {
+            Some(contents) => contents,
+            None => return Ok(false),
+        };
+    let lines1 = significant_lines(&file1_content, policy.line_ending);
+    let lines2 = significant_lines(&file2_content, policy.line_ending);
+    Ok(lines1.len() == lines2.len()
+        && lines1
+            .iter()
+            .zip(&lines2)
+            .all(|(line1, line2)| same_line_tokens(line1, line2)))
+}
 
-    let file1_content: String = file1.lines().map(|l| l.unwrap()).collect();
-    let file2_content: String = file2.lines().map(|l| l.unwrap()).collect();
+fn same_line_tokens(line1: &str, line2: &str) -> bool {
+    line1.split_whitespace().eq(line2.split_whitespace())
+}
+
+/// The lines of `content` which are compared, up to its last one with non-whitespace content.
+///
+/// Blank lines before it are kept, the whitespace ending each line is dropped, but for a `\r`
+/// with `LineEnding::Lf`.
+fn significant_lines(content: &str, line_ending: LineEnding) -> Vec<&str> {
+    let content = content.trim_end();
+    match line_ending {
+        _ if content.is_empty() => vec![],
+        LineEnding::Lenient => content.lines().map(str::trim_end).collect(),
+        LineEnding::Lf => content
+            .split('\n')
+            .map(|line| line.trim_end_matches(|c: char| c.is_whitespace() && c != '\r'))
+            .collect(),
+    }
+}
+
+fn sorted_lines(content: &str, line_ending: LineEnding) -> Vec<&str> {
+    let mut lines = significant_lines(content, line_ending);
+    lines.sort_unstable();
+    lines
+}
+
+/// Both files decoded according to `policy` then normalized, `None` when one is not valid.
+fn read_normalized(
+    file_path1: &Path,
+    file_path2: &Path,
+    normalizers: &[Normalizer],
+    policy: &TextPolicy,
+) -> Result<Option<(String, String)>, JudgeCoreError> {
+    let (mut file1_content, mut file2_content) =
+        match (policy.read(file_path1)?, policy.read(file_path2)?) {
+            (Some(file1_content), Some(file2_content)) => (file1_content, file2_content),
+            _ => return Ok(None),
+        };
+    for normalizer in normalizers {
+        log::debug!("Applying normalizer {:?}", normalizer);
+        file1_content = normalizer.apply(&file1_content);
+        file2_content = normalizer.apply(&file2_content);
+    }
+    Ok(Some((file1_content, file2_content)))
+}
 
-    file1_content.trim_end() == file2_content.trim_end()
+/// How the default checker compares the output with the answer, see `CheckerConfig::comparison`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub enum OutputComparison {
+    /// `compare_files()`, after `CheckerConfig::normalizers`
+    #[default]
+    Lines,
+    /// `compare_unordered_lines()`, after `CheckerConfig::normalizers`, for outputs
+    /// which may list their lines in any order
+    UnorderedLines,
+    /// `compare_line_tokens()`, after `CheckerConfig::normalizers`, for outputs
+    /// which may space the tokens of a line differently
+    LineTokens,
+    /// `compare_files_by_tokens()`, for outputs too large to be normalized in memory
+    Tokens(TokenComparison),
+    /// `compare_bytes()`, for binary outputs
+    Bytes,
+}
+
+/// Compare two files byte for byte, returning the offset of the first byte which differs.
+///
+/// When one file is a prefix of the other, the offset is the length of the shorter one.
+/// Nothing is decoded, so any content can be compared, unlike `compare_files()`.
+pub fn compare_bytes(file_path1: &Path, file_path2: &Path) -> io::Result<Option<u64>> {
+    log::debug!("Comparing output files byte for byte");
+    let mut reader1 =
+        BufReader::with_capacity(COMPARE_READ_BUFFER_SIZE, read_testdata(file_path1)?);
+    let mut reader2 =
+        BufReader::with_capacity(COMPARE_READ_BUFFER_SIZE, read_testdata(file_path2)?);
+    let mut offset = 0;
+    loop {
+        let buf1 = reader1.fill_buf()?;
+        let buf2 = reader2.fill_buf()?;
+        if buf1.is_empty() || buf2.is_empty() {
+            return Ok((buf1.len() != buf2.len()).then_some(offset));
+        }
+        let len = buf1.len().min(buf2.len());
+        if let Some(index) = buf1[..len]
+            .iter()
+            .zip(&buf2[..len])
+            .position(|(a, b)| a != b)
+        {
+            return Ok(Some(offset + index as u64));
+        }
+        reader1.consume(len);
+        reader2.consume(len);
+        offset += len as u64;
+    }
+}
+
+/// How `compare_files_by_tokens()` compares a token of the output with one of the answer.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum TokenComparison {
+    /// Tokens must be equal byte for byte
+    Exact,
+    /// Tokens which both parse as floats are compared as numbers, the other ones must be equal
+    Float(FloatComparison),
+    /// Tokens are compared as 64-bit integers, e.g. `007` is `7`. A token which is not one
+    /// never matches
+    Integer,
+}
+
+impl TokenComparison {
+    fn matches(&self, output: &[u8], answer: &[u8]) -> bool {
+        match self {
+            Self::Exact => output == answer,
+            Self::Float(comparison) => {
+                match (parse_float_token(output), parse_float_token(answer)) {
+                    (Some(output), Some(answer)) => comparison.matches(output, answer),
+                    _ => output == answer,
+                }
+            }
+            Self::Integer => match (parse_integer_token(output), parse_integer_token(answer)) {
+                (Some(output), Some(answer)) => output == answer,
+                _ => false,
+            },
+        }
+    }
+}
+
+/// How `TokenComparison::Float` compares two numbers, IEEE special values included.
+///
+/// Tokens are parsed as `f64`, so `inf`, `infinity` and `nan` are numbers whatever their case.
+/// `-0` and `0` are always equal.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct FloatComparison {
+    /// Absolute or relative error allowed between finite numbers
+    pub eps: f64,
+    /// An infinity matches the infinity of the same sign, otherwise an infinite number
+    /// never matches
+    pub infinities_match: bool,
+    /// NaN matches NaN, otherwise a NaN never matches, not even itself
+    pub nan_matches_nan: bool,
+}
+
+impl FloatComparison {
+    /// Allow `eps` of error, infinities matching and NaN never matching.
+    pub fn new(eps: f64) -> Self {
+        Self {
+            eps,
+            infinities_match: true,
+            nan_matches_nan: false,
+        }
+    }
+
+    pub fn matches(&self, output: f64, answer: f64) -> bool {
+        if output.is_nan() || answer.is_nan() {
+            return self.nan_matches_nan && output.is_nan() && answer.is_nan();
+        }
+        if output.is_infinite() || answer.is_infinite() {
+            return self.infinities_match && output == answer;
+        }
+        (output - answer).abs() <= self.eps * answer.abs().max(1.0)
+    }
+}
+
+fn parse_float_token(token: &[u8]) -> Option<f64> {
+    std::str::from_utf8(token).ok()?.parse().ok()
+}
+
+fn parse_integer_token(token: &[u8]) -> Option<i64> {
+    std::str::from_utf8(token).ok()?.parse().ok()
+}
+
+/// Size of the buffers `compare_files_by_tokens()` and `compare_bytes()` read the files through.
+const COMPARE_READ_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Whitespace-separated tokens of a reader, read lazily through its buffer.
+///
+/// A token is only yielded once its end is found, so one straddling two reads of the
+/// buffer is never split.
+pub struct TokenReader<R: BufRead> {
+    reader: R,
+}
+
+impl<R: BufRead> TokenReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self { reader }
+    }
+
+    fn read_token(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let mut token = vec![];
+        loop {
+            let buf = self.reader.fill_buf()?;
+            if buf.is_empty() {
+                return Ok((!token.is_empty()).then_some(token));
+            }
+            let skipped = if token.is_empty() {
+                buf.iter().take_while(|b| b.is_ascii_whitespace()).count()
+            } else {
+                0
+            };
+            let len = buf[skipped..]
+                .iter()
+                .take_while(|b| !b.is_ascii_whitespace())
+                .count();
+            token.extend_from_slice(&buf[skipped..skipped + len]);
+            let ended = skipped + len < buf.len();
+            self.reader.consume(skipped + len);
+            if ended && !token.is_empty() {
+                return Ok(Some(token));
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for TokenReader<R> {
+    type Item = io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_token().transpose()
+    }
+}
+
+/// Compare the output with the answer token by token, whitespace being ignored.
+///
+/// Unlike `compare_files()`, neither file is loaded in memory, they are streamed side
+/// by side. Both must have the same number of tokens.
+pub fn compare_files_by_tokens(
+    file_path1: &Path,
+    file_path2: &Path,
+    comparison: TokenComparison,
+) -> io::Result<bool> {
+    log::debug!("Comparing output files by tokens with {:?}", comparison);
+    let mut tokens1 = TokenReader::new(BufReader::with_capacity(
+        COMPARE_READ_BUFFER_SIZE,
+        read_testdata(file_path1)?,
+    ));
+    let mut tokens2 = TokenReader::new(BufReader::with_capacity(
+        COMPARE_READ_BUFFER_SIZE,
+        read_testdata(file_path2)?,
+    ));
+    loop {
+        match (tokens1.next().transpose()?, tokens2.next().transpose()?) {
+            (None, None) => return Ok(true),
+            (Some(token1), Some(token2)) if comparison.matches(&token1, &token2) => {}
+            _ => return Ok(false),
+        }
+    }
+}
+
+/// Lines `generate_diff()` keeps around each change, like `diff -u`.
+const DIFF_CONTEXT_LINES: usize = 3;
+
+/// Pairs of lines `generate_diff()` may match once the common prefix and suffix are skipped,
+/// above which the lines in between are reported as replaced as a whole.
+const DIFF_MAX_PAIRS: usize = 4 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum DiffOp {
+    Equal,
+    Delete,
+    Insert,
+}
+
+/// A unified diff from `answer` to `output`, line by line, empty when they are equal.
+///
+/// Like `diff -u`, changes are grouped in hunks with 3 lines of context. The diff is cut after
+/// `max_lines` lines, its headers included, and then ends with a line telling how many were
+/// left out.
+pub fn generate_diff(output: &str, answer: &str, max_lines: usize) -> String {
+    let answer: Vec<&str> = answer.lines().collect();
+    let output: Vec<&str> = output.lines().collect();
+    diff_lines_by(&output, &answer, max_lines, |output, answer| {
+        output == answer
+    })
+}
+
+/// `generate_diff()` of lines which are the same according to `same`.
+fn diff_lines_by(
+    output: &[&str],
+    answer: &[&str],
+    max_lines: usize,
+    same: impl Fn(&str, &str) -> bool,
+) -> String {
+    let ops = diff_ops(output, answer, &same);
+    let changes: Vec<usize> = (0..ops.len())
+        .filter(|&i| ops[i].0 != DiffOp::Equal)
+        .collect();
+    if changes.is_empty() {
+        return String::new();
+    }
+
+    let mut lines = vec![String::from("--- answer"), String::from("+++ output")];
+    let mut hunk_start = 0;
+    while hunk_start < changes.len() {
+        // Changes closer than twice the context share a hunk
+        let mut hunk_end = hunk_start;
+        while hunk_end + 1 < changes.len()
+            && changes[hunk_end + 1] - changes[hunk_end] <= 2 * DIFF_CONTEXT_LINES + 1
+        {
+            hunk_end += 1;
+        }
+        let first = changes[hunk_start].saturating_sub(DIFF_CONTEXT_LINES);
+        let last = (changes[hunk_end] + DIFF_CONTEXT_LINES).min(ops.len() - 1);
+        let hunk = &ops[first..=last];
+        let (answer_start, output_start) = (ops[first].1, ops[first].2);
+        let answer_len = hunk.iter().filter(|op| op.0 != DiffOp::Insert).count();
+        let output_len = hunk.iter().filter(|op| op.0 != DiffOp::Delete).count();
+        // An empty range starts at the line before it, as in `diff -u`
+        lines.push(format!(
+            "@@ -{},{} +{},{} @@",
+            answer_start + usize::from(answer_len > 0),
+            answer_len,
+            output_start + usize::from(output_len > 0),
+            output_len
+        ));
+        lines.extend(
+            hunk.iter()
+                .map(|&(op, answer_index, output_index)| match op {
+                    DiffOp::Equal => format!(" {}", answer[answer_index]),
+                    DiffOp::Delete => format!("-{}", answer[answer_index]),
+                    DiffOp::Insert => format!("+{}", output[output_index]),
+                }),
+        );
+        hunk_start = hunk_end + 1;
+    }
+
+    if lines.len() > max_lines {
+        let left_out = lines.len() - max_lines;
+        lines.truncate(max_lines);
+        lines.push(format!("... {} more lines of diff truncated", left_out));
+    }
+    lines.join("\n")
+}
+
+/// The edits turning `answer` into `output`, each with the index of its line in `answer`
+/// and in `output`, or where it would be for the side it is not in.
+fn diff_ops(
+    output: &[&str],
+    answer: &[&str],
+    same: &impl Fn(&str, &str) -> bool,
+) -> Vec<(DiffOp, usize, usize)> {
+    let prefix = output
+        .iter()
+        .zip(answer)
+        .take_while(|(output, answer)| same(output, answer))
+        .count();
+    let suffix = output[prefix..]
+        .iter()
+        .rev()
+        .zip(answer[prefix..].iter().rev())
+        .take_while(|(output, answer)| same(output, answer))
+        .count();
+    let answer_middle = &answer[prefix..answer.len() - suffix];
+    let output_middle = &output[prefix..output.len() - suffix];
+
+    let mut ops: Vec<(DiffOp, usize, usize)> = (0..prefix).map(|i| (DiffOp::Equal, i, i)).collect();
+    let (n, m) = (answer_middle.len(), output_middle.len());
+    let (mut i, mut j) = (0, 0);
+    if (n + 1).saturating_mul(m + 1) <= DIFF_MAX_PAIRS {
+        // Length of the longest common subsequence of the suffixes starting at `i` and `j`
+        let mut lcs = vec![0u32; (n + 1) * (m + 1)];
+        for i in (0..n).rev() {
+            for j in (0..m).rev() {
+                lcs[i * (m + 1) + j] = if same(output_middle[j], answer_middle[i]) {
+                    lcs[(i + 1) * (m + 1) + j + 1] + 1
+                } else {
+                    lcs[(i + 1) * (m + 1) + j].max(lcs[i * (m + 1) + j + 1])
+                };
+            }
+        }
+        while i < n && j < m {
+            if same(output_middle[j], answer_middle[i]) {
+                ops.push((DiffOp::Equal, prefix + i, prefix + j));
+                i += 1;
+                j += 1;
+            } else if lcs[(i + 1) * (m + 1) + j] >= lcs[i * (m + 1) + j + 1] {
+                ops.push((DiffOp::Delete, prefix + i, prefix + j));
+                i += 1;
+            } else {
+                ops.push((DiffOp::Insert, prefix + i, prefix + j));
+                j += 1;
+            }
+        }
+    }
+    ops.extend((i..n).map(|i| (DiffOp::Delete, prefix + i, prefix + j)));
+    ops.extend((j..m).map(|j| (DiffOp::Insert, prefix + n, prefix + j)));
+    ops.extend((0..suffix).map(|k| (DiffOp::Equal, prefix + n + k, prefix + m + k)));
+    ops
+}
+
+/// `generate_diff()` of two files as `comparison` sees them, `None` when it can't tell.
+///
+/// `OutputComparison::Lines` diffs the lines left by `normalizers` and
+/// `OutputComparison::UnorderedLines` the sorted ones, both decoded by `policy`,
+/// as does `OutputComparison::LineTokens` with lines of the same tokens being the same.
+/// `OutputComparison::Tokens` diffs one token per line, tokens which match according
+/// to it being the same. `OutputComparison::Bytes` and files which are not valid in
+/// the encoding are never diffed.
+pub fn diff_files(
+    file_path1: &Path,
+    file_path2: &Path,
+    comparison: OutputComparison,
+    normalizers: &[Normalizer],
+    policy: &TextPolicy,
+    max_lines: usize,
+) -> Result<Option<String>, JudgeCoreError> {
+    let diff = match comparison {
+        OutputComparison::Lines
+        | OutputComparison::UnorderedLines
+        | OutputComparison::LineTokens => {
+            let (file1_content, file2_content) =
+                match read_normalized(file_path1, file_path2, normalizers, policy)? {
+                    Some(contents) => contents,
+                    None => return Ok(None),
+                };
+            let (output, answer) = match comparison {
+                OutputComparison::UnorderedLines => (
+                    sorted_lines(&file1_content, policy.line_ending),
+                    sorted_lines(&file2_content, policy.line_ending),
+                ),
+                _ => (
+                    significant_lines(&file1_content, policy.line_ending),
+                    significant_lines(&file2_content, policy.line_ending),
+                ),
+            };
+            diff_lines_by(
+                &output,
+                &answer,
+                max_lines,
+                |output, answer| match comparison {
+                    OutputComparison::LineTokens => same_line_tokens(output, answer),
+                    _ => output == answer,
+                },
+            )
+        }
+        OutputComparison::Tokens(token_comparison) => {
+            let read_tokens = |path: &Path| -> io::Result<Vec<String>> {
+                TokenReader::new(BufReader::with_capacity(
+                    COMPARE_READ_BUFFER_SIZE,
+                    read_testdata(path)?,
+                ))
+                .map(|token| token.map(|token| String::from_utf8_lossy(&token).into_owned()))
+                .collect()
+            };
+            let (output, answer) = (read_tokens(file_path1)?, read_tokens(file_path2)?);
+            let output: Vec<&str> = output.iter().map(String::as_str).collect();
+            let answer: Vec<&str> = answer.iter().map(String::as_str).collect();
+            diff_lines_by(&output, &answer, max_lines, |output, answer| {
+                token_comparison.matches(output.as_bytes(), answer.as_bytes())
+            })
+        }
+        OutputComparison::Bytes => return Ok(None),
+    };
+    Ok(Some(diff))
+}
+
+#[cfg(feature = "gzip")]
+static GZIP_TEMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Check the magic bytes of `file`, leaving it at the start.
+fn is_gzip(file: &mut File) -> io::Result<bool> {
+    let mut magic = [0u8; 2];
+    let is_gzip = file.read_exact(&mut magic).is_ok() && magic == GZIP_MAGIC;
+    file.seek(SeekFrom::Start(0))?;
+    Ok(is_gzip)
+}
+
+/// Open a test data file to feed a program.
+///
+/// With the `gzip` feature, a gzip-compressed file is decompressed to
+/// an already unlinked file of `scratch_dir`, which is returned instead.
+pub fn open_testdata(path: &Path, scratch_dir: &Path) -> io::Result<File> {
+    let mut file = File::open(path)?;
+    if !is_gzip(&mut file)? {
+        return Ok(file);
+    }
+    #[cfg(feature = "gzip")]
+    {
+        let temp_path = scratch_dir.join(format!(
+            "judge-gzip-{}-{}",
+            std::process::id(),
+            GZIP_TEMP_COUNTER.fetch_add(1, Ordering::SeqCst)
+        ));
+        let mut temp_file = File::options()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&temp_path)?;
+        std::fs::remove_file(&temp_path)?;
+        log::debug!("Decompressing {:?}", path);
+        io::copy(&mut flate2::read::GzDecoder::new(file), &mut temp_file)?;
+        temp_file.seek(SeekFrom::Start(0))?;
+        Ok(temp_file)
+    }
+    #[cfg(not(feature = "gzip"))]
+    {
+        let _ = scratch_dir;
+        log::warn!(
+            "{:?} is gzip-compressed, but the gzip feature is disabled",
+            path
+        );
+        Ok(file)
+    }
+}
+
+/// Read a test data file, decompressing it on the fly like `open_testdata()` does,
+/// without writing it anywhere.
+pub fn read_testdata(path: &Path) -> io::Result<Box<dyn Read>> {
+    let mut file = File::open(path)?;
+    if !is_gzip(&mut file)? {
+        return Ok(Box::new(file));
+    }
+    #[cfg(feature = "gzip")]
+    {
+        log::debug!("Decompressing {:?}", path);
+        Ok(Box::new(flate2::read::GzDecoder::new(file)))
+    }
+    #[cfg(not(feature = "gzip"))]
+    {
+        log::warn!(
+            "{:?} is gzip-compressed, but the gzip feature is disabled",
+            path
+        );
+        Ok(Box::new(file))
+    }
+}
+
+/// Read a test data file to string, see `read_testdata()` for compressed files.
+pub fn read_testdata_to_string(path: &Path) -> io::Result<String> {
+    let mut file = read_testdata(path)?;
+    let mut content = String::new();
+    file.read_to_string(&mut content)?;
+    Ok(content)
+}
+
+/// SHA-256 of the content of the file at `path`, in lowercase hex.
+pub fn sha256_file(path: &Path) -> io::Result<String> {
+    sha256_with_file(Sha256::new(), path)
+}
+
+/// SHA-256 of `fields` followed by the content of the file at `path`, in lowercase hex.
+///
+/// The fields are counted and each is prefixed by its length, so that no other fields nor
+/// file hash the same.
+pub fn sha256_fields_and_file(fields: &[&[u8]], path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    hasher.update((fields.len() as u64).to_le_bytes());
+    for field in fields {
+        hasher.update((field.len() as u64).to_le_bytes());
+        hasher.update(field);
+    }
+    sha256_with_file(hasher, path)
+}
+
+fn sha256_with_file(mut hasher: Sha256, path: &Path) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut buffer = [0u8; 8192];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
 }
 
 pub fn get_pathbuf_str(path: &PathBuf) -> Result<String, JudgeCoreError> {
@@ -26,3 +824,114 @@ pub fn get_pathbuf_str(path: &PathBuf) -> Result<String, JudgeCoreError> {
         ))),
     }
 }
+
+/// Units of `parse_memory()`, decimal and binary ones are told apart.
+const MEMORY_UNITS: [(&str, u64); 9] = [
+    ("B", 1),
+    ("KB", 1000),
+    ("MB", 1000 * 1000),
+    ("GB", 1000 * 1000 * 1000),
+    ("TB", 1000 * 1000 * 1000 * 1000),
+    ("KiB", 1 << 10),
+    ("MiB", 1 << 20),
+    ("GiB", 1 << 30),
+    ("TiB", 1 << 40),
+];
+
+/// Units of `parse_duration()` in nanoseconds, `m` is left out for it could be minutes.
+const DURATION_UNITS: [(&str, u64); 6] = [
+    ("ns", 1),
+    ("us", 1000),
+    ("ms", 1000 * 1000),
+    ("s", 1000 * 1000 * 1000),
+    ("min", 60 * 1000 * 1000 * 1000),
+    ("h", 60 * 60 * 1000 * 1000 * 1000),
+];
+
+/// Parse a quantity like `"1.5GiB"` into the smallest unit of `units`.
+///
+/// The number must be non-negative and the unit is required, a bare number is ambiguous.
+fn parse_quantity(quantity: &str, units: &[(&str, u64)]) -> anyhow::Result<u64> {
+    let trimmed = quantity.trim();
+    let unit_start = trimmed
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(trimmed.len());
+    let (number, unit) = trimmed.split_at(unit_start);
+    let unit = unit.trim_start();
+    let factor = match units.iter().find(|(name, _)| *name == unit) {
+        Some((_, factor)) => *factor,
+        None if unit.is_empty() => return Err(anyhow!("Missing unit in {:?}", quantity)),
+        None => {
+            return Err(anyhow!(
+                "Unknown unit {:?} in {:?}, expected one of {:?}",
+                unit,
+                quantity,
+                units.iter().map(|(name, _)| *name).collect::<Vec<_>>()
+            ))
+        }
+    };
+    let invalid_number = || {
+        anyhow!(
+            "{:?} is not a non-negative number followed by a unit",
+            quantity
+        )
+    };
+    let too_large = || anyhow!("{:?} is too large", quantity);
+    if number.contains('.') {
+        let value = number.parse::<f64>().map_err(|_| invalid_number())? * factor as f64;
+        if value >= u64::MAX as f64 {
+            return Err(too_large());
+        }
+        Ok(value.round() as u64)
+    } else {
+        number
+            .parse::<u64>()
+            .map_err(|_| invalid_number())?
+            .checked_mul(factor)
+            .ok_or_else(too_large)
+    }
+}
+
+/// Parse a size like `"256MiB"` or `"1.5GB"` into bytes.
+///
+/// Binary units (`KiB`, `MiB`, `GiB`, `TiB`) are powers of 1024,
+/// decimal ones (`KB`, `MB`, `GB`, `TB`) powers of 1000.
+pub fn parse_memory(memory: &str) -> Result<u64, JudgeCoreError> {
+    Ok(parse_quantity(memory, &MEMORY_UNITS)?)
+}
+
+/// Parse a duration like `"1500ms"` or `"2s"`, see `DURATION_UNITS` for the units.
+pub fn parse_duration(duration: &str) -> Result<Duration, JudgeCoreError> {
+    Ok(Duration::from_nanos(parse_quantity(
+        duration,
+        &DURATION_UNITS,
+    )?))
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum MemoryValue {
+    Bytes(u64),
+    Text(String),
+}
+
+/// For `#[serde(deserialize_with)]`, reads a number of bytes or a string for `parse_memory()`.
+pub fn deserialize_memory<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    let value: MemoryValue = serde::Deserialize::deserialize(deserializer)?;
+    match value {
+        MemoryValue::Bytes(bytes) => Ok(bytes),
+        MemoryValue::Text(text) => {
+            parse_quantity(&text, &MEMORY_UNITS).map_err(serde::de::Error::custom)
+        }
+    }
+}
+
+/// For `#[serde(deserialize_with)]`, reads a string for `parse_duration()`.
+pub fn deserialize_duration<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Duration, D::Error> {
+    let text: String = serde::Deserialize::deserialize(deserializer)?;
+    parse_quantity(&text, &DURATION_UNITS)
+        .map(Duration::from_nanos)
+        .map_err(serde::de::Error::custom)
+}
@@ -0,0 +1,7 @@
+#include <csignal>
+
+// A broken checker which is killed by a signal before giving any verdict
+int main() {
+    raise(SIGSEGV);
+    return 0;
+}
@@ -0,0 +1,15 @@
+#include <fstream>
+#include <iostream>
+#include <iterator>
+#include <string>
+
+// A checker which writes its extra arguments and stdin to the checker output file
+int main(int argc, char *argv[]) {
+    std::ofstream out(argv[4]);
+    for (int i = 5; i < argc; i++) {
+        out << argv[i] << "\n";
+    }
+    std::string input((std::istreambuf_iterator<char>(std::cin)), std::istreambuf_iterator<char>());
+    out << "stdin:" << input;
+    return 0;
+}
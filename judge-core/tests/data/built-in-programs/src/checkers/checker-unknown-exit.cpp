@@ -0,0 +1,4 @@
+// A broken checker which exits with a code testlib never uses
+int main() {
+    return 42;
+}
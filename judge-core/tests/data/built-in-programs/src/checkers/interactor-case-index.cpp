@@ -0,0 +1,13 @@
+#include <cstdlib>
+#include <iostream>
+#include <string>
+
+// An interactor which tells the user program the case index from its environment,
+// then waits for its answer
+int main() {
+    const char *index = std::getenv("JUDGE_CASE_INDEX");
+    std::cout << (index ? index : "unset") << std::endl;
+    std::string answer;
+    std::cin >> answer;
+    return 0;
+}
@@ -0,0 +1,18 @@
+#include <csignal>
+#include <fstream>
+#include <iostream>
+#include <string>
+
+// An interactor which exits with the code written in the input file once the user program
+// answers, or is killed by the signal of a negative code
+int main(int argc, char *argv[]) {
+    int code = 0;
+    std::ifstream(argv[1]) >> code;
+    std::cout << "ping" << std::endl;
+    std::string answer;
+    std::cin >> answer;
+    if (code < 0) {
+        std::raise(-code);
+    }
+    return code;
+}
@@ -0,0 +1,14 @@
+#include <cstdio>
+#include <cstring>
+
+// An interactor which keeps writing to the user program, whether it reads or not
+int main() {
+    char line[4096];
+    memset(line, '1', sizeof(line) - 1);
+    line[sizeof(line) - 1] = '\n';
+    for (int i = 0; i < 2048; i++) {
+        fwrite(line, 1, sizeof(line), stdout);
+    }
+    fflush(stdout);
+    return 0;
+}
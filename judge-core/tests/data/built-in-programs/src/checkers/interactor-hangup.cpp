@@ -0,0 +1,4 @@
+// An interactor which exits right away, reading nothing from the user program
+int main() {
+    return 0;
+}
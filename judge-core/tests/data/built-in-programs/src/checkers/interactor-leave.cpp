@@ -0,0 +1,14 @@
+#include <cstdio>
+#include <unistd.h>
+
+// Answers the first line of the user program, then leaves without reading the rest.
+int main() {
+    char line[256];
+    if (!fgets(line, sizeof(line), stdin)) {
+        return 1;
+    }
+    printf("bye\n");
+    fflush(stdout);
+    close(STDIN_FILENO);
+    return 0;
+}
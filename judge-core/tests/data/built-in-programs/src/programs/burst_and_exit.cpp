@@ -0,0 +1,22 @@
+#include <cstdio>
+#include <cstring>
+#include <ctime>
+
+// Writes less than a pipe can hold and exits at once, reading nothing.
+// Starts on the next tenth of a second, so that both sides of an interaction
+// using it write and exit at about the same time.
+int main() {
+    timespec now;
+    clock_gettime(CLOCK_REALTIME, &now);
+    timespec start = {now.tv_sec, (now.tv_nsec / 100000000 + 1) * 100000000};
+    if (start.tv_nsec >= 1000000000) {
+        start.tv_sec++;
+        start.tv_nsec = 0;
+    }
+    clock_nanosleep(CLOCK_REALTIME, TIMER_ABSTIME, &start, nullptr);
+
+    static char burst[60000];
+    memset(burst, 'u', sizeof(burst));
+    fwrite(burst, 1, sizeof(burst), stdout);
+    return 0;
+}
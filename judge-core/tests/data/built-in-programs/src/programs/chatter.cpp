@@ -0,0 +1,27 @@
+#include <cstdio>
+
+// Talks with interactor-chatter in rounds: writes a round of lines without waiting, then reads
+// the round of the other side. Each line tells the last line read from the other side,
+// which must come before it in the transcript.
+const int ROUNDS = 300;
+const int LINES_PER_ROUND = 8;
+
+int main() {
+    int last_round = -1, last_line = -1;
+    for (int round = 0; round < ROUNDS; round++) {
+        for (int line = 0; line < LINES_PER_ROUND; line++) {
+            printf("U %d %d %d %d\n", round, line, last_round, last_line);
+            fflush(stdout);
+        }
+        while (last_round < round || last_line < LINES_PER_ROUND - 1) {
+            char tag;
+            int seen_round, seen_line;
+            if (scanf(" %c %d %d %*d %*d", &tag, &seen_round, &seen_line) != 3) {
+                return 1;
+            }
+            last_round = seen_round;
+            last_line = seen_line;
+        }
+    }
+    return 0;
+}
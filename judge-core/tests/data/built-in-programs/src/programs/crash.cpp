@@ -0,0 +1,6 @@
+// Crashes right away, dumping its core when allowed to
+int main() {
+    volatile int *null = nullptr;
+    *null = 1;
+    return 0;
+}
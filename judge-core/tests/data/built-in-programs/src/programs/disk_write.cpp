@@ -0,0 +1,25 @@
+#include <cstdlib>
+#include <fstream>
+#include <string>
+#include <unistd.h>
+
+// Writes as many bytes as the second argument to the file of the first one,
+// then sleeps for a while when there is a third argument
+int main(int argc, char *argv[]) {
+    if (argc < 3) {
+        return 2;
+    }
+    std::ofstream file(argv[1], std::ios::binary);
+    long long remaining = std::atoll(argv[2]);
+    std::string chunk(64 * 1024, 'd');
+    while (file && remaining > 0) {
+        long long size = remaining < (long long)chunk.size() ? remaining : chunk.size();
+        file.write(chunk.data(), size);
+        remaining -= size;
+    }
+    file.flush();
+    if (argc > 3) {
+        sleep(10);
+    }
+    return file ? 0 : 1;
+}
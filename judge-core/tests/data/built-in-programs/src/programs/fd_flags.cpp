@@ -0,0 +1,16 @@
+#include <fcntl.h>
+
+#include <iostream>
+#include <string>
+
+using namespace std;
+
+// Tells whether stdin and stdout are blocking, then waits for a line from the peer
+int main() {
+    bool stdin_blocking = !(fcntl(0, F_GETFL) & O_NONBLOCK);
+    bool stdout_blocking = !(fcntl(1, F_GETFL) & O_NONBLOCK);
+    cout << "stdin=" << (stdin_blocking ? "blocking" : "nonblocking")
+         << " stdout=" << (stdout_blocking ? "blocking" : "nonblocking") << endl;
+    string line;
+    getline(cin, line);
+}
@@ -0,0 +1,22 @@
+#include <iostream>
+#include <sys/wait.h>
+#include <unistd.h>
+
+using namespace std;
+
+// Greets from a child process, exits with 1 when it can't start one
+int main() {
+    string s;
+    cin >> s;
+    pid_t pid = fork();
+    if (pid < 0) {
+        return 1;
+    }
+    if (pid == 0) {
+        cout << "Hello! " << s << endl;
+        _exit(0);
+    }
+    int status;
+    waitpid(pid, &status, 0);
+    return WIFEXITED(status) ? WEXITSTATUS(status) : 1;
+}
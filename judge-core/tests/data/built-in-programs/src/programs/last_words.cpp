@@ -0,0 +1,10 @@
+#include <cstdio>
+#include <unistd.h>
+
+// Writes its only line once the interactor is likely gone, then exits right away
+int main() {
+    usleep(100 * 1000);
+    fputs("last words\n", stdout);
+    fflush(stdout);
+    return 0;
+}
@@ -0,0 +1,13 @@
+#include <cerrno>
+#include <cstdio>
+#include <fcntl.h>
+
+// Opens files until it can't, failing with the reason
+int main() {
+    int opened = 0;
+    while (opened < 1024 && open("/dev/null", O_RDONLY) >= 0) {
+        opened++;
+    }
+    printf("%d\n", opened);
+    return errno == EMFILE ? 1 : 2;
+}
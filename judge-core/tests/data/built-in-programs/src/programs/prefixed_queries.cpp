@@ -0,0 +1,19 @@
+#include <cstdio>
+
+// Like queries, but reads how many queries to make from its stdin before interacting.
+int main() {
+    int queries;
+    if (scanf("%d", &queries) != 1) {
+        return 1;
+    }
+    char reply[16];
+    for (int i = 0; i < queries; i++) {
+        printf("? %d\n", i);
+        fflush(stdout);
+        if (scanf("%15s", reply) != 1) {
+            return 1;
+        }
+    }
+    printf("!\n");
+    return 0;
+}
@@ -0,0 +1,9 @@
+#include <cstdio>
+
+// Writes its arguments, one per line
+int main(int argc, char *argv[]) {
+    for (int i = 1; i < argc; i++) {
+        puts(argv[i]);
+    }
+    return 0;
+}
@@ -0,0 +1,17 @@
+#include <cstdio>
+#include <cstdlib>
+
+// Makes as many queries to interactor-queries as its argument, then answers.
+int main(int argc, char *argv[]) {
+    int queries = argc > 1 ? atoi(argv[1]) : 0;
+    char reply[16];
+    for (int i = 0; i < queries; i++) {
+        printf("? %d\n", i);
+        fflush(stdout);
+        if (scanf("%15s", reply) != 1) {
+            return 1;
+        }
+    }
+    printf("!\n");
+    return 0;
+}
@@ -0,0 +1,21 @@
+#include <cstdlib>
+#include <iostream>
+
+using namespace std;
+
+// Answers as many lines as its first argument like read_and_write, e.g. one per phase of an
+// interaction. With a second argument it then reads one more line and leaves without answering.
+int main(int argc, char *argv[]) {
+    int rounds = argc > 1 ? atoi(argv[1]) : 1;
+    for (int i = 0; i < rounds; i++) {
+        string s;
+        if (!(cin >> s)) {
+            return 1;
+        }
+        cout << "Hello! " << s << endl;
+    }
+    if (argc > 2) {
+        string s;
+        cin >> s;
+    }
+}
@@ -0,0 +1,15 @@
+#include <fstream>
+#include <iostream>
+
+using namespace std;
+
+// Like read_and_write, but reads the file left by setup_scenario
+int main(int argc, char *argv[]) {
+    if (argc < 2) {
+        return 2;
+    }
+    ifstream scenario(argv[1]);
+    string s;
+    scenario >> s;
+    cout << "Hello! " << s << endl;
+}
@@ -0,0 +1,11 @@
+#include <iostream>
+#include <string>
+#include <unistd.h>
+
+// Reads the first line of the interaction, then never answers
+int main() {
+    std::string line;
+    std::getline(std::cin, line);
+    sleep(100);
+    return 0;
+}
@@ -0,0 +1,15 @@
+#include <fstream>
+
+// Copies the input file to the scenario file, as a setup program
+int main(int argc, char *argv[]) {
+    if (argc < 3) {
+        return 2;
+    }
+    std::ifstream input(argv[1]);
+    std::ofstream scenario(argv[2]);
+    if (!input || !scenario) {
+        return 1;
+    }
+    scenario << input.rdbuf();
+    return scenario ? 0 : 1;
+}
@@ -0,0 +1,7 @@
+#include <unistd.h>
+
+// Uses almost no CPU time, only a wall time limit can stop it
+int main() {
+    sleep(100);
+    return 0;
+}
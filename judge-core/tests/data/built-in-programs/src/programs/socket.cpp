@@ -0,0 +1,13 @@
+#include <netinet/in.h>
+#include <sys/socket.h>
+
+// Opening a socket is allowed, serving on it is not
+int main() {
+    int fd = socket(AF_INET, SOCK_STREAM, 0);
+    sockaddr_in addr = {};
+    addr.sin_family = AF_INET;
+    addr.sin_addr.s_addr = htonl(INADDR_LOOPBACK);
+    bind(fd, reinterpret_cast<sockaddr *>(&addr), sizeof(addr));
+    listen(fd, 1);
+    return 0;
+}
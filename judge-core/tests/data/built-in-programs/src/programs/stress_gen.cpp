@@ -0,0 +1,9 @@
+#include <cstdlib>
+#include <iostream>
+
+// Generates two small numbers from the seed in argv[1]
+int main(int argc, char *argv[]) {
+    unsigned long long seed = std::strtoull(argv[1], nullptr, 10);
+    std::cout << seed % 10 << " " << seed * 7 % 13 << std::endl;
+    return 0;
+}
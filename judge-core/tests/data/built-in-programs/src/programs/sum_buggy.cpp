@@ -0,0 +1,9 @@
+#include <iostream>
+
+// Wrong when the first number is 5
+int main() {
+    long long a, b;
+    std::cin >> a >> b;
+    std::cout << (a == 5 ? a + b + 1 : a + b) << std::endl;
+    return 0;
+}
@@ -0,0 +1,18 @@
+#include <cstdio>
+#include <cstring>
+
+// Says hello to interactor-leave, then keeps writing more than a pipe holds after it left.
+int main() {
+    printf("hello\n");
+    fflush(stdout);
+    char reply[16];
+    if (scanf("%15s", reply) != 1) {
+        return 1;
+    }
+    static char line[1001];
+    memset(line, 'u', sizeof(line) - 1);
+    for (int i = 0; i < 100; i++) {
+        puts(line);
+    }
+    return 0;
+}
@@ -0,0 +1,27 @@
+#include <cstdlib>
+#include <pthread.h>
+#include <unistd.h>
+
+static void *linger(void *) {
+    usleep(200 * 1000);
+    return nullptr;
+}
+
+// Starts as many threads as its argument says, all alive at once,
+// exiting with 1 as soon as one can't be created
+int main(int argc, char *argv[]) {
+    int count = argc > 1 ? std::atoi(argv[1]) : 0;
+    pthread_attr_t attr;
+    pthread_attr_init(&attr);
+    pthread_attr_setstacksize(&attr, 64 * 1024);
+    pthread_t threads[64];
+    for (int i = 0; i < count && i < 64; i++) {
+        if (pthread_create(&threads[i], &attr, linger, nullptr) != 0) {
+            return 1;
+        }
+    }
+    for (int i = 0; i < count && i < 64; i++) {
+        pthread_join(threads[i], nullptr);
+    }
+    return 0;
+}
@@ -1,19 +1,61 @@
-use std::path::PathBuf;
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    fs::{File, Permissions},
+    io::Write,
+    os::unix::{
+        fs::PermissionsExt,
+        io::{AsRawFd, RawFd},
+    },
+    path::PathBuf,
+    sync::{mpsc, Arc},
+    time::Duration,
+};
 
 use judge_core::{
     compiler::Language,
+    error::JudgeCoreError,
+    health::{
+        check_compiler, check_rlimits, check_seccomp, health_check, HealthCheckConfig, HealthStatus,
+    },
     judge::{
-        builder::{JudgeBuilder, JudgeBuilderInput},
-        interact::run_interact,
-        result::JudgeVerdict,
-        CheckerConfig, JudgeConfig, ProgramConfig, RuntimeConfig, TestdataConfig,
+        builder::{JudgeBuilder, JudgeBuilderInput, JudgeType},
+        expect::{check_solutions, ExpectedSolution},
+        result::{
+            check_checker_result, check_output_file, check_user_result, describe_user_result,
+            read_checker_result_file, user_reason_code, CheckerOutcome, CustomComparison,
+            DecidedBy, DefaultVerdictPolicy, ExitCodeVerdictPolicy, JudgeResultInfo, JudgeVerdict,
+            PlainResultParser, TestlibXmlResultParser, VerdictPolicy, VerdictPriority,
+        },
+        stress::{run_stress, StressConfig},
+        testset::{
+            aggregate_verdict, run_testset, run_testset_iter, run_testset_with, CaseAction,
+            CaseFilter, JudgeProgress, TestSet, TestsetHooks,
+        },
+        CheckerConfig, CheckerSource, JudgeConfig, ProgramConfig, ReferenceConfig, RuntimeConfig,
+        SetupConfig, TestdataConfig, ValidationSeverity, DEFAULT_MAX_TESTDATA_BYTES,
     },
     package::PackageType,
-    run::executor::Executor,
-    sandbox::RlimitConfigs,
+    run::{
+        executor::Executor,
+        process_listener::ProcessListener,
+        sandbox::{ExecutorSandbox, SandboxBackend},
+    },
+    sandbox::{
+        clock::MockClock, host::HostResources, Limits, RlimitConfigs, Rusage, Sandbox,
+        SandboxExitInfo, SandboxSetupStep, SeccompMode, SyscallFilterLoader,
+        DEFAULT_CHECKER_LIMITS,
+    },
+    utils::{FloatComparison, OutputComparison, TextPolicy, TokenComparison},
 };
 
-use judge_core::judge::common::run_judge;
+use judge_core::judge::common::{
+    judge_single, judge_single_bytes, run_checker, run_judge, run_judge_bytes, run_judge_with,
+};
+#[cfg(target_os = "linux")]
+use judge_core::judge::interact::{replay_interaction, run_interact, run_interact_phases};
+#[cfg(target_os = "linux")]
+use judge_core::judge::result::TestlibInteractorPolicy;
 
 const TEST_DATA_PATH: &str = "tests/data";
 const TEST_TEMP_PATH: &str = "tests/temp";
@@ -24,6 +66,7 @@ const TEST_CONFIG: RlimitConfigs = RlimitConfigs {
     cpu_limit: Some((1, 2)),
     nproc_limit: Some((1, 1)),
     fsize_limit: Some((1024, 1024)),
+    open_files_limit: Some((64, 64)),
 };
 
 fn init() {
@@ -36,21 +79,90 @@ fn build_test_config(program_executor: Executor) -> JudgeConfig {
     JudgeConfig {
         runtime: RuntimeConfig {
             rlimit_configs: TEST_CONFIG,
+            verbose: false,
+            wall_time_limit: None,
+            nice: None,
+            cpu_affinity: None,
+            idle_time_limit: None,
+            disk_write_limit: None,
+            max_threads: None,
+            allow_subprocesses: false,
+            kill_grace_period: Duration::ZERO,
+            cpu_time_grace: Duration::ZERO,
+            sync_transcript: false,
+            max_interactions: None,
+            max_stdin_bytes: None,
+            total_time_budget: None,
+            warmup_input: None,
+            timing_runs: 1,
+            seccomp_mode: SeccompMode::default(),
+            verdict_policy: None,
+            clock: None,
+            record_timings: false,
+            checker_limits: DEFAULT_CHECKER_LIMITS,
+            max_testdata_bytes: DEFAULT_MAX_TESTDATA_BYTES,
+            submission_id: None,
+            scratch_dir: std::env::temp_dir(),
+            core_dump_dir: None,
         },
         test_data: TestdataConfig {
             input_file_path: PathBuf::from(TEST_DATA_PATH)
                 .join("packages/icpc/hello_world/data/secret/0.in"),
             answer_file_path: PathBuf::from(TEST_DATA_PATH)
                 .join("packages/icpc/hello_world/data/secret/0.ans"),
+            extra_answer_file_paths: vec![],
+            input_sha256: None,
+            answer_sha256: None,
+            case_index: None,
         },
-        checker: CheckerConfig {
-            executor: None,
-            output_file_path: PathBuf::from(TEST_TEMP_PATH).join("checker.out"),
-        },
+        checker: build_test_checker_config(PathBuf::from(TEST_TEMP_PATH).join("checker.out")),
         program: ProgramConfig {
             executor: program_executor,
-            output_file_path: PathBuf::from(TEST_TEMP_PATH).join("program.out"),
+            output_file_path: Some(PathBuf::from(TEST_TEMP_PATH).join("program.out")),
+            program_args: vec![],
+            stdin_prefix_path: None,
         },
+        setup: None,
+        reference: None,
+    }
+}
+
+/// The default checker writing to `output_file_path`, set `executor` for another one.
+fn build_test_checker_config(output_file_path: PathBuf) -> CheckerConfig {
+    CheckerConfig {
+        executor: None,
+        output_file_path,
+        normalizers: vec![],
+        text: TextPolicy::default(),
+        comparison: OutputComparison::Lines,
+        diff_lines: None,
+        extra_args: vec![],
+        feed_input_to_stdin: false,
+        pass_transcript: false,
+        pass_case_index: false,
+        result_parser: None,
+        interactor_policy: None,
+        custom_comparison: None,
+    }
+}
+
+/// The built-in checker `name`, from `built-in-programs/src/checkers`.
+fn built_in_checker(name: &str) -> Executor {
+    Executor::new(
+        Language::Cpp,
+        PathBuf::from(TEST_DATA_PATH)
+            .join("built-in-programs/build/src/checkers")
+            .join(name),
+    )
+    .unwrap()
+}
+
+/// `checker-echo-args`, writing its argv and stdin to `output_file_path`, which the checker
+/// appends to as the sandbox user.
+fn echo_args_checker_config(output_file_path: PathBuf) -> CheckerConfig {
+    CheckerConfig {
+        executor: Some(built_in_checker("checker-echo-args")),
+        ..build_test_checker_config(output_file_path)
     }
 }
 
@@ -68,6 +180,8 @@ fn test_run_judge() {
     if let Ok(result) = result {
         log::debug!("{:?}", result);
         assert_eq!(result.verdict, JudgeVerdict::Accepted);
+        assert_eq!(result.interactor_time_usage, None);
+        assert_eq!(result.score, 1.0);
     } else {
         log::debug!("{:?}", result);
         unreachable!()
@@ -75,129 +189,4187 @@ fn test_run_judge() {
 }
 
 #[test]
-fn test_run_judge_python() {
+fn test_run_judge_captured_stdout() {
     init();
-    log::debug!("current dir: {:?}", std::env::current_dir().unwrap());
+    let program_path =
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/read_and_write");
+    let program_executor = Executor::new(Language::Cpp, program_path).unwrap();
+    let mut runner_config = build_test_config(program_executor);
+    runner_config.program.output_file_path = None;
+
+    let result = run_judge(&runner_config).unwrap();
+    assert_eq!(result.verdict, JudgeVerdict::Accepted);
+    assert_eq!(result.score, 1.0);
+    assert!(runner_config.program.output_file_path.is_none());
+
+    // The captured stdout is really what gets checked
+    std::fs::write(
+        PathBuf::from(TEST_TEMP_PATH).join("captured.ans"),
+        "not the output\n",
+    )
+    .unwrap();
+    runner_config.test_data.answer_file_path = PathBuf::from(TEST_TEMP_PATH).join("captured.ans");
+    let result = run_judge(&runner_config).unwrap();
+    assert_eq!(result.verdict, JudgeVerdict::WrongAnswer);
+}
 
+#[test]
+fn test_run_judge_effective_limits() {
+    init();
     let program_path =
-        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/src/programs/read_and_write.py");
-    let program_executor = Executor::new(Language::Python, program_path.clone()).unwrap();
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/read_and_write");
+    let program_executor = Executor::new(Language::Cpp, program_path).unwrap();
+    let mut runner_config = build_test_config(program_executor);
+    let result = run_judge(&runner_config).unwrap();
+    assert_eq!(result.effective_limits, None);
 
-    let runner_config = build_test_config(program_executor);
-    let result = run_judge(&runner_config);
-    if let Ok(result) = result {
-        log::debug!("{:?}", result);
-        assert_eq!(result.verdict, JudgeVerdict::Accepted);
-    } else {
-        log::debug!("{:?}", result);
-        unreachable!()
+    // Past `fs.nr_open` and the hard limit of the judger, the host can't grant that many
+    let (_, host_ceiling) =
+        nix::sys::resource::getrlimit(nix::sys::resource::Resource::RLIMIT_NOFILE).unwrap();
+    runner_config.runtime.rlimit_configs.open_files_limit = Some((1 << 40, 1 << 40));
+    runner_config.runtime.verbose = true;
+    let result = run_judge(&runner_config).unwrap();
+    assert_eq!(result.verdict, JudgeVerdict::Accepted);
+    let requested = runner_config.runtime.limits();
+    let effective = result.effective_limits.unwrap();
+    assert_eq!(requested.open_files, Some(1 << 40));
+    assert_eq!(effective.open_files, Some(host_ceiling));
+    // Enforced as requested, the CPU time with the margin of `RlimitConfigs::load()`
+    assert_eq!(effective.memory_bytes, requested.memory_bytes);
+    assert_eq!(effective.stack_bytes, requested.stack_bytes);
+    assert_eq!(effective.cpu_time, Some(Duration::from_secs(2)));
+    assert_eq!(requested.cpu_time, Some(Duration::from_secs(1)));
+    assert_eq!(effective.lowered, vec!["open_files".to_string()]);
+    assert_eq!(
+        result.raw.unwrap().effective_limits,
+        Some(effective.clone())
+    );
+
+    // Lowered limits are reported even without asking
+    runner_config.runtime.verbose = false;
+    let result = run_judge(&runner_config).unwrap();
+    assert_eq!(result.effective_limits, Some(effective));
+}
+
+/// Records the name and fields of every span created while it is the default subscriber.
+#[cfg(feature = "tracing")]
+struct SpanRecorder {
+    spans: Arc<std::sync::Mutex<Vec<String>>>,
+}
+
+#[cfg(feature = "tracing")]
+impl tracing::Subscriber for SpanRecorder {
+    fn enabled(&self, _metadata: &tracing::Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, span: &tracing::span::Attributes<'_>) -> tracing::span::Id {
+        let mut fields = String::new();
+        span.record(
+            &mut |field: &tracing::field::Field, value: &dyn std::fmt::Debug| {
+                fields.push_str(&format!(" {}={:?}", field, value));
+            },
+        );
+        let mut spans = self.spans.lock().unwrap();
+        spans.push(format!("{}{}", span.metadata().name(), fields));
+        tracing::span::Id::from_u64(spans.len() as u64)
     }
+
+    fn record(&self, _span: &tracing::span::Id, _values: &tracing::span::Record<'_>) {}
+
+    fn record_follows_from(&self, _span: &tracing::span::Id, _follows: &tracing::span::Id) {}
+
+    fn event(&self, _event: &tracing::Event<'_>) {}
+
+    fn enter(&self, _span: &tracing::span::Id) {}
+
+    fn exit(&self, _span: &tracing::span::Id) {}
 }
 
+#[cfg(feature = "tracing")]
 #[test]
-fn test_run_tle() {
+fn test_run_judge_tracing_spans() {
     init();
     let program_path =
-        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/infinite_loop");
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/read_and_write");
     let program_executor = Executor::new(Language::Cpp, program_path).unwrap();
+    let mut runner_config = build_test_config(program_executor);
+    runner_config.runtime.submission_id = Some("42".to_string());
 
-    let runner_config = build_test_config(program_executor);
-    let result = run_judge(&runner_config);
-    assert!(result.is_ok());
-    if let Ok(result) = result {
-        log::debug!("{:?}", result);
-        assert_eq!(result.verdict, JudgeVerdict::TimeLimitExceeded);
-    }
+    let spans = Arc::new(std::sync::Mutex::new(vec![]));
+    let recorder = SpanRecorder {
+        spans: spans.clone(),
+    };
+    let result = tracing::subscriber::with_default(recorder, || run_judge(&runner_config)).unwrap();
+    assert_eq!(result.verdict, JudgeVerdict::Accepted);
+    assert_eq!(
+        *spans.lock().unwrap(),
+        vec!["judge submission_id=\"42\"", "run", "check"]
+    );
 }
 
 #[test]
-fn test_run_mle() {
+fn test_run_judge_verbose() {
     init();
     let program_path =
-        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/memory_limit");
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/read_and_write");
+    let program_executor = Executor::new(Language::Cpp, program_path).unwrap();
+
+    let mut runner_config = build_test_config(program_executor);
+    let result = run_judge(&runner_config).unwrap();
+    assert!(result.raw.is_none());
+
+    runner_config.runtime.verbose = true;
+    let result = run_judge(&runner_config).unwrap();
+    log::debug!("{:?}", result);
+    let raw = result.raw.as_ref().unwrap();
+    assert_eq!(raw.exit_status, result.exit_status);
+    assert!(raw.start_timestamp_ms <= raw.end_timestamp_ms);
+
+    let serialized = serde_json::to_value(&result).unwrap();
+    assert!(serialized["raw"]["resource_usage"]["minor_page_faults"].is_i64());
+}
+
+#[test]
+fn test_run_judge_timestamps() {
+    init();
+    let program_path =
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/read_and_write");
+    let mut runner_config = build_test_config(Executor::new(Language::Cpp, program_path).unwrap());
+    runner_config.runtime.verbose = true;
+    let unix_ms = || {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as u64
+    };
+
+    let before = unix_ms();
+    let result = run_judge(&runner_config).unwrap();
+    let after = unix_ms();
+    let started_at = result.started_at.unwrap();
+    let finished_at = result.finished_at.unwrap();
+    assert!(before <= started_at && started_at <= finished_at && finished_at <= after);
+    // Both are truncated to milliseconds
+    let real_time_cost = result.raw.as_ref().unwrap().real_time_cost;
+    assert!(u128::from(finished_at - started_at) + 1 >= real_time_cost.as_millis());
+    let serialized = serde_json::to_value(&result).unwrap();
+    assert_eq!(serialized["started_at"], started_at);
+
+    // Nothing is run for a case which is not judged
+    runner_config.runtime.max_testdata_bytes = 1;
+    let result = run_judge(&runner_config).unwrap();
+    assert_eq!(result.verdict, JudgeVerdict::SystemError);
+    assert_eq!((result.started_at, result.finished_at), (None, None));
+}
+
+#[test]
+fn test_run_judge_performance_signals() {
+    init();
+    let program_path =
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/read_and_write");
     let program_executor = Executor::new(Language::Cpp, program_path).unwrap();
 
     let runner_config = build_test_config(program_executor);
-    let result = run_judge(&runner_config);
-    assert!(result.is_ok());
-    if let Ok(result) = result {
-        log::debug!("{:?}", result);
-        assert_eq!(result.verdict, JudgeVerdict::RuntimeError);
-    }
+    let result = run_judge(&runner_config).unwrap();
+    log::debug!("{:?}", result);
+    let performance = result.performance.unwrap();
+    // Loading the program alone touches fresh pages
+    assert!(performance.minor_page_faults > 0);
+    assert!(performance.voluntary_context_switches >= 0);
+    assert!(performance.involuntary_context_switches >= 0);
+    assert!(performance.major_page_faults >= 0);
+
+    let serialized = serde_json::to_value(&result).unwrap();
+    assert!(serialized["performance"]["voluntary_context_switches"].is_i64());
 }
 
 #[test]
-fn test_run_interact() {
+fn test_run_judge_excludes_fork_baseline() {
     init();
-    let interactor_executor = Executor::new(
-        Language::Cpp,
-        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/checkers/interactor-echo"),
-    )
-    .unwrap();
+    // Keep the judger's own resident set far larger than the program's while it forks
+    let ballast = std::hint::black_box(vec![1u8; 96 * 1024 * 1024]);
+    let program_path =
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/read_and_write");
+    let program_executor = Executor::new(Language::Cpp, program_path).unwrap();
+
+    let mut runner_config = build_test_config(program_executor);
+    runner_config.runtime.verbose = true;
+    let result = run_judge(&runner_config).unwrap();
+    log::debug!("{:?}", result);
+    let raw = result.raw.as_ref().unwrap();
+    assert!(raw.baseline_rss >= 96 * 1024);
+    assert!(raw.resource_usage.max_rss >= 96 * 1024);
+    assert!(result.memory_usage_bytes < 8 * 1024);
+    assert_eq!(
+        ballast.iter().map(|&b| b as usize).sum::<usize>(),
+        ballast.len()
+    );
+}
+
+#[test]
+fn test_run_judge_timings() {
+    init();
+    let program_path =
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/read_and_write");
+    let program_executor = Executor::new(Language::Cpp, program_path).unwrap();
+    let mut runner_config = build_test_config(program_executor);
+    runner_config.program.output_file_path =
+        Some(PathBuf::from(TEST_TEMP_PATH).join("timings.out"));
+    assert!(run_judge(&runner_config).unwrap().timings.is_none());
+
+    runner_config.runtime.record_timings = true;
+    let result = run_judge(&runner_config).unwrap();
+    assert_eq!(result.verdict, JudgeVerdict::Accepted);
+    let timings = result.timings.unwrap();
+    log::debug!("{:?}", timings);
+    assert!(timings.execution > Duration::ZERO);
+    let phases = timings.prepare + timings.execution + timings.checking;
+    assert!(phases <= timings.total);
+    assert!(timings.total - phases < Duration::from_millis(1));
+    assert!(serde_json::to_string(&timings)
+        .unwrap()
+        .contains("\"execution\""));
+
+    // Measured by the clock of the config, which stands still
+    runner_config.runtime.clock = Some(Arc::new(MockClock::new()));
+    let result = run_judge(&runner_config).unwrap();
+    assert_eq!(result.verdict, JudgeVerdict::Accepted);
+    let timings = result.timings.unwrap();
+    assert_eq!(timings.total, Duration::ZERO);
+}
+
+#[test]
+fn test_run_judge_multiple_answers() {
+    init();
+    let program_path =
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/read_and_write");
+    let program_executor = Executor::new(Language::Cpp, program_path).unwrap();
+    let wrong_answer_path = PathBuf::from(TEST_TEMP_PATH).join("multiple_answers_wrong.ans");
+    std::fs::write(&wrong_answer_path, "Goodbye!\n").unwrap();
+
+    let mut runner_config = build_test_config(program_executor);
+    let right_answer_path = runner_config.test_data.answer_file_path.clone();
+    runner_config.test_data.answer_file_path = wrong_answer_path.clone();
+    runner_config.test_data.extra_answer_file_paths = vec![right_answer_path];
+    let result = run_judge(&runner_config).unwrap();
+    log::debug!("{:?}", result);
+    assert_eq!(result.verdict, JudgeVerdict::Accepted);
+    assert!(result.message.contains("0.ans"));
+
+    runner_config.test_data.extra_answer_file_paths = vec![wrong_answer_path];
+    let result = run_judge(&runner_config).unwrap();
+    assert_eq!(result.verdict, JudgeVerdict::WrongAnswer);
+}
+
+#[cfg(feature = "checksum")]
+#[test]
+fn test_run_judge_checksums() {
+    init();
+    let program_path =
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/read_and_write");
+    let program_executor = Executor::new(Language::Cpp, program_path).unwrap();
+    let answer_path = PathBuf::from(TEST_TEMP_PATH).join("checksums.ans");
+    std::fs::write(&answer_path, "Hello! world!\n").unwrap();
+
+    let mut runner_config = build_test_config(program_executor);
+    runner_config.program.output_file_path =
+        Some(PathBuf::from(TEST_TEMP_PATH).join("checksums.out"));
+    runner_config.test_data.answer_file_path = answer_path.clone();
+    runner_config.test_data.populate_checksums().unwrap();
+    let result = run_judge(&runner_config).unwrap();
+    assert_eq!(result.verdict, JudgeVerdict::Accepted);
+
+    std::fs::write(&answer_path, "Hello! world?\n").unwrap();
+    let result = run_judge(&runner_config).unwrap();
+    log::debug!("{:?}", result);
+    assert_eq!(result.verdict, JudgeVerdict::SystemError);
+    assert!(result.message.contains("test data integrity"));
+    assert!(result.message.contains("checksums.ans"));
+
+    // The checksums are of the files, not of the bytes judged in their place
+    let result = run_judge_bytes(&runner_config, b"there", b"Hello! there\n").unwrap();
+    assert_eq!(result.verdict, JudgeVerdict::Accepted);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_run_judge_disk_write_limit() {
+    init();
+    // Written by the user program, which may run as another user
+    let disk_dir = PathBuf::from(TEST_TEMP_PATH).join("disk");
+    std::fs::create_dir_all(&disk_dir).unwrap();
+    std::fs::set_permissions(&disk_dir, Permissions::from_mode(0o777)).unwrap();
+    let written_path = disk_dir.join("written.bin");
     let program_executor = Executor::new(
         Language::Cpp,
-        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/read_and_write"),
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/disk_write"),
     )
     .unwrap();
-    let runner_config = JudgeConfig {
-        checker: CheckerConfig {
-            executor: Some(
-                Executor::new(
-                    Language::Cpp,
-                    PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/checkers/lcmp"),
-                )
-                .unwrap(),
-            ),
-            output_file_path: PathBuf::from(TEST_TEMP_PATH).join("checker.out"),
-        },
-        ..build_test_config(program_executor)
+    let mut runner_config = build_test_config(program_executor);
+    runner_config.runtime.rlimit_configs.fsize_limit = Some((64 << 20, 64 << 20));
+    runner_config.runtime.disk_write_limit = Some(16 << 20);
+    runner_config.runtime.verbose = true;
+    let args = |bytes: u64, stay: bool| {
+        let mut args = vec![
+            written_path.to_str().unwrap().to_string(),
+            bytes.to_string(),
+        ];
+        if stay {
+            args.push("stay".to_string());
+        }
+        args
     };
-    let result = run_interact(
-        &runner_config,
-        interactor_executor,
-        &PathBuf::from(TEST_TEMP_PATH).join("interact.out"),
+
+    // Under the limit, the output is wrong though
+    runner_config.program.program_args = args(4 << 20, false);
+    let result = run_judge(&runner_config).unwrap();
+    assert_eq!(result.verdict, JudgeVerdict::WrongAnswer);
+    assert!(
+        result.disk_write_bytes >= 4 << 20,
+        "{}",
+        result.disk_write_bytes
     );
-    match result {
-        Ok(Some(result)) => {
-            log::debug!("{:?}", result);
-            assert!(result.verdict == JudgeVerdict::Accepted);
-        }
-        Ok(None) => {
-            log::debug!("Ignoring this result, for it's from a fork child process");
-        }
-        Err(e) => {
-            log::error!("meet error: {:?}", e);
-            unreachable!()
-        }
+
+    // Killed while it is still running
+    runner_config.runtime.disk_write_limit = Some(1 << 20);
+    runner_config.program.program_args = args(8 << 20, true);
+    let result = run_judge(&runner_config).unwrap();
+    log::debug!("{:?}", result);
+    assert_eq!(result.verdict, JudgeVerdict::DiskLimitExceeded);
+    assert_eq!(result.reason_code.as_deref(), Some("DISK_LIMIT"));
+    assert!(
+        result.disk_write_bytes > 1 << 20,
+        "{}",
+        result.disk_write_bytes
+    );
+    assert!(result.raw.unwrap().disk_limit_killed);
+    assert!(result.time_usage < Duration::from_secs(5));
+
+    // Exited before the sandbox noticed, caught by its rusage
+    runner_config.runtime.disk_write_limit = Some(1 << 20);
+    runner_config.program.program_args = args(4 << 20, false);
+    let result = run_judge(&runner_config).unwrap();
+    assert_eq!(result.verdict, JudgeVerdict::DiskLimitExceeded);
+    assert!(
+        result.disk_write_bytes > 1 << 20,
+        "{}",
+        result.disk_write_bytes
+    );
+    std::fs::remove_file(&written_path).unwrap();
+}
+
+#[test]
+fn test_run_judge_testdata_too_large() {
+    init();
+    let program_path =
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/read_and_write");
+    let program_executor = Executor::new(Language::Cpp, program_path).unwrap();
+    let answer_path = PathBuf::from(TEST_TEMP_PATH).join("too_large.ans");
+    std::fs::write(&answer_path, "Hello! world!\n".repeat(1000)).unwrap();
+
+    let mut runner_config = build_test_config(program_executor);
+    runner_config.test_data.answer_file_path = answer_path;
+    assert_eq!(
+        runner_config.runtime.max_testdata_bytes,
+        DEFAULT_MAX_TESTDATA_BYTES
+    );
+    let result = run_judge(&runner_config).unwrap();
+    assert_eq!(result.verdict, JudgeVerdict::WrongAnswer);
+
+    runner_config.runtime.max_testdata_bytes = 1024;
+    let result = run_judge(&runner_config).unwrap();
+    log::debug!("{:?}", result);
+    assert_eq!(result.verdict, JudgeVerdict::SystemError);
+    assert_eq!(result.reason_code.as_deref(), Some("SE_TESTDATA_TOO_LARGE"));
+    assert!(result.message.contains("too_large.ans"));
+    assert!(result.message.contains("14000 bytes"));
+}
+
+#[test]
+fn test_run_judge_decided_by() {
+    init();
+    let program_path =
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/read_and_write");
+    let program_executor = Executor::new(Language::Cpp, program_path).unwrap();
+    let mut runner_config = build_test_config(program_executor);
+
+    let comparisons = [
+        (OutputComparison::Lines, DecidedBy::BuiltinExact),
+        (OutputComparison::Bytes, DecidedBy::BuiltinExact),
+        (
+            OutputComparison::Tokens(TokenComparison::Exact),
+            DecidedBy::BuiltinToken,
+        ),
+        (
+            OutputComparison::Tokens(TokenComparison::Float(FloatComparison::new(1e-6))),
+            DecidedBy::BuiltinFloat,
+        ),
+    ];
+    for (comparison, decided_by) in comparisons {
+        runner_config.checker.comparison = comparison;
+        let result = run_judge(&runner_config).unwrap();
+        assert_eq!(result.verdict, JudgeVerdict::Accepted);
+        assert_eq!(result.decided_by, Some(decided_by));
     }
+
+    let checker_path =
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/checkers/lcmp");
+    runner_config.checker.executor =
+        Some(Executor::new(Language::Cpp, checker_path.clone()).unwrap());
+    let result = run_judge(&runner_config).unwrap();
+    assert_eq!(result.verdict, JudgeVerdict::Accepted);
+    assert_eq!(
+        result.decided_by,
+        Some(DecidedBy::ExternalChecker(checker_path))
+    );
+
+    // Decided by the time limit, not by checking the output
+    let program_path =
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/infinite_loop");
+    runner_config.program.executor = Executor::new(Language::Cpp, program_path).unwrap();
+    let result = run_judge(&runner_config).unwrap();
+    assert_eq!(result.verdict, JudgeVerdict::TimeLimitExceeded);
+    assert_eq!(result.decided_by, None);
 }
 
 #[test]
-fn test_build_icpc() {
+fn test_builtin_checkers() {
     init();
-    let builder = JudgeBuilder::new(JudgeBuilderInput {
-        package_type: PackageType::ICPC,
-        package_path: PathBuf::from(TEST_DATA_PATH).join("packages/icpc/hello_world"),
-        runtime_path: PathBuf::from(TEST_TEMP_PATH).join("hello_world"),
-        src_language: Language::Cpp,
-        src_path: PathBuf::from(TEST_DATA_PATH)
-            .join("built-in-programs/src/programs/read_and_write.cpp"),
-    })
+    let program_path =
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/read_and_write");
+    let mut runner_config = build_test_config(Executor::new(Language::Cpp, program_path).unwrap());
+    runner_config.program.output_file_path =
+        Some(PathBuf::from(TEST_TEMP_PATH).join("builtin.out"));
+    runner_config.test_data.answer_file_path = PathBuf::from(TEST_TEMP_PATH).join("builtin.ans");
+    let exited = build_exit_info(Duration::from_millis(100));
+
+    let cases = [
+        (
+            "wcmp",
+            "007 3.0000001\n",
+            "007\n  3.0000001",
+            "7 3.0000001",
+            DecidedBy::BuiltinToken,
+        ),
+        (
+            "fcmp",
+            "007 3.0000001\n",
+            "7.0 3",
+            "7 3.1",
+            DecidedBy::BuiltinFloat,
+        ),
+        // The tokens of each line are compared, not the spacing nor the line breaks
+        (
+            "lcmp",
+            "007 3.0000001\n",
+            "007  3.0000001 \n\n",
+            "007\n3.0000001",
+            DecidedBy::BuiltinToken,
+        ),
+        (
+            "lcmp",
+            "1\n\n2\n",
+            "1\n \n2",
+            "1\n2\n",
+            DecidedBy::BuiltinToken,
+        ),
+        (
+            "ncmp",
+            "7 -3\n",
+            "007 -3",
+            "7 -3.0",
+            DecidedBy::BuiltinInteger,
+        ),
+    ];
+    for (name, answer, accepted, rejected, decided_by) in cases {
+        std::fs::write(&runner_config.test_data.answer_file_path, answer).unwrap();
+        runner_config
+            .checker
+            .select(&CheckerSource::from(name))
+            .unwrap();
+        let result = judge_with_mock_runs(&runner_config, vec![(accepted, exited.clone())]);
+        assert_eq!(result.verdict, JudgeVerdict::Accepted, "{}", name);
+        assert_eq!(result.decided_by, Some(decided_by), "{}", name);
+        let result = judge_with_mock_runs(&runner_config, vec![(rejected, exited.clone())]);
+        assert_eq!(result.verdict, JudgeVerdict::WrongAnswer, "{}", name);
+    }
+    // A token which is not an integer is rejected, even when the answer has it too
+    std::fs::write(&runner_config.test_data.answer_file_path, "7 x\n").unwrap();
+    let result = judge_with_mock_runs(&runner_config, vec![("7 x", exited.clone())]);
+    assert_eq!(result.verdict, JudgeVerdict::WrongAnswer);
+
+    assert!(matches!(
+        runner_config.checker.select(&CheckerSource::from("rcmp")),
+        Err(JudgeCoreError::InvalidConfig(message)) if message.contains("rcmp")
+    ));
+    let checker_path =
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/checkers/lcmp");
+    let source = CheckerSource::from(checker_path.to_str().unwrap());
+    assert_eq!(source, CheckerSource::External(checker_path.clone()));
+    runner_config.checker.select(&source).unwrap();
+    assert_eq!(
+        runner_config.checker.decided_by(),
+        DecidedBy::ExternalChecker(checker_path)
+    );
+    std::fs::remove_file(&runner_config.test_data.answer_file_path).unwrap();
+}
+
+#[test]
+fn test_skipped_verdict_serialization() {
+    assert_eq!(
+        serde_json::to_string(&JudgeVerdict::Skipped).unwrap(),
+        "\"Skipped\""
+    );
+    assert_eq!(JudgeVerdict::Skipped.to_string(), "Skipped");
+    assert_eq!(
+        serde_json::to_string(&JudgeVerdict::NotRun).unwrap(),
+        "\"NotRun\""
+    );
+}
+
+#[test]
+fn test_judge_single() {
+    init();
+    let program_path =
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/read_and_write");
+    let program_executor = Executor::new(Language::Cpp, program_path).unwrap();
+
+    let result = judge_single(
+        program_executor,
+        &PathBuf::from(TEST_DATA_PATH).join("packages/icpc/hello_world/data/secret/0.in"),
+        &PathBuf::from(TEST_DATA_PATH).join("packages/icpc/hello_world/data/secret/0.ans"),
+        Limits {
+            wall_time: Some(Duration::from_secs(2)),
+            ..Limits::from(&TEST_CONFIG)
+        },
+    )
     .unwrap();
-    log::info!("builder: {:?}", builder);
-    for idx in 0..builder.testdata_configs.len() {
-        log::info!("runing testdata {}", idx);
-        let judge_config = JudgeConfig {
-            test_data: builder.testdata_configs[idx].clone(),
-            program: builder.program_config.clone(),
-            checker: builder.checker_config.clone(),
-            runtime: builder.runtime_config.clone(),
-        };
+    log::debug!("{:?}", result);
+    assert_eq!(result.verdict, JudgeVerdict::Accepted);
 
-        let res = run_judge(&judge_config);
-        match res {
-            Ok(info) => log::info!("{:?}", info),
-            Err(e) => panic!("{:?}", e),
+    // The caps map through the runtime config, a fraction of a second of CPU time included
+    let limits = Limits {
+        cpu_time: Some(Duration::from_millis(1500)),
+        wall_time: Some(Duration::from_secs(3)),
+        memory_bytes: Some(64 * 1024 * 1024),
+        max_threads: Some(4),
+        disk_write_bytes: Some(4096),
+        ..Limits::default()
+    };
+    let runtime = RuntimeConfig::with_limits(&limits, PathBuf::from(TEST_TEMP_PATH));
+    assert_eq!(runtime.cpu_time_limit(), Some(Duration::from_millis(1500)));
+    assert_eq!(
+        runtime.limits(),
+        Limits {
+            cpu_time: Some(Duration::from_secs(1)),
+            ..limits
         }
-    }
+    );
+}
+
+#[test]
+fn test_judge_bytes() {
+    init();
+    let program_path =
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/read_and_write");
+    let program_executor = Executor::new(Language::Cpp, program_path).unwrap();
+
+    let result = judge_single_bytes(
+        program_executor.clone(),
+        b"world!",
+        b"Hello! world!\n",
+        Limits::from(&TEST_CONFIG),
+    )
+    .unwrap();
+    assert_eq!(result.verdict, JudgeVerdict::Accepted);
+
+    // Nothing is left in the scratch directory
+    let scratch_dir = PathBuf::from(TEST_TEMP_PATH).join("bytes_scratch");
+    let _ = std::fs::remove_dir_all(&scratch_dir);
+    std::fs::create_dir_all(&scratch_dir).unwrap();
+    let mut runner_config = build_test_config(program_executor);
+    runner_config.program.output_file_path = None;
+    runner_config.runtime.scratch_dir = scratch_dir.clone();
+    let result = run_judge_bytes(&runner_config, b"there", b"Hello! there\n").unwrap();
+    assert_eq!(result.verdict, JudgeVerdict::Accepted);
+    let result = run_judge_bytes(&runner_config, b"there", b"Hello! world!\n").unwrap();
+    assert_eq!(result.verdict, JudgeVerdict::WrongAnswer);
+    assert_eq!(std::fs::read_dir(&scratch_dir).unwrap().count(), 0);
+}
+
+#[test]
+fn test_stdin_bytes_read() {
+    init();
+    let program_path =
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/read_and_write");
+    let mut runner_config = build_test_config(Executor::new(Language::Cpp, program_path).unwrap());
+    let input_len = std::fs::metadata(&runner_config.test_data.input_file_path)
+        .unwrap()
+        .len();
+
+    let result = run_judge(&runner_config).unwrap();
+    assert_eq!(result.verdict, JudgeVerdict::Accepted);
+    assert_eq!(result.stdin_bytes_read, Some(input_len));
+
+    // The whole input fits in the limit
+    runner_config.runtime.max_stdin_bytes = Some(input_len);
+    let result = run_judge(&runner_config).unwrap();
+    assert_eq!(result.verdict, JudgeVerdict::Accepted);
+    assert_eq!(result.stdin_bytes_read, Some(input_len));
+
+    // Blocked reading the rest of the word past the limit
+    runner_config.runtime.max_stdin_bytes = Some(2);
+    let result = run_judge(&runner_config).unwrap();
+    assert_eq!(result.verdict, JudgeVerdict::RestrictedFunction);
+    assert_eq!(result.reason_code.as_deref(), Some("STDIN_LIMIT"));
+    assert_eq!(result.stdin_bytes_read, Some(2));
+
+    // Never reading its stdin
+    let program_path =
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/print_args");
+    let mut runner_config = build_test_config(Executor::new(Language::Cpp, program_path).unwrap());
+    runner_config.program.output_file_path =
+        Some(PathBuf::from(TEST_TEMP_PATH).join("stdin_bytes_read.out"));
+    let result = run_judge(&runner_config).unwrap();
+    assert_eq!(result.stdin_bytes_read, Some(0));
+}
+
+#[test]
+fn test_run_judge_custom_comparison() {
+    init();
+    let program_path =
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/read_and_write");
+    let program_executor = Executor::new(Language::Cpp, program_path).unwrap();
+    let mut runner_config = build_test_config(program_executor);
+    runner_config.program.output_file_path = None;
+    runner_config.checker.custom_comparison =
+        Some(CustomComparison::new(|_input, output, _answer| {
+            let len = std::fs::metadata(output).unwrap().len();
+            if len % 2 == 1 {
+                CheckerOutcome::new(JudgeVerdict::Accepted, String::new())
+            } else {
+                CheckerOutcome::new(JudgeVerdict::WrongAnswer, format!("{} bytes", len))
+            }
+        }));
+
+    // "Hello! odd\n", the answer is never read
+    let result = run_judge_bytes(&runner_config, b"odd", b"").unwrap();
+    assert_eq!(result.verdict, JudgeVerdict::Accepted);
+    assert_eq!(result.decided_by, Some(DecidedBy::CustomClosure));
+    let result = run_judge_bytes(&runner_config, b"even", b"").unwrap();
+    assert_eq!(result.verdict, JudgeVerdict::WrongAnswer);
+    assert_eq!(result.message, "12 bytes");
+    assert_eq!(result.decided_by, Some(DecidedBy::CustomClosure));
+}
+
+fn build_exit_info(cpu_time: Duration) -> SandboxExitInfo {
+    SandboxExitInfo {
+        exit_status: 0,
+        exit_signal: 0,
+        exit_code: 0,
+        real_time_cost: cpu_time,
+        resource_usage: Rusage {
+            user_time: cpu_time,
+            system_time: Duration::ZERO,
+            max_rss: 0,
+            page_faults: 0,
+            minor_page_faults: 0,
+            involuntary_context_switches: 0,
+            voluntary_context_switches: 0,
+            block_output_operations: 0,
+        },
+        baseline_rss: 0,
+        effective_limits: None,
+        idle_killed: false,
+        disk_limit_killed: false,
+        subprocess_killed: false,
+        blocked_syscall: None,
+        stdin_limit_killed: false,
+        core_dump: None,
+        start_timestamp_ms: 0,
+        end_timestamp_ms: 0,
+    }
+}
+
+thread_local! {
+    /// What the next `MockSandbox`es spawned on this thread write to stdout and exit with
+    static MOCK_RUNS: RefCell<VecDeque<(&'static str, SandboxExitInfo)>> =
+        const { RefCell::new(VecDeque::new()) };
+}
+
+/// Runs nothing, reports the canned runs of `MOCK_RUNS` instead.
+struct MockSandbox {
+    output_redirect: Option<RawFd>,
+    exit_info: Option<SandboxExitInfo>,
+}
+
+impl SandboxBackend for MockSandbox {
+    fn create(
+        _executor: Executor,
+        _rlimit_configs: RlimitConfigs,
+        _input_redirect: Option<RawFd>,
+        output_redirect: Option<RawFd>,
+        _restricted: bool,
+    ) -> Result<Self, JudgeCoreError> {
+        Ok(Self {
+            output_redirect,
+            exit_info: None,
+        })
+    }
+
+    fn set_seccomp_mode(&mut self, _seccomp_mode: SeccompMode) {}
+
+    fn apply_runtime_config(&mut self, _runtime: &RuntimeConfig) {}
+
+    fn set_limits(&mut self, _limits: Limits) {}
+
+    fn spawn(&mut self) -> Result<i32, JudgeCoreError> {
+        let (output, exit_info) = MOCK_RUNS
+            .with(|runs| runs.borrow_mut().pop_front())
+            .expect("No canned run left");
+        if let Some(output_redirect) = self.output_redirect {
+            let fd = unsafe { std::os::fd::BorrowedFd::borrow_raw(output_redirect) };
+            nix::unistd::write(fd, output.as_bytes())?;
+        }
+        self.exit_info = Some(exit_info);
+        Ok(0)
+    }
+
+    fn wait(&self) -> Result<SandboxExitInfo, JudgeCoreError> {
+        Ok(self.exit_info.clone().expect("Not spawned"))
+    }
+
+    fn kill(&self) -> Result<(), JudgeCoreError> {
+        Ok(())
+    }
+}
+
+fn judge_with_mock_runs(
+    config: &JudgeConfig,
+    runs: Vec<(&'static str, SandboxExitInfo)>,
+) -> JudgeResultInfo {
+    MOCK_RUNS.with(|mock_runs| *mock_runs.borrow_mut() = runs.into());
+    let result = run_judge_with::<MockSandbox>(config).unwrap();
+    assert!(MOCK_RUNS.with(|mock_runs| mock_runs.borrow().is_empty()));
+    result
+}
+
+#[test]
+fn test_cpu_time_grace() {
+    init();
+    let program_path =
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/read_and_write");
+    let mut runner_config = build_test_config(Executor::new(Language::Cpp, program_path).unwrap());
+    runner_config.program.output_file_path = Some(PathBuf::from(TEST_TEMP_PATH).join("grace.out"));
+    let at_limit = build_exit_info(Duration::from_secs(1));
+    let jittered = build_exit_info(Duration::from_millis(1005));
+    let over = build_exit_info(Duration::from_millis(1020));
+
+    // Without a margin the limit of 1 second is exact
+    assert_eq!(
+        runner_config.runtime.cpu_time_limit(),
+        Some(Duration::from_secs(1))
+    );
+    assert_eq!(
+        runner_config.runtime.user_rlimit_configs().cpu_limit,
+        Some((1, 2))
+    );
+    let result = judge_with_mock_runs(&runner_config, vec![("Hello! world!\n", at_limit.clone())]);
+    assert_eq!(result.verdict, JudgeVerdict::Accepted);
+    let result = judge_with_mock_runs(&runner_config, vec![("Hello! world!\n", jittered.clone())]);
+    assert_eq!(result.verdict, JudgeVerdict::TimeLimitExceeded);
+    assert_eq!(result.reason_code.as_deref(), Some("TLE_CPU"));
+
+    runner_config.runtime.cpu_time_grace = Duration::from_millis(10);
+    assert_eq!(
+        runner_config.runtime.cpu_time_limit(),
+        Some(Duration::from_millis(1010))
+    );
+    // The kernel kills a second later, beyond the effective limit
+    assert_eq!(
+        runner_config.runtime.user_rlimit_configs().cpu_limit,
+        Some((2, 3))
+    );
+    let result = judge_with_mock_runs(&runner_config, vec![("Hello! world!\n", at_limit)]);
+    assert_eq!(result.verdict, JudgeVerdict::Accepted);
+    let result = judge_with_mock_runs(&runner_config, vec![("Hello! world!\n", jittered)]);
+    assert_eq!(result.verdict, JudgeVerdict::Accepted);
+    let result = judge_with_mock_runs(&runner_config, vec![("Hello! world!\n", over)]);
+    assert_eq!(result.verdict, JudgeVerdict::TimeLimitExceeded);
+    assert_eq!(result.reason_code.as_deref(), Some("TLE_CPU"));
+}
+
+#[test]
+fn test_timing_runs() {
+    init();
+    let program_path =
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/read_and_write");
+    let mut runner_config = build_test_config(Executor::new(Language::Cpp, program_path).unwrap());
+    let output_path = PathBuf::from(TEST_TEMP_PATH).join("timing_runs.out");
+    runner_config.program.output_file_path = Some(output_path.clone());
+    let cold = build_exit_info(Duration::from_millis(1500));
+    let warm = build_exit_info(Duration::from_millis(400));
+
+    // A cold run alone is over the limit of 1 second
+    let result = judge_with_mock_runs(&runner_config, vec![("Hello! world!\n", cold.clone())]);
+    assert_eq!(result.verdict, JudgeVerdict::TimeLimitExceeded);
+
+    // A faster rerun is kept along with its output
+    runner_config.runtime.timing_runs = 2;
+    let result = judge_with_mock_runs(
+        &runner_config,
+        vec![
+            ("Hello! cold!\n", cold.clone()),
+            ("Hello! world!\n", warm.clone()),
+        ],
+    );
+    assert_eq!(result.verdict, JudgeVerdict::Accepted);
+    assert_eq!(result.time_usage, Duration::from_millis(400));
+    assert_eq!(
+        std::fs::read_to_string(&output_path).unwrap(),
+        "Hello! world!\n"
+    );
+
+    // A cold run killed at the limit of its CPU time is replaced by a rerun exiting normally
+    let cpu_killed = SandboxExitInfo {
+        exit_status: libc::SIGXCPU,
+        exit_signal: libc::SIGXCPU,
+        ..build_exit_info(Duration::from_millis(2000))
+    };
+    let result = judge_with_mock_runs(
+        &runner_config,
+        vec![("", cpu_killed.clone()), ("Hello! world!\n", warm.clone())],
+    );
+    assert_eq!(result.verdict, JudgeVerdict::Accepted);
+    assert_eq!(result.time_usage, Duration::from_millis(400));
+    assert_eq!(
+        std::fs::read_to_string(&output_path).unwrap(),
+        "Hello! world!\n"
+    );
+
+    // Only a run over the time limit is rerun
+    let result = judge_with_mock_runs(&runner_config, vec![("Hello! cold!\n", warm.clone())]);
+    assert_eq!(result.verdict, JudgeVerdict::WrongAnswer);
+
+    // A rerun ending otherwise is discarded, however fast
+    let crashed = SandboxExitInfo {
+        exit_status: 1 << 8,
+        exit_code: 1,
+        ..warm
+    };
+    let result = judge_with_mock_runs(
+        &runner_config,
+        vec![("Hello! world!\n", cold.clone()), ("Hello!", crashed)],
+    );
+    assert_eq!(result.verdict, JudgeVerdict::TimeLimitExceeded);
+    assert_eq!(result.time_usage, Duration::from_millis(1500));
+    assert_eq!(
+        std::fs::read_to_string(&output_path).unwrap(),
+        "Hello! world!\n"
+    );
+
+    // Every run is real, nothing is left beside the output
+    runner_config.runtime.timing_runs = 3;
+    let result = run_judge(&runner_config).unwrap();
+    assert_eq!(result.verdict, JudgeVerdict::Accepted);
+    assert!(result.time_usage < Duration::from_secs(1));
+    assert!(
+        !std::fs::read_dir(TEST_TEMP_PATH).unwrap().any(|entry| entry
+            .unwrap()
+            .file_name()
+            .to_string_lossy()
+            .starts_with("judge-rerun-"))
+    );
+}
+
+#[test]
+fn test_run_judge_with_mock_sandbox() {
+    init();
+    let program_path =
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/read_and_write");
+    let program_executor = Executor::new(Language::Cpp, program_path).unwrap();
+    let mut runner_config = build_test_config(program_executor.clone());
+    runner_config.program.output_file_path = Some(PathBuf::from(TEST_TEMP_PATH).join("mock.out"));
+
+    let exited = build_exit_info(Duration::from_millis(100));
+    let result = judge_with_mock_runs(&runner_config, vec![("Hello! world!\n", exited.clone())]);
+    assert_eq!(result.verdict, JudgeVerdict::Accepted);
+    assert_eq!(result.time_usage, Duration::from_millis(100));
+
+    let result = judge_with_mock_runs(&runner_config, vec![("Hello!\n", exited.clone())]);
+    assert_eq!(result.verdict, JudgeVerdict::WrongAnswer);
+
+    let slow = build_exit_info(Duration::from_secs(5));
+    let result = judge_with_mock_runs(&runner_config, vec![("Hello! world!\n", slow)]);
+    assert_eq!(result.verdict, JudgeVerdict::TimeLimitExceeded);
+
+    let mut crashed = build_exit_info(Duration::ZERO);
+    crashed.exit_status = 139;
+    crashed.exit_signal = 11;
+    let result = judge_with_mock_runs(&runner_config, vec![("", crashed)]);
+    assert_eq!(result.verdict, JudgeVerdict::RuntimeError);
+
+    // The checker runs in a mock sandbox too, after the user program
+    runner_config.checker.executor = Some(program_executor);
+    let mut rejected = build_exit_info(Duration::ZERO);
+    rejected.exit_code = 1;
+    rejected.exit_status = 1 << 8;
+    let result = judge_with_mock_runs(
+        &runner_config,
+        vec![("Hello! world!\n", exited), ("", rejected)],
+    );
+    assert_eq!(result.verdict, JudgeVerdict::WrongAnswer);
+    assert_eq!(result.checker_exit_status, 1 << 8);
+}
+
+#[test]
+fn test_check_time_limit_boundary() {
+    init();
+    let program_path =
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/read_and_write");
+    let program_executor = Executor::new(Language::Cpp, program_path).unwrap();
+    let runner_config = build_test_config(program_executor);
+    let time_limit = runner_config
+        .runtime
+        .rlimit_configs
+        .get_cpu_limit_duration()
+        .unwrap();
+
+    let at_limit = build_exit_info(time_limit);
+    assert_eq!(check_user_result(&runner_config, &at_limit), None);
+    let over_limit = build_exit_info(time_limit + Duration::from_nanos(1));
+    assert_eq!(
+        check_user_result(&runner_config, &over_limit),
+        Some(JudgeVerdict::TimeLimitExceeded)
+    );
+}
+
+#[test]
+fn test_default_verdict_policy() {
+    init();
+    let program_path =
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/read_and_write");
+    let program_executor = Executor::new(Language::Cpp, program_path).unwrap();
+    let mut runner_config = build_test_config(program_executor);
+    runner_config.runtime.wall_time_limit = Some(Duration::from_secs(2));
+
+    let mut cases = vec![
+        (build_exit_info(Duration::from_millis(100)), None),
+        (
+            build_exit_info(Duration::from_secs(5)),
+            Some(JudgeVerdict::TimeLimitExceeded),
+        ),
+    ];
+    let mut idle = build_exit_info(Duration::ZERO);
+    idle.idle_killed = true;
+    idle.real_time_cost = Duration::from_secs(3);
+    cases.push((idle, Some(JudgeVerdict::IdlenessLimitExceeded)));
+    let mut sleeping = build_exit_info(Duration::ZERO);
+    sleeping.real_time_cost = Duration::from_secs(3);
+    cases.push((sleeping, Some(JudgeVerdict::TimeLimitExceeded)));
+    let mut filtered = build_exit_info(Duration::ZERO);
+    filtered.exit_status = libc::SIGSYS;
+    filtered.exit_signal = libc::SIGSYS;
+    cases.push((filtered, Some(JudgeVerdict::RestrictedFunction)));
+    let mut killed = build_exit_info(Duration::ZERO);
+    killed.exit_status = libc::SIGKILL;
+    killed.exit_signal = libc::SIGKILL;
+    cases.push((killed, Some(JudgeVerdict::RuntimeError)));
+    let mut failed = build_exit_info(Duration::ZERO);
+    failed.exit_code = 137;
+    failed.exit_status = 137 << 8;
+    cases.push((failed, Some(JudgeVerdict::RuntimeError)));
+
+    for (exit_info, expected) in &cases {
+        assert_eq!(check_user_result(&runner_config, exit_info), *expected);
+        assert_eq!(
+            DefaultVerdictPolicy.classify(exit_info, &runner_config.runtime),
+            *expected
+        );
+    }
+    runner_config.runtime.verdict_policy = Some(Arc::new(DefaultVerdictPolicy));
+    for (exit_info, expected) in &cases {
+        assert_eq!(check_user_result(&runner_config, exit_info), *expected);
+    }
+}
+
+/// Counts programs killed by `SIGKILL` as out of time, e.g. by an external watchdog.
+#[derive(Debug)]
+struct KilledAsTimeLimitPolicy;
+
+impl VerdictPolicy for KilledAsTimeLimitPolicy {
+    fn classify(
+        &self,
+        raw_info: &SandboxExitInfo,
+        runtime: &RuntimeConfig,
+    ) -> Option<JudgeVerdict> {
+        if raw_info.exit_signal == libc::SIGKILL {
+            return Some(JudgeVerdict::TimeLimitExceeded);
+        }
+        DefaultVerdictPolicy.classify(raw_info, runtime)
+    }
+}
+
+#[test]
+fn test_custom_verdict_policy() {
+    init();
+    let program_path =
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/read_and_write");
+    let program_executor = Executor::new(Language::Cpp, program_path).unwrap();
+    let mut runner_config = build_test_config(program_executor);
+    runner_config.program.output_file_path = Some(PathBuf::from(TEST_TEMP_PATH).join("policy.out"));
+    runner_config.runtime.verdict_policy = Some(Arc::new(KilledAsTimeLimitPolicy));
+
+    let mut killed = build_exit_info(Duration::ZERO);
+    killed.exit_status = libc::SIGKILL;
+    killed.exit_signal = libc::SIGKILL;
+    let result = judge_with_mock_runs(&runner_config, vec![("", killed)]);
+    assert_eq!(result.verdict, JudgeVerdict::TimeLimitExceeded);
+
+    let exited = build_exit_info(Duration::from_millis(100));
+    let result = judge_with_mock_runs(&runner_config, vec![("Hello! world!\n", exited)]);
+    assert_eq!(result.verdict, JudgeVerdict::Accepted);
+}
+
+#[test]
+fn test_exit_code_verdict_policy() {
+    init();
+    let program_path =
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/read_and_write");
+    let program_executor = Executor::new(Language::Cpp, program_path).unwrap();
+    let mut runner_config = build_test_config(program_executor);
+    runner_config.program.output_file_path =
+        Some(PathBuf::from(TEST_TEMP_PATH).join("exit_code_policy.out"));
+    runner_config.runtime.verdict_policy = Some(Arc::new(ExitCodeVerdictPolicy::new(
+        std::collections::HashMap::from([(42, JudgeVerdict::Accepted)]),
+    )));
+    let exited_with = |exit_code: i32| {
+        let mut exit_info = build_exit_info(Duration::from_millis(100));
+        exit_info.exit_code = exit_code;
+        exit_info.exit_status = exit_code << 8;
+        exit_info
+    };
+
+    // Accepted by its exit code, whatever it wrote
+    let result = judge_with_mock_runs(&runner_config, vec![("", exited_with(42))]);
+    assert_eq!(result.verdict, JudgeVerdict::Accepted);
+    assert_eq!(result.score, 1.0);
+    assert_eq!(result.decided_by, None);
+
+    // The other exit codes as by default
+    let result = judge_with_mock_runs(&runner_config, vec![("", exited_with(1))]);
+    assert_eq!(result.verdict, JudgeVerdict::RuntimeError);
+    assert_eq!(result.reason_code.as_deref(), Some("RE_NONZERO_EXIT"));
+    let result = judge_with_mock_runs(&runner_config, vec![("Hello! world!\n", exited_with(0))]);
+    assert_eq!(result.verdict, JudgeVerdict::Accepted);
+
+    // Limits come first
+    let mut over_limit = exited_with(42);
+    over_limit.resource_usage.user_time = Duration::from_secs(5);
+    let result = judge_with_mock_runs(&runner_config, vec![("", over_limit)]);
+    assert_eq!(result.verdict, JudgeVerdict::TimeLimitExceeded);
+}
+
+#[test]
+fn test_check_checker_result() {
+    init();
+    let outcome_of = |exit_code: i32| {
+        let mut exit_info = build_exit_info(Duration::ZERO);
+        exit_info.exit_code = exit_code;
+        exit_info.exit_status = exit_code << 8;
+        check_checker_result(&exit_info)
+    };
+    assert_eq!(
+        outcome_of(0),
+        CheckerOutcome {
+            verdict: JudgeVerdict::Accepted,
+            score: 1.0,
+            message: String::new(),
+            reason_code: None,
+        }
+    );
+    for exit_code in [1, 2] {
+        let outcome = outcome_of(exit_code);
+        assert_eq!(outcome.verdict, JudgeVerdict::WrongAnswer);
+        assert_eq!(outcome.score, 0.0);
+    }
+    let outcome = outcome_of(3);
+    assert_eq!(outcome.verdict, JudgeVerdict::SystemError);
+    assert_eq!(outcome.message, "Checker reported failure");
+    assert_eq!(outcome_of(7).verdict, JudgeVerdict::PartialScore);
+    let outcome = outcome_of(4);
+    assert_eq!(outcome.verdict, JudgeVerdict::SystemError);
+    assert!(outcome.message.contains('4'));
+
+    let mut killed = build_exit_info(Duration::ZERO);
+    killed.exit_signal = libc::SIGKILL;
+    assert_eq!(
+        check_checker_result(&killed).verdict,
+        JudgeVerdict::SystemError
+    );
+}
+
+#[test]
+fn test_reason_codes() {
+    init();
+    let program_path =
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/read_and_write");
+    let program_executor = Executor::new(Language::Cpp, program_path).unwrap();
+    let mut runner_config = build_test_config(program_executor.clone());
+    runner_config.program.output_file_path = Some(PathBuf::from(TEST_TEMP_PATH).join("reason.out"));
+    runner_config.runtime.wall_time_limit = Some(Duration::from_secs(2));
+    let reason_of = |config: &JudgeConfig, runs| {
+        judge_with_mock_runs(config, runs)
+            .reason_code
+            .unwrap_or_default()
+    };
+
+    let exited = build_exit_info(Duration::from_millis(100));
+    assert_eq!(
+        judge_with_mock_runs(&runner_config, vec![("Hello! world!\n", exited.clone())]).reason_code,
+        None
+    );
+    assert_eq!(
+        reason_of(&runner_config, vec![("Hello!\n", exited.clone())]),
+        "WA_MISMATCH"
+    );
+    let slow = build_exit_info(Duration::from_secs(5));
+    assert_eq!(reason_of(&runner_config, vec![("", slow)]), "TLE_CPU");
+    let mut sleeping = build_exit_info(Duration::ZERO);
+    sleeping.real_time_cost = Duration::from_secs(3);
+    assert_eq!(reason_of(&runner_config, vec![("", sleeping)]), "TLE_WALL");
+    let mut idle = build_exit_info(Duration::ZERO);
+    idle.idle_killed = true;
+    assert_eq!(reason_of(&runner_config, vec![("", idle)]), "IDLE");
+    let mut filtered = build_exit_info(Duration::ZERO);
+    filtered.exit_status = libc::SIGSYS;
+    filtered.exit_signal = libc::SIGSYS;
+    assert_eq!(reason_of(&runner_config, vec![("", filtered)]), "SECCOMP");
+    let mut crashed = build_exit_info(Duration::ZERO);
+    crashed.exit_status = libc::SIGSEGV;
+    crashed.exit_signal = libc::SIGSEGV;
+    assert_eq!(
+        reason_of(&runner_config, vec![("", crashed)]),
+        "RE_SIGNAL_SIGSEGV"
+    );
+    let mut failed = build_exit_info(Duration::ZERO);
+    failed.exit_code = 1;
+    failed.exit_status = 1 << 8;
+    assert_eq!(
+        reason_of(&runner_config, vec![("", failed.clone())]),
+        "RE_NONZERO_EXIT"
+    );
+
+    let mut missing_config = runner_config.clone();
+    missing_config.program.output_file_path =
+        Some(PathBuf::from(TEST_TEMP_PATH).join("missing.out"));
+    let _ = std::fs::remove_file(missing_config.program.output_path().unwrap());
+    let mut result = check_output_file(&missing_config, &exited).unwrap();
+    assert_eq!(
+        user_reason_code(&missing_config, &exited, &result.0).unwrap(),
+        "SE_NO_OUTPUT"
+    );
+    result = check_output_file(&missing_config, &failed).unwrap();
+    assert_eq!(
+        user_reason_code(&missing_config, &failed, &result.0).unwrap(),
+        "RE_NONZERO_EXIT"
+    );
+
+    let reason_of_checker = |exit_code: i32| {
+        let mut exit_info = build_exit_info(Duration::ZERO);
+        exit_info.exit_code = exit_code;
+        exit_info.exit_status = exit_code << 8;
+        check_checker_result(&exit_info).reason_code
+    };
+    assert_eq!(reason_of_checker(0), None);
+    assert_eq!(reason_of_checker(1).unwrap(), "WA_CHECKER");
+    assert_eq!(reason_of_checker(2).unwrap(), "WA_PRESENTATION");
+    assert_eq!(reason_of_checker(3).unwrap(), "SE_CHECKER_FAIL");
+    assert_eq!(reason_of_checker(4).unwrap(), "SE_CHECKER_EXIT");
+    assert_eq!(reason_of_checker(7).unwrap(), "CHECKER_POINTS");
+    let mut killed = build_exit_info(Duration::ZERO);
+    killed.exit_signal = libc::SIGKILL;
+    assert_eq!(
+        check_checker_result(&killed).reason_code.unwrap(),
+        "SE_CHECKER_SIGNAL_SIGKILL"
+    );
+
+    // Checked before anything runs
+    runner_config.test_data.input_sha256 = Some(String::from("0"));
+    assert_eq!(reason_of(&runner_config, vec![]), "SE_TESTDATA_INTEGRITY");
+}
+
+#[test]
+fn test_check_missing_output_file() {
+    init();
+    let program_path =
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/read_and_write");
+    let program_executor = Executor::new(Language::Cpp, program_path).unwrap();
+    let mut runner_config = build_test_config(program_executor);
+    runner_config.program.output_file_path =
+        Some(PathBuf::from(TEST_TEMP_PATH).join("missing.out"));
+    let _ = std::fs::remove_file(runner_config.program.output_path().unwrap());
+
+    let normal_exit = build_exit_info(Duration::ZERO);
+    let (verdict, message) = check_output_file(&runner_config, &normal_exit).unwrap();
+    assert_eq!(verdict, JudgeVerdict::SystemError);
+    assert!(message.contains("exited normally"));
+
+    let mut crashed = build_exit_info(Duration::ZERO);
+    crashed.exit_status = 139;
+    crashed.exit_signal = 11;
+    let (verdict, message) = check_output_file(&runner_config, &crashed).unwrap();
+    assert_eq!(verdict, JudgeVerdict::RuntimeError);
+    assert!(message.contains("exited abnormally"));
+
+    std::fs::write(runner_config.program.output_path().unwrap(), "").unwrap();
+    assert!(check_output_file(&runner_config, &normal_exit).is_none());
+}
+
+#[test]
+fn test_sandbox_mock_clock() {
+    init();
+    let program_path =
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/read_and_write");
+    let program_executor = Executor::new(Language::Cpp, program_path).unwrap();
+    let runner_config = build_test_config(program_executor);
+
+    let input_file = File::open(&runner_config.test_data.input_file_path).unwrap();
+    let output_file = File::create(runner_config.program.output_path().unwrap()).unwrap();
+    let mut sandbox = ExecutorSandbox::new(
+        runner_config.program.executor.clone(),
+        TEST_CONFIG,
+        Some(input_file.as_raw_fd()),
+        Some(output_file.as_raw_fd()),
+        true,
+    )
+    .unwrap();
+    let clock = MockClock::new();
+    sandbox.sandbox.set_clock(Arc::new(clock.clone()));
+
+    sandbox.spawn().unwrap();
+    clock.advance(Duration::from_millis(1500));
+    let exit_info = sandbox.wait().unwrap();
+    assert_eq!(exit_info.real_time_cost, Duration::from_millis(1500));
+
+    // The wall-clock deadline is one of the clock too
+    let mut sleeper = Sandbox::new(None, None).unwrap();
+    sleeper.set_wall_time_limit(Some(Duration::from_secs(1)));
+    sleeper.set_kill_grace_period(Duration::ZERO);
+    let clock = MockClock::new();
+    sleeper.set_clock(Arc::new(clock.clone()));
+    sleeper
+        .spawn(
+            || {},
+            || unsafe {
+                libc::sleep(10);
+            },
+        )
+        .unwrap();
+    std::thread::sleep(Duration::from_millis(1200));
+    assert!(sleeper.try_wait().unwrap().is_none());
+    clock.advance(Duration::from_secs(2));
+    let exit_info = sleeper
+        .try_wait()
+        .unwrap()
+        .expect("Killed past the deadline");
+    assert_eq!(exit_info.exit_signal, libc::SIGKILL);
+    assert_eq!(exit_info.real_time_cost, Duration::from_secs(2));
+}
+
+#[test]
+fn test_sandbox_mock_clock_boundary() {
+    init();
+    let spawn_sleeper = |configure: &dyn Fn(&mut Sandbox)| {
+        let mut sleeper = Sandbox::new(None, None).unwrap();
+        configure(&mut sleeper);
+        let clock = MockClock::new();
+        sleeper.set_clock(Arc::new(clock.clone()));
+        sleeper
+            .spawn(
+                || {},
+                || unsafe {
+                    libc::signal(libc::SIGTERM, libc::SIG_IGN);
+                    libc::sleep(10);
+                },
+            )
+            .unwrap();
+        (sleeper, clock)
+    };
+
+    // Running for exactly the wall time limit is within it
+    let (mut sleeper, clock) = spawn_sleeper(&|sandbox| {
+        sandbox.set_wall_time_limit(Some(Duration::from_secs(1)));
+    });
+    clock.advance(Duration::from_secs(1));
+    assert!(sleeper.try_wait().unwrap().is_none());
+    clock.advance(Duration::from_nanos(1));
+    let exit_info = sleeper.try_wait().unwrap().expect("Killed past the limit");
+    assert_eq!(exit_info.exit_signal, libc::SIGKILL);
+    assert!(!exit_info.idle_killed);
+    assert_eq!(
+        exit_info.real_time_cost,
+        Duration::from_secs(1) + Duration::from_nanos(1)
+    );
+
+    // The idle window is one of the clock, however long the process really slept
+    let (mut sleeper, clock) = spawn_sleeper(&|sandbox| {
+        sandbox.set_idle_time_limit(Some(Duration::from_secs(1)));
+    });
+    assert!(sleeper.try_wait().unwrap().is_none());
+    std::thread::sleep(Duration::from_millis(1200));
+    clock.advance(Duration::from_millis(999));
+    assert!(sleeper.try_wait().unwrap().is_none());
+    clock.advance(Duration::from_millis(1));
+    let exit_info = sleeper.try_wait().unwrap().expect("Killed as idle");
+    assert!(exit_info.idle_killed);
+
+    // The grace period too, the process ignoring SIGTERM is only killed once it is over
+    let (sleeper, clock) = spawn_sleeper(&|sandbox| {
+        sandbox.set_wall_time_limit(Some(Duration::from_secs(1)));
+        sandbox.set_kill_grace_period(Duration::from_secs(5));
+    });
+    let advancer = {
+        let clock = clock.clone();
+        std::thread::spawn(move || {
+            clock.advance(Duration::from_secs(2));
+            std::thread::sleep(Duration::from_millis(500));
+            clock.advance(Duration::from_secs(6));
+        })
+    };
+    let exit_info = sleeper.wait().unwrap();
+    advancer.join().unwrap();
+    assert_eq!(exit_info.exit_signal, libc::SIGKILL);
+    assert_eq!(exit_info.real_time_cost, Duration::from_secs(8));
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_sandbox_kills_process_group() {
+    init();
+    let (pid_read, pid_write) = nix::unistd::pipe().unwrap();
+    let mut sandbox = Sandbox::new(None, None).unwrap();
+    sandbox.set_wall_time_limit(Some(Duration::from_millis(300)));
+    sandbox
+        .spawn(
+            || {},
+            || unsafe {
+                let grandchild = libc::fork();
+                if grandchild == 0 {
+                    libc::sleep(10);
+                    libc::_exit(0);
+                }
+                let buf = grandchild.to_ne_bytes();
+                libc::write(
+                    pid_write.as_raw_fd(),
+                    buf.as_ptr() as *const libc::c_void,
+                    buf.len(),
+                );
+                libc::sleep(10);
+            },
+        )
+        .unwrap();
+    drop(pid_write);
+    let exit_info = sandbox.wait().unwrap();
+    assert_eq!(exit_info.exit_signal, libc::SIGKILL);
+    let mut buf = [0u8; 4];
+    std::io::Read::read_exact(&mut File::from(pid_read), &mut buf).unwrap();
+    let grandchild = i32::from_ne_bytes(buf);
+    // Killed along with the process, a zombie until init reaps it
+    let alive = || {
+        std::fs::read_to_string(format!("/proc/{}/stat", grandchild))
+            .is_ok_and(|stat| !stat.contains(") Z "))
+    };
+    let give_up = std::time::Instant::now() + Duration::from_secs(5);
+    while alive() && std::time::Instant::now() < give_up {
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    assert!(!alive());
+}
+
+#[test]
+fn test_sandbox_usage_per_child() {
+    init();
+    const BIG_SIZE: usize = 48 * 1024 * 1024;
+    let mut big = Sandbox::new(None, None).unwrap();
+    let mut small = Sandbox::new(None, None).unwrap();
+    big.spawn(
+        || {},
+        || unsafe {
+            // Allocate without going through the allocator of the forked process
+            let ptr = libc::mmap(
+                std::ptr::null_mut(),
+                BIG_SIZE,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            );
+            if ptr != libc::MAP_FAILED {
+                std::ptr::write_bytes(ptr as *mut u8, 1, BIG_SIZE);
+            }
+        },
+    )
+    .unwrap();
+    small.spawn(|| {}, || {}).unwrap();
+
+    // Reap in the reverse order to make sure nothing is attributed by arrival
+    let small_info = small.wait().unwrap();
+    let big_info = big.wait().unwrap();
+    log::debug!("{:?} {:?}", small_info, big_info);
+    assert!(
+        big_info.resource_usage.max_rss
+            >= small_info.resource_usage.max_rss + (BIG_SIZE / 1024) as i64 * 3 / 4
+    );
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_sandbox_scheduling() {
+    use nix::sched::{sched_getaffinity, CpuSet};
+    use nix::unistd::Pid;
+
+    init();
+    let mut sandbox = Sandbox::new(None, None).unwrap();
+    sandbox.set_nice(Some(5));
+    sandbox.set_cpu_affinity(Some(vec![0]));
+    // Settings are applied before `spawn()` returns, the child only has to stay alive
+    let pid = sandbox
+        .spawn(
+            || {},
+            || unsafe {
+                libc::sleep(10);
+            },
+        )
+        .unwrap();
+
+    let cpu_set = sched_getaffinity(Pid::from_raw(pid)).unwrap();
+    let priority = unsafe { libc::getpriority(libc::PRIO_PROCESS, pid as libc::id_t) };
+    unsafe { libc::kill(pid, libc::SIGKILL) };
+    sandbox.wait().unwrap();
+
+    assert!(cpu_set.is_set(0).unwrap());
+    assert!((1..CpuSet::count()).all(|cpu| !cpu_set.is_set(cpu).unwrap()));
+    assert_eq!(priority, 5);
+
+    // A setting which can't be applied fails the spawn, the child never runs
+    let children_before = thread_children();
+    let mut sandbox = Sandbox::new(None, None).unwrap();
+    sandbox.set_cpu_affinity(Some(vec![CpuSet::count() - 1]));
+    assert!(matches!(
+        sandbox.spawn(|| {}, || unsafe { libc::_exit(0) }),
+        Err(JudgeCoreError::SandboxSetupFailed {
+            step: SandboxSetupStep::CpuAffinity,
+            errno: nix::errno::Errno::EINVAL,
+        })
+    ));
+    drop(sandbox);
+    assert_eq!(thread_children(), children_before);
+
+    // Judged as a failure of the host, not of the program
+    let program_executor = Executor::new(
+        Language::Cpp,
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/read_and_write"),
+    )
+    .unwrap();
+    let mut runner_config = build_test_config(program_executor);
+    runner_config.runtime.cpu_affinity = Some(vec![CpuSet::count() - 1]);
+    let result = run_judge(&runner_config).unwrap();
+    assert_eq!(result.verdict, JudgeVerdict::SystemError);
+    assert_eq!(result.reason_code.as_deref(), Some("SE_SANDBOX_SETUP"));
+}
+
+fn spawn_and_kill(ignore_sigterm: bool, grace_period: Duration) -> SandboxExitInfo {
+    let mut sandbox = Sandbox::new(None, None).unwrap();
+    sandbox.set_wall_time_limit(Some(Duration::from_millis(300)));
+    sandbox.set_kill_grace_period(grace_period);
+    sandbox
+        .spawn(
+            || {},
+            || unsafe {
+                if ignore_sigterm {
+                    libc::signal(libc::SIGTERM, libc::SIG_IGN);
+                }
+                libc::sleep(10);
+            },
+        )
+        .unwrap();
+    sandbox.wait().unwrap()
+}
+
+#[test]
+fn test_sandbox_kill_grace_period() {
+    init();
+    let exit_info = spawn_and_kill(false, Duration::ZERO);
+    assert_eq!(exit_info.exit_signal, libc::SIGKILL);
+
+    let exit_info = spawn_and_kill(false, Duration::from_secs(2));
+    assert_eq!(exit_info.exit_signal, libc::SIGTERM);
+    assert!(exit_info.real_time_cost < Duration::from_secs(2));
+
+    // Falls through to SIGKILL once the grace period is over
+    let exit_info = spawn_and_kill(true, Duration::from_millis(300));
+    assert_eq!(exit_info.exit_signal, libc::SIGKILL);
+    assert!(exit_info.real_time_cost >= Duration::from_millis(600));
+    assert!(exit_info.real_time_cost < Duration::from_secs(5));
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_sandbox_reap_timeout() {
+    use nix::sys::wait::waitpid;
+    use nix::unistd::Pid;
+
+    init();
+    // Tearing down that much memory keeps a killed process around for a while
+    const SLOW_TO_REAP_SIZE: usize = 256 * 1024 * 1024;
+    let mut sandbox = Sandbox::new(None, None).unwrap();
+    sandbox.set_wall_time_limit(Some(Duration::from_millis(300)));
+    sandbox.set_reap_timeout(Duration::ZERO);
+    let pid = sandbox
+        .spawn(
+            || {},
+            || unsafe {
+                let ptr = libc::mmap(
+                    std::ptr::null_mut(),
+                    SLOW_TO_REAP_SIZE,
+                    libc::PROT_READ | libc::PROT_WRITE,
+                    libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                    -1,
+                    0,
+                );
+                if ptr != libc::MAP_FAILED {
+                    std::ptr::write_bytes(ptr as *mut u8, 1, SLOW_TO_REAP_SIZE);
+                }
+                libc::sleep(10);
+            },
+        )
+        .unwrap();
+    match sandbox.wait() {
+        Err(JudgeCoreError::ProcessUnkillable {
+            pid: unkillable_pid,
+            stat,
+        }) => {
+            assert_eq!(unkillable_pid, pid);
+            assert!(stat.starts_with(&format!("{} (", pid)));
+        }
+        other => panic!("Expected an unkillable process, got {:?}", other),
+    }
+
+    // Reaped in the background once it dies, not left a zombie
+    let give_up = std::time::Instant::now() + Duration::from_secs(10);
+    while PathBuf::from(format!("/proc/{}", pid)).exists() && std::time::Instant::now() < give_up {
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    assert!(!PathBuf::from(format!("/proc/{}", pid)).exists());
+    assert_eq!(
+        waitpid(Pid::from_raw(pid), None),
+        Err(nix::errno::Errno::ECHILD)
+    );
+}
+
+/// Its user program never dies, see `JudgeCoreError::ProcessUnkillable`.
+struct UnkillableSandbox;
+
+impl SandboxBackend for UnkillableSandbox {
+    fn create(
+        _executor: Executor,
+        _rlimit_configs: RlimitConfigs,
+        _input_redirect: Option<RawFd>,
+        _output_redirect: Option<RawFd>,
+        _restricted: bool,
+    ) -> Result<Self, JudgeCoreError> {
+        Ok(Self)
+    }
+
+    fn set_seccomp_mode(&mut self, _seccomp_mode: SeccompMode) {}
+
+    fn apply_runtime_config(&mut self, _runtime: &RuntimeConfig) {}
+
+    fn set_limits(&mut self, _limits: Limits) {}
+
+    fn spawn(&mut self) -> Result<i32, JudgeCoreError> {
+        Ok(0)
+    }
+
+    fn wait(&self) -> Result<SandboxExitInfo, JudgeCoreError> {
+        Err(JudgeCoreError::ProcessUnkillable {
+            pid: 42,
+            stat: "42 (stuck) D".to_string(),
+        })
+    }
+
+    fn kill(&self) -> Result<(), JudgeCoreError> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_run_judge_unkillable() {
+    init();
+    let program_path =
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/read_and_write");
+    let program_executor = Executor::new(Language::Cpp, program_path).unwrap();
+    let runner_config = build_test_config(program_executor);
+
+    let result = run_judge_with::<UnkillableSandbox>(&runner_config).unwrap();
+    assert_eq!(result.verdict, JudgeVerdict::SystemError);
+    assert_eq!(result.reason_code.as_deref(), Some("SE_UNKILLABLE"));
+    assert_eq!(
+        result.message,
+        "Process 42 could not be killed, left behind in state: 42 (stuck) D"
+    );
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_run_judge_core_dump() {
+    init();
+    let program_path =
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/crash");
+    let program_executor = Executor::new(Language::Cpp, program_path).unwrap();
+    let mut runner_config = build_test_config(program_executor);
+
+    // Off by default
+    let result = run_judge(&runner_config).unwrap();
+    assert_eq!(result.verdict, JudgeVerdict::RuntimeError);
+    assert!(result.core_dump.is_none());
+
+    let core_dump_dir = PathBuf::from(TEST_TEMP_PATH).join("core_dumps");
+    let _ = std::fs::remove_dir_all(&core_dump_dir);
+    runner_config.runtime.core_dump_dir = Some(core_dump_dir.clone());
+    // The core is cut at the file size limit
+    runner_config.runtime.rlimit_configs.fsize_limit = None;
+    let result = run_judge(&runner_config).unwrap();
+    assert_eq!(result.verdict, JudgeVerdict::RuntimeError);
+    let core_pattern = std::fs::read_to_string("/proc/sys/kernel/core_pattern").unwrap();
+    if core_pattern.starts_with('/') || core_pattern.starts_with('|') {
+        log::warn!("Cores of this host don't land in the core dump directory");
+        return;
+    }
+    let core_dump = result.core_dump.unwrap();
+    assert!(core_dump.starts_with(core_dump_dir.canonicalize().unwrap()));
+    assert!(std::fs::metadata(&core_dump).unwrap().len() > 0);
+    let core_name = core_dump.file_name().unwrap().to_string_lossy();
+    assert!(core_name == "core" || core_name.starts_with("core."));
+
+    // Other files of the program are never taken for its core
+    let mut sandbox = Sandbox::new(None, None).unwrap();
+    sandbox.set_core_dump_dir(Some(core_dump_dir.clone()));
+    // Newer than any core, as far as the times tell
+    let write_notes = || unsafe {
+        let path = b"notes.txt\0";
+        let fd = libc::open(
+            path.as_ptr() as *const libc::c_char,
+            libc::O_CREAT | libc::O_WRONLY,
+            0o644,
+        );
+        let future = libc::timespec {
+            tv_sec: 4_000_000_000,
+            tv_nsec: 0,
+        };
+        libc::futimens(fd, [future, future].as_ptr());
+        libc::close(fd);
+    };
+    sandbox.spawn(|| {}, write_notes).unwrap();
+    assert!(sandbox.wait().unwrap().core_dump.is_none());
+    sandbox
+        .spawn(
+            || {},
+            || {
+                write_notes();
+                // Not dumpable since it dropped its privileges without an exec
+                unsafe {
+                    libc::prctl(libc::PR_SET_DUMPABLE, 1);
+                    libc::abort();
+                }
+            },
+        )
+        .unwrap();
+    let core_dump = sandbox.wait().unwrap().core_dump.unwrap();
+    assert_ne!(core_dump.file_name().unwrap(), "notes.txt");
+
+    // A directory which can't be entered fails the spawn
+    let mut sandbox = Sandbox::new(None, None).unwrap();
+    sandbox.set_core_dump_dir(Some(core_dump_dir.clone()));
+    let remove_run_dirs = || {
+        for entry in std::fs::read_dir(&core_dump_dir).unwrap() {
+            let _ = std::fs::remove_dir_all(entry.unwrap().path());
+        }
+    };
+    assert!(matches!(
+        sandbox.spawn(remove_run_dirs, || unsafe { libc::_exit(0) }),
+        Err(JudgeCoreError::SandboxSetupFailed {
+            step: SandboxSetupStep::CoreDumpDir,
+            errno: nix::errno::Errno::ENOENT,
+        })
+    ));
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_sandbox_try_wait() {
+    use nix::sys::epoll::{Epoll, EpollCreateFlags, EpollEvent, EpollFlags, EpollTimeout};
+
+    init();
+    let program_path =
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/read_and_write");
+    let program_executor = Executor::new(Language::Cpp, program_path).unwrap();
+    let runner_config = build_test_config(program_executor);
+    let input_file = File::open(&runner_config.test_data.input_file_path).unwrap();
+    let output_file = File::create(runner_config.program.output_path().unwrap()).unwrap();
+    let mut sandbox = ExecutorSandbox::new(
+        runner_config.program.executor.clone(),
+        TEST_CONFIG,
+        Some(input_file.as_raw_fd()),
+        Some(output_file.as_raw_fd()),
+        true,
+    )
+    .unwrap();
+    sandbox.spawn().unwrap();
+
+    // Watched along with anything else of an event loop
+    let exit_fd = sandbox.exit_fd().unwrap();
+    let epoll = Epoll::new(EpollCreateFlags::EPOLL_CLOEXEC).unwrap();
+    epoll
+        .add(&exit_fd, EpollEvent::new(EpollFlags::EPOLLIN, 7))
+        .unwrap();
+    let mut events = [EpollEvent::empty(); 1];
+    let ready = epoll
+        .wait(&mut events, EpollTimeout::from(5000u16))
+        .unwrap();
+    assert_eq!((ready, events[0].data()), (1, 7));
+    let exit_info = sandbox
+        .try_wait()
+        .unwrap()
+        .expect("Exited when its fd is ready");
+    assert_eq!(exit_info.exit_code, 0);
+    assert!(
+        std::fs::read_to_string(runner_config.program.output_path().unwrap())
+            .unwrap()
+            .starts_with("Hello! ")
+    );
+
+    // The limits are enforced by the polls
+    let mut sleeper = Sandbox::new(None, None).unwrap();
+    sleeper.set_wall_time_limit(Some(Duration::from_millis(200)));
+    sleeper
+        .spawn(
+            || {},
+            || unsafe {
+                libc::sleep(10);
+            },
+        )
+        .unwrap();
+    let mut polls = 0;
+    let exit_info = loop {
+        if let Some(exit_info) = sleeper.try_wait().unwrap() {
+            break exit_info;
+        }
+        polls += 1;
+        std::thread::sleep(Duration::from_millis(20));
+    };
+    assert!(polls > 1);
+    assert_eq!(exit_info.exit_signal, libc::SIGKILL);
+    assert!(exit_info.real_time_cost < Duration::from_secs(5));
+    // Gone for good, there is no exit to report twice
+    assert!(sleeper.try_wait().is_err());
+    assert!(sleeper.wait().is_err());
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_dropped_sandbox_leaves_no_child() {
+    init();
+    let children_before = thread_children();
+    let mut sleeper = Sandbox::new(None, None).unwrap();
+    sleeper
+        .spawn(
+            || {},
+            || unsafe {
+                libc::sleep(10);
+            },
+        )
+        .unwrap();
+    assert_ne!(thread_children(), children_before);
+    drop(sleeper);
+    assert_eq!(thread_children(), children_before);
+
+    // Nothing is left to do for a waited one
+    let mut sandbox = Sandbox::new(None, None).unwrap();
+    sandbox.spawn(|| {}, || {}).unwrap();
+    sandbox.wait().unwrap();
+    drop(sandbox);
+    assert_eq!(thread_children(), children_before);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_sandbox_pidfd() {
+    init();
+    let mut sandbox = Sandbox::new(None, None).unwrap();
+    sandbox.spawn(|| {}, || {}).unwrap();
+    if !sandbox.has_pidfd() {
+        log::warn!("No pidfd on this kernel, the pid is used");
+        sandbox.wait().unwrap();
+        return;
+    }
+    sandbox.wait().unwrap();
+    // Reaped, its pid may belong to anyone by now
+    assert!(matches!(
+        sandbox.kill(),
+        Err(JudgeCoreError::NixErrno(nix::errno::Errno::ESRCH))
+    ));
+
+    let sleeper = || unsafe {
+        libc::sleep(10);
+    };
+    sandbox.spawn(|| {}, sleeper).unwrap();
+    sandbox.kill().unwrap();
+    assert_eq!(sandbox.wait().unwrap().exit_signal, libc::SIGKILL);
+
+    // Exiting at SIGTERM is noticed right away, not at the end of the grace period
+    sandbox.set_wall_time_limit(Some(Duration::from_millis(200)));
+    sandbox.set_kill_grace_period(Duration::from_secs(3));
+    sandbox.spawn(|| {}, sleeper).unwrap();
+    let exit_info = sandbox.wait().unwrap();
+    assert_eq!(exit_info.exit_signal, libc::SIGTERM);
+    assert!(exit_info.real_time_cost < Duration::from_secs(2));
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_sandbox_thread_limit() {
+    init();
+    let run_with_threads = |threads: u64| {
+        let mut executor = Executor::new(
+            Language::Cpp,
+            PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/threads"),
+        )
+        .unwrap();
+        executor.set_program_args(vec![threads.to_string()]);
+        let rlimit_configs = RlimitConfigs {
+            cpu_limit: Some((1, 2)),
+            ..Default::default()
+        };
+        let mut sandbox =
+            ExecutorSandbox::new(executor, rlimit_configs, None, None, false).unwrap();
+        // The main thread is one of the tasks
+        sandbox.set_limits(Limits {
+            max_threads: Some(4),
+            ..Default::default()
+        });
+        sandbox.spawn().unwrap();
+        sandbox.wait().unwrap().exit_code
+    };
+    assert_eq!(run_with_threads(3), 0);
+    assert_eq!(run_with_threads(4), 1);
+    assert_eq!(run_with_threads(8), 1);
+}
+
+#[test]
+fn test_sandbox_limits() {
+    init();
+    let limits = Limits::from(&TEST_CONFIG);
+    assert_eq!(limits.cpu_time, Some(Duration::from_secs(1)));
+    assert_eq!(limits.memory_bytes, Some(64 * 1024 * 1024));
+    assert_eq!(limits.output_bytes, Some(1024));
+    assert_eq!(limits.max_processes, Some(1));
+    assert_eq!(limits.wall_time, None);
+    assert_eq!(limits.open_files, Some(64));
+
+    let program_path =
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/read_and_write");
+    let mut config = build_test_config(Executor::new(Language::Cpp, program_path).unwrap());
+    config.runtime.wall_time_limit = Some(Duration::from_secs(3));
+    assert_eq!(
+        config.runtime.limits().wall_time,
+        Some(Duration::from_secs(3))
+    );
+
+    let mut sandbox = Sandbox::new(None, None).unwrap();
+    sandbox.set_limits(Limits {
+        wall_time: Some(Duration::from_millis(300)),
+        open_files: Some(8),
+        ..Limits::default()
+    });
+    sandbox
+        .spawn(
+            || {},
+            || unsafe {
+                let mut rlimit: libc::rlimit = std::mem::zeroed();
+                libc::getrlimit(libc::RLIMIT_NOFILE, &mut rlimit);
+                if rlimit.rlim_cur != 8 {
+                    libc::_exit(1);
+                }
+                libc::sleep(10);
+            },
+        )
+        .unwrap();
+    let exit_info = sandbox.wait().unwrap();
+    assert_eq!(exit_info.exit_signal, libc::SIGKILL, "{:?}", exit_info);
+
+    // The limits set replace those of the rlimit configs, the others are kept
+    let loaded = TEST_CONFIG.loaded_limits();
+    assert_eq!(loaded.output_bytes, None);
+    assert_eq!(loaded.max_processes, None);
+    let mut sandbox = Sandbox::new(Some(TEST_CONFIG), None).unwrap();
+    sandbox.set_limits(Limits {
+        wall_time: Some(Duration::from_millis(300)),
+        open_files: Some(128),
+        ..Limits::default()
+    });
+    sandbox
+        .spawn(
+            || {},
+            || unsafe {
+                let mut rlimit: libc::rlimit = std::mem::zeroed();
+                libc::getrlimit(libc::RLIMIT_NOFILE, &mut rlimit);
+                if rlimit.rlim_cur != 128 {
+                    libc::_exit(1);
+                }
+                libc::getrlimit(libc::RLIMIT_AS, &mut rlimit);
+                if rlimit.rlim_cur != 64 * 1024 * 1024 {
+                    libc::_exit(2);
+                }
+                libc::sleep(10);
+            },
+        )
+        .unwrap();
+    let exit_info = sandbox.wait().unwrap();
+    assert_eq!(exit_info.exit_signal, libc::SIGKILL, "{:?}", exit_info);
+}
+
+struct FailingFilter;
+
+impl SyscallFilterLoader for FailingFilter {
+    fn load(&self) -> Result<(), JudgeCoreError> {
+        Err(JudgeCoreError::SeccompSetupFailed("mock".to_string()))
+    }
+}
+
+struct ExitingFilter;
+
+impl SyscallFilterLoader for ExitingFilter {
+    fn load(&self) -> Result<(), JudgeCoreError> {
+        unsafe { libc::_exit(3) }
+    }
+}
+
+fn spawn_with_filter(
+    scmp_filter: Box<dyn SyscallFilterLoader>,
+    seccomp_mode: SeccompMode,
+) -> Result<SandboxExitInfo, JudgeCoreError> {
+    let mut sandbox = Sandbox::new(None, None)?;
+    sandbox.set_syscall_filter(Some(scmp_filter));
+    sandbox.set_seccomp_mode(seccomp_mode);
+    sandbox.spawn(|| {}, || {})?;
+    sandbox.wait()
+}
+
+#[test]
+fn test_sandbox_seccomp_mode() {
+    init();
+    let required = spawn_with_filter(Box::new(FailingFilter), SeccompMode::Required);
+    assert!(matches!(
+        required,
+        Err(JudgeCoreError::SeccompSetupFailed(_))
+    ));
+
+    let preferred = spawn_with_filter(Box::new(FailingFilter), SeccompMode::Preferred).unwrap();
+    assert_eq!(preferred.exit_code, 0);
+
+    // The filter is never loaded, so the child doesn't exit from inside `load()`
+    let off = spawn_with_filter(Box::new(ExitingFilter), SeccompMode::Off).unwrap();
+    assert_eq!(off.exit_code, 0);
+    let required = spawn_with_filter(Box::new(ExitingFilter), SeccompMode::Required).unwrap();
+    assert_eq!(required.exit_code, 3);
+}
+
+#[test]
+fn test_run_judge_python() {
+    init();
+    log::debug!("current dir: {:?}", std::env::current_dir().unwrap());
+
+    let program_path =
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/src/programs/read_and_write.py");
+    let program_executor = Executor::new(Language::Python, program_path.clone()).unwrap();
+
+    let runner_config = build_test_config(program_executor);
+    let result = run_judge(&runner_config);
+    if let Ok(result) = result {
+        log::debug!("{:?}", result);
+        assert_eq!(result.verdict, JudgeVerdict::Accepted);
+    } else {
+        log::debug!("{:?}", result);
+        unreachable!()
+    }
+}
+
+#[test]
+fn test_run_tle() {
+    init();
+    let program_path =
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/infinite_loop");
+    let program_executor = Executor::new(Language::Cpp, program_path).unwrap();
+
+    let runner_config = build_test_config(program_executor);
+    let result = run_judge(&runner_config);
+    assert!(result.is_ok());
+    if let Ok(result) = result {
+        log::debug!("{:?}", result);
+        assert_eq!(result.verdict, JudgeVerdict::TimeLimitExceeded);
+    }
+}
+
+#[test]
+fn test_run_restricted_function() {
+    init();
+    let program_path =
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/socket");
+    let program_executor = Executor::new(Language::Cpp, program_path).unwrap();
+
+    let mut runner_config = build_test_config(program_executor);
+    runner_config.program.output_file_path = Some(PathBuf::from(TEST_TEMP_PATH).join("socket.out"));
+    let result = run_judge(&runner_config).unwrap();
+    log::debug!("{:?}", result);
+    assert_eq!(result.verdict, JudgeVerdict::RestrictedFunction);
+    assert_eq!(result.reason_code.as_deref(), Some("SECCOMP_bind"));
+    assert_eq!(
+        result.message,
+        "Killed for making the system call bind, forbidden by the sandbox"
+    );
+
+    // Killed right away by the filter, which doesn't tell for which system call
+    let mut filtered = build_exit_info(Duration::ZERO);
+    filtered.exit_status = libc::SIGSYS;
+    filtered.exit_signal = libc::SIGSYS;
+    assert_eq!(
+        check_user_result(&runner_config, &filtered),
+        Some(JudgeVerdict::RestrictedFunction)
+    );
+    assert!(describe_user_result(&filtered, &JudgeVerdict::RestrictedFunction).contains("SIGSYS"));
+
+    // Other crashes are still runtime errors
+    let mut crashed = build_exit_info(Duration::ZERO);
+    crashed.exit_status = libc::SIGSEGV;
+    crashed.exit_signal = libc::SIGSEGV;
+    assert_eq!(
+        check_user_result(&runner_config, &crashed),
+        Some(JudgeVerdict::RuntimeError)
+    );
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_run_subprocess() {
+    init();
+    let program_path =
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/fork");
+    let program_executor = Executor::new(Language::Cpp, program_path).unwrap();
+
+    let mut runner_config = build_test_config(program_executor);
+    runner_config.program.output_file_path = Some(PathBuf::from(TEST_TEMP_PATH).join("fork.out"));
+    let result = run_judge(&runner_config).unwrap();
+    log::debug!("{:?}", result);
+    assert_eq!(result.verdict, JudgeVerdict::RestrictedFunction);
+    assert_eq!(result.reason_code.as_deref(), Some("SUBPROCESS"));
+    assert_eq!(result.message, "Subprocess creation not allowed");
+
+    // Still capped by the process limits when allowed
+    runner_config.runtime.allow_subprocesses = true;
+    runner_config.runtime.rlimit_configs.nproc_limit = None;
+    runner_config.runtime.max_threads = Some(2);
+    let result = run_judge(&runner_config).unwrap();
+    log::debug!("{:?}", result);
+    assert_eq!(result.verdict, JudgeVerdict::Accepted);
+    runner_config.runtime.max_threads = Some(1);
+    let result = run_judge(&runner_config).unwrap();
+    log::debug!("{:?}", result);
+    assert_eq!(result.verdict, JudgeVerdict::RuntimeError);
+}
+
+#[test]
+fn test_run_open_files_limit() {
+    init();
+    let rlimit_nofile = || unsafe {
+        let mut rlimit: libc::rlimit = std::mem::zeroed();
+        libc::getrlimit(libc::RLIMIT_NOFILE, &mut rlimit);
+        (rlimit.rlim_cur, rlimit.rlim_max)
+    };
+    let judger_limit = rlimit_nofile();
+    let program_path =
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/open_files");
+    let program_executor = Executor::new(Language::Cpp, program_path).unwrap();
+
+    let mut runner_config = build_test_config(program_executor);
+    runner_config.program.output_file_path =
+        Some(PathBuf::from(TEST_TEMP_PATH).join("open_files.out"));
+    let result = run_judge(&runner_config).unwrap();
+    log::debug!("{:?}", result);
+    assert_eq!(result.verdict, JudgeVerdict::RuntimeError);
+    // Exited 1 on EMFILE
+    assert_eq!(result.exit_status, 1 << 8);
+    let opened: u64 = std::fs::read_to_string(runner_config.program.output_path().unwrap())
+        .unwrap()
+        .trim()
+        .parse()
+        .unwrap();
+    assert!(opened < 64, "{}", opened);
+
+    assert_eq!(rlimit_nofile(), judger_limit);
+    let files: Vec<File> = (0..128).map(|_| File::open("/dev/null").unwrap()).collect();
+    assert_eq!(files.len(), 128);
+}
+
+#[test]
+fn test_validate_against_host() {
+    init();
+    let program_path =
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/read_and_write");
+    let program_executor = Executor::new(Language::Cpp, program_path).unwrap();
+    let mut config = build_test_config(program_executor);
+    let host = HostResources {
+        total_memory_bytes: Some(32 * 1024 * 1024),
+        cpu_count: Some(2),
+        rlimit_ceilings: Some(RlimitConfigs {
+            open_files_limit: Some((32, 32)),
+            cpu_limit: Some((3, 3)),
+            ..RlimitConfigs::default()
+        }),
+    };
+    config.runtime.cpu_affinity = Some(vec![1, 2]);
+    let problems = config.runtime.host_capability_problems(&host);
+    log::debug!("{:?}", problems);
+    assert_eq!(problems.len(), 3, "{:?}", problems);
+    assert!(problems[0].starts_with("memory limit"));
+    assert!(problems[1].starts_with("CPU 2"));
+    assert!(problems[2].starts_with("open files limit of 64"));
+
+    config.runtime.cpu_affinity = None;
+    config.runtime.rlimit_configs.as_limit = Some((u64::MAX, u64::MAX));
+    let problems = config.validate(ValidationSeverity::Warn).unwrap();
+    assert!(problems[0].starts_with("memory limit"), "{:?}", problems);
+    let err = config.validate(ValidationSeverity::Error).unwrap_err();
+    assert!(matches!(err, JudgeCoreError::InvalidConfig(_)), "{:?}", err);
+
+    config.runtime.rlimit_configs = TEST_CONFIG;
+    assert!(config.validate(ValidationSeverity::Error).is_ok());
+}
+
+#[test]
+fn test_health_check() {
+    init();
+    assert_eq!(check_compiler(Language::Cpp).status, HealthStatus::Pass);
+    assert_eq!(check_rlimits(&TEST_CONFIG).status, HealthStatus::Pass);
+    assert_eq!(check_seccomp(SeccompMode::Off).status, HealthStatus::Skip);
+
+    // Over the hard limit of the judger, which only root may raise, but not the sandbox user
+    let (_, judger_limit) =
+        nix::sys::resource::getrlimit(nix::sys::resource::Resource::RLIMIT_NOFILE).unwrap();
+    let over_hard_limit = RlimitConfigs {
+        open_files_limit: Some((judger_limit + 1, judger_limit + 1)),
+        ..TEST_CONFIG
+    };
+    let check = check_rlimits(&over_hard_limit);
+    assert_eq!(check.status, HealthStatus::Fail, "{:?}", check);
+
+    let report = health_check(&HealthCheckConfig {
+        languages: vec![Language::Cpp],
+        rlimit_configs: TEST_CONFIG,
+        seccomp_mode: SeccompMode::Required,
+    });
+    log::debug!("{}", serde_json::to_string(&report).unwrap());
+    assert!(report.is_healthy(), "{:?}", report);
+    assert_eq!(report.failures().count(), 0);
+    let names: Vec<&str> = report
+        .checks
+        .iter()
+        .map(|check| check.name.as_str())
+        .collect();
+    assert!(names.contains(&"seccomp"), "{:?}", names);
+    assert!(names.contains(&"compiler cpp"), "{:?}", names);
+}
+
+#[test]
+fn test_run_program_args() {
+    init();
+    let program_path =
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/print_args");
+    let program_executor = Executor::new(Language::Cpp, program_path).unwrap();
+
+    let mut runner_config = build_test_config(program_executor);
+    runner_config.program.output_file_path =
+        Some(PathBuf::from(TEST_TEMP_PATH).join("print_args.out"));
+    runner_config.program.program_args = vec![
+        "--mode".to_string(),
+        "case-{case}".to_string(),
+        "{input}".to_string(),
+    ];
+    runner_config.program = runner_config.program.with_case_index(3);
+    let result = run_judge(&runner_config).unwrap();
+    log::debug!("{:?}", result);
+    assert_eq!(result.exit_status, 0);
+    assert_eq!(
+        std::fs::read_to_string(runner_config.program.output_path().unwrap()).unwrap(),
+        format!(
+            "--mode\ncase-3\n{}\n",
+            runner_config.test_data.input_file_path.display()
+        )
+    );
+}
+
+#[test]
+fn test_run_wall_tle() {
+    init();
+    let program_path =
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/sleep");
+    let program_executor = Executor::new(Language::Cpp, program_path).unwrap();
+
+    let mut runner_config = build_test_config(program_executor);
+    runner_config.runtime.wall_time_limit = Some(Duration::from_secs(1));
+    runner_config.runtime.verbose = true;
+    let result = run_judge(&runner_config).unwrap();
+    log::debug!("{:?}", result);
+    assert_eq!(result.verdict, JudgeVerdict::TimeLimitExceeded);
+    let raw = result.raw.unwrap();
+    assert_eq!(raw.exit_signal, libc::SIGKILL);
+    assert!(raw.real_time_cost < Duration::from_secs(5));
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_run_idle() {
+    init();
+    let program_path =
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/read_and_write");
+    let program_executor = Executor::new(Language::Cpp, program_path).unwrap();
+    let mut runner_config = build_test_config(program_executor);
+    runner_config.runtime.idle_time_limit = Some(Duration::from_millis(500));
+    runner_config.program.output_file_path = Some(PathBuf::from(TEST_TEMP_PATH).join("idle.out"));
+
+    // The write end is held open, so reading stdin never returns
+    let (input_read, _input_write) = nix::unistd::pipe().unwrap();
+    let output_file = File::create(runner_config.program.output_path().unwrap()).unwrap();
+    let mut sandbox = ExecutorSandbox::new(
+        runner_config.program.executor.clone(),
+        TEST_CONFIG,
+        Some(input_read.as_raw_fd()),
+        Some(output_file.as_raw_fd()),
+        true,
+    )
+    .unwrap();
+    sandbox
+        .sandbox
+        .set_idle_time_limit(runner_config.runtime.idle_time_limit);
+    sandbox.spawn().unwrap();
+    let exit_info = sandbox.wait().unwrap();
+    log::debug!("{:?}", exit_info);
+    assert!(exit_info.idle_killed);
+    assert_eq!(exit_info.exit_signal, libc::SIGKILL);
+    assert!(exit_info.real_time_cost < Duration::from_secs(5));
+    assert_eq!(
+        check_user_result(&runner_config, &exit_info),
+        Some(JudgeVerdict::IdlenessLimitExceeded)
+    );
+
+    // Burning CPU is never idle, the CPU limit catches it instead
+    let program_path =
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/infinite_loop");
+    runner_config.program.executor = Executor::new(Language::Cpp, program_path).unwrap();
+    let result = run_judge(&runner_config).unwrap();
+    assert_eq!(result.verdict, JudgeVerdict::TimeLimitExceeded);
+}
+
+#[test]
+fn test_run_mle() {
+    init();
+    let program_path =
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/memory_limit");
+    let program_executor = Executor::new(Language::Cpp, program_path).unwrap();
+
+    let runner_config = build_test_config(program_executor);
+    let result = run_judge(&runner_config);
+    assert!(result.is_ok());
+    if let Ok(result) = result {
+        log::debug!("{:?}", result);
+        assert_eq!(result.verdict, JudgeVerdict::RuntimeError);
+    }
+}
+
+#[test]
+fn test_run_checker_crash() {
+    init();
+    let program_executor = Executor::new(
+        Language::Cpp,
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/read_and_write"),
+    )
+    .unwrap();
+    let runner_config = JudgeConfig {
+        checker: CheckerConfig {
+            executor: Some(built_in_checker("checker-crash")),
+            ..build_test_checker_config(PathBuf::from(TEST_TEMP_PATH).join("checker.out"))
+        },
+        ..build_test_config(program_executor)
+    };
+    let result = run_judge(&runner_config).unwrap();
+    log::debug!("{:?}", result);
+    assert_eq!(result.verdict, JudgeVerdict::SystemError);
+    assert!(result.message.contains("signal"));
+}
+
+#[test]
+fn test_run_checker_unknown_exit() {
+    init();
+    let program_executor = Executor::new(
+        Language::Cpp,
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/read_and_write"),
+    )
+    .unwrap();
+    let runner_config = JudgeConfig {
+        checker: CheckerConfig {
+            executor: Some(built_in_checker("checker-unknown-exit")),
+            ..build_test_checker_config(PathBuf::from(TEST_TEMP_PATH).join("checker.out"))
+        },
+        ..build_test_config(program_executor)
+    };
+    let result = run_judge(&runner_config).unwrap();
+    log::debug!("{:?}", result);
+    assert_eq!(result.verdict, JudgeVerdict::SystemError);
+    assert!(result.message.contains("42"));
+}
+
+#[test]
+fn test_read_checker_result_file() {
+    init();
+    let results_path = PathBuf::from(TEST_DATA_PATH).join("checker-results");
+    let outcome = read_checker_result_file(
+        &TestlibXmlResultParser,
+        &results_path.join("testlib-accepted.xml"),
+    );
+    assert_eq!(
+        outcome,
+        CheckerOutcome::new(JudgeVerdict::Accepted, "ok 3 numbers".to_string())
+    );
+    let outcome = read_checker_result_file(
+        &TestlibXmlResultParser,
+        &results_path.join("testlib-pe.xml"),
+    );
+    assert_eq!(outcome.verdict, JudgeVerdict::WrongAnswer);
+    assert_eq!(outcome.message, "expected <int>");
+    assert_eq!(outcome.reason_code.as_deref(), Some("WA_PRESENTATION"));
+
+    let outcome =
+        read_checker_result_file(&PlainResultParser, &results_path.join("plain-partial.txt"));
+    assert_eq!(outcome.verdict, JudgeVerdict::PartialScore);
+    assert_eq!(outcome.score, 0.5);
+    assert_eq!(outcome.message, "first half correct");
+    let outcome =
+        read_checker_result_file(&PlainResultParser, &results_path.join("plain-unknown.txt"));
+    assert_eq!(outcome.verdict, JudgeVerdict::SystemError);
+    assert_eq!(
+        outcome.reason_code.as_deref(),
+        Some("SE_CHECKER_RESULT_FILE")
+    );
+    let outcome = read_checker_result_file(&PlainResultParser, &results_path.join("missing.txt"));
+    assert_eq!(outcome.verdict, JudgeVerdict::SystemError);
+}
+
+#[test]
+fn test_run_checker_result_file() {
+    init();
+    let program_executor = Executor::new(
+        Language::Cpp,
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/read_and_write"),
+    )
+    .unwrap();
+    let checker_output_path = PathBuf::from(TEST_TEMP_PATH).join("checker_result.xml");
+    let runner_config = JudgeConfig {
+        checker: CheckerConfig {
+            executor: Some(built_in_checker("checker-result-xml")),
+            result_parser: Some(Arc::new(TestlibXmlResultParser)),
+            ..build_test_checker_config(checker_output_path)
+        },
+        ..build_test_config(program_executor)
+    };
+
+    let (outcome, checker_exit_status) = run_checker(&runner_config).unwrap();
+    assert_eq!(checker_exit_status, 0);
+    assert_eq!(outcome.verdict, JudgeVerdict::PartialScore);
+    assert_eq!(outcome.score, 0.25);
+    assert_eq!(outcome.message, "1 of 4 & more");
+}
+
+#[test]
+fn test_run_checker_timeout() {
+    init();
+    let program_executor = Executor::new(
+        Language::Cpp,
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/read_and_write"),
+    )
+    .unwrap();
+    let mut runner_config = JudgeConfig {
+        checker: CheckerConfig {
+            // Never exits by itself
+            executor: Some(
+                Executor::new(
+                    Language::Cpp,
+                    PathBuf::from(TEST_DATA_PATH)
+                        .join("built-in-programs/build/src/programs/sleep"),
+                )
+                .unwrap(),
+            ),
+            ..build_test_checker_config(PathBuf::from(TEST_TEMP_PATH).join("checker_timeout.out"))
+        },
+        ..build_test_config(program_executor)
+    };
+    runner_config.runtime.checker_limits = Limits {
+        wall_time: Some(Duration::from_millis(300)),
+        ..DEFAULT_CHECKER_LIMITS
+    };
+
+    let result = run_judge(&runner_config).unwrap();
+    assert_eq!(result.verdict, JudgeVerdict::SystemError);
+    assert_eq!(result.message, "Checker timed out");
+    assert_eq!(result.reason_code.as_deref(), Some("SE_CHECKER_TIMEOUT"));
+}
+
+#[test]
+fn test_run_judge_setup() {
+    init();
+    // Written by the setup program, which may run as another user
+    let scenario_dir = PathBuf::from(TEST_TEMP_PATH).join("setup");
+    std::fs::create_dir_all(&scenario_dir).unwrap();
+    std::fs::set_permissions(&scenario_dir, Permissions::from_mode(0o777)).unwrap();
+    let scenario_path = scenario_dir.join("scenario.txt");
+    let _ = std::fs::remove_file(&scenario_path);
+    let program_executor = Executor::new(
+        Language::Cpp,
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/read_scenario"),
+    )
+    .unwrap();
+    let mut runner_config = build_test_config(program_executor);
+    runner_config.program.program_args = vec![scenario_path.to_str().unwrap().to_string()];
+    // Not written, the user program reads nothing
+    let result = run_judge(&runner_config).unwrap();
+    assert_eq!(result.verdict, JudgeVerdict::WrongAnswer);
+
+    let setup_executor = Executor::new(
+        Language::Cpp,
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/setup_scenario"),
+    )
+    .unwrap();
+    runner_config.setup = Some(SetupConfig {
+        executor: setup_executor,
+        args: vec![scenario_path.to_str().unwrap().to_string()],
+        limits: DEFAULT_CHECKER_LIMITS,
+    });
+    let result = run_judge(&runner_config).unwrap();
+    log::debug!("{:?}", result);
+    assert_eq!(result.verdict, JudgeVerdict::Accepted);
+    assert!(scenario_path.exists());
+
+    // The setup program can't write there
+    runner_config.setup.as_mut().unwrap().args = vec![PathBuf::from(TEST_TEMP_PATH)
+        .join("setup/missing/scenario.txt")
+        .to_str()
+        .unwrap()
+        .to_string()];
+    let result = run_judge(&runner_config).unwrap();
+    assert_eq!(result.verdict, JudgeVerdict::SystemError);
+    assert_eq!(result.message, "Setup program exited with code 1");
+    assert_eq!(result.reason_code.as_deref(), Some("SE_SETUP_FAILED"));
+    assert!(result.performance.is_none());
+}
+
+#[test]
+fn test_run_judge_reference() {
+    init();
+    let programs_path = PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs");
+    let program_executor =
+        Executor::new(Language::Cpp, programs_path.join("read_and_write")).unwrap();
+    let mut runner_config = build_test_config(program_executor.clone());
+    runner_config.test_data.answer_file_path = PathBuf::from(TEST_TEMP_PATH).join("missing.ans");
+    let cache_dir = PathBuf::from(TEST_TEMP_PATH).join("reference_cache");
+    let _ = std::fs::remove_dir_all(&cache_dir);
+    let reference = ReferenceConfig {
+        executor: program_executor,
+        limits: DEFAULT_CHECKER_LIMITS,
+        cache_dir: cache_dir.clone(),
+    };
+    runner_config.reference = Some(reference.clone());
+
+    // The output of the reference is the answer
+    let result = run_judge(&runner_config).unwrap();
+    assert_eq!(result.verdict, JudgeVerdict::Accepted);
+    let answer_path = reference
+        .answer_path(&runner_config.test_data.input_file_path)
+        .unwrap();
+    assert_eq!(
+        std::fs::read_to_string(&answer_path).unwrap(),
+        "Hello! world!\n"
+    );
+    assert_eq!(std::fs::read_dir(&cache_dir).unwrap().count(), 1);
+    // Named by a SHA-256, the same for every build
+    let digest = answer_path.file_stem().unwrap().to_string_lossy();
+    assert_eq!(digest.len(), 64);
+    assert!(digest.chars().all(|c| c.is_ascii_hexdigit()));
+
+    // Then read from the cache, the reference is not run again
+    std::fs::write(&answer_path, "Hello! cache!\n").unwrap();
+    let result = run_judge(&runner_config).unwrap();
+    assert_eq!(result.verdict, JudgeVerdict::WrongAnswer);
+
+    // Exits with 1 as it reads a second line
+    let mut failing_reference =
+        Executor::new(Language::Cpp, programs_path.join("read_and_write_rounds")).unwrap();
+    failing_reference.set_program_args(vec!["2".to_string()]);
+    runner_config.reference = Some(ReferenceConfig {
+        executor: failing_reference,
+        ..reference
+    });
+    let result = run_judge(&runner_config).unwrap();
+    assert_eq!(result.verdict, JudgeVerdict::SystemError);
+    assert_eq!(result.message, "Reference solution exited with code 1");
+    assert_eq!(result.reason_code.as_deref(), Some("SE_REFERENCE_FAILED"));
+    assert_eq!(std::fs::read_dir(&cache_dir).unwrap().count(), 1);
+}
+
+#[test]
+fn test_run_checker_extra_args_and_stdin() {
+    init();
+    let program_executor = Executor::new(
+        Language::Cpp,
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/read_and_write"),
+    )
+    .unwrap();
+    let checker_output_path = PathBuf::from(TEST_TEMP_PATH).join("checker_args.out");
+    let runner_config = JudgeConfig {
+        checker: CheckerConfig {
+            extra_args: vec!["--eps".to_string(), "1e-6".to_string()],
+            feed_input_to_stdin: true,
+            ..echo_args_checker_config(checker_output_path.clone())
+        },
+        ..build_test_config(program_executor)
+    };
+    // The checker runs as the sandbox user
+    std::fs::write(&checker_output_path, "").unwrap();
+    std::fs::set_permissions(&checker_output_path, Permissions::from_mode(0o666)).unwrap();
+
+    let (outcome, _) = run_checker(&runner_config).unwrap();
+    assert_eq!(outcome.verdict, JudgeVerdict::Accepted);
+    let input = std::fs::read_to_string(&runner_config.test_data.input_file_path).unwrap();
+    assert_eq!(
+        std::fs::read_to_string(&checker_output_path).unwrap(),
+        format!("--eps\n1e-6\nstdin:{}", input)
+    );
+}
+
+#[test]
+fn test_structured_errors() {
+    init();
+    let missing_path = PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/missing");
+    assert!(matches!(
+        Executor::new(Language::Cpp, missing_path.clone()),
+        Err(JudgeCoreError::ExecutableNotFound(path)) if path == missing_path
+    ));
+
+    let program_executor = Executor::new(
+        Language::Cpp,
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/read_and_write"),
+    )
+    .unwrap();
+    let mut runner_config = build_test_config(program_executor);
+    assert!(matches!(
+        run_checker(&runner_config),
+        Err(JudgeCoreError::CheckerNotConfigured)
+    ));
+
+    runner_config.test_data.input_file_path = PathBuf::from(TEST_TEMP_PATH).join("missing.in");
+    assert!(matches!(
+        run_judge(&runner_config),
+        Err(JudgeCoreError::InputFileMissing(path)) if path == runner_config.test_data.input_file_path
+    ));
+}
+
+fn spawn_executable(name: &str, content: &str, mode: u32) -> Result<i32, JudgeCoreError> {
+    let path = PathBuf::from(TEST_TEMP_PATH).join(name);
+    std::fs::write(&path, content).unwrap();
+    std::fs::set_permissions(&path, Permissions::from_mode(mode)).unwrap();
+    let executor = Executor::new(Language::Cpp, path).unwrap();
+    ExecutorSandbox::new(executor, TEST_CONFIG, None, None, true)?.spawn()
+}
+
+fn assert_not_runnable(result: Result<i32, JudgeCoreError>, expected_reason: &str) {
+    match result {
+        Err(JudgeCoreError::ExecutableNotRunnable { reason, .. }) => {
+            assert!(reason.contains(expected_reason), "{}", reason)
+        }
+        other => panic!("Unexpected result {:?}", other),
+    }
+}
+
+#[test]
+fn test_executable_not_runnable() {
+    init();
+    assert_not_runnable(
+        spawn_executable("not_executable", "", 0o644),
+        "not executable",
+    );
+    // Neither ELF nor a script, only `execve` can tell
+    assert_not_runnable(
+        spawn_executable("wrong_format", "garbage", 0o755),
+        "ENOEXEC",
+    );
+
+    let deleted_path = PathBuf::from(TEST_TEMP_PATH).join("deleted_executable");
+    std::fs::write(&deleted_path, "").unwrap();
+    let executor = Executor::new(Language::Cpp, deleted_path.clone()).unwrap();
+    std::fs::remove_file(&deleted_path).unwrap();
+    let mut runner_config = build_test_config(executor);
+    runner_config.program.output_file_path =
+        Some(PathBuf::from(TEST_TEMP_PATH).join("deleted.out"));
+    assert!(matches!(
+        run_judge(&runner_config),
+        Err(JudgeCoreError::ExecutableNotRunnable { path, reason })
+            if path == deleted_path && reason == "not found"
+    ));
+
+    let executor = Executor::new(Language::Cpp, PathBuf::from(TEST_TEMP_PATH)).unwrap();
+    assert!(matches!(
+        executor.check_runnable(),
+        Err(JudgeCoreError::ExecutableNotRunnable { reason, .. }) if reason == "not a regular file"
+    ));
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_run_interact() {
+    init();
+    let interactor_executor = Executor::new(
+        Language::Cpp,
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/checkers/interactor-echo"),
+    )
+    .unwrap();
+    let program_executor = Executor::new(
+        Language::Cpp,
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/read_and_write"),
+    )
+    .unwrap();
+    let runner_config = JudgeConfig {
+        checker: CheckerConfig {
+            executor: Some(built_in_checker("lcmp")),
+            ..build_test_checker_config(PathBuf::from(TEST_TEMP_PATH).join("checker.out"))
+        },
+        ..build_test_config(program_executor)
+    };
+    let result = run_interact(
+        &runner_config,
+        interactor_executor,
+        &PathBuf::from(TEST_TEMP_PATH).join("interact.out"),
+    );
+    match result {
+        Ok(Some(result)) => {
+            log::debug!("{:?}", result);
+            assert!(result.verdict == JudgeVerdict::Accepted);
+            assert!(result.interactor_time_usage.is_some());
+            assert!(result.interactor_memory_usage_bytes.is_some());
+        }
+        Ok(None) => {
+            log::debug!("Ignoring this result, for it's from a fork child process");
+        }
+        Err(e) => {
+            log::error!("meet error: {:?}", e);
+            unreachable!()
+        }
+    }
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_run_interact_mock_clock() {
+    init();
+    let interactor_executor = Executor::new(
+        Language::Cpp,
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/checkers/interactor-echo"),
+    )
+    .unwrap();
+    let program_executor = Executor::new(
+        Language::Cpp,
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/read_and_write"),
+    )
+    .unwrap();
+    let mut runner_config = JudgeConfig {
+        checker: CheckerConfig {
+            executor: Some(built_in_checker("lcmp")),
+            ..build_test_checker_config(
+                PathBuf::from(TEST_TEMP_PATH).join("mock_clock_checker.out"),
+            )
+        },
+        ..build_test_config(program_executor)
+    };
+    runner_config.runtime.record_timings = true;
+    runner_config.runtime.verbose = true;
+    let clock = MockClock::new();
+    clock.advance(Duration::from_secs(1));
+    runner_config.runtime.clock = Some(Arc::new(clock));
+    let result = run_interact(
+        &runner_config,
+        interactor_executor,
+        &PathBuf::from(TEST_TEMP_PATH).join("mock_clock_interact.out"),
+    )
+    .unwrap()
+    .unwrap();
+    // The loop and the user sandbox, in the listener, measure by the clock, which stands still
+    assert_eq!(result.timings.unwrap().total, Duration::ZERO);
+    assert_eq!(result.raw.unwrap().real_time_cost, Duration::ZERO);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_run_interact_flood() {
+    init();
+    // The interactor writes 8 MiB to a user program which reads one line and exits
+    let interactor_executor = Executor::new(
+        Language::Cpp,
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/checkers/interactor-flood"),
+    )
+    .unwrap();
+    let program_executor = Executor::new(
+        Language::Cpp,
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/read_and_write"),
+    )
+    .unwrap();
+    let runner_config = JudgeConfig {
+        checker: CheckerConfig {
+            executor: Some(built_in_checker("lcmp")),
+            ..build_test_checker_config(PathBuf::from(TEST_TEMP_PATH).join("checker_flood.out"))
+        },
+        ..build_test_config(program_executor)
+    };
+    let result = run_interact(
+        &runner_config,
+        interactor_executor,
+        &PathBuf::from(TEST_TEMP_PATH).join("interact_flood.out"),
+    );
+    match result {
+        Ok(Some(result)) => {
+            log::debug!("{:?}", result);
+            assert_eq!(result.exit_status, 0);
+        }
+        Ok(None) => {
+            log::debug!("Ignoring this result, for it's from a fork child process");
+        }
+        Err(e) => {
+            log::error!("meet error: {:?}", e);
+            unreachable!()
+        }
+    }
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_run_interact_simultaneous_exits() {
+    init();
+    // Both sides write a burst and exit without reading, their exit reports usually come
+    // in the same epoll batch as the data still in the pipes
+    let interactor_executor = Executor::new(
+        Language::Cpp,
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/checkers/interactor-burst"),
+    )
+    .unwrap();
+    let program_executor = Executor::new(
+        Language::Cpp,
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/burst_and_exit"),
+    )
+    .unwrap();
+    let runner_config = JudgeConfig {
+        checker: CheckerConfig {
+            pass_transcript: true,
+            ..echo_args_checker_config(
+                PathBuf::from(TEST_TEMP_PATH).join("checker_simultaneous.out"),
+            )
+        },
+        ..build_test_config(program_executor)
+    };
+    let output_path = PathBuf::from(TEST_TEMP_PATH).join("interact_simultaneous.out");
+    for _ in 0..3 {
+        let result = run_interact(&runner_config, interactor_executor.clone(), &output_path)
+            .unwrap()
+            .expect("Not a fork child process");
+        log::debug!("{:?}", result);
+        assert_eq!(result.verdict, JudgeVerdict::Accepted);
+        assert!(result.interactor_time_usage.is_some());
+
+        // Everything both sides wrote is drained to the transcript
+        let transcript = std::fs::read(&output_path).unwrap();
+        assert_eq!(transcript.len(), 120000);
+        assert_eq!(transcript.iter().filter(|&&c| c == b'u').count(), 60000);
+        assert_eq!(transcript.iter().filter(|&&c| c == b'i').count(), 60000);
+    }
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_run_interact_query_limit() {
+    init();
+    let interactor_executor = Executor::new(
+        Language::Cpp,
+        PathBuf::from(TEST_DATA_PATH)
+            .join("built-in-programs/build/src/checkers/interactor-queries"),
+    )
+    .unwrap();
+    let program_executor = Executor::new(
+        Language::Cpp,
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/queries"),
+    )
+    .unwrap();
+    let mut runner_config = JudgeConfig {
+        checker: CheckerConfig {
+            ..echo_args_checker_config(PathBuf::from(TEST_TEMP_PATH).join("checker_queries.out"))
+        },
+        ..build_test_config(program_executor)
+    };
+    // The answer line counts as a query too
+    runner_config.runtime.max_interactions = Some(5);
+    let output_path = PathBuf::from(TEST_TEMP_PATH).join("interact_queries.out");
+
+    runner_config.program.program_args = vec!["4".to_string()];
+    let result = run_interact(&runner_config, interactor_executor.clone(), &output_path)
+        .unwrap()
+        .expect("Not a fork child process");
+    assert_eq!(result.verdict, JudgeVerdict::Accepted);
+    assert!(matches!(
+        result.decided_by,
+        Some(DecidedBy::ExternalChecker(path)) if path.ends_with("checker-echo-args")
+    ));
+
+    runner_config.program.program_args = vec!["5".to_string()];
+    runner_config.runtime.verbose = true;
+    let result = run_interact(&runner_config, interactor_executor, &output_path)
+        .unwrap()
+        .expect("Not a fork child process");
+    log::debug!("{:?}", result);
+    assert_eq!(result.verdict, JudgeVerdict::QueryLimitExceeded);
+    assert_eq!(result.message, "Made 6 queries, over the limit of 5");
+    assert_eq!(result.reason_code.as_deref(), Some("QUERY_LIMIT"));
+    // The user program is killed alone, its listener still reports how it ran
+    assert!(result.started_at.is_some());
+    assert!(result.memory_usage_bytes > 0);
+    assert_eq!(
+        result.effective_limits.unwrap().memory_bytes,
+        runner_config.runtime.limits().memory_bytes
+    );
+    assert!(matches!(
+        result.decided_by,
+        Some(DecidedBy::Interactor(path)) if path.ends_with("interactor-queries")
+    ));
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_run_interact_stdin_prefix() {
+    init();
+    let interactor_executor = Executor::new(
+        Language::Cpp,
+        PathBuf::from(TEST_DATA_PATH)
+            .join("built-in-programs/build/src/checkers/interactor-queries"),
+    )
+    .unwrap();
+    // Waits for how many queries to make before talking to the interactor
+    let program_executor = Executor::new(
+        Language::Cpp,
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/prefixed_queries"),
+    )
+    .unwrap();
+    let mut runner_config = JudgeConfig {
+        checker: CheckerConfig {
+            ..echo_args_checker_config(PathBuf::from(TEST_TEMP_PATH).join("checker_prefix.out"))
+        },
+        ..build_test_config(program_executor)
+    };
+    let prefix_path = PathBuf::from(TEST_TEMP_PATH).join("interact_prefix.in");
+    std::fs::write(&prefix_path, "3\n").unwrap();
+    runner_config.program.stdin_prefix_path = Some(prefix_path);
+    let output_path = PathBuf::from(TEST_TEMP_PATH).join("interact_prefix.out");
+    let result = run_interact(&runner_config, interactor_executor, &output_path)
+        .unwrap()
+        .expect("Not a fork child process");
+    log::debug!("{:?}", result);
+    assert_eq!(result.verdict, JudgeVerdict::Accepted);
+
+    let transcript = std::fs::read_to_string(&output_path).unwrap();
+    assert!(transcript.starts_with("3\n? 0\n"), "{:?}", transcript);
+    assert_eq!(transcript.matches("? ").count(), 3);
+    assert_eq!(transcript.matches("ok\n").count(), 3);
+    assert!(transcript.ends_with("!\n"));
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_run_interact_interactor_leaves_early() {
+    init();
+    // Gone after the first line, the user program writes 100 KiB more
+    let interactor_executor = Executor::new(
+        Language::Cpp,
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/checkers/interactor-leave"),
+    )
+    .unwrap();
+    let program_executor = Executor::new(
+        Language::Cpp,
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/talk_after_leave"),
+    )
+    .unwrap();
+    let runner_config = JudgeConfig {
+        checker: CheckerConfig {
+            ..echo_args_checker_config(PathBuf::from(TEST_TEMP_PATH).join("checker_leave.out"))
+        },
+        ..build_test_config(program_executor)
+    };
+    let output_path = PathBuf::from(TEST_TEMP_PATH).join("interact_leave.out");
+    let result = run_interact(&runner_config, interactor_executor, &output_path)
+        .unwrap()
+        .expect("Not a fork child process");
+    log::debug!("{:?}", result);
+    assert_eq!(result.verdict, JudgeVerdict::Accepted);
+    assert_eq!(result.exit_status, 0);
+
+    // Dropped for the interactor, but recorded
+    let transcript = std::fs::read(&output_path).unwrap();
+    assert_eq!(transcript.len(), "hello\nbye\n".len() + 100 * 1001);
+    assert_eq!(
+        transcript.iter().filter(|&&c| c == b'u').count(),
+        100 * 1000
+    );
+}
+
+/// Children forked by the current test thread, zombies included.
+#[cfg(target_os = "linux")]
+fn thread_children() -> Vec<String> {
+    std::fs::read_to_string("/proc/thread-self/children")
+        .unwrap()
+        .split_whitespace()
+        .map(str::to_string)
+        .collect()
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_run_interact_leaves_no_child() {
+    init();
+    let program_executor = Executor::new(
+        Language::Cpp,
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/read_and_write"),
+    )
+    .unwrap();
+    let runner_config = JudgeConfig {
+        checker: CheckerConfig {
+            executor: Some(built_in_checker("lcmp")),
+            ..build_test_config(program_executor.clone()).checker
+        },
+        ..build_test_config(program_executor)
+    };
+    let output_path = PathBuf::from(TEST_TEMP_PATH).join("interact_no_child.out");
+    let children_before = thread_children();
+
+    // Fails once the user program is running, waiting for a line which never comes
+    let not_executable = PathBuf::from(TEST_TEMP_PATH).join("interactor_not_executable");
+    std::fs::write(&not_executable, "").unwrap();
+    std::fs::set_permissions(&not_executable, Permissions::from_mode(0o644)).unwrap();
+    let interactor_executor = Executor::new(Language::Cpp, not_executable).unwrap();
+    assert!(matches!(
+        run_interact(&runner_config, interactor_executor, &output_path),
+        Err(JudgeCoreError::ExecutableNotRunnable { reason, .. }) if reason == "not executable"
+    ));
+    assert_eq!(thread_children(), children_before);
+
+    let interactor_executor = Executor::new(
+        Language::Cpp,
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/checkers/interactor-echo"),
+    )
+    .unwrap();
+    let result = run_interact(&runner_config, interactor_executor, &output_path)
+        .unwrap()
+        .expect("Not a fork child process");
+    assert_eq!(result.verdict, JudgeVerdict::Accepted);
+    assert_eq!(thread_children(), children_before);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_run_interact_transcript_order() {
+    init();
+    // Both sides write rounds of lines without waiting for each other
+    let interactor_executor = Executor::new(
+        Language::Cpp,
+        PathBuf::from(TEST_DATA_PATH)
+            .join("built-in-programs/build/src/checkers/interactor-chatter"),
+    )
+    .unwrap();
+    let program_executor = Executor::new(
+        Language::Cpp,
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/chatter"),
+    )
+    .unwrap();
+    let runner_config = JudgeConfig {
+        checker: CheckerConfig {
+            pass_transcript: true,
+            ..echo_args_checker_config(PathBuf::from(TEST_TEMP_PATH).join("checker_order.out"))
+        },
+        ..build_test_config(program_executor)
+    };
+    let output_path = PathBuf::from(TEST_TEMP_PATH).join("interact_order.out");
+    let result = run_interact(&runner_config, interactor_executor, &output_path)
+        .unwrap()
+        .expect("Not a fork child process");
+    assert_eq!(result.verdict, JudgeVerdict::Accepted);
+
+    // Each line is `<side> <round> <line> <round> <line>`, the last two telling the last line
+    // its side read from the other one, which must be recorded before it
+    let transcript = std::fs::read_to_string(&output_path).unwrap();
+    let mut recorded = std::collections::HashSet::new();
+    let mut last_written = std::collections::HashMap::new();
+    for line in transcript.lines() {
+        let fields: Vec<&str> = line.split(' ').collect();
+        assert_eq!(fields.len(), 5, "Mangled line {:?}", line);
+        let side = fields[0];
+        let other_side = if side == "U" { "I" } else { "U" };
+        let written: (i32, i32) = (fields[1].parse().unwrap(), fields[2].parse().unwrap());
+        let seen: (i32, i32) = (fields[3].parse().unwrap(), fields[4].parse().unwrap());
+        if let Some(previous) = last_written.insert(side, written) {
+            assert!(previous < written, "{:?} recorded out of order", line);
+        }
+        if seen != (-1, -1) {
+            assert!(
+                recorded.contains(&(other_side, seen)),
+                "{:?} recorded before the line it answers",
+                line
+            );
+        }
+        recorded.insert((side, written));
+    }
+    assert_eq!(recorded.len(), 2 * 300 * 8);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_run_interact_idle_partial_transcript() {
+    init();
+    // The user program gets the first line from the interactor, then stalls
+    let interactor_executor = Executor::new(
+        Language::Cpp,
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/checkers/interactor-echo"),
+    )
+    .unwrap();
+    let program_executor = Executor::new(
+        Language::Cpp,
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/read_then_stall"),
+    )
+    .unwrap();
+    let mut runner_config = build_test_config(program_executor);
+    runner_config.runtime.idle_time_limit = Some(Duration::from_millis(300));
+    let output_path = PathBuf::from(TEST_TEMP_PATH).join("interact_stall.out");
+    let result = run_interact(&runner_config, interactor_executor, &output_path)
+        .unwrap()
+        .expect("Not a fork child process");
+    log::debug!("{:?}", result);
+    assert_eq!(result.verdict, JudgeVerdict::IdlenessLimitExceeded);
+    assert!(result.memory_usage_bytes > 0);
+    let transcript = result.transcript.unwrap();
+    assert!(!transcript.is_empty());
+    assert_eq!(transcript, std::fs::read_to_string(&output_path).unwrap());
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_process_listener_spawned_peak_rss() {
+    init();
+    let program_executor = Executor::new(
+        Language::Cpp,
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/sleep"),
+    )
+    .unwrap();
+    let mut sandbox =
+        ExecutorSandbox::new(program_executor, TEST_CONFIG, None, None, true).unwrap();
+    let mut listener = ProcessListener::new().unwrap();
+    assert_eq!(listener.spawned_peak_rss(), None);
+    listener.spawn_with_sandbox(&mut sandbox).unwrap();
+    let give_up = std::time::Instant::now() + Duration::from_secs(5);
+    let mut peak_rss = None;
+    while peak_rss.is_none() && std::time::Instant::now() < give_up {
+        peak_rss = listener.spawned_peak_rss();
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    assert!(peak_rss.unwrap() > 0);
+    listener.kill().unwrap();
+    assert_eq!(listener.spawned_peak_rss(), None);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_run_interact_transcript_replay() {
+    init();
+    let interactor_executor = Executor::new(
+        Language::Cpp,
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/checkers/interactor-echo"),
+    )
+    .unwrap();
+    let program_executor = Executor::new(
+        Language::Cpp,
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/read_and_write"),
+    )
+    .unwrap();
+    let mut runner_config = build_test_config(program_executor.clone());
+    runner_config.checker.executor = Some(built_in_checker("lcmp"));
+    runner_config.checker.output_file_path =
+        PathBuf::from(TEST_TEMP_PATH).join("checker_replay.out");
+    runner_config.runtime.sync_transcript = true;
+    let transcript_path = PathBuf::from(TEST_TEMP_PATH).join("interact_replay.out");
+    let result = run_interact(&runner_config, interactor_executor, &transcript_path).unwrap();
+    log::debug!("{:?}", result);
+
+    // The interactor speaks first, replaying its line to the program gives back the rest
+    let transcript = std::fs::read_to_string(&transcript_path).unwrap();
+    let (interactor_part, user_part) = transcript.split_at(transcript.find('\n').unwrap() + 1);
+    let replay_input_path = PathBuf::from(TEST_TEMP_PATH).join("interact_replay.in");
+    let replay_output_path = PathBuf::from(TEST_TEMP_PATH).join("interact_replay_user.out");
+    std::fs::write(&replay_input_path, interactor_part).unwrap();
+    let input_file = File::open(&replay_input_path).unwrap();
+    let output_file = File::create(&replay_output_path).unwrap();
+    let mut sandbox = ExecutorSandbox::new(
+        program_executor,
+        TEST_CONFIG,
+        Some(input_file.as_raw_fd()),
+        Some(output_file.as_raw_fd()),
+        true,
+    )
+    .unwrap();
+    sandbox.spawn().unwrap();
+    assert_eq!(sandbox.wait().unwrap().exit_status, 0);
+    assert!(!user_part.is_empty());
+    assert_eq!(
+        std::fs::read_to_string(&replay_output_path).unwrap(),
+        user_part
+    );
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_run_interact_blocking_stdio() {
+    init();
+    // Both sides report the flags of their stdin and stdout to each other
+    let fd_flags_path =
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/fd_flags");
+    let interactor_executor = Executor::new(Language::Cpp, fd_flags_path.clone()).unwrap();
+    let mut runner_config = build_test_config(Executor::new(Language::Cpp, fd_flags_path).unwrap());
+    runner_config.checker.executor = Some(built_in_checker("lcmp"));
+    runner_config.checker.output_file_path =
+        PathBuf::from(TEST_TEMP_PATH).join("checker_blocking.out");
+    let transcript_path = PathBuf::from(TEST_TEMP_PATH).join("interact_blocking.out");
+    run_interact(&runner_config, interactor_executor, &transcript_path).unwrap();
+
+    let transcript = std::fs::read_to_string(&transcript_path).unwrap();
+    assert_eq!(
+        transcript,
+        "stdin=blocking stdout=blocking\n".repeat(2),
+        "{}",
+        transcript
+    );
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_run_interact_drain_after_exit() {
+    init();
+    // The user program writes its only line as it exits, after the interactor is gone
+    let interactor_executor = Executor::new(
+        Language::Cpp,
+        PathBuf::from(TEST_DATA_PATH)
+            .join("built-in-programs/build/src/checkers/interactor-hangup"),
+    )
+    .unwrap();
+    let program_executor = Executor::new(
+        Language::Cpp,
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/last_words"),
+    )
+    .unwrap();
+    let mut runner_config = build_test_config(program_executor);
+    runner_config.checker.executor = Some(built_in_checker("lcmp"));
+    runner_config.checker.output_file_path =
+        PathBuf::from(TEST_TEMP_PATH).join("checker_drain.out");
+    let transcript_path = PathBuf::from(TEST_TEMP_PATH).join("interact_drain.out");
+    for _ in 0..5 {
+        run_interact(
+            &runner_config,
+            interactor_executor.clone(),
+            &transcript_path,
+        )
+        .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&transcript_path).unwrap(),
+            "last words\n"
+        );
+    }
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_run_interact_checker_transcript() {
+    init();
+    let interactor_executor = Executor::new(
+        Language::Cpp,
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/checkers/interactor-echo"),
+    )
+    .unwrap();
+    let program_executor = Executor::new(
+        Language::Cpp,
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/read_and_write"),
+    )
+    .unwrap();
+    let checker_output_path = PathBuf::from(TEST_TEMP_PATH).join("checker_transcript.out");
+    let mut runner_config = build_test_config(program_executor);
+    runner_config.checker = CheckerConfig {
+        extra_args: vec!["--strict".to_string()],
+        pass_transcript: true,
+        ..echo_args_checker_config(checker_output_path.clone())
+    };
+    // The checker runs as the sandbox user
+    std::fs::write(&checker_output_path, "").unwrap();
+    std::fs::set_permissions(&checker_output_path, Permissions::from_mode(0o666)).unwrap();
+
+    let transcript_path = PathBuf::from(TEST_TEMP_PATH).join("interact_checker_transcript.out");
+    let result = run_interact(&runner_config, interactor_executor, &transcript_path).unwrap();
+    log::debug!("{:?}", result);
+    assert_eq!(
+        std::fs::read_to_string(&checker_output_path).unwrap(),
+        format!("{}\n--strict\nstdin:", transcript_path.to_str().unwrap())
+    );
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_replay_interaction() {
+    init();
+    let interactor_executor = Executor::new(
+        Language::Cpp,
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/checkers/interactor-echo"),
+    )
+    .unwrap();
+    let program_executor = Executor::new(
+        Language::Cpp,
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/read_and_write"),
+    )
+    .unwrap();
+    let checker_output_path = PathBuf::from(TEST_TEMP_PATH).join("checker_replay_session.out");
+    let mut runner_config = build_test_config(program_executor);
+    runner_config.checker.executor = Some(built_in_checker("checker-echo-args"));
+    runner_config.checker.output_file_path = checker_output_path.clone();
+    runner_config.checker.pass_transcript = true;
+    // The checker runs as the sandbox user
+    std::fs::write(&checker_output_path, "").unwrap();
+    std::fs::set_permissions(&checker_output_path, Permissions::from_mode(0o666)).unwrap();
+
+    let transcript_path = PathBuf::from(TEST_TEMP_PATH).join("interact_replay_session.out");
+    let recorded = run_interact(&runner_config, interactor_executor, &transcript_path)
+        .unwrap()
+        .unwrap();
+    let recorded_checker_output = std::fs::read_to_string(&checker_output_path).unwrap();
+    std::fs::write(&checker_output_path, "").unwrap();
+
+    let replayed = replay_interaction(&runner_config, &transcript_path).unwrap();
+    log::debug!("{:?}", replayed);
+    assert_eq!(replayed.verdict, recorded.verdict);
+    assert_eq!(replayed.checker_exit_status, recorded.checker_exit_status);
+    assert_eq!(replayed.time_usage, Duration::ZERO);
+    assert_eq!(
+        std::fs::read_to_string(&checker_output_path).unwrap(),
+        recorded_checker_output
+    );
+
+    runner_config.checker.executor = Some(built_in_checker("checker-unknown-exit"));
+    let replayed = replay_interaction(&runner_config, &transcript_path).unwrap();
+    assert_eq!(replayed.verdict, JudgeVerdict::SystemError);
+
+    // The transcript is passed all the same
+    runner_config.checker.executor = Some(built_in_checker("checker-echo-args"));
+    runner_config.checker.pass_transcript = false;
+    std::fs::write(&checker_output_path, "").unwrap();
+    replay_interaction(&runner_config, &transcript_path).unwrap();
+    assert_eq!(
+        std::fs::read_to_string(&checker_output_path).unwrap(),
+        recorded_checker_output
+    );
+
+    let missing_path = PathBuf::from(TEST_TEMP_PATH).join("interact_replay_missing.out");
+    assert!(replay_interaction(&runner_config, &missing_path).is_err());
+}
+
+#[test]
+fn test_run_stress() {
+    init();
+    let programs_path = PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs");
+    let buggy_executor = Executor::new(Language::Cpp, programs_path.join("sum_buggy")).unwrap();
+    let reference_executor = Executor::new(Language::Cpp, programs_path.join("sum")).unwrap();
+    let mut runner_config = build_test_config(buggy_executor);
+    runner_config.program.output_file_path = Some(PathBuf::from(TEST_TEMP_PATH).join("stress.out"));
+    let stress_config = StressConfig {
+        generator: Executor::new(Language::Cpp, programs_path.join("stress_gen")).unwrap(),
+        reference: reference_executor.clone(),
+        rlimit_configs: TEST_CONFIG,
+        seed: 0,
+        iterations: 10,
+        work_dir: PathBuf::from(TEST_TEMP_PATH).join("stress"),
+        failing_input_path: PathBuf::from(TEST_TEMP_PATH).join("stress_failing.in"),
+    };
+
+    let failure = run_stress(&runner_config, &stress_config).unwrap().unwrap();
+    log::debug!("{:?}", failure);
+    assert_eq!(failure.iteration, 5);
+    assert_eq!(failure.seed, 5);
+    assert_eq!(failure.result.verdict, JudgeVerdict::WrongAnswer);
+    assert_eq!(
+        std::fs::read_to_string(&stress_config.failing_input_path).unwrap(),
+        "5 9\n"
+    );
+
+    runner_config.program.executor = reference_executor;
+    assert!(run_stress(&runner_config, &stress_config)
+        .unwrap()
+        .is_none());
+}
+
+#[test]
+fn test_scratch_dir() {
+    init();
+    let programs_path = PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs");
+    let scratch_dir = PathBuf::from(TEST_TEMP_PATH).join("scratch");
+    let _ = std::fs::remove_dir_all(&scratch_dir);
+    let mut runner_config =
+        build_test_config(Executor::new(Language::Cpp, programs_path.join("sum")).unwrap());
+    runner_config.program.output_file_path = None;
+    runner_config.runtime.scratch_dir = scratch_dir.clone();
+
+    // The captured stdout can't be created until the directory exists
+    let exited = build_exit_info(Duration::from_millis(100));
+    MOCK_RUNS.with(|mock_runs| {
+        *mock_runs.borrow_mut() = vec![("Hello! world!\n", exited.clone())].into()
+    });
+    assert!(run_judge_with::<MockSandbox>(&runner_config).is_err());
+    MOCK_RUNS.with(|mock_runs| mock_runs.borrow_mut().clear());
+
+    std::fs::create_dir_all(&scratch_dir).unwrap();
+    let result = judge_with_mock_runs(&runner_config, vec![("Hello! world!\n", exited)]);
+    assert_eq!(result.verdict, JudgeVerdict::Accepted);
+    // Removed once judged
+    assert_eq!(std::fs::read_dir(&scratch_dir).unwrap().count(), 0);
+
+    // A file already at the predictable name is never written through
+    let victim_path = scratch_dir.join("victim");
+    std::fs::write(&victim_path, "untouched").unwrap();
+    for counter in 0..50 {
+        std::os::unix::fs::symlink(
+            &victim_path,
+            scratch_dir.join(format!(
+                "judge-stdout-{}-{}.out",
+                std::process::id(),
+                counter
+            )),
+        )
+        .unwrap();
+    }
+    let exited = build_exit_info(Duration::from_millis(100));
+    let result = judge_with_mock_runs(&runner_config, vec![("Hello! world!\n", exited)]);
+    assert_eq!(result.verdict, JudgeVerdict::Accepted);
+    assert_eq!(std::fs::read_to_string(&victim_path).unwrap(), "untouched");
+
+    // A relative work dir of stress testing is in it too
+    let stress_config = StressConfig {
+        generator: Executor::new(Language::Cpp, programs_path.join("stress_gen")).unwrap(),
+        reference: Executor::new(Language::Cpp, programs_path.join("sum")).unwrap(),
+        rlimit_configs: TEST_CONFIG,
+        seed: 0,
+        iterations: 1,
+        work_dir: PathBuf::from("stress"),
+        failing_input_path: PathBuf::from(TEST_TEMP_PATH).join("scratch_failing.in"),
+    };
+    assert!(run_stress(&runner_config, &stress_config)
+        .unwrap()
+        .is_none());
+    assert!(scratch_dir.join("stress/stress.in").exists());
+    assert!(scratch_dir.join("stress/stress.ans").exists());
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_disk_full() {
+    init();
+    let program_executor = Executor::new(
+        Language::Cpp,
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/read_and_write"),
+    )
+    .unwrap();
+
+    // The scratch directory on a tiny tmpfs, filled before the judge writes to it
+    let mount_path = PathBuf::from(TEST_TEMP_PATH).join("disk_full");
+    std::fs::create_dir_all(&mount_path).unwrap();
+    let mounted = std::process::Command::new("mount")
+        .args(["-t", "tmpfs", "-o", "size=64k", "tmpfs"])
+        .arg(&mount_path)
+        .status()
+        .unwrap();
+    assert!(mounted.success());
+    let mut filler = File::create(mount_path.join("filler")).unwrap();
+    while filler.write_all(&[0; 4096]).is_ok() {}
+    drop(filler);
+    let mut runner_config = build_test_config(program_executor.clone());
+    runner_config.runtime.scratch_dir = mount_path.clone();
+    let result = run_judge_bytes(&runner_config, &[b'x'; 8192], b"Hello! x\n");
+    // The failed writes of the program are its own
+    runner_config.runtime.scratch_dir = std::env::temp_dir();
+    runner_config.program.output_file_path = Some(mount_path.join("disk_full.out"));
+    let user_result = run_judge(&runner_config);
+    let unmounted = std::process::Command::new("umount")
+        .arg(&mount_path)
+        .status()
+        .unwrap();
+    let result = result.unwrap();
+    assert!(unmounted.success());
+    assert_ne!(user_result.unwrap().verdict, JudgeVerdict::SystemError);
+    assert_eq!(result.verdict, JudgeVerdict::SystemError);
+    assert_eq!(result.reason_code.as_deref(), Some("SE_DISK_FULL"));
+    assert!(result.message.starts_with("Disk full"));
+
+    // The transcript of an interaction can't be written
+    let runner_config = JudgeConfig {
+        checker: CheckerConfig {
+            executor: Some(built_in_checker("lcmp")),
+            ..build_test_config(program_executor.clone()).checker
+        },
+        ..build_test_config(program_executor)
+    };
+    let interactor_executor = Executor::new(
+        Language::Cpp,
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/checkers/interactor-echo"),
+    )
+    .unwrap();
+    let children_before = thread_children();
+    let result = run_interact(
+        &runner_config,
+        interactor_executor,
+        &PathBuf::from("/dev/full"),
+    )
+    .unwrap()
+    .expect("Not a fork child process");
+    assert_eq!(result.verdict, JudgeVerdict::SystemError);
+    assert_eq!(result.reason_code.as_deref(), Some("SE_DISK_FULL"));
+    assert_eq!(thread_children(), children_before);
+}
+
+#[test]
+fn test_check_solutions() {
+    init();
+    let solution = |name: &str, expected_verdict| ExpectedSolution {
+        name: name.to_string(),
+        executor: Executor::new(
+            Language::Cpp,
+            PathBuf::from(TEST_DATA_PATH)
+                .join("built-in-programs/build/src/programs")
+                .join(name),
+        )
+        .unwrap(),
+        expected_verdict,
+    };
+    let cases = TestSet::from_dir(
+        &PathBuf::from(TEST_DATA_PATH).join("packages/icpc/hello_world/data/secret"),
+        "in",
+        "ans",
+    )
+    .unwrap()
+    .cases;
+    let config = build_test_config(solution("read_and_write", JudgeVerdict::Accepted).executor);
+
+    let solutions = [
+        solution("read_and_write", JudgeVerdict::Accepted),
+        solution("print_args", JudgeVerdict::WrongAnswer),
+        solution("infinite_loop", JudgeVerdict::TimeLimitExceeded),
+        // Declared wrong by its setter
+        solution("sum_buggy", JudgeVerdict::Accepted),
+    ];
+    let checks = check_solutions(&config, &cases, &solutions).unwrap();
+    assert_eq!(checks.len(), solutions.len());
+    for check in &checks[..3] {
+        assert!(check.matched(), "{:?}", check.mismatch());
+        assert_eq!(check.results.len(), cases.len());
+    }
+    assert!(!checks[3].matched());
+    assert_ne!(checks[3].verdict, JudgeVerdict::Accepted);
+    let mismatch = checks[3].mismatch().unwrap();
+    assert!(
+        mismatch.starts_with(&format!(
+            "sum_buggy: expected Accepted, got {} on case ",
+            checks[3].verdict
+        )),
+        "{}",
+        mismatch
+    );
+}
+
+fn build_test_testset(program_executor: Executor) -> JudgeBuilder {
+    let config = build_test_config(program_executor);
+    let secret_path = PathBuf::from(TEST_DATA_PATH).join("packages/icpc/hello_world/data/secret");
+    JudgeBuilder {
+        judge_type: JudgeType::COMMON,
+        testdata_configs: (0..2)
+            .map(|idx| TestdataConfig {
+                input_file_path: secret_path.join(format!("{}.in", idx)),
+                answer_file_path: secret_path.join(format!("{}.ans", idx)),
+                extra_answer_file_paths: vec![],
+                input_sha256: None,
+                answer_sha256: None,
+                case_index: None,
+            })
+            .collect(),
+        program_config: config.program,
+        checker_config: config.checker,
+        runtime_config: config.runtime,
+        setup_config: config.setup,
+        reference_config: config.reference,
+        compile_time: Duration::ZERO,
+    }
+}
+
+#[test]
+fn test_run_testset_hooks() {
+    init();
+    let program_executor = Executor::new(
+        Language::Cpp,
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/read_and_write"),
+    )
+    .unwrap();
+    let judge = build_test_testset(program_executor);
+
+    let results = run_testset(&judge, &TestsetHooks::default()).unwrap();
+    assert_eq!(results.len(), 2);
+    assert!(results.iter().all(|r| r.verdict == JudgeVerdict::Accepted));
+
+    let finished = RefCell::new(vec![]);
+    let hooks = TestsetHooks {
+        before_case: Some(Box::new(|idx, _| match idx {
+            0 => CaseAction::Skip,
+            _ => CaseAction::Run,
+        })),
+        after_case: Some(Box::new(|idx, _| finished.borrow_mut().push(idx))),
+        ..Default::default()
+    };
+    let results = run_testset(&judge, &hooks).unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(*finished.borrow(), vec![1]);
+
+    let hooks = TestsetHooks {
+        before_case: Some(Box::new(|_, _| CaseAction::Abort)),
+        ..Default::default()
+    };
+    assert!(run_testset(&judge, &hooks).unwrap().is_empty());
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_pass_case_index() {
+    init();
+    let program_executor = Executor::new(
+        Language::Cpp,
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/read_and_write"),
+    )
+    .unwrap();
+    let checker_output_path = PathBuf::from(TEST_TEMP_PATH).join("checker_case_index.out");
+    // Appended to by the checker, which runs as the sandbox user
+    let reset_checker_output = || {
+        std::fs::write(&checker_output_path, "").unwrap();
+        std::fs::set_permissions(&checker_output_path, Permissions::from_mode(0o666)).unwrap();
+    };
+    let mut judge = build_test_testset(program_executor.clone());
+    judge.checker_config = CheckerConfig {
+        executor: Some(built_in_checker("checker-case-index")),
+        pass_case_index: true,
+        ..build_test_checker_config(checker_output_path.clone())
+    };
+
+    reset_checker_output();
+    let results = run_testset(&judge, &TestsetHooks::default()).unwrap();
+    assert!(results.iter().all(|r| r.verdict == JudgeVerdict::Accepted));
+    assert_eq!(
+        std::fs::read_to_string(&checker_output_path).unwrap(),
+        "case 0\ncase 1\n"
+    );
+
+    judge.checker_config.pass_case_index = false;
+    reset_checker_output();
+    run_testset(&judge, &TestsetHooks::default()).unwrap();
+    assert_eq!(
+        std::fs::read_to_string(&checker_output_path).unwrap(),
+        "case unset\ncase unset\n"
+    );
+
+    // The interactor is told as well
+    judge.checker_config.pass_case_index = true;
+    let mut runner_config = JudgeConfig {
+        checker: judge.checker_config.clone(),
+        ..build_test_config(program_executor)
+    };
+    runner_config.test_data.case_index = Some(2);
+    let interactor_executor = Executor::new(
+        Language::Cpp,
+        PathBuf::from(TEST_DATA_PATH)
+            .join("built-in-programs/build/src/checkers/interactor-case-index"),
+    )
+    .unwrap();
+    let transcript_path = PathBuf::from(TEST_TEMP_PATH).join("interact_case_index.out");
+    reset_checker_output();
+    let result = run_interact(&runner_config, interactor_executor, &transcript_path)
+        .unwrap()
+        .expect("Not a fork child process");
+    assert_eq!(result.verdict, JudgeVerdict::Accepted);
+    assert_eq!(
+        std::fs::read_to_string(&checker_output_path).unwrap(),
+        "case 2\n"
+    );
+    assert!(std::fs::read_to_string(&transcript_path)
+        .unwrap()
+        .contains("Hello! 2"));
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_interactor_exit_policy() {
+    init();
+    let program_executor = Executor::new(
+        Language::Cpp,
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/read_and_write"),
+    )
+    .unwrap();
+    let interactor_executor = Executor::new(
+        Language::Cpp,
+        PathBuf::from(TEST_DATA_PATH)
+            .join("built-in-programs/build/src/checkers/interactor-exit-code"),
+    )
+    .unwrap();
+    let broken_checker = built_in_checker("checker-unknown-exit");
+    let transcript_path = PathBuf::from(TEST_TEMP_PATH).join("interact_exit_code.out");
+    let mut config = build_test_config(program_executor);
+    config.checker.interactor_policy = Some(Arc::new(TestlibInteractorPolicy));
+    let run_with_exit_code = |config: &mut JudgeConfig, code: i32| {
+        let input_path = PathBuf::from(TEST_TEMP_PATH).join(format!("interactor_exit_{}.in", code));
+        std::fs::write(&input_path, code.to_string()).unwrap();
+        config.test_data.input_file_path = input_path;
+        run_interact(config, interactor_executor.clone(), &transcript_path)
+            .unwrap()
+            .expect("Not a fork child process")
+    };
+
+    let cases = [
+        (0, JudgeVerdict::Accepted, None),
+        (1, JudgeVerdict::WrongAnswer, Some("WA_INTERACTOR")),
+        (2, JudgeVerdict::WrongAnswer, Some("WA_PRESENTATION")),
+        (3, JudgeVerdict::SystemError, Some("SE_INTERACTOR_FAIL")),
+        (7, JudgeVerdict::PartialScore, Some("INTERACTOR_POINTS")),
+        (42, JudgeVerdict::SystemError, Some("SE_INTERACTOR_EXIT")),
+        (
+            -libc::SIGSEGV,
+            JudgeVerdict::SystemError,
+            Some("SE_INTERACTOR_SIGNAL_SIGSEGV"),
+        ),
+    ];
+    for (code, verdict, reason) in cases {
+        let result = run_with_exit_code(&mut config, code);
+        assert_eq!(result.verdict, verdict, "exit code {}", code);
+        assert_eq!(result.reason_code.as_deref(), reason, "exit code {}", code);
+        assert_eq!(
+            result.decided_by,
+            Some(DecidedBy::Interactor(interactor_executor.path.clone()))
+        );
+    }
+
+    // A checker has the last word when the interactor accepts
+    config.checker.executor = Some(broken_checker);
+    let result = run_with_exit_code(&mut config, 0);
+    assert_eq!(result.reason_code.as_deref(), Some("SE_CHECKER_EXIT"));
+    let result = run_with_exit_code(&mut config, 1);
+    assert_eq!(result.reason_code.as_deref(), Some("WA_INTERACTOR"));
+
+    // Without a policy the exit code of the interactor is not looked at
+    config.checker.interactor_policy = None;
+    let result = run_with_exit_code(&mut config, 1);
+    assert_eq!(result.reason_code.as_deref(), Some("SE_CHECKER_EXIT"));
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_run_interact_phases() {
+    init();
+    let checkers_path = PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/checkers");
+    let echo = Executor::new(Language::Cpp, checkers_path.join("interactor-echo")).unwrap();
+    let exit_code =
+        Executor::new(Language::Cpp, checkers_path.join("interactor-exit-code")).unwrap();
+    let mut program_executor = Executor::new(
+        Language::Cpp,
+        PathBuf::from(TEST_DATA_PATH)
+            .join("built-in-programs/build/src/programs/read_and_write_rounds"),
+    )
+    .unwrap();
+    program_executor.set_program_args(vec!["2".to_string()]);
+    let mut config = build_test_config(program_executor);
+    config.checker.interactor_policy = Some(Arc::new(TestlibInteractorPolicy));
+    let transcript_path = PathBuf::from(TEST_TEMP_PATH).join("interact_phases.out");
+    let run = |config: &JudgeConfig, phases: &[Executor]| {
+        run_interact_phases(config, phases, &transcript_path)
+            .unwrap()
+            .expect("Not a fork child process")
+    };
+
+    // The user program answers each interactor in turn
+    let result = run(&config, &[echo.clone(), echo.clone()]);
+    assert_eq!(result.verdict, JudgeVerdict::Accepted);
+    assert!(result.interactor_time_usage.is_some());
+    assert_eq!(
+        std::fs::read_to_string(&transcript_path).unwrap(),
+        "world!\nHello! world!\nworld!\nHello! world!\n"
+    );
+
+    // It leaves in the second phase without answering
+    config
+        .program
+        .executor
+        .set_program_args(vec!["1".to_string(), "leave".to_string()]);
+    let result = run(&config, &[echo.clone(), echo.clone()]);
+    assert_eq!(result.verdict, JudgeVerdict::WrongAnswer);
+    assert_eq!(
+        result.reason_code.as_deref(),
+        Some("WA_UNFINISHED_INTERACTION")
+    );
+    assert_eq!(result.message, "The interaction ended in phase 2 of 2");
+
+    // The interactor of the first phase rejects, the user program waiting for the second
+    // one is killed
+    config
+        .program
+        .executor
+        .set_program_args(vec!["2".to_string()]);
+    let input_path = PathBuf::from(TEST_TEMP_PATH).join("interact_phases.in");
+    std::fs::write(&input_path, "1").unwrap();
+    config.test_data.input_file_path = input_path;
+    let result = run(&config, &[exit_code.clone(), echo.clone()]);
+    assert_eq!(result.reason_code.as_deref(), Some("WA_INTERACTOR"));
+    config.checker.interactor_policy = None;
+    let result = run(&config, &[exit_code, echo]);
+    assert_eq!(
+        result.reason_code.as_deref(),
+        Some("WA_UNFINISHED_INTERACTION")
+    );
+    assert_eq!(result.message, "The interaction ended in phase 1 of 2");
+
+    assert!(matches!(
+        run_interact_phases(&config, &[], &transcript_path),
+        Err(JudgeCoreError::InteractorNotConfigured)
+    ));
+}
+
+#[test]
+fn test_verdict_priority() {
+    let verdicts = [
+        JudgeVerdict::Accepted,
+        JudgeVerdict::WrongAnswer,
+        JudgeVerdict::TimeLimitExceeded,
+        JudgeVerdict::PartialScore,
+        JudgeVerdict::RuntimeError,
+    ];
+    let priority = VerdictPriority::default();
+    assert_eq!(priority.aggregate(&verdicts), JudgeVerdict::RuntimeError);
+    assert_eq!(
+        priority.aggregate(&verdicts[..4]),
+        JudgeVerdict::TimeLimitExceeded
+    );
+    assert_eq!(
+        priority.aggregate(&[JudgeVerdict::SystemError, JudgeVerdict::CompileError]),
+        JudgeVerdict::CompileError
+    );
+    assert_eq!(
+        priority.aggregate(&[JudgeVerdict::PartialScore, JudgeVerdict::Accepted]),
+        JudgeVerdict::PartialScore
+    );
+    assert_eq!(priority.aggregate(&[]), JudgeVerdict::Accepted);
+
+    // Cases not run count neither as a pass nor as a failure
+    assert_eq!(
+        priority.aggregate(&[JudgeVerdict::NotRun, JudgeVerdict::Accepted]),
+        JudgeVerdict::Accepted
+    );
+    assert_eq!(
+        priority.aggregate(&[JudgeVerdict::WrongAnswer, JudgeVerdict::NotRun]),
+        JudgeVerdict::WrongAnswer
+    );
+    assert_eq!(
+        priority.aggregate(&[JudgeVerdict::NotRun, JudgeVerdict::NotRun]),
+        JudgeVerdict::NotRun
+    );
+
+    // Unlisted verdicts come last, equals are kept in order
+    let wrong_answer_first = VerdictPriority {
+        order: vec![JudgeVerdict::WrongAnswer, JudgeVerdict::TimeLimitExceeded],
+    };
+    assert_eq!(
+        wrong_answer_first.aggregate(&verdicts),
+        JudgeVerdict::WrongAnswer
+    );
+    assert_eq!(
+        wrong_answer_first.aggregate(&[JudgeVerdict::RuntimeError, JudgeVerdict::Accepted]),
+        JudgeVerdict::RuntimeError
+    );
+}
+
+#[test]
+fn test_aggregate_testset_verdict() {
+    init();
+    let program_executor = Executor::new(
+        Language::Cpp,
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/read_and_write"),
+    )
+    .unwrap();
+    let mut judge = build_test_testset(program_executor);
+    let priority = VerdictPriority::default();
+    let results = run_testset(&judge, &TestsetHooks::default()).unwrap();
+    assert_eq!(
+        aggregate_verdict(&results, &priority),
+        JudgeVerdict::Accepted
+    );
+
+    let wrong_answer_path = PathBuf::from(TEST_TEMP_PATH).join("aggregate_wrong.ans");
+    std::fs::write(&wrong_answer_path, "Goodbye!\n").unwrap();
+    judge.testdata_configs[1].answer_file_path = wrong_answer_path;
+    let results = run_testset(&judge, &TestsetHooks::default()).unwrap();
+    assert_eq!(
+        aggregate_verdict(&results, &priority),
+        JudgeVerdict::WrongAnswer
+    );
+}
+
+#[test]
+fn test_run_testset_iter() {
+    init();
+    let program_executor = Executor::new(
+        Language::Cpp,
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/sleep"),
+    )
+    .unwrap();
+    let mut judge = build_test_testset(program_executor);
+    judge.runtime_config.wall_time_limit = Some(Duration::from_millis(500));
+
+    let finished = RefCell::new(vec![]);
+    let hooks = TestsetHooks {
+        after_case: Some(Box::new(|idx, _| finished.borrow_mut().push(idx))),
+        ..Default::default()
+    };
+    let start = std::time::Instant::now();
+    // The second case is never started, the iterator being dropped first
+    let first = run_testset_iter(&judge, &hooks).next().unwrap().unwrap();
+    assert_eq!(first.verdict, JudgeVerdict::TimeLimitExceeded);
+    assert!(start.elapsed() < Duration::from_millis(1000));
+    assert_eq!(*finished.borrow(), vec![0]);
+
+    judge.judge_type = JudgeType::INTERACT;
+    let mut results = run_testset_iter(&judge, &hooks);
+    assert!(matches!(
+        results.next(),
+        Some(Err(JudgeCoreError::InteractorNotConfigured))
+    ));
+    assert!(results.next().is_none());
+}
+
+#[test]
+fn test_run_testset_time_budget() {
+    init();
+    let program_executor = Executor::new(
+        Language::Cpp,
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/sleep"),
+    )
+    .unwrap();
+    let mut judge = build_test_testset(program_executor);
+    judge.runtime_config.wall_time_limit = Some(Duration::from_millis(300));
+    judge.runtime_config.total_time_budget = Some(Duration::from_millis(200));
+
+    // The first case spends the budget, cut short by it, the second one is not run
+    let finished = RefCell::new(vec![]);
+    let hooks = TestsetHooks {
+        after_case: Some(Box::new(|idx, _| finished.borrow_mut().push(idx))),
+        ..Default::default()
+    };
+    let start = std::time::Instant::now();
+    let results = run_testset(&judge, &hooks).unwrap();
+    assert!(start.elapsed() < Duration::from_millis(600));
+    assert_eq!(*finished.borrow(), vec![0, 1]);
+    assert_eq!(results[0].verdict, JudgeVerdict::TimeLimitExceeded);
+    assert_eq!(results[0].reason_code.as_deref(), Some("TIME_BUDGET"));
+    assert!(results[0].time_usage < Duration::from_millis(300));
+    assert_eq!(results[1].verdict, JudgeVerdict::NotRun);
+    assert_eq!(results[1].reason_code.as_deref(), Some("TIME_BUDGET"));
+    assert_eq!(
+        aggregate_verdict(&results, &VerdictPriority::default()),
+        JudgeVerdict::TimeLimitExceeded
+    );
+    assert_eq!(results[1].message, "Total time budget of 200ms exceeded");
+
+    judge.runtime_config.total_time_budget = Some(Duration::from_secs(10));
+    let results = run_testset(&judge, &TestsetHooks::default()).unwrap();
+    assert!(results.iter().all(|result| {
+        result.verdict == JudgeVerdict::TimeLimitExceeded
+            && result.reason_code.as_deref() == Some("TLE_WALL")
+    }));
+}
+
+#[test]
+fn test_run_testset_warmup() {
+    init();
+    let program_executor = Executor::new(
+        Language::Cpp,
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/read_and_write"),
+    )
+    .unwrap();
+    let mut judge = build_test_testset(program_executor);
+    judge.program_config.output_file_path =
+        Some(PathBuf::from(TEST_TEMP_PATH).join("testset_warmup.out"));
+    let warmup_input = judge.testdata_configs[0].input_file_path.clone();
+    judge.runtime_config.warmup_input = Some(warmup_input);
+    let cold = build_exit_info(Duration::from_millis(1500));
+    let warm = build_exit_info(Duration::from_millis(400));
+
+    // The cold run is the discarded warmup, its output included
+    MOCK_RUNS.with(|mock_runs| {
+        *mock_runs.borrow_mut() = vec![
+            ("Cold output\n", cold),
+            ("Hello! world!\n", warm.clone()),
+            ("Hello! oj-lab!\n", warm),
+        ]
+        .into()
+    });
+    let results = run_testset_with::<MockSandbox>(&judge, &TestsetHooks::default()).unwrap();
+    assert!(MOCK_RUNS.with(|mock_runs| mock_runs.borrow().is_empty()));
+    assert!(results
+        .iter()
+        .all(|result| result.verdict == JudgeVerdict::Accepted));
+
+    let results = run_testset(&judge, &TestsetHooks::default()).unwrap();
+    assert!(results
+        .iter()
+        .all(|result| result.verdict == JudgeVerdict::Accepted));
+}
+
+#[test]
+fn test_run_testset_progress() {
+    init();
+    let program_executor = Executor::new(
+        Language::Cpp,
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/read_and_write"),
+    )
+    .unwrap();
+    let judge = build_test_testset(program_executor);
+
+    let (sender, receiver) = mpsc::channel();
+    let hooks = TestsetHooks {
+        progress: Some(sender),
+        ..Default::default()
+    };
+    let results = run_testset(&judge, &hooks).unwrap();
+    drop(hooks);
+    let progress: Vec<JudgeProgress> = receiver.iter().collect();
+    assert_eq!(progress.len(), results.len());
+    for (idx, (progress, result)) in progress.iter().zip(results.iter()).enumerate() {
+        assert_eq!(progress.case_index, idx);
+        assert_eq!(progress.total, 2);
+        assert_eq!(progress.verdict, result.verdict);
+    }
+}
+
+#[test]
+fn test_run_testset_case_filter() {
+    init();
+    let program_executor = Executor::new(
+        Language::Cpp,
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/read_and_write"),
+    )
+    .unwrap();
+    let judge = build_test_testset(program_executor);
+
+    let asked = RefCell::new(vec![]);
+    let (sender, receiver) = mpsc::channel();
+    let hooks = TestsetHooks {
+        cases: Some(CaseFilter::Indices(std::collections::BTreeSet::from([1]))),
+        before_case: Some(Box::new(|idx, _| {
+            asked.borrow_mut().push(idx);
+            CaseAction::Run
+        })),
+        progress: Some(sender),
+        ..Default::default()
+    };
+    let results = run_testset(&judge, &hooks).unwrap();
+    drop(hooks);
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0].verdict, JudgeVerdict::NotRun);
+    assert_eq!(results[0].reason_code.as_deref(), Some("FILTERED_OUT"));
+    assert_eq!(results[1].verdict, JudgeVerdict::Accepted);
+    assert_eq!(*asked.borrow(), vec![1]);
+    let progress: Vec<JudgeProgress> = receiver.iter().collect();
+    assert_eq!(progress.len(), 2);
+    assert_eq!(progress[0].verdict, JudgeVerdict::NotRun);
+    assert_eq!(progress[1].case_index, 1);
+    assert_eq!(progress[1].total, 2);
+
+    let hooks = TestsetHooks {
+        cases: Some(CaseFilter::Range(0..=0)),
+        ..Default::default()
+    };
+    let verdicts: Vec<JudgeVerdict> = run_testset_iter(&judge, &hooks)
+        .map(|result| result.unwrap().verdict)
+        .collect();
+    assert_eq!(verdicts, vec![JudgeVerdict::Accepted, JudgeVerdict::NotRun]);
+    let hooks = TestsetHooks {
+        cases: Some(CaseFilter::Range(2..=5)),
+        ..Default::default()
+    };
+    let results = run_testset(&judge, &hooks).unwrap();
+    assert!(results
+        .iter()
+        .all(|result| result.verdict == JudgeVerdict::NotRun));
+    assert_eq!(
+        aggregate_verdict(&results, &VerdictPriority::default()),
+        JudgeVerdict::NotRun
+    );
+}
+
+#[test]
+fn test_build_icpc() {
+    init();
+    let builder = JudgeBuilder::new(JudgeBuilderInput {
+        package_type: PackageType::ICPC,
+        package_path: PathBuf::from(TEST_DATA_PATH).join("packages/icpc/hello_world"),
+        runtime_path: PathBuf::from(TEST_TEMP_PATH).join("hello_world"),
+        src_language: Language::Cpp,
+        src_path: PathBuf::from(TEST_DATA_PATH)
+            .join("built-in-programs/src/programs/read_and_write.cpp"),
+        checker: None,
+    })
+    .unwrap();
+    log::info!("builder: {:?}", builder);
+    assert!(builder.compile_time > Duration::ZERO);
+    for idx in 0..builder.testdata_configs.len() {
+        log::info!("runing testdata {}", idx);
+        let judge_config = JudgeConfig {
+            test_data: builder.testdata_configs[idx].clone(),
+            program: builder.program_config.clone(),
+            checker: builder.checker_config.clone(),
+            runtime: builder.runtime_config.clone(),
+            setup: builder.setup_config.clone(),
+            reference: builder.reference_config.clone(),
+        };
+
+        let res = run_judge(&judge_config);
+        match res {
+            Ok(info) => log::info!("{:?}", info),
+            Err(e) => panic!("{:?}", e),
+        }
+    }
+}
+
+#[test]
+fn test_package_checker_directives() {
+    init();
+    let package_path = PathBuf::from(TEST_DATA_PATH).join("packages/icpc/float_eps");
+    let agent = PackageType::ICPC
+        .get_package_agent(package_path.clone())
+        .unwrap();
+    let checker = agent
+        .load_checker(PathBuf::from(TEST_TEMP_PATH).join("float_eps_checker.out"))
+        .unwrap();
+    assert_eq!(
+        checker.comparison,
+        OutputComparison::Tokens(TokenComparison::Float(FloatComparison::new(1e-4)))
+    );
+    assert_eq!(checker.decided_by(), DecidedBy::BuiltinFloat);
+
+    let program_path =
+        PathBuf::from(TEST_DATA_PATH).join("built-in-programs/build/src/programs/read_and_write");
+    let mut runner_config = build_test_config(Executor::new(Language::Cpp, program_path).unwrap());
+    runner_config.program.output_file_path =
+        Some(PathBuf::from(TEST_TEMP_PATH).join("float_eps.out"));
+    runner_config.test_data.input_file_path = package_path.join("data/secret/0.in");
+    runner_config.test_data.answer_file_path = package_path.join("data/secret/0.ans");
+    runner_config.checker = checker;
+    let exited = build_exit_info(Duration::from_millis(100));
+    let result = judge_with_mock_runs(&runner_config, vec![("0.33333333\n", exited.clone())]);
+    assert_eq!(result.verdict, JudgeVerdict::Accepted);
+    let result = judge_with_mock_runs(&runner_config, vec![("0.334\n", exited)]);
+    assert_eq!(result.verdict, JudgeVerdict::WrongAnswer);
+
+    let load_with_directives = |directives: &str| {
+        let package_path = PathBuf::from(TEST_TEMP_PATH).join("checker_directives");
+        std::fs::create_dir_all(&package_path).unwrap();
+        std::fs::write(package_path.join("checker.txt"), directives).unwrap();
+        PackageType::ICPC
+            .get_package_agent(package_path)
+            .unwrap()
+            .load_checker(PathBuf::from(TEST_TEMP_PATH).join("checker_directives.out"))
+    };
+    let checker = load_with_directives("checker wcmp\n\ncomparison unordered_lines\n").unwrap();
+    assert_eq!(checker.comparison, OutputComparison::UnorderedLines);
+    for directives in [
+        "float tolerance=1e-4",
+        "float eps=x",
+        "comparison fuzzy",
+        "wcmp",
+    ] {
+        assert!(matches!(
+            load_with_directives(directives),
+            Err(JudgeCoreError::InvalidConfig(message)) if message.contains("line 1")
+        ));
+    }
+
+    // An output validator checks the outputs, directives can't say otherwise
+    let package_path = PathBuf::from(TEST_TEMP_PATH).join("checker_directives_validator");
+    std::fs::create_dir_all(package_path.join("output_validators")).unwrap();
+    std::fs::write(
+        package_path.join("checker.txt"),
+        "# tolerance\nfloat eps=1e-4\n",
+    )
+    .unwrap();
+    assert!(matches!(
+        PackageType::ICPC
+            .get_package_agent(package_path)
+            .unwrap()
+            .load_checker(PathBuf::from(TEST_TEMP_PATH).join("checker_directives.out")),
+        Err(JudgeCoreError::InvalidConfig(message)) if message.contains("output validator")
+    ));
+}
+
+#[test]
+fn test_testset_from_dir() {
+    init();
+    let dir = PathBuf::from(TEST_TEMP_PATH).join("testset_from_dir");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    for stem in ["10", "9", "1", "sample"] {
+        std::fs::write(dir.join(format!("{}.in", stem)), "").unwrap();
+        std::fs::write(dir.join(format!("{}.out", stem)), "").unwrap();
+    }
+    std::fs::write(dir.join("README"), "").unwrap();
+
+    let testset = TestSet::from_dir(&dir, "in", "out").unwrap();
+    let stems: Vec<_> = testset
+        .cases
+        .iter()
+        .map(|case| case.input_file_path.file_stem().unwrap().to_owned())
+        .collect();
+    assert_eq!(stems, ["1", "9", "10", "sample"]);
+    assert_eq!(testset.cases[2].answer_file_path, dir.join("10.out"));
+
+    std::fs::write(dir.join("11.in"), "").unwrap();
+    assert!(TestSet::from_dir(&dir, "in", "out").is_err());
 }
@@ -0,0 +1,131 @@
+use std::{os::unix::io::AsRawFd, thread, time::Duration};
+
+use judge_core::error::JudgeCoreError;
+use judge_core::run::process_listener::{
+    encode_exit_message, read_exit_message, read_exit_message_with_limit, ProcessExitMessage,
+    MAX_EXIT_MESSAGE_DEPTH, MAX_EXIT_MESSAGE_LEN,
+};
+use nix::unistd::{pipe, write};
+use std::os::fd::OwnedFd;
+
+fn init() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+#[test]
+fn test_read_exit_message_in_chunks() {
+    init();
+    let (exit_read, exit_write) = pipe().unwrap();
+    let buf = encode_exit_message(&ProcessExitMessage {
+        exit_signal: 41,
+        option_run_result: None,
+    })
+    .unwrap();
+
+    let writer = thread::spawn(move || {
+        let (first, second) = buf.split_at(buf.len() / 2);
+        write(&exit_write, first).unwrap();
+        thread::sleep(Duration::from_millis(100));
+        write(&exit_write, second).unwrap();
+    });
+
+    let msg = read_exit_message(exit_read.as_raw_fd()).unwrap();
+    writer.join().unwrap();
+    assert_eq!(msg.exit_signal, 41);
+    assert!(msg.option_run_result.is_none());
+}
+
+#[test]
+fn test_read_exit_message_truncated() {
+    init();
+    let (exit_read, exit_write) = pipe().unwrap();
+    let buf = encode_exit_message(&ProcessExitMessage {
+        exit_signal: 42,
+        option_run_result: None,
+    })
+    .unwrap();
+    write(&exit_write, &buf[..buf.len() - 1]).unwrap();
+    drop(exit_write);
+
+    assert!(read_exit_message(exit_read.as_raw_fd()).is_err());
+}
+
+#[test]
+fn test_read_exit_message_oversized() {
+    init();
+    // Only the header is sent, the message it announces isn't waited for
+    let (exit_read, exit_write) = pipe().unwrap();
+    write(&exit_write, &u32::MAX.to_be_bytes()).unwrap();
+    let err = read_exit_message(exit_read.as_raw_fd()).unwrap_err();
+    assert!(
+        format!("{:?}", err).contains("exceeds the limit"),
+        "{:?}",
+        err
+    );
+
+    let buf = encode_exit_message(&ProcessExitMessage {
+        exit_signal: 43,
+        option_run_result: None,
+    })
+    .unwrap();
+    write(&exit_write, &buf).unwrap();
+    assert!(read_exit_message_with_limit(exit_read.as_raw_fd(), buf.len() - 5).is_err());
+}
+
+/// A pipe holding `payload` framed as an exit message, returning its read end.
+fn send_payload(payload: &[u8]) -> OwnedFd {
+    let (exit_read, exit_write) = pipe().unwrap();
+    write(&exit_write, &(payload.len() as u32).to_be_bytes()).unwrap();
+    write(&exit_write, payload).unwrap();
+    exit_read
+}
+
+fn read_malformed(exit_read: &OwnedFd) -> String {
+    match read_exit_message(exit_read.as_raw_fd()) {
+        Err(JudgeCoreError::MalformedExitReport(reason)) => reason,
+        other => panic!("Expected a malformed exit report, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_read_exit_message_deeply_nested() {
+    init();
+    let nested = format!(
+        "{{\"exit_signal\":41,\"option_run_result\":{}{}}}",
+        "[".repeat(10000),
+        "]".repeat(10000)
+    );
+    let reason = read_malformed(&send_payload(nested.as_bytes()));
+    assert!(reason.contains("nests deeper"), "{}", reason);
+
+    // Brackets in strings don't count, unknown fields are ignored
+    let in_string = format!(
+        "{{\"exit_signal\":41,\"option_run_result\":null,\"note\":\"\\\"{}\"}}",
+        "[".repeat(MAX_EXIT_MESSAGE_DEPTH * 2)
+    );
+    let msg = read_exit_message(send_payload(in_string.as_bytes()).as_raw_fd()).unwrap();
+    assert_eq!(msg.exit_signal, 41);
+}
+
+#[test]
+fn test_read_exit_message_malformed() {
+    init();
+    let reason = read_malformed(&send_payload(b"{\"exit_signal\":"));
+    assert!(reason.contains("not valid"), "{}", reason);
+    read_malformed(&send_payload(b"\xff\xfe"));
+    read_malformed(&send_payload(
+        b"{\"exit_signal\":4100,\"option_run_result\":null}",
+    ));
+
+    // Refused before it is read, the writer is left blocked on the full pipe
+    let (exit_read, exit_write) = pipe().unwrap();
+    let oversized = vec![b'['; MAX_EXIT_MESSAGE_LEN + 1];
+    let writer = thread::spawn(move || {
+        write(&exit_write, &(oversized.len() as u32).to_be_bytes()).unwrap();
+        let _ = write(&exit_write, &oversized);
+    });
+    let reason = read_malformed(&exit_read);
+    assert!(reason.contains("exceeds the limit"), "{}", reason);
+    drop(exit_read);
+    writer.join().unwrap();
+}
@@ -0,0 +1,533 @@
+use std::{fs, io::BufReader, path::PathBuf, time::Duration};
+
+use judge_core::utils::{
+    compare_bytes, compare_files, compare_files_by_tokens, compare_line_tokens, compare_text_files,
+    compare_unordered_lines, deserialize_duration, deserialize_memory, diff_files, generate_diff,
+    parse_duration, parse_memory, sha256_fields_and_file, sha256_file, FloatComparison, LineEnding,
+    Normalizer, OutputComparison, TextPolicy, TokenComparison, TokenReader,
+};
+use serde_derive::Deserialize;
+
+const TEST_TEMP_PATH: &str = "tests/temp";
+
+fn write_temp_file(name: &str, content: impl AsRef<[u8]>) -> PathBuf {
+    let path = PathBuf::from(TEST_TEMP_PATH).join(name);
+    fs::create_dir_all(TEST_TEMP_PATH).unwrap();
+    fs::write(&path, content).unwrap();
+    path
+}
+
+#[test]
+fn test_compare_sort_lines() {
+    let output = write_temp_file("sort_lines.out", "3 4\n1 2  \n");
+    let answer = write_temp_file("sort_lines.ans", "1 2\n3 4\n\n");
+    assert!(!compare_files(&output, &answer, &[]));
+    assert!(compare_files(&output, &answer, &[Normalizer::SortLines]));
+}
+
+#[test]
+fn test_compare_blank_lines() {
+    let compare = |output: &str, answer: &str| {
+        let output = write_temp_file("blank_lines.out", output);
+        let answer = write_temp_file("blank_lines.ans", answer);
+        compare_files(&output, &answer, &[])
+    };
+    // Interior blank lines are significant
+    assert!(compare("1\n\n2\n", "1\n\n2\n"));
+    assert!(!compare("1\n2\n", "1\n\n2\n"));
+    assert!(!compare("1\n\n\n2\n", "1\n\n2\n"));
+    assert!(!compare("\n1\n", "1\n"));
+    // Lines are not joined together
+    assert!(!compare("12\n", "1\n2\n"));
+    // The whitespace ending each line is not significant
+    assert!(compare("1 \n\t\n2\n", "1\n\n2\n"));
+    assert!(!compare("1 2\n", "1  2\n"));
+    // Trailing blank lines are not, whatever their number on either side
+    assert!(compare("1\n\n2", "1\n\n2\n"));
+    assert!(compare("1\n\n2\n\n\n\n", "1\n\n2\n"));
+    assert!(compare("1\n\n2\n", "1\n\n2\n \n\r\n\n"));
+    assert!(compare("", "\n\n"));
+    assert!(!compare("", "1\n"));
+}
+
+#[test]
+fn test_compare_unordered_lines() {
+    let policy = TextPolicy::default();
+    let compare = |output: &str, answer: &str| {
+        let output = write_temp_file("unordered_lines.out", output);
+        let answer = write_temp_file("unordered_lines.ans", answer);
+        compare_unordered_lines(&output, &answer, &[], &policy).unwrap()
+    };
+    assert!(compare("3 4\n1 2  \n1 2\n", "1 2\n3 4\n1 2\n\n"));
+    // Lines are a multiset, duplicates must match
+    assert!(!compare("1 2\n3 4\n1 2\n", "1 2\n3 4\n"));
+    assert!(!compare("a\na\nb\n", "a\nb\nb\n"));
+    // Lines are not joined together
+    assert!(!compare("ab\n", "a\nb\n"));
+    assert!(compare("", "\n\n"));
+    assert!(!compare("", "a\n"));
+
+    let output = write_temp_file("unordered_lowercase.out", "YES\nno\n");
+    let answer = write_temp_file("unordered_lowercase.ans", "No\nyes\n");
+    assert!(compare_unordered_lines(&output, &answer, &[Normalizer::Lowercase], &policy).unwrap());
+}
+
+#[test]
+fn test_compare_line_tokens() {
+    let compare = |output: &str, answer: &str| {
+        let output = write_temp_file("line_tokens.out", output);
+        let answer = write_temp_file("line_tokens.ans", answer);
+        compare_line_tokens(&output, &answer, &[], &TextPolicy::default()).unwrap()
+    };
+    assert!(compare("1  2\t3 \n4\n", "1 2 3\n4\n\n"));
+    assert!(compare(" 1 2\n", "1 2\n"));
+    // Tokens don't move from a line to another
+    assert!(!compare("1\n2\n", "1 2\n"));
+    assert!(!compare("1 2\n", "12\n"));
+    // Blank lines count like in `compare_files()`
+    assert!(!compare("1\n2\n", "1\n\n2\n"));
+    assert!(compare("1\n \n2\n", "1\n\n2\n\n"));
+}
+
+#[test]
+fn test_compare_round_floats() {
+    let output = write_temp_file("round_floats.out", "3.14159 -0.0001\n");
+    let answer = write_temp_file("round_floats.ans", "3.142  0.000\n");
+    assert!(!compare_files(&output, &answer, &[]));
+    assert!(compare_files(
+        &output,
+        &answer,
+        &[Normalizer::RoundFloats(2)]
+    ));
+    assert!(!compare_files(
+        &output,
+        &answer,
+        &[Normalizer::RoundFloats(4)]
+    ));
+}
+
+#[test]
+fn test_compare_lowercase_then_sort() {
+    let output = write_temp_file("lowercase.out", "YES\nno\n");
+    let answer = write_temp_file("lowercase.ans", "No\nyes\n");
+    assert!(compare_files(
+        &output,
+        &answer,
+        &[Normalizer::Lowercase, Normalizer::SortLines]
+    ));
+}
+
+#[test]
+fn test_compare_first_lines() {
+    let answer = write_temp_file("first_lines.ans", "42\n");
+    let output = write_temp_file("first_lines.out", "42\ndebug: took 3 steps\nmore debug\n");
+    assert!(!compare_files(&output, &answer, &[]));
+    assert!(compare_files(
+        &output,
+        &answer,
+        &[Normalizer::FirstLines(1)]
+    ));
+
+    let wrong = write_temp_file("first_lines_wrong.out", "41\n42\n");
+    assert!(!compare_files(
+        &wrong,
+        &answer,
+        &[Normalizer::FirstLines(1)]
+    ));
+    // Too few lines are not padded
+    let answer = write_temp_file("first_lines_two.ans", "42\n43\n");
+    let short = write_temp_file("first_lines_short.out", "42\n");
+    assert!(!compare_files(
+        &short,
+        &answer,
+        &[Normalizer::FirstLines(2)]
+    ));
+}
+
+#[test]
+fn test_compare_field() {
+    let answer = write_temp_file("field.ans", "1 0.500\n2 0.250\n");
+    let output = write_temp_file("field.out", "case1 0.5001 ok\ncase2 0.2499\ndebug\n");
+    assert!(compare_files(
+        &output,
+        &answer,
+        &[
+            Normalizer::FirstLines(2),
+            Normalizer::Field(1),
+            Normalizer::RoundFloats(3)
+        ]
+    ));
+    assert!(!compare_files(
+        &output,
+        &answer,
+        &[Normalizer::FirstLines(2), Normalizer::Field(0)]
+    ));
+
+    let missing = write_temp_file("field_missing.out", "case1 0.5\ncase2\n");
+    assert!(!compare_files(
+        &missing,
+        &answer,
+        &[Normalizer::Field(1), Normalizer::RoundFloats(3)]
+    ));
+}
+
+#[cfg(feature = "gzip")]
+#[test]
+fn test_compare_gzip_answer() {
+    use flate2::{write::GzEncoder, Compression};
+    use std::io::Write;
+
+    let output = write_temp_file("gzip.out", "1 2\n3 4\n");
+    let answer = PathBuf::from(TEST_TEMP_PATH).join("gzip.ans.gz");
+    let mut encoder = GzEncoder::new(fs::File::create(&answer).unwrap(), Compression::default());
+    encoder.write_all(b"1 2\n3 4\n").unwrap();
+    encoder.finish().unwrap();
+
+    assert!(compare_files(&output, &answer, &[]));
+    let wrong = write_temp_file("gzip_wrong.out", "1 2\n");
+    assert!(!compare_files(&wrong, &answer, &[]));
+}
+
+#[test]
+fn test_parse_memory() {
+    assert_eq!(parse_memory("256MiB").unwrap(), 256 * 1024 * 1024);
+    assert_eq!(parse_memory("256MB").unwrap(), 256 * 1000 * 1000);
+    assert_eq!(parse_memory("1.5GiB").unwrap(), 3 * 512 * 1024 * 1024);
+    assert_eq!(parse_memory(" 64 KiB ").unwrap(), 64 * 1024);
+    assert_eq!(parse_memory("0B").unwrap(), 0);
+    for invalid in [
+        "256",
+        "-1MiB",
+        "256mb",
+        "256Mb",
+        "1.2.3MB",
+        "MiB",
+        "",
+        "99999999TiB",
+    ] {
+        assert!(
+            parse_memory(invalid).is_err(),
+            "{:?} should be rejected",
+            invalid
+        );
+    }
+}
+
+#[test]
+fn test_parse_duration() {
+    assert_eq!(
+        parse_duration("1500ms").unwrap(),
+        Duration::from_millis(1500)
+    );
+    assert_eq!(parse_duration("2s").unwrap(), Duration::from_secs(2));
+    assert_eq!(parse_duration("0.5s").unwrap(), Duration::from_millis(500));
+    assert_eq!(parse_duration("1min").unwrap(), Duration::from_secs(60));
+    assert_eq!(parse_duration("10us").unwrap(), Duration::from_micros(10));
+    for invalid in ["2", "-2s", "2m", "s", "2 seconds"] {
+        assert!(
+            parse_duration(invalid).is_err(),
+            "{:?} should be rejected",
+            invalid
+        );
+    }
+}
+
+#[derive(Deserialize)]
+struct LimitsInput {
+    #[serde(deserialize_with = "deserialize_memory")]
+    memory: u64,
+    #[serde(deserialize_with = "deserialize_duration")]
+    time: Duration,
+}
+
+#[test]
+fn test_deserialize_limits() {
+    let limits: LimitsInput =
+        serde_json::from_str(r#"{"memory": "256MiB", "time": "1500ms"}"#).unwrap();
+    assert_eq!(limits.memory, 256 * 1024 * 1024);
+    assert_eq!(limits.time, Duration::from_millis(1500));
+
+    let limits: LimitsInput = serde_json::from_str(r#"{"memory": 1024, "time": "1s"}"#).unwrap();
+    assert_eq!(limits.memory, 1024);
+
+    assert!(serde_json::from_str::<LimitsInput>(r#"{"memory": "-1MB", "time": "1s"}"#).is_err());
+    assert!(serde_json::from_str::<LimitsInput>(r#"{"memory": "1MB", "time": 1}"#).is_err());
+}
+
+#[test]
+fn test_compare_collapse_internal_whitespace() {
+    let output = write_temp_file("collapse.out", "1  2\t 3 \n\tfoo   bar\t\n");
+    let answer = write_temp_file("collapse.ans", "1 2 3\n foo bar\n");
+    // Strict comparison sees the spacing
+    assert!(!compare_files(&output, &answer, &[]));
+    assert!(compare_files(
+        &output,
+        &answer,
+        &[Normalizer::CollapseInternalWhitespace]
+    ));
+
+    // Unlike tokens, lines must still match
+    let rewrapped = write_temp_file("collapse_rewrapped.ans", "1 2\n3 foo bar\n");
+    assert!(compare_files_by_tokens(&output, &rewrapped, TokenComparison::Exact).unwrap());
+    assert!(!compare_files(
+        &output,
+        &rewrapped,
+        &[Normalizer::CollapseInternalWhitespace]
+    ));
+}
+
+#[test]
+fn test_token_reader_buffer_boundaries() {
+    // Every token straddles a boundary of the 4 byte buffer
+    let content = "  123456 7.5\n\n  abcdefgh\t9";
+    let tokens: Vec<Vec<u8>> = TokenReader::new(BufReader::with_capacity(4, content.as_bytes()))
+        .collect::<Result<_, _>>()
+        .unwrap();
+    let expected: Vec<&[u8]> = vec![b"123456", b"7.5", b"abcdefgh", b"9"];
+    assert_eq!(tokens, expected);
+}
+
+#[test]
+fn test_compare_files_by_tokens() {
+    // The second number straddles the first 64 KiB read of the output
+    let boundary = 64 * 1024;
+    let padding = " ".repeat(boundary - 4);
+    let output = write_temp_file(
+        "tokens.out",
+        format!("1{}3.14159265\n{}\n", padding, "2 ".repeat(1000)),
+    );
+    let answer = write_temp_file(
+        "tokens.ans",
+        format!("1 3.14159266\n{}", "2\n".repeat(1000)),
+    );
+    assert!(!compare_files_by_tokens(&output, &answer, TokenComparison::Exact).unwrap());
+    assert!(compare_files_by_tokens(
+        &output,
+        &answer,
+        TokenComparison::Float(FloatComparison::new(1e-6))
+    )
+    .unwrap());
+    assert!(!compare_files_by_tokens(
+        &output,
+        &answer,
+        TokenComparison::Float(FloatComparison::new(1e-9))
+    )
+    .unwrap());
+
+    let exact = write_temp_file(
+        "tokens_exact.ans",
+        fs::read_to_string(&output).unwrap().replace(' ', "\n"),
+    );
+    assert!(compare_files_by_tokens(&output, &exact, TokenComparison::Exact).unwrap());
+    // A missing token is a mismatch
+    let short = write_temp_file(
+        "tokens_short.ans",
+        format!("1 3.14159265\n{}", "2\n".repeat(999)),
+    );
+    assert!(!compare_files_by_tokens(&output, &short, TokenComparison::Exact).unwrap());
+}
+
+#[test]
+fn test_float_comparison_special_values() {
+    let comparison = FloatComparison::new(1e-6);
+    assert!(comparison.matches(f64::INFINITY, f64::INFINITY));
+    assert!(comparison.matches(f64::NEG_INFINITY, f64::NEG_INFINITY));
+    assert!(!comparison.matches(f64::INFINITY, f64::NEG_INFINITY));
+    assert!(!comparison.matches(f64::MAX, f64::INFINITY));
+    assert!(!comparison.matches(f64::NAN, f64::NAN));
+    assert!(!comparison.matches(1.0, f64::NAN));
+    assert!(comparison.matches(-0.0, 0.0));
+    assert!(comparison.matches(0.0, -0.0));
+
+    let strict = FloatComparison {
+        infinities_match: false,
+        ..comparison
+    };
+    assert!(!strict.matches(f64::INFINITY, f64::INFINITY));
+    let lenient = FloatComparison {
+        nan_matches_nan: true,
+        ..comparison
+    };
+    assert!(lenient.matches(f64::NAN, f64::NAN));
+    assert!(!lenient.matches(0.0, f64::NAN));
+
+    // Spelled differently, the tokens are still the same numbers
+    let output = write_temp_file("tokens_special.out", "inf -Infinity nan -0\n");
+    let answer = write_temp_file("tokens_special.ans", "infinity -inf NaN 0.0\n");
+    assert!(
+        !compare_files_by_tokens(&output, &answer, TokenComparison::Float(comparison)).unwrap()
+    );
+    assert!(compare_files_by_tokens(&output, &answer, TokenComparison::Float(lenient)).unwrap());
+    // Equal bytes don't make a NaN match
+    assert!(
+        !compare_files_by_tokens(&output, &output, TokenComparison::Float(comparison)).unwrap()
+    );
+    assert!(compare_files_by_tokens(&output, &output, TokenComparison::Exact).unwrap());
+}
+
+#[test]
+fn test_compare_bytes() {
+    // Not UTF-8, and longer than one read of the buffers
+    let content: Vec<u8> = (0..100_000u32).map(|i| (i * 7 % 256) as u8).collect();
+    let output = write_temp_file("bytes.out", &content);
+    let same = write_temp_file("bytes_same.ans", &content);
+    assert_eq!(compare_bytes(&output, &same).unwrap(), None);
+
+    let mut differing = content.clone();
+    differing[70_000] ^= 0xff;
+    let differing = write_temp_file("bytes_differing.ans", &differing);
+    assert_eq!(compare_bytes(&output, &differing).unwrap(), Some(70_000));
+
+    let truncated = write_temp_file("bytes_truncated.ans", &content[..65_536]);
+    assert_eq!(compare_bytes(&output, &truncated).unwrap(), Some(65_536));
+    assert_eq!(compare_bytes(&truncated, &output).unwrap(), Some(65_536));
+}
+
+#[test]
+fn test_compare_crlf_answer() {
+    let output = write_temp_file("crlf.out", "1 2\n3\n");
+    let answer = write_temp_file("crlf.ans", "\u{feff}1 2\r\n3\r\n");
+    let old_mac = write_temp_file("cr.ans", "1 2\r3\r");
+    assert!(compare_files(&output, &answer, &[]));
+    assert!(compare_files(&output, &old_mac, &[]));
+
+    let strict = TextPolicy {
+        encoding: None,
+        line_ending: LineEnding::Lf,
+    };
+    assert!(!compare_text_files(&output, &old_mac, &[], &strict).unwrap());
+    let crlf_output = write_temp_file("crlf_output.out", "1 2\r\n3\r\n");
+    assert!(compare_text_files(&crlf_output, &answer, &[], &strict).unwrap());
+    // A `\r` ending a line is not trimmed like other whitespace
+    let crlf_first = write_temp_file("crlf_first.out", "1 2\r\n3\n");
+    assert!(!compare_text_files(&crlf_first, &output, &[], &strict).unwrap());
+}
+
+#[test]
+fn test_compare_invalid_utf8() {
+    // "こんにちは" in Shift-JIS
+    let shift_jis = [
+        0x82, 0xb1, 0x82, 0xf1, 0x82, 0xc9, 0x82, 0xbf, 0x82, 0xcd, b'\n',
+    ];
+    let output = write_temp_file("invalid_utf8.out", shift_jis);
+    let answer = write_temp_file("invalid_utf8.ans", shift_jis);
+    assert!(!compare_files(&output, &answer, &[]));
+}
+
+#[cfg(feature = "encoding")]
+#[test]
+fn test_compare_shift_jis_answer() {
+    // "こんにちは 42" in Shift-JIS, with CRLF
+    let answer = [
+        0x82, 0xb1, 0x82, 0xf1, 0x82, 0xc9, 0x82, 0xbf, 0x82, 0xcd, b' ', b'4', b'2', b'\r', b'\n',
+    ];
+    let answer = write_temp_file("shift_jis.ans", answer);
+    let matching = write_temp_file("shift_jis.out", &fs::read(&answer).unwrap()[..13]);
+    // "こんにちわ 42"
+    let mut differing = fs::read(&answer).unwrap();
+    differing[9] = 0xed;
+    let differing = write_temp_file("shift_jis_differing.out", differing);
+
+    let policy = TextPolicy {
+        encoding: Some("Shift_JIS".to_string()),
+        line_ending: LineEnding::Lenient,
+    };
+    assert!(compare_text_files(&matching, &answer, &[], &policy).unwrap());
+    assert!(!compare_text_files(&differing, &answer, &[], &policy).unwrap());
+
+    let unknown = TextPolicy {
+        encoding: Some("no-such-encoding".to_string()),
+        line_ending: LineEnding::Lenient,
+    };
+    assert!(compare_text_files(&matching, &answer, &[], &unknown).is_err());
+}
+
+#[cfg(not(feature = "encoding"))]
+#[test]
+fn test_compare_encoding_needs_feature() {
+    let output = write_temp_file("needs_feature.out", "1\n");
+    let policy = TextPolicy {
+        encoding: Some("Shift_JIS".to_string()),
+        line_ending: LineEnding::Lenient,
+    };
+    assert!(compare_text_files(&output, &output, &[], &policy).is_err());
+    let utf8 = TextPolicy {
+        encoding: Some("UTF-8".to_string()),
+        line_ending: LineEnding::Lenient,
+    };
+    assert!(compare_text_files(&output, &output, &[], &utf8).unwrap());
+}
+
+#[test]
+fn test_generate_diff() {
+    assert_eq!(generate_diff("a\nb\n", "a\nb\n", 100), "");
+    assert_eq!(
+        generate_diff("1\n2\nthree\n4\n", "1\n2\n3\n4\n", 100),
+        "--- answer\n+++ output\n@@ -1,4 +1,4 @@\n 1\n 2\n-3\n+three\n 4"
+    );
+    // Only 3 lines of context are kept around each change
+    let answer: String = (1..=20).map(|i| format!("{}\n", i)).collect();
+    let output = answer.replace("\n10\n", "\n").replace("20\n", "20\n21\n");
+    assert_eq!(
+        generate_diff(&output, &answer, 100),
+        "--- answer\n+++ output\n@@ -7,7 +7,6 @@\n 7\n 8\n 9\n-10\n 11\n 12\n 13\n\
+         @@ -18,3 +17,4 @@\n 18\n 19\n 20\n+21"
+    );
+    assert_eq!(
+        generate_diff("", "a\n", 100),
+        "--- answer\n+++ output\n@@ -1,1 +0,0 @@\n-a"
+    );
+}
+
+#[test]
+fn test_generate_diff_truncated() {
+    let answer = "0\n".repeat(1000);
+    let output = "1\n".repeat(1000);
+    let diff = generate_diff(&output, &answer, 10);
+    let lines: Vec<&str> = diff.lines().collect();
+    assert_eq!(lines.len(), 11);
+    assert_eq!(
+        &lines[..3],
+        ["--- answer", "+++ output", "@@ -1,1000 +1,1000 @@"]
+    );
+    assert_eq!(lines[10], "... 1993 more lines of diff truncated");
+}
+
+#[test]
+fn test_diff_files() {
+    let policy = TextPolicy::default();
+    let output = write_temp_file("diff.out", "YES\n1.0000001 2\n");
+    let answer = write_temp_file("diff.ans", "yes\n1 3\n");
+    let diff = |comparison, normalizers: &[Normalizer]| {
+        diff_files(&output, &answer, comparison, normalizers, &policy, 100).unwrap()
+    };
+    assert_eq!(
+        diff(OutputComparison::Lines, &[Normalizer::Lowercase]).unwrap(),
+        "--- answer\n+++ output\n@@ -1,2 +1,2 @@\n yes\n-1 3\n+1.0000001 2"
+    );
+    // One token per line, the ones matching within the tolerance are the same
+    assert_eq!(
+        diff(
+            OutputComparison::Tokens(TokenComparison::Float(FloatComparison::new(1e-6))),
+            &[]
+        )
+        .unwrap(),
+        "--- answer\n+++ output\n@@ -1,3 +1,3 @@\n-yes\n+YES\n 1\n-3\n+2"
+    );
+    assert_eq!(diff(OutputComparison::Bytes, &[]), None);
+}
+
+#[test]
+fn test_sha256_fields_and_file() {
+    let path = write_temp_file("sha256_fields.txt", "abc");
+    // A stable hash, the plain SHA-256 of the content without fields
+    assert_eq!(
+        sha256_file(&path).unwrap(),
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+    let hash = |fields: &[&[u8]]| sha256_fields_and_file(fields, &path).unwrap();
+    assert_ne!(hash(&[]), sha256_file(&path).unwrap());
+    assert_ne!(hash(&[b"ab", b"c"]), hash(&[b"a", b"bc"]));
+    assert_ne!(hash(&[b"a"]), hash(&[b"a", b""]));
+    assert_eq!(hash(&[b"a", b"bc"]), hash(&[b"a", b"bc"]));
+}
@@ -8,12 +8,12 @@ mod worker;
 extern crate serde_derive;
 extern crate lazy_static;
 
-use std::{fs, path::PathBuf, time::Duration};
+use std::{fs, path::PathBuf};
 
 use actix_web::{App, HttpServer};
 use agent::{platform, rclone::RcloneClient};
 use judge_core::judge::{
-    result::{JudgeResultInfo, JudgeVerdict},
+    result::{reason_code, JudgeResultInfo, JudgeVerdict},
     JudgeConfig,
 };
 use option::JudgerCommad;
@@ -151,24 +151,18 @@ async fn judge(
             program: judge.program_config.clone(),
             checker: judge.checker_config.clone(),
             runtime: judge.runtime_config.clone(),
+            setup: judge.setup_config.clone(),
+            reference: judge.reference_config.clone(),
         };
 
         let judge_result = worker.run_judge(judge_config);
-        let mut result = JudgeResultInfo {
-            verdict: JudgeVerdict::SystemError,
-            time_usage: Duration::from_secs(0),
-            memory_usage_bytes: 0,
-            exit_status: -1,
-            checker_exit_status: -1,
-        };
-        match judge_result {
-            Ok(r) => {
-                result = r;
-            }
+        let result = match judge_result {
+            Ok(result) => result,
             Err(e) => {
                 log::debug!("Failed to run judge: {:?}", e);
+                JudgeResultInfo::system_error(e.to_string(), reason_code::SE_JUDGE_ERROR)
             }
-        }
+        };
         if result.verdict != JudgeVerdict::Accepted {
             verdict = result.verdict;
             break;
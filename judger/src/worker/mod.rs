@@ -5,7 +5,7 @@ use anyhow::Error;
 use judge_core::compiler::Language;
 use judge_core::error::JudgeCoreError;
 use judge_core::judge;
-use judge_core::judge::result::JudgeVerdict;
+use judge_core::judge::result::{reason_code, JudgeVerdict};
 use judge_core::{
     judge::builder::{JudgeBuilder, JudgeBuilderInput},
     judge::result::JudgeResultInfo,
@@ -112,24 +112,21 @@ impl JudgeWorker {
                             program: judge.program_config.clone(),
                             checker: judge.checker_config.clone(),
                             runtime: judge.runtime_config.clone(),
+                            setup: judge.setup_config.clone(),
+                            reference: judge.reference_config.clone(),
                         };
 
                         let judge_result = self.run_judge(judge_config);
-                        let mut result = JudgeResultInfo {
-                            verdict: JudgeVerdict::SystemError,
-                            time_usage: Duration::from_secs(0),
-                            memory_usage_bytes: 0,
-                            exit_status: -1,
-                            checker_exit_status: -1,
-                        };
-                        match judge_result {
-                            Ok(r) => {
-                                result = r;
-                            }
+                        let result = match judge_result {
+                            Ok(result) => result,
                             Err(e) => {
                                 log::debug!("Failed to run judge: {:?}", e);
+                                JudgeResultInfo::system_error(
+                                    e.to_string(),
+                                    reason_code::SE_JUDGE_ERROR,
+                                )
                             }
-                        }
+                        };
 
                         let _ = platform_client
                             .report_judge_result(
@@ -193,6 +190,7 @@ impl JudgeWorker {
             runtime_path: runtime_path.clone(),
             src_language: language,
             src_path: runtime_path.clone().join(&src_file_name),
+            checker: None,
         })?;
         log::info!("Builder created success: {:?}", builder);
         Ok(builder)